@@ -0,0 +1,209 @@
+//! Sets up the processes that Ground Control starts, in the child
+//! process between `fork` and `exec`. This is the only code in Ground
+//! Control that needs `unsafe` (the `groundcontrol` crate itself forbids
+//! it), and so it is kept to the two functions below.
+
+#![forbid(future_incompatible)]
+#![deny(
+    unsafe_code,
+    missing_debug_implementations,
+    nonstandard_style,
+    missing_docs,
+    unreachable_pub,
+    missing_copy_implementations,
+    unused_qualifications,
+    clippy::unwrap_in_result,
+    clippy::unwrap_used
+)]
+
+use std::{ffi::CString, io};
+
+use nix::{
+    sched::{sched_setaffinity, CpuSet},
+    sys::{
+        resource::{setrlimit, Resource},
+        signal::Signal,
+    },
+    unistd::Pid,
+};
+use tokio::process::Command;
+
+/// `ioprio_set` target for a single process (from `linux/ioprio.h`,
+/// which `libc` does not provide).
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+/// Changes made to the process of a command before it runs the command,
+/// in this order (so that the process still has the privileges to raise
+/// its priorities and its limits, and to change its root directory and
+/// its groups, before it switches its user).
+#[derive(Debug, Default)]
+pub struct ProcessSetup {
+    /// `cgroup.procs` file of the cgroup to move the process into.
+    pub cgroup_procs: Option<CString>,
+
+    /// Niceness of the process.
+    pub nice: Option<libc::c_int>,
+
+    /// IO priority of the process (its IO scheduling class and its
+    /// priority within the class, as `ioprio_set` expects them).
+    pub io_priority: Option<libc::c_int>,
+
+    /// CPUs that the process may run on.
+    pub cpu_affinity: Option<CpuSet>,
+
+    /// Resource limits (both soft and hard) of the process.
+    pub limits: Vec<(Resource, libc::rlim_t)>,
+
+    /// Directory to confine the process to (which also becomes its
+    /// working directory).
+    pub root_directory: Option<CString>,
+
+    /// Supplementary groups of the process.
+    pub groups: Option<Vec<libc::gid_t>>,
+
+    /// Whether the supplementary groups must be switched (otherwise they
+    /// are only switched if Ground Control is allowed to, as the standard
+    /// library does when it changes the uid).
+    pub explicit_groups: bool,
+
+    /// Group to run the process as.
+    pub gid: Option<libc::gid_t>,
+
+    /// User to run the process as.
+    pub uid: Option<libc::uid_t>,
+
+    /// Whether to keep the process from gaining privileges.
+    pub no_new_privileges: bool,
+
+    /// Whether to clear the ambient capabilities of the process.
+    pub clear_ambient_capabilities: bool,
+}
+
+/// Makes the changes of the setup to the process of the command before
+/// the process runs the command (the command fails to start if any of
+/// the changes fail).
+#[allow(unsafe_code)]
+pub fn set_up(command: &mut Command, setup: ProcessSetup) {
+    let ProcessSetup {
+        cgroup_procs,
+        nice,
+        io_priority,
+        cpu_affinity,
+        limits,
+        root_directory,
+        groups,
+        explicit_groups,
+        gid,
+        uid,
+        no_new_privileges,
+        clear_ambient_capabilities,
+    } = setup;
+
+    // SAFETY: the closure runs in the forked child, where only
+    // async-signal-safe functions may be called: it only makes system
+    // calls (through `libc`, and through `nix` for `sched_setaffinity`
+    // and `setrlimit`), and only uses memory that was allocated before
+    // the fork (the pointers passed to the kernel all point into the
+    // values that the closure owns). Nothing is allocated, locked, or
+    // freed in the child (errors are made from OS error codes, which
+    // does not allocate).
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(cgroup_procs) = &cgroup_procs {
+                let fd = libc::open(cgroup_procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::write(fd, b"0".as_ptr().cast(), 1) == -1 {
+                    let error = io::Error::last_os_error();
+                    libc::close(fd);
+                    return Err(error);
+                }
+                libc::close(fd);
+            }
+            if let Some(nice) = nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(io_priority) = io_priority {
+                if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, io_priority) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(cpu_affinity) = &cpu_affinity {
+                sched_setaffinity(Pid::from_raw(0), cpu_affinity)?;
+            }
+            for &(resource, limit) in &limits {
+                setrlimit(resource, limit, limit)?;
+            }
+            if let Some(root_directory) = &root_directory {
+                if libc::chroot(root_directory.as_ptr()) == -1
+                    || libc::chdir(b"/\0".as_ptr().cast()) == -1
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(groups) = &groups {
+                if libc::setgroups(groups.len(), groups.as_ptr()) == -1 {
+                    let error = io::Error::last_os_error();
+                    if explicit_groups || error.raw_os_error() != Some(libc::EPERM) {
+                        return Err(error);
+                    }
+                }
+            }
+            if let Some(gid) = gid {
+                if libc::setgid(gid) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(uid) = uid {
+                if libc::setuid(uid) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if no_new_privileges && libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if clear_ambient_capabilities
+                && libc::prctl(
+                    libc::PR_CAP_AMBIENT,
+                    libc::PR_CAP_AMBIENT_CLEAR_ALL,
+                    0,
+                    0,
+                    0,
+                ) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Asks the kernel to send the signal to the process of the command once
+/// the calling process (Ground Control) dies. The signal is raised right
+/// away if Ground Control died before the signal could be set. Call this
+/// after [`set_up`], so that the signal is set after the uid and gid are
+/// changed (which would otherwise clear it).
+#[allow(unsafe_code)]
+pub fn set_parent_death_signal(command: &mut Command, signal: Signal) {
+    let parent = nix::unistd::getpid().as_raw();
+    let signal = signal as libc::c_int;
+
+    // SAFETY: the closure runs in the forked child, where only
+    // async-signal-safe functions may be called: `prctl`, `getppid`, and
+    // `raise` are all system calls, and the closure only uses the
+    // integers that it copied before the fork.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, signal as libc::c_ulong) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::getppid() != parent {
+                libc::raise(signal);
+            }
+            Ok(())
+        });
+    }
+}
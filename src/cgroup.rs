@@ -0,0 +1,241 @@
+//! Runs the commands of processes in cgroups (v2) of their own, which
+//! limit the resources of the commands, and keep track of every process
+//! that the commands start (even the ones that leave their process
+//! group).
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use color_eyre::eyre::{self, eyre, WrapErr};
+use nix::{
+    sys::signal::{kill as kill_pid, Signal},
+    unistd::Pid,
+};
+use once_cell::sync::Lazy;
+
+use crate::config::{CgroupConfig, CpuMax, MemoryMax};
+
+/// Where the cgroup v2 hierarchy is mounted.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Name of the cgroup that the processes in Ground Control's own cgroup
+/// (Ground Control itself, and the commands that do not have a cgroup)
+/// are moved to, since a cgroup that has processes cannot enable
+/// controllers for its children.
+const SUPERVISOR_CGROUP: &str = "groundcontrol";
+
+/// Controllers that are enabled for the cgroups of the processes (if
+/// they are available).
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+
+/// Ground Control's own cgroup (which the cgroups of the processes are
+/// created in), once its controllers have been enabled.
+static BASE: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(Default::default);
+
+/// Returns `true` if the cgroup v2 hierarchy is mounted (and not only the
+/// cgroup v1 hierarchies).
+pub(crate) fn is_available() -> bool {
+    Path::new(CGROUP_ROOT).join("cgroup.controllers").is_file()
+}
+
+/// Creates the cgroup of the given process (unless it already exists),
+/// sets its limits, and returns the path of the cgroup.
+pub(crate) fn prepare(process_name: &str, config: &CgroupConfig) -> eyre::Result<PathBuf> {
+    let path = base()?.join(cgroup_name(process_name));
+    match fs::create_dir(&path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => {
+            return Err(err).wrap_err_with(|| format!("Failed to create cgroup {path:?}"));
+        }
+    }
+
+    if let Some(memory_max) = config.memory_max {
+        let memory_max = match memory_max {
+            MemoryMax::Bytes(bytes) => bytes.to_string(),
+            MemoryMax::Max => String::from("max"),
+        };
+        write(&path, "memory.max", &memory_max)?;
+    }
+    if let Some(cpu_max) = config.cpu_max {
+        let cpu_max = match cpu_max {
+            CpuMax::Quota(quota) => format!("{quota} {}", CpuMax::PERIOD),
+            CpuMax::Max => String::from("max"),
+        };
+        write(&path, "cpu.max", &cpu_max)?;
+    }
+
+    Ok(path)
+}
+
+/// Kills every process in the cgroup of the given process (if the
+/// process has a cgroup).
+pub(crate) fn kill(process_name: &str) -> eyre::Result<()> {
+    let base = BASE.lock().unwrap_or_else(PoisonError::into_inner).clone();
+    let path = match base {
+        Some(base) => base.join(cgroup_name(process_name)),
+        None => return Ok(()),
+    };
+
+    // `cgroup.kill` (Linux 5.14 and later) kills every process at once,
+    // including the ones that are being forked; otherwise kill the
+    // processes that are in the cgroup now.
+    if fs::write(path.join("cgroup.kill"), "1").is_ok() {
+        return Ok(());
+    }
+    let procs = fs::read_to_string(path.join("cgroup.procs"))
+        .wrap_err_with(|| format!("Failed to list the processes in cgroup {path:?}"))?;
+    for pid in procs.lines().filter_map(|pid| pid.parse().ok()) {
+        let _ = kill_pid(Pid::from_raw(pid), Signal::SIGKILL);
+    }
+
+    Ok(())
+}
+
+/// Removes the cgroup of the given process (if the process has a cgroup
+/// that still exists), which fails while there are processes left in the
+/// cgroup.
+pub(crate) fn remove(process_name: &str) -> io::Result<()> {
+    let base = BASE.lock().unwrap_or_else(PoisonError::into_inner).clone();
+    let path = match base {
+        Some(base) => base.join(cgroup_name(process_name)),
+        None => return Ok(()),
+    };
+
+    match fs::remove_dir(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Returns the name of the cgroup of the given process.
+fn cgroup_name(process_name: &str) -> String {
+    format!("process-{process_name}")
+}
+
+/// Returns Ground Control's own cgroup, enabling its controllers the
+/// first time.
+fn base() -> eyre::Result<PathBuf> {
+    let mut base = BASE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(base) = &*base {
+        return Ok(base.clone());
+    }
+
+    let self_cgroup = fs::read_to_string("/proc/self/cgroup")
+        .wrap_err("Failed to read the cgroup of Ground Control")?;
+    let path = enable_controllers(Path::new(CGROUP_ROOT), &self_cgroup)?;
+    *base = Some(path.clone());
+    Ok(path)
+}
+
+/// Finds Ground Control's own cgroup (given the contents of
+/// `/proc/self/cgroup`) in the cgroup v2 hierarchy mounted at the given
+/// root, moves the processes in the cgroup to a child cgroup (unless it
+/// is the root cgroup, which can have processes of its own), and enables
+/// the controllers for the child cgroups. Returns the path of the cgroup.
+fn enable_controllers(root: &Path, self_cgroup: &str) -> eyre::Result<PathBuf> {
+    if !root.join("cgroup.controllers").is_file() {
+        return Err(eyre!("cgroup v2 is not mounted at {root:?}"));
+    }
+    let cgroup = parse_cgroup(self_cgroup)
+        .ok_or_else(|| eyre!("Ground Control does not run in a cgroup v2 cgroup"))?;
+    let base = root.join(cgroup.trim_start_matches('/'));
+
+    // Only non-root cgroups have a type.
+    if base.join("cgroup.type").exists() {
+        let supervisor = base.join(SUPERVISOR_CGROUP);
+        match fs::create_dir(&supervisor) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => {
+                return Err(err)
+                    .wrap_err_with(|| format!("Failed to create cgroup {supervisor:?}"));
+            }
+        }
+
+        // Processes that exit in the meantime cannot be moved (nor do
+        // they need to be).
+        let procs = fs::read_to_string(base.join("cgroup.procs"))
+            .wrap_err_with(|| format!("Failed to list the processes in cgroup {base:?}"))?;
+        for pid in procs.lines() {
+            let _ = fs::write(supervisor.join("cgroup.procs"), pid);
+        }
+    }
+
+    let available = fs::read_to_string(base.join("cgroup.controllers"))
+        .wrap_err_with(|| format!("Failed to list the controllers of cgroup {base:?}"))?;
+    let controllers: Vec<String> = available
+        .split_whitespace()
+        .filter(|controller| CONTROLLERS.contains(controller))
+        .map(|controller| format!("+{controller}"))
+        .collect();
+    write(&base, "cgroup.subtree_control", &controllers.join(" "))?;
+
+    Ok(base)
+}
+
+/// Returns the cgroup v2 cgroup in the given contents of
+/// `/proc/self/cgroup` (the path after `0::`).
+fn parse_cgroup(self_cgroup: &str) -> Option<&str> {
+    self_cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+}
+
+/// Writes the given value to the given file of the cgroup.
+fn write(cgroup: &Path, file: &str, value: &str) -> eyre::Result<()> {
+    fs::write(cgroup.join(file), value)
+        .wrap_err_with(|| format!("Failed to set {file} of cgroup {cgroup:?} to \"{value}\""))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cgroup_v2_path_is_parsed() {
+        assert_eq!(
+            Some("/system.slice/app.service"),
+            parse_cgroup("1:name=systemd:/\n0::/system.slice/app.service\n")
+        );
+        assert_eq!(None, parse_cgroup("4:memory:/docker/abc\n"));
+    }
+
+    #[test]
+    fn controllers_are_enabled_after_moving_processes() {
+        let root = tempfile::tempdir().unwrap();
+        let base = root.path().join("app");
+        fs::create_dir(&base).unwrap();
+        fs::write(root.path().join("cgroup.controllers"), "cpu io memory pids").unwrap();
+        fs::write(base.join("cgroup.controllers"), "cpu io memory").unwrap();
+        fs::write(base.join("cgroup.type"), "domain").unwrap();
+        fs::write(base.join("cgroup.procs"), "1\n").unwrap();
+
+        let path = enable_controllers(root.path(), "0::/app\n").unwrap();
+
+        assert_eq!(base, path);
+        assert_eq!(
+            "1",
+            fs::read_to_string(base.join(SUPERVISOR_CGROUP).join("cgroup.procs")).unwrap()
+        );
+        assert_eq!(
+            "+cpu +memory",
+            fs::read_to_string(base.join("cgroup.subtree_control")).unwrap()
+        );
+    }
+
+    #[test]
+    fn controllers_require_cgroup_v2() {
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(
+            format!("cgroup v2 is not mounted at {:?}", root.path()),
+            enable_controllers(root.path(), "0::/\n")
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
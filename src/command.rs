@@ -1,18 +1,42 @@
 //! Runs commands and monitors their completion.
 
-use std::{env, process::Stdio};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::{CString, OsStr, OsString},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        process::ExitStatusExt,
+    },
+    path::Path,
+    process::Stdio,
+    time::{Duration, Instant},
+};
 
 use color_eyre::eyre::{self, eyre, WrapErr};
 use command_group::{AsyncCommandGroup, AsyncGroupChild};
-use nix::unistd::Pid;
+use groundcontrol_pre_exec::ProcessSetup;
+use nix::{
+    sched::CpuSet,
+    sys::{resource::Resource, signal::Signal},
+    unistd::{Gid, Pid},
+};
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
-    sync::oneshot,
+    sync::{mpsc, oneshot},
 };
+use users::os::unix::UserExt;
 
-use crate::config::CommandConfig;
+use crate::{
+    cgroup,
+    config::{
+        CgroupConfig, CommandConfig, EnvFileConfig, InheritEnv, IoClass, Limit, LimitsConfig,
+        MissingEnvFilePolicy,
+    },
+    init,
+};
 
 /// Exit status returned by a command.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -20,25 +44,72 @@ pub(crate) enum ExitStatus {
     /// Command exited with the given exit code.
     Exited(i32),
 
-    /// Command was killed before it could exit.
-    Killed,
+    /// Command was killed before it could exit (by the given signal, if
+    /// known).
+    Killed(Option<Signal>),
+}
+
+impl ExitStatus {
+    /// Returns the exit code of the command or, as shells do, 128 plus
+    /// the number of the signal that killed the command (1 if that
+    /// signal is not known).
+    pub(crate) fn exit_code(self) -> i32 {
+        match self {
+            ExitStatus::Exited(exit_code) => exit_code,
+            ExitStatus::Killed(Some(signal)) => 128 + signal as i32,
+            ExitStatus::Killed(None) => 1,
+        }
+    }
+}
+
+/// Settings of the process that a command belongs to, which are needed
+/// to start the command (but are not part of the command's own
+/// configuration).
+#[derive(Copy, Clone, Default, Debug)]
+pub(crate) struct SpawnContext<'a> {
+    /// Name of the command's process (if any), which the command gets in
+    /// `GROUNDCONTROL_PROCESS_NAME` (and which names the cgroup of the
+    /// process).
+    pub(crate) process_name: Option<&'a str>,
+
+    /// `cgroup` settings of the process, if the command runs in the
+    /// cgroup of its process (only its `run` command does, so that killing
+    /// the cgroup does not kill the process's other commands).
+    pub(crate) cgroup: Option<&'a CgroupConfig>,
 }
 
 /// Control handle for a Command, used to send signals to the command.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct CommandControl {
     name: String,
     pid: Pid,
 }
 
 impl CommandControl {
+    /// Returns the PID of the process.
+    pub(crate) fn pid(&self) -> Pid {
+        self.pid
+    }
+
     /// Sends a signal to the process.
-    pub(crate) fn kill(&self, signal: nix::sys::signal::Signal) -> eyre::Result<()> {
+    pub(crate) fn kill(&self, signal: Signal) -> eyre::Result<()> {
         nix::sys::signal::kill(self.pid, signal).wrap_err_with(|| {
             format!("Error sending {signal} signal to process \"{}\"", self.name)
         })?;
         Ok(())
     }
+
+    /// Sends a signal to every process in the process group of the
+    /// process.
+    pub(crate) fn kill_group(&self, signal: Signal) -> eyre::Result<()> {
+        nix::sys::signal::killpg(self.pid, signal).wrap_err_with(|| {
+            format!(
+                "Error sending {signal} signal to process group of \"{}\"",
+                self.name
+            )
+        })?;
+        Ok(())
+    }
 }
 
 /// Monitoring handle for a Command, used to wait for the Command to
@@ -50,62 +121,36 @@ pub(crate) struct CommandMonitor {
 
 impl CommandMonitor {
     /// Waits for the command to exit and returns the exit status.
-    pub(crate) async fn wait(self) -> ExitStatus {
-        self.monitor
+    ///
+    /// Note that this must only be awaited to completion once.
+    pub(crate) async fn wait(&mut self) -> ExitStatus {
+        (&mut self.monitor)
             .await
             .expect("Command Monitor sender dropped before sending a result.")
     }
 }
 
-/// Runs the command and returns the control and monitor handles.
+/// Runs the command and returns the control and monitor handles. The
+/// given environment variables are set for the command (even if the
+/// command uses `only-env`; only their names are logged, since they may
+/// be secrets), and every line that the command writes to stdout or
+/// stderr is also sent to the given channel (if any). The command is
+/// started in its own session (and process group) unless `new_session`
+/// is `false`, and receives the `parent_death_signal` (if any) when
+/// Ground Control dies.
 pub(crate) fn run(
     name: &str,
     config: &CommandConfig,
+    context: SpawnContext<'_>,
+    extra_env: &[(&str, String)],
+    output: Option<mpsc::UnboundedSender<String>>,
+    new_session: bool,
+    parent_death_signal: Option<Signal>,
 ) -> eyre::Result<(CommandControl, CommandMonitor)> {
-    tracing::debug!(%name, ?config, "Running command");
-
-    // Initialize the command.
-    let mut command = tokio::process::Command::new(&config.program);
-
-    // Add the arguments, and perform environment variable substitution.
-    match config
-        .args
-        .iter()
-        .map(substitute_env_var)
-        .collect::<eyre::Result<Vec<String>>>()
-    {
-        Ok(args) => command.args(args),
-        Err(err) => {
-            return Err(err.wrap_err(format!(
-                "Environment variable expansion failed for command \"{}\"",
-                config.program
-            )))
-        }
-    };
+    let extra_env_names: Vec<&str> = extra_env.iter().map(|(key, _)| *key).collect();
+    tracing::debug!(%name, ?config, extra_env = ?extra_env_names, "Running command");
 
-    // Clear the environment if `only_env` was provided, then add back
-    // in `PATH` and any other allowed environment variables.
-    if let Some(only_env) = &config.only_env {
-        command.env_clear();
-
-        if let Ok(path) = env::var("PATH") {
-            command.env("PATH", path);
-        }
-
-        for key in only_env {
-            command.env(
-                key,
-                env::var(key).map_err(|_| eyre!("Unknown environment variable \"{key}\""))?,
-            );
-        }
-    }
-
-    // Set the uid and gid if provided.
-    if let Some(username) = &config.user {
-        let user = users::get_user_by_name(username)
-            .ok_or_else(|| eyre!("Unknown username \"{username}\""))?;
-        command.uid(user.uid()).gid(user.primary_group_id());
-    };
+    let mut command = prepare(name, config, context, extra_env)?;
 
     // Disable stdin, and pipe stdout and stderr so that we can read
     // and process the output.
@@ -114,21 +159,49 @@ pub(crate) fn run(
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
-    // Run the command.
-    let mut child = command
-        .group_spawn()
-        .wrap_err_with(|| format!("Error starting command \"{}\"", config.program))?;
-    let pid = nix::unistd::Pid::from_raw(child.id().ok_or_else(|| {
+    // Ask the kernel to signal the command when Ground Control dies.
+    // This happens after the uid and gid are changed (which would
+    // otherwise clear the signal), and the signal is raised right away
+    // if Ground Control died before the signal could be set.
+    if let Some(signal) = parent_death_signal {
+        groundcontrol_pre_exec::set_parent_death_signal(&mut command, signal);
+    }
+
+    // Run the command (and keep it from being reaped as an orphan, since
+    // its exit status is collected by the monitor).
+    let mut commands = init::commands();
+    let spawned = if new_session {
+        command.group_spawn().map(Child::Session)
+    } else {
+        command.spawn().map(Child::Shared)
+    };
+    let mut child =
+        spawned.wrap_err_with(|| format!("Error starting command \"{}\"", config.program))?;
+    let pid = Pid::from_raw(child.inner().id().ok_or_else(|| {
         eyre!(
             "Failed to get PID of just-started command \"{}\"",
             config.program
         )
     })? as i32);
+    commands.insert(pid);
+    drop(commands);
+
+    // Adjust the command's OOM score (from Ground Control, which may be
+    // allowed to lower it even if the command is not). The command keeps
+    // running if the score cannot be adjusted.
+    if let Some(oom_score_adj) = config.oom_score_adj {
+        if let Err(err) = std::fs::write(
+            format!("/proc/{pid}/oom_score_adj"),
+            oom_score_adj.to_string(),
+        ) {
+            tracing::warn!(%name, %pid, ?err, "Error adjusting OOM score of command");
+        }
+    }
 
     tracing::debug!(%name, %pid, "Command running");
 
     // Read stdout and stderr and send them to the console via
-    // specially-targeted `tracing` events.
+    // specially-targeted `tracing` events (and to the output channel).
     let stdout = child
         .inner()
         .stdout
@@ -136,10 +209,14 @@ pub(crate) fn run(
         .expect("failed to get stdout from child process");
     let mut reader = BufReader::new(stdout).lines();
     let process = name.to_string();
+    let stdout_output = output.clone();
     tokio::task::spawn({
         async move {
             while let Ok(Some(line)) = reader.next_line().await {
                 tracing::info!(target: "stdout", %process, output = line);
+                if let Some(output) = &stdout_output {
+                    let _ = output.send(line);
+                }
             }
         }
     });
@@ -155,6 +232,9 @@ pub(crate) fn run(
         async move {
             while let Ok(Some(line)) = reader.next_line().await {
                 tracing::info!(target: "stderr", %process, output = line);
+                if let Some(output) = &output {
+                    let _ = output.send(line);
+                }
             }
         }
     });
@@ -173,7 +253,632 @@ pub(crate) fn run(
     ))
 }
 
-fn substitute_env_var(s: impl AsRef<str>) -> eyre::Result<String> {
+/// Initializes the command: its arguments, its environment (see [`run`]),
+/// and its user.
+fn prepare(
+    name: &str,
+    config: &CommandConfig,
+    context: SpawnContext<'_>,
+    extra_env: &[(&str, String)],
+) -> eyre::Result<tokio::process::Command> {
+    // Initialize the command.
+    let mut command = tokio::process::Command::new(&config.program);
+
+    // Expand the templates in the command's own environment variables,
+    // then perform environment variable substitution in the arguments
+    // (using the expanded variables).
+    let (own_env, args) = expand_env(&config.env, &HashMap::new())
+        .and_then(|own_env| {
+            let args = config
+                .args
+                .iter()
+                .map(|arg| substitute_env_var(arg, &own_env))
+                .collect::<eyre::Result<Vec<String>>>()?;
+            Ok((own_env, args))
+        })
+        .wrap_err_with(|| {
+            format!(
+                "Environment variable expansion failed for command \"{}\"",
+                config.program
+            )
+        })?;
+
+    // Clear the environment unless the command inherits all of it, then
+    // add back in `PATH` and any other allowed environment variables
+    // (including the ones that match the allowed patterns) if the
+    // command inherits the listed variables.
+    let inherit_env = config.inherit_env.unwrap_or(if config.only_env.is_some() {
+        InheritEnv::Listed
+    } else {
+        InheritEnv::All
+    });
+    if inherit_env != InheritEnv::All {
+        command.env_clear();
+    }
+    if inherit_env == InheritEnv::Listed {
+        if let Ok(path) = env::var("PATH") {
+            command.env("PATH", path);
+        }
+
+        for key in config.only_env.iter().flatten() {
+            if key.contains('*') {
+                command.envs(env::vars().filter(|(name, _)| matches_pattern(key, name)));
+            } else {
+                command.env(
+                    key,
+                    env::var(key).map_err(|_| eyre!("Unknown environment variable \"{key}\""))?,
+                );
+            }
+        }
+    }
+
+    // Add the login variables of the user that runs the command (if
+    // asked to), then the variables in the process's env file (if any), the
+    // command's own environment variables (including the ones that are
+    // read from files), and the ones that Ground Control itself provides
+    // to the command (including the ones that identify Ground Control
+    // and the command's process).
+    if config.login_env {
+        command.envs(login_env(config)?);
+    }
+    if let Some(env_file) = &config.env_file {
+        command.envs(read_env_file(name, env_file)?);
+    }
+    if context.process_name.is_some() {
+        // Instances of replicated processes set their own index.
+        command.env("GROUNDCONTROL_INSTANCE", "0");
+    }
+    command.envs(&own_env);
+    command.envs(read_env_from_files(&config.env_from_file)?);
+    command.envs(extra_env.iter().map(|(key, value)| (key, value)));
+    command.env(
+        "GROUNDCONTROL_SUPERVISOR_PID",
+        std::process::id().to_string(),
+    );
+    if let Some(process_name) = context.process_name {
+        command.env("GROUNDCONTROL_PROCESS_NAME", process_name);
+    }
+
+    // Now that the environment of the command is known, replace the
+    // references to its variables (such as `${PORT}`) in the arguments,
+    // except in the script of a shell command (where the values would be
+    // parsed as shell code), which leaves them to the shell.
+    let child_env = child_env(&command, inherit_env);
+    let script = shell_script_index(&config.program, &args);
+    command.args(args.iter().enumerate().map(|(index, arg)| {
+        if Some(index) == script {
+            arg.clone()
+        } else {
+            expand_vars(arg, &child_env)
+        }
+    }));
+
+    // Look up the user, group, and supplementary groups to run the
+    // command as, if provided (the supplementary groups default to the
+    // groups that list the user as a member, except for numeric users,
+    // which only have the supplementary groups that are listed in
+    // `groups`).
+    let (uid, username, gid) = lookup_user(config)?;
+    let groups = match (&config.groups, &username, gid) {
+        (Some(groups), _, _) => Some(
+            groups
+                .iter()
+                .map(|groupname| group_id(groupname))
+                .collect::<eyre::Result<Vec<libc::gid_t>>>()?,
+        ),
+        (None, Some(username), Some(gid)) => Some(
+            CString::new(username.as_bytes())
+                .map_err(eyre::Report::new)
+                .and_then(|username| {
+                    nix::unistd::getgrouplist(&username, Gid::from_raw(gid))
+                        .map_err(eyre::Report::new)
+                })
+                .wrap_err_with(|| {
+                    format!(
+                        "Failed to read the groups of user \"{}\"",
+                        username.to_string_lossy()
+                    )
+                })?
+                .into_iter()
+                .map(Gid::as_raw)
+                .collect(),
+        ),
+        (None, None, _) if uid.is_some() => Some(Vec::new()),
+        (None, _, _) => None,
+    };
+
+    // Move the command into the cgroup of its process (if any, so that the
+    // command and every process that it starts are limited by the cgroup from
+    // the start), set the niceness, the IO priority, the CPU affinity, and
+    // the resource limits of the command and confine it to its root directory
+    // (if any), then switch the supplementary groups, the gid, and then the
+    // uid (in that order, while the command still has the privileges to raise
+    // its priorities and its limits, and to change the root directory and the
+    // groups), and finally keep the command from gaining privileges and clear
+    // its ambient capabilities (if asked to). The default supplementary
+    // groups are only switched if Ground Control is allowed to (as the
+    // standard library does when it changes the uid), but explicit groups
+    // must be switched.
+    let cgroup_procs = context
+        .process_name
+        .zip(context.cgroup)
+        .map(|(process_name, cgroup)| {
+            let path = cgroup::prepare(process_name, cgroup)?.join("cgroup.procs");
+            CString::new(path.into_os_string().into_vec()).map_err(eyre::Report::new)
+        })
+        .transpose()
+        .wrap_err_with(|| {
+            format!(
+                "Failed to prepare the cgroup of command \"{}\"",
+                config.program
+            )
+        })?;
+    let root_directory = config
+        .root_directory
+        .as_ref()
+        .map(|root_directory| CString::new(root_directory.as_bytes()))
+        .transpose()
+        .wrap_err_with(|| format!("Invalid root directory for command \"{}\"", config.program))?;
+    let nice = config.nice;
+    let io_priority = io_priority(config);
+    let cpu_affinity = config
+        .cpu_affinity
+        .as_ref()
+        .map(|cpus| {
+            let mut cpu_set = CpuSet::new();
+            for &cpu in cpus {
+                cpu_set
+                    .set(cpu)
+                    .wrap_err_with(|| format!("Invalid CPU {cpu} in `cpu-affinity`"))?;
+            }
+            Ok::<_, eyre::Report>(cpu_set)
+        })
+        .transpose()?;
+    let limits = config.limits.as_ref().map(resource_limits);
+    let no_new_privileges = config.no_new_privileges;
+    let clear_ambient_capabilities = config.clear_ambient_capabilities;
+    if cgroup_procs.is_some()
+        || nice.is_some()
+        || io_priority.is_some()
+        || cpu_affinity.is_some()
+        || limits.is_some()
+        || root_directory.is_some()
+        || uid.is_some()
+        || gid.is_some()
+        || groups.is_some()
+        || no_new_privileges
+        || clear_ambient_capabilities
+    {
+        groundcontrol_pre_exec::set_up(
+            &mut command,
+            ProcessSetup {
+                cgroup_procs,
+                nice,
+                io_priority,
+                cpu_affinity,
+                limits: limits.unwrap_or_default(),
+                root_directory,
+                groups,
+                explicit_groups: config.groups.is_some(),
+                gid,
+                uid,
+                no_new_privileges,
+                clear_ambient_capabilities,
+            },
+        );
+    }
+
+    Ok(command)
+}
+
+/// Returns the uid and the gid that the command runs as (if it switches
+/// its user or its group).
+pub(crate) fn owner(
+    config: &CommandConfig,
+) -> eyre::Result<(Option<libc::uid_t>, Option<libc::gid_t>)> {
+    let (uid, _, gid) = lookup_user(config)?;
+    Ok((uid, gid))
+}
+
+/// Looks up the uid, the username, and the gid to run the command as, if
+/// provided (the gid defaults to the primary group of the user).
+/// Numeric ids are used as is, without looking them up (so that they
+/// work in images without `/etc/passwd`), but a numeric user needs a
+/// group (and has no username).
+fn lookup_user(
+    config: &CommandConfig,
+) -> eyre::Result<(Option<libc::uid_t>, Option<OsString>, Option<libc::gid_t>)> {
+    let (user, group) = config.user_and_group();
+    let (uid, username, default_gid) = match user {
+        Some(user) => match user.parse::<libc::uid_t>() {
+            Ok(uid) => (Some(uid), None, None),
+            Err(_) => {
+                let user = users::get_user_by_name(user)
+                    .ok_or_else(|| eyre!("Unknown username \"{user}\""))?;
+                (
+                    Some(user.uid()),
+                    Some(user.name().to_owned()),
+                    Some(user.primary_group_id()),
+                )
+            }
+        },
+        None => (None, None, None),
+    };
+    let gid = match (group, uid, default_gid) {
+        (Some(groupname), _, _) => Some(group_id(groupname)?),
+        (None, Some(uid), None) => {
+            return Err(eyre!(
+                "Numeric user \"{uid}\" needs a group (such as \"{uid}:{uid}\")"
+            ))
+        }
+        (None, _, default_gid) => default_gid,
+    };
+    Ok((uid, username, gid))
+}
+
+/// Returns the resource limits to set (with `setrlimit`) for the given
+/// limits.
+fn resource_limits(limits: &LimitsConfig) -> Vec<(Resource, libc::rlim_t)> {
+    [
+        (Resource::RLIMIT_AS, limits.address_space),
+        (Resource::RLIMIT_CORE, limits.core),
+        (Resource::RLIMIT_CPU, limits.cpu),
+        (Resource::RLIMIT_DATA, limits.data),
+        (Resource::RLIMIT_FSIZE, limits.fsize),
+        (Resource::RLIMIT_MEMLOCK, limits.memlock),
+        (Resource::RLIMIT_NOFILE, limits.nofile),
+        (Resource::RLIMIT_NPROC, limits.nproc),
+        (Resource::RLIMIT_STACK, limits.stack),
+    ]
+    .into_iter()
+    .filter_map(|(resource, limit)| match limit? {
+        Limit::Value(value) => Some((resource, value)),
+        Limit::Unlimited => Some((resource, libc::RLIM_INFINITY)),
+    })
+    .collect()
+}
+
+/// Position of the IO scheduling class in an IO priority.
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// The realtime IO scheduling class.
+const IOPRIO_CLASS_RT: libc::c_int = 1;
+
+/// The best-effort IO scheduling class.
+const IOPRIO_CLASS_BE: libc::c_int = 2;
+
+/// The idle IO scheduling class.
+const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+/// Returns the IO priority of the command (its IO scheduling class and
+/// its priority within the class, as `ioprio_set` expects them), if it
+/// has one.
+fn io_priority(config: &CommandConfig) -> Option<libc::c_int> {
+    if config.io_class.is_none() && config.io_priority.is_none() {
+        return None;
+    }
+
+    let (class, default_priority) = match config.io_class.unwrap_or(IoClass::BestEffort) {
+        IoClass::Realtime => (IOPRIO_CLASS_RT, 4),
+        IoClass::BestEffort => (IOPRIO_CLASS_BE, 4),
+        IoClass::Idle => (IOPRIO_CLASS_IDLE, 0),
+    };
+    let priority = config
+        .io_priority
+        .map_or(default_priority, libc::c_int::from);
+    Some(class << IOPRIO_CLASS_SHIFT | priority)
+}
+
+/// Returns the login variables (`HOME`, `USER`, `LOGNAME`, and `SHELL`)
+/// of the user that runs the command, from its passwd entry (numeric
+/// users are looked up by their uid, and commands without a user use
+/// the user that runs Ground Control).
+fn login_env(config: &CommandConfig) -> eyre::Result<[(&'static str, OsString); 4]> {
+    let user = match config.user_and_group().0 {
+        Some(user) => match user.parse::<libc::uid_t>() {
+            Ok(uid) => users::get_user_by_uid(uid),
+            Err(_) => users::get_user_by_name(user),
+        }
+        .ok_or_else(|| eyre!("Unknown username \"{user}\""))?,
+        None => {
+            let uid = users::get_current_uid();
+            users::get_user_by_uid(uid).ok_or_else(|| eyre!("Unknown uid {uid}"))?
+        }
+    };
+
+    Ok([
+        ("HOME", user.home_dir().as_os_str().to_owned()),
+        ("USER", user.name().to_owned()),
+        ("LOGNAME", user.name().to_owned()),
+        ("SHELL", user.shell().as_os_str().to_owned()),
+    ])
+}
+
+/// Returns the gid of the group with the given name (or the given gid,
+/// if the group is numeric).
+fn group_id(groupname: &str) -> eyre::Result<libc::gid_t> {
+    if let Ok(gid) = groupname.parse() {
+        return Ok(gid);
+    }
+
+    users::get_group_by_name(groupname)
+        .map(|group| group.gid())
+        .ok_or_else(|| eyre!("Unknown group \"{groupname}\""))
+}
+
+/// Runs the command to completion, and returns the environment variables
+/// that it writes to stdout (as `KEY=VALUE` lines, in the `.env`
+/// format). Unlike [`run`], the command's stdout is never logged (only
+/// its stderr is), since the variables are usually secrets. The command
+/// (along with its process group) is killed if it does not finish within
+/// the (optional) timeout.
+pub(crate) async fn capture_env(
+    name: &str,
+    config: &CommandConfig,
+    context: SpawnContext<'_>,
+    timeout: Option<Duration>,
+) -> eyre::Result<Vec<(String, String)>> {
+    tracing::debug!(%name, ?config, "Running command");
+
+    let mut command = prepare(name, config, context, &[])?;
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // Run the command in its own process group (so that whatever it
+    // starts is killed along with it), and keep it from being reaped as
+    // an orphan, since its exit status is collected here.
+    let (child, pid) = {
+        let mut commands = init::commands();
+        let child = command
+            .group_spawn()
+            .wrap_err_with(|| format!("Error starting command \"{}\"", config.program))?;
+        let pid = child.id().map(|pid| Pid::from_raw(pid as i32));
+        commands.extend(pid);
+        (child, pid)
+    };
+
+    let output = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, child.wait_with_output()).await,
+        None => Ok(child.wait_with_output().await),
+    };
+    if let Some(pid) = pid {
+        if output.is_err() {
+            let _ = nix::sys::signal::killpg(pid, Signal::SIGKILL);
+        }
+        init::commands().remove(&pid);
+    }
+    let output = output
+        .map_err(|_| {
+            eyre!(
+                "Command \"{}\" did not finish within {:?}; killed it",
+                config.program,
+                timeout.unwrap_or_default()
+            )
+        })?
+        .wrap_err_with(|| format!("Error waiting for command \"{}\"", config.program))?;
+
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        tracing::info!(target: "stderr", process = %name, output = line);
+    }
+    if !output.status.success() {
+        return Err(eyre!(
+            "Command \"{}\" failed ({})",
+            config.program,
+            output.status
+        ));
+    }
+
+    // The errors of the parser include the invalid line, which must not
+    // be logged.
+    dotenvy::from_read_iter(output.stdout.as_slice())
+        .collect::<Result<_, _>>()
+        .map_err(|_| {
+            eyre!(
+                "Command \"{}\" wrote invalid `KEY=VALUE` lines",
+                config.program
+            )
+        })
+}
+
+/// Runs the command of a `forking` daemon -- which starts the daemon in
+/// the background, writes the daemon's PID to the given file, and then
+/// exits -- and returns the control and monitor handles for the daemon
+/// itself (instead of for the command). The daemon's exit status is not
+/// known, and so its exits are reported as [`ExitStatus::Killed`].
+pub(crate) async fn run_forking(
+    name: &str,
+    config: &CommandConfig,
+    context: SpawnContext<'_>,
+    extra_env: &[(&str, String)],
+    output: Option<mpsc::UnboundedSender<String>>,
+    pid_file: &str,
+    new_session: bool,
+) -> eyre::Result<(CommandControl, CommandMonitor)> {
+    // Remove the PID file left behind by a previous instance of the
+    // daemon, so that its PID is not mistaken for the new daemon's.
+    let _ = tokio::fs::remove_file(pid_file).await;
+
+    let (_, mut monitor) = run(name, config, context, extra_env, output, new_session, None)?;
+    let exit_status = monitor.wait().await;
+    if exit_status != ExitStatus::Exited(0) {
+        return Err(eyre!(
+            "Command \"{}\" failed to start the daemon ({exit_status:?})",
+            config.program
+        ));
+    }
+
+    let pid = read_pid_file(pid_file)
+        .await
+        .wrap_err_with(|| format!("Error reading PID file \"{pid_file}\""))?;
+
+    tracing::debug!(%name, %pid, "Forked daemon running");
+
+    let (sender, receiver) = oneshot::channel();
+    monitor_pid(name.to_owned(), pid, sender);
+
+    Ok((
+        CommandControl {
+            name: name.to_owned(),
+            pid,
+        },
+        CommandMonitor { monitor: receiver },
+    ))
+}
+
+/// How long to wait for a `forking` daemon to write its PID file (after
+/// its command has exited).
+const PID_FILE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often to check if a `forking` daemon is still running (and if it
+/// has written its PID file).
+const PID_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+async fn read_pid_file(pid_file: &str) -> eyre::Result<Pid> {
+    let deadline = Instant::now() + PID_FILE_TIMEOUT;
+    loop {
+        let result = tokio::fs::read_to_string(pid_file)
+            .await
+            .wrap_err("Error reading file")
+            .and_then(|text| {
+                text.trim()
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|pid| *pid > 0)
+                    .map(Pid::from_raw)
+                    .ok_or_else(|| eyre!("Invalid PID \"{}\"", text.trim()))
+            });
+
+        match result {
+            Ok(pid) => return Ok(pid),
+            Err(err) if Instant::now() >= deadline => return Err(err),
+            Err(_) => tokio::time::sleep(PID_POLL_INTERVAL).await,
+        }
+    }
+}
+
+/// Polls the (non-child) process with the given PID until it exits.
+fn monitor_pid(name: String, pid: Pid, sender: oneshot::Sender<ExitStatus>) {
+    tokio::spawn(async move {
+        while is_running(pid) {
+            tokio::time::sleep(PID_POLL_INTERVAL).await;
+        }
+
+        tracing::debug!(%name, %pid, "Forked daemon exited");
+        let _ = sender.send(ExitStatus::Killed(None));
+    });
+}
+
+/// Returns `true` if the process with the given PID exists, and is not a
+/// zombie (which is only an exited process that has not been reaped).
+fn is_running(pid: Pid) -> bool {
+    if nix::sys::signal::kill(pid, None).is_err() {
+        return false;
+    }
+
+    // The process state follows the (parenthesized) command name.
+    match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+        Ok(stat) => !matches!(
+            stat.rsplit_once(')').map(|(_, rest)| rest.trim_start()),
+            Some(rest) if rest.starts_with('Z')
+        ),
+        Err(_) => true,
+    }
+}
+
+/// Returns `true` if the name of the environment variable matches the
+/// given `only-env` pattern, in which every `*` matches any run of
+/// characters (including none).
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, pattern)) => match name.strip_prefix(prefix) {
+            Some(name) => name
+                .char_indices()
+                .map(|(index, _)| index)
+                .chain(std::iter::once(name.len()))
+                .any(|index| matches_pattern(pattern, &name[index..])),
+            None => false,
+        },
+    }
+}
+
+/// Reads the values of the given variables from their files (without
+/// the trailing newline that most editors add).
+fn read_env_from_files(
+    env_from_file: &HashMap<String, String>,
+) -> eyre::Result<Vec<(&str, String)>> {
+    env_from_file
+        .iter()
+        .map(|(key, path)| {
+            let value = std::fs::read_to_string(path).wrap_err_with(|| {
+                format!("Failed to read environment variable \"{key}\" from file \"{path}\"")
+            })?;
+            Ok((
+                key.as_str(),
+                value.trim_end_matches(['\r', '\n']).to_owned(),
+            ))
+        })
+        .collect()
+}
+
+/// Reads the variables in the given env file (in the `.env` format),
+/// which has none if it does not exist and its policy allows that.
+fn read_env_file(name: &str, env_file: &EnvFileConfig) -> eyre::Result<Vec<(String, String)>> {
+    let path = &env_file.path;
+    let vars = match dotenvy::from_path_iter(path) {
+        Ok(vars) => vars,
+        Err(dotenvy::Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            return match env_file.if_missing {
+                MissingEnvFilePolicy::Fail => Err(eyre!("Env file \"{path}\" does not exist")),
+                MissingEnvFilePolicy::Warn => {
+                    tracing::warn!(%name, %path, "Env file does not exist; ignoring");
+                    Ok(Vec::new())
+                }
+                MissingEnvFilePolicy::Ignore => Ok(Vec::new()),
+            };
+        }
+        Err(err) => {
+            return Err(err).wrap_err_with(|| format!("Failed to read env file \"{path}\""))
+        }
+    };
+
+    vars.collect::<Result<_, _>>()
+        .wrap_err_with(|| format!("Invalid env file \"{path}\""))
+}
+
+/// Replaces the template expressions (such as `{{USER}}`) in the values
+/// of the given environment variables (of a command), preferring the
+/// given variables over the environment of Ground Control (as
+/// [`substitute_env_var`] does).
+pub(crate) fn expand_env(
+    env: &HashMap<String, String>,
+    vars: &HashMap<String, String>,
+) -> eyre::Result<HashMap<String, String>> {
+    env.iter()
+        .map(|(key, value)| {
+            substitute_env_var(value, vars)
+                .map(|value| (key.clone(), value))
+                .wrap_err_with(|| format!("Invalid value for environment variable \"{key}\""))
+        })
+        .collect()
+}
+
+/// Replaces the template expressions (such as `{{USER}}`) in the given
+/// string with the values of the environment variables, preferring the
+/// given variables over the environment of Ground Control.
+pub(crate) fn substitute_env_var(
+    s: impl AsRef<str>,
+    env: &HashMap<String, String>,
+) -> eyre::Result<String> {
+    // The command's own environment variables take precedence over the
+    // environment of Ground Control.
+    let var = |key: &str| match env.get(key) {
+        Some(value) => Ok(value.clone()),
+        None => env::var(key),
+    };
+
     static TEMPLATE_VAR_REGEX: Lazy<Regex> =
         Lazy::new(|| Regex::new(r"\{\{ *([A-Za-z0-9_]+) *\}\}").expect("regex should be valid"));
 
@@ -185,29 +890,120 @@ fn substitute_env_var(s: impl AsRef<str>) -> eyre::Result<String> {
     TEMPLATE_VAR_REGEX
         .captures_iter(s.as_ref())
         .map(|caps| {
-            std::env::var(&caps[1])
-                .map_err(|_| eyre!("Unknown environment variable \"{}\"", &caps[1]))
+            var(&caps[1]).map_err(|_| eyre!("Unknown environment variable \"{}\"", &caps[1]))
         })
         .collect::<eyre::Result<String>>()?;
 
     Ok(TEMPLATE_VAR_REGEX
         .replace_all(s.as_ref(), |caps: &Captures| {
-            std::env::var(&caps[1]).expect("Unable to find environment variable")
+            var(&caps[1]).expect("Unable to find environment variable")
         })
         .into_owned())
 }
 
-fn monitor_process(
-    name: String,
-    pid: Pid,
-    mut child: AsyncGroupChild,
-    sender: oneshot::Sender<ExitStatus>,
-) {
+/// Returns the environment that the command gets: the environment of
+/// Ground Control (if the command inherits all of it), with the
+/// variables that were set for the command.
+fn child_env(
+    command: &tokio::process::Command,
+    inherit_env: InheritEnv,
+) -> HashMap<OsString, OsString> {
+    let mut child_env: HashMap<OsString, OsString> = if inherit_env == InheritEnv::All {
+        env::vars_os().collect()
+    } else {
+        HashMap::new()
+    };
+    for (key, value) in command.as_std().get_envs() {
+        match value {
+            Some(value) => child_env.insert(key.to_owned(), value.to_owned()),
+            None => child_env.remove(key),
+        };
+    }
+    child_env
+}
+
+/// Returns the index of the script in the given arguments if the program
+/// is a shell that runs a script from its arguments (`sh -c <script>`,
+/// which includes `shell` commands).
+fn shell_script_index(program: &str, args: &[String]) -> Option<usize> {
+    const SHELLS: [&str; 7] = ["sh", "ash", "bash", "dash", "ksh", "mksh", "zsh"];
+
+    let is_shell = Path::new(program)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .map_or(false, |name| SHELLS.contains(&name));
+    if !is_shell {
+        return None;
+    }
+
+    // The script is the first operand after the `-c` option (which may be
+    // combined with other options, such as `-ec`).
+    let command_option = args.iter().position(|arg| {
+        arg.len() > 1
+            && arg.starts_with('-')
+            && arg[1..].bytes().all(|byte| byte.is_ascii_alphabetic())
+            && arg.contains('c')
+    })?;
+    args.iter()
+        .skip(command_option + 1)
+        .position(|arg| !arg.starts_with('-') && !arg.starts_with('+'))
+        .map(|offset| command_option + 1 + offset)
+}
+
+/// Replaces the references to environment variables (such as `${PORT}`)
+/// in the given argument with the values of the variables in the given
+/// environment (of the command). References to variables that are not
+/// set are left as is (so that they can be used in shell scripts), and
+/// `$${VAR}` is replaced with `${VAR}`.
+fn expand_vars(arg: &str, env: &HashMap<OsString, OsString>) -> String {
+    static VAR_REFERENCE_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"\$(\$)?\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("regex should be valid")
+    });
+
+    VAR_REFERENCE_REGEX
+        .replace_all(arg, |caps: &Captures| {
+            match (caps.get(1), env.get(OsStr::new(&caps[2]))) {
+                (Some(_), _) => format!("${{{}}}", &caps[2]),
+                (None, Some(value)) => value.to_string_lossy().into_owned(),
+                (None, None) => caps[0].to_owned(),
+            }
+        })
+        .into_owned()
+}
+
+/// Child process, which is either the leader of its own session (and
+/// process group), or shares Ground Control's session.
+#[derive(Debug)]
+enum Child {
+    Session(AsyncGroupChild),
+    Shared(tokio::process::Child),
+}
+
+impl Child {
+    fn inner(&mut self) -> &mut tokio::process::Child {
+        match self {
+            Child::Session(child) => child.inner(),
+            Child::Shared(child) => child,
+        }
+    }
+
+    async fn wait(&mut self) -> std::io::Result<std::process::ExitStatus> {
+        match self {
+            Child::Session(child) => child.wait().await,
+            Child::Shared(child) => child.wait().await,
+        }
+    }
+}
+
+fn monitor_process(name: String, pid: Pid, mut child: Child, sender: oneshot::Sender<ExitStatus>) {
     tokio::spawn(async move {
-        match child.wait().await {
+        let result = child.wait().await;
+        init::commands().remove(&pid);
+
+        match result {
             Err(err) => {
                 tracing::error!(%name, ?err, "Error waiting for command to exit");
-                let _ = sender.send(ExitStatus::Killed);
+                let _ = sender.send(ExitStatus::Killed(None));
             }
             Ok(exit_status) => match exit_status.code() {
                 Some(exit_code) => {
@@ -220,8 +1016,11 @@ fn monitor_process(
                     let _ = sender.send(ExitStatus::Exited(exit_code));
                 }
                 None => {
-                    tracing::debug!(%name, %pid, "Command was killed");
-                    let _ = sender.send(ExitStatus::Killed);
+                    let signal = exit_status
+                        .signal()
+                        .and_then(|signal| Signal::try_from(signal).ok());
+                    tracing::debug!(%name, %pid, ?signal, "Command was killed");
+                    let _ = sender.send(ExitStatus::Killed(signal));
                 }
             },
         }
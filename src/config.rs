@@ -1,237 +1,4158 @@
 //! Configuration structs.
 
-use std::collections::{HashMap, HashSet};
+// Commands are much larger than the other variants of the enums that
+// include them (such as signals), but the configuration is only moved
+// around when it is loaded.
+#![allow(clippy::large_enum_variant)]
 
-use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use nix::sys::signal::Signal;
+use serde::{Deserialize, Serialize};
 
 /// Ground Control configuration.
-#[derive(Clone, Debug, Deserialize)]
-#[serde(deny_unknown_fields)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Config {
+    /// Reject unknown keys (the default); if turned off, unknown keys
+    /// are ignored (and logged as warnings), so that configurations
+    /// written for newer versions of Ground Control still run.
+    #[serde(default = "Config::default_strict")]
+    pub strict: bool,
+
     /// Suppress the timestamp field from the log output (useful on
     /// systems that prepend the log output with their own, timestamped
     /// log output).
     #[serde(default)]
     pub suppress_timestamps: bool,
 
+    /// Stop processes that are at the same level of the dependency
+    /// graph concurrently during shutdown (instead of stopping every
+    /// process, one at a time, in the reverse of the startup order).
+    #[serde(default)]
+    pub parallel_shutdown: bool,
+
+    /// Optional limit on how long the shutdown may take; daemons that are
+    /// still running once the limit expires are killed (with `SIGKILL`),
+    /// and the shutdown then continues.
+    #[serde(default, alias = "shutdown-timeout", with = "option_duration")]
+    pub shutdown_timeout: Option<Duration>,
+
+    /// Address (such as `0.0.0.0:8080`) on which to serve the aggregated
+    /// status of every process at `/healthz`, if any.
+    #[serde(default)]
+    pub healthz: Option<String>,
+
+    /// File to create once every process has started (and to remove
+    /// during shutdown), if any.
+    #[serde(default)]
+    pub ready_file: Option<String>,
+
+    /// Command to run once every process has started, if any.
+    #[serde(default)]
+    pub ready_command: Option<CommandConfig>,
+
     /// Optional list of additional variables to add to the environment.
     #[serde(default)]
     pub env: HashMap<String, String>,
 
-    /// *Ordered* list of processes to start.
+    /// Profiles that are active; processes that list `profiles` are only
+    /// started if at least one of their profiles is active.
+    #[serde(default)]
+    pub profiles: Vec<String>,
+
+    /// Settings that every process inherits (unless the process
+    /// overrides them).
+    #[serde(default)]
+    pub defaults: DefaultsConfig,
+
+    /// *Ordered* list of processes to start (processes with
+    /// dependencies are started after their dependencies). Processes
+    /// with `replicas` have already been expanded into their instances.
+    #[serde(with = "replicas")]
     pub processes: Vec<ProcessConfig>,
+
+    /// Unknown keys that were ignored (because `strict` is off), with
+    /// their location in the configuration file, if known.
+    #[serde(skip)]
+    pub unknown_keys: Vec<String>,
 }
 
-/// Process configuration.
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct ProcessConfig {
-    /// Name of the process (used in logging/monitoring).
-    pub name: String,
+/// Settings that every process inherits, unless the process (or, for
+/// `user`, `group`, `groups`, and `only-env`, the command) sets them
+/// itself.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DefaultsConfig {
+    /// User to run every command as (or `user:group`).
+    #[serde(default)]
+    pub user: Option<String>,
 
-    /// Optional command to run *before* the `run` command.
+    /// Group to run every command as.
     #[serde(default)]
-    pub pre: Option<CommandConfig>,
+    pub group: Option<String>,
 
-    /// Optional `run` command; if present, this process is considered a
-    /// "daemon process" and Ground Control will monitor the run
-    /// command, shutting down all of the processes if any run command
-    /// exits.
+    /// Supplementary groups to run every command with.
     #[serde(default)]
-    pub run: Option<CommandConfig>,
+    pub groups: Option<Vec<String>>,
 
-    /// Mechanism for stopping the process *if this is a daemon process*
-    /// (ignored if the process does not have a `run` command).
+    /// Environment variables to pass through to every command (see
+    /// [`CommandConfig::only_env`]).
     #[serde(default)]
-    pub stop: StopMechanism,
+    pub only_env: Option<HashSet<String>>,
+
+    /// How long to wait for every daemon to stop (see
+    /// [`ProcessConfig::stop_timeout`]).
+    #[serde(default, with = "option_duration")]
+    pub stop_timeout: Option<Duration>,
 
-    /// Optional command to run after the process has been stopped.
+    /// Policy used to restart every daemon.
     #[serde(default)]
-    pub post: Option<CommandConfig>,
+    pub restart: Option<RestartPolicy>,
 }
 
-/// Mechanism used to stop a daemon process.
-#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(untagged)]
-pub enum StopMechanism {
-    /// Stop the process using a signal.
-    Signal(SignalConfig),
+/// Number of CPUs that a `cpu-affinity` can use.
+const CPU_SETSIZE: usize = libc::CPU_SETSIZE as usize;
 
-    /// Stop the process by running a command.
-    Command(CommandConfig),
+/// Returns `true` if the given user or group is a numeric id (which is
+/// used without looking it up).
+fn is_numeric_id(id: &str) -> bool {
+    id.parse::<u32>().is_ok()
 }
 
-impl Default for StopMechanism {
-    fn default() -> Self {
-        StopMechanism::Signal(SignalConfig::SIGTERM)
+/// Checks that the given program (a path, or the name of a program in
+/// the `PATH`) exists and is executable (in the given root directory, if
+/// any), returning what is wrong with it otherwise.
+fn check_program(program: &str, root_directory: Option<&str>) -> Result<(), &'static str> {
+    let in_root = |path: &Path| match root_directory {
+        Some(root_directory) => {
+            Path::new(root_directory).join(path.strip_prefix("/").unwrap_or(path))
+        }
+        None => path.to_path_buf(),
+    };
+    let is_executable = |path: &Path| {
+        in_root(path)
+            .metadata()
+            .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+
+    if program.contains('/') {
+        let path = Path::new(program);
+        if !in_root(path).exists() {
+            Err("does not exist")
+        } else if !is_executable(path) {
+            Err("is not executable")
+        } else {
+            Ok(())
+        }
+    } else if std::env::var_os("PATH").map_or(false, |paths| {
+        std::env::split_paths(&paths).any(|path| is_executable(&path.join(program)))
+    }) {
+        Ok(())
+    } else {
+        Err("was not found in the `PATH`")
     }
 }
 
-/// Signals used to stop a daemon process.
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
-pub enum SignalConfig {
-    /// SIGINT
-    SIGINT,
+/// Format of a configuration file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// TOML (the default).
+    Toml,
+
+    /// JSON, for configurations that are generated by other programs.
+    Json,
 
-    /// SIGQUIT
-    SIGQUIT,
+    /// supervisord's `supervisord.conf` (only its `[program:x]` sections,
+    /// and their common settings), for migrating from supervisord.
+    Supervisord,
 
-    /// SIGTERM
-    SIGTERM,
+    /// Compose files (only their `services`, and their common settings),
+    /// for collapsing a multi-container stack into a single container.
+    Compose,
 }
 
-impl From<SignalConfig> for nix::sys::signal::Signal {
-    fn from(signal: SignalConfig) -> Self {
-        match signal {
-            SignalConfig::SIGINT => Self::SIGINT,
-            SignalConfig::SIGQUIT => Self::SIGQUIT,
-            SignalConfig::SIGTERM => Self::SIGTERM,
+impl ConfigFormat {
+    /// Returns the format of the configuration file at the given path,
+    /// based on its extension (`.json` files are JSON, and everything
+    /// else is TOML), or its name (for the standard names of Compose
+    /// files).
+    pub fn from_path(path: &str) -> Self {
+        let file_name = Path::new(path)
+            .file_name()
+            .and_then(|file_name| file_name.to_str());
+        if path.ends_with(".json") {
+            Self::Json
+        } else if matches!(
+            file_name,
+            Some("compose.yaml" | "compose.yml" | "docker-compose.yaml" | "docker-compose.yml")
+        ) {
+            Self::Compose
+        } else {
+            Self::Toml
+        }
+    }
+
+    /// Returns the extension of configuration files in this format.
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Toml => "toml",
+            Self::Json => "json",
+            Self::Supervisord => "conf",
+            Self::Compose => "yml",
         }
     }
 }
 
-impl From<&SignalConfig> for nix::sys::signal::Signal {
-    fn from(signal: &SignalConfig) -> Self {
-        match signal {
-            SignalConfig::SIGINT => Self::SIGINT,
-            SignalConfig::SIGQUIT => Self::SIGQUIT,
-            SignalConfig::SIGTERM => Self::SIGTERM,
+impl FromStr for ConfigFormat {
+    type Err = UnknownConfigFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "toml" => Ok(Self::Toml),
+            "json" => Ok(Self::Json),
+            "supervisord" => Ok(Self::Supervisord),
+            "compose" => Ok(Self::Compose),
+            _ => Err(UnknownConfigFormat(s.to_owned())),
         }
     }
 }
 
-/// Configuration for a command, its arguments, and any execution
-/// properties (such as the user under which to run the command, or the
-/// environment variables to pass through to the command).
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
-#[serde(from = "CommandLineConfig")]
-pub struct CommandConfig {
-    /// User to run this command as, otherwise run the command as the
-    /// user that executed Ground Control (most likely `root`).
-    pub user: Option<String>,
+/// Error returned when parsing an unknown [`ConfigFormat`].
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Unknown config format \"{0}\" (expected \"toml\", \"json\", \"supervisord\", or \"compose\")"
+)]
+pub struct UnknownConfigFormat(String);
 
-    /// If present, then only the given list of environment variables
-    /// will be passed through to the command (all other variables will
-    /// be removed from the command's environment). Note that `PATH` is
-    /// always allowed. All environment variables will be allowed if
-    /// this value is `None`. If provided, but empty, then no variables
-    /// other than `PATH` will be allowed.
-    pub only_env: Option<HashSet<String>>,
+impl Config {
+    /// Parses the configuration from the given text (which is in the
+    /// given format), and applies the overrides in the `GC__*`
+    /// environment variables.
+    pub fn parse(text: &str, format: ConfigFormat) -> eyre::Result<Config> {
+        Config::parse_with_overrides(text, format, &crate::overrides::from_env())
+    }
 
-    /// Program to execute.
-    pub program: String,
+    /// Parses the configuration from the given text, and applies the
+    /// given overrides (see [`crate::overrides::apply`]).
+    fn parse_with_overrides(
+        text: &str,
+        format: ConfigFormat,
+        overrides: &[(String, String)],
+    ) -> eyre::Result<Config> {
+        let mut unknown_keys = Vec::new();
+        let mut config = if overrides.is_empty() {
+            match format {
+                ConfigFormat::Toml => {
+                    Config::from_deserializer(toml::Deserializer::new(text), &mut unknown_keys)?
+                }
+                ConfigFormat::Json => {
+                    let mut deserializer = serde_json::Deserializer::from_str(text);
+                    let config = Config::from_deserializer(&mut deserializer, &mut unknown_keys)?;
+                    deserializer.end()?;
+                    config
+                }
+                ConfigFormat::Supervisord => {
+                    Config::from_deserializer(crate::import::supervisord(text)?, &mut unknown_keys)?
+                }
+                ConfigFormat::Compose => {
+                    Config::from_deserializer(crate::import::compose(text)?, &mut unknown_keys)?
+                }
+            }
+        } else {
+            // Overrides are applied to the value of the configuration
+            // (which loses the location of TOML errors).
+            let mut value = Config::to_value(text, format)?;
+            crate::overrides::apply(&mut value, overrides)?;
+            Config::from_deserializer(value, &mut unknown_keys)?
+        };
+        config.unknown_keys = unknown_keys
+            .iter()
+            .map(|path| match format {
+                ConfigFormat::Toml => {
+                    unknown_key::describe(path, unknown_key::toml_location(text, path))
+                }
+                _ => unknown_key::describe(path, None),
+            })
+            .collect();
+        config.check_unknown_keys()?;
+        config.apply_defaults();
+        Ok(config)
+    }
 
-    /// Arguments to pass to the program.
-    pub args: Vec<String>,
-}
+    fn default_strict() -> bool {
+        true
+    }
 
-#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(untagged)]
-enum CommandLineConfig {
-    Simple(CommandLine),
+    /// Parses the configuration from the given text into a JSON value
+    /// (without checking it).
+    fn to_value(text: &str, format: ConfigFormat) -> eyre::Result<serde_json::Value> {
+        Ok(match format {
+            ConfigFormat::Toml => serde_json::to_value(toml::from_str::<toml::Value>(text)?)?,
+            ConfigFormat::Json => serde_json::from_str(text)?,
+            ConfigFormat::Supervisord => crate::import::supervisord(text)?,
+            ConfigFormat::Compose => crate::import::compose(text)?,
+        })
+    }
 
-    Detailed(DetailedCommandLine),
-}
+    /// Deserializes the configuration, collecting the paths of the keys
+    /// that it does not know (instead of rejecting them).
+    fn from_deserializer<'de, D>(
+        deserializer: D,
+        unknown_keys: &mut Vec<Vec<unknown_key::Segment>>,
+    ) -> Result<Config, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        serde_ignored::deserialize(deserializer, |path| {
+            unknown_keys.push(unknown_key::segments(&path))
+        })
+    }
 
-impl From<CommandLineConfig> for CommandConfig {
-    fn from(config: CommandLineConfig) -> Self {
-        match config {
-            CommandLineConfig::Simple(config) => {
-                let (program, args) = config.program_and_args();
-                Self {
-                    user: None,
-                    only_env: None,
-                    program,
-                    args,
+    /// Rejects the unknown keys in the configuration, unless `strict` is
+    /// off.
+    pub fn check_unknown_keys(&self) -> eyre::Result<()> {
+        if !self.strict || self.unknown_keys.is_empty() {
+            return Ok(());
+        }
+
+        Err(eyre::eyre!(
+            "Unknown config {} {} (set `strict = false` to ignore unknown keys)",
+            if self.unknown_keys.len() == 1 {
+                "key"
+            } else {
+                "keys"
+            },
+            self.unknown_keys.join(", ")
+        ))
+    }
+
+    /// Fills in the [`DefaultsConfig`] settings that the processes (and
+    /// their commands) do not set themselves, and gives every command the
+    /// `env-file`, the `root-directory`, the `cpu-affinity`, the
+    /// `oom-score-adj`, the `limits`, and the privilege hardening
+    /// settings of its process.
+    fn apply_defaults(&mut self) {
+        let defaults = &self.defaults;
+        for process in &mut self.processes {
+            let env_file = process.env_file.clone();
+            let root_directory = process.root_directory.clone();
+            let cpu_affinity = process.cpu_affinity.clone();
+            let oom_score_adj = process.oom_score_adj;
+            let limits = process.limits;
+            let no_new_privileges = process.no_new_privileges;
+            let clear_ambient_capabilities = process.clear_ambient_capabilities;
+            if process.is_daemon() {
+                if process.stop_timeout.is_none() {
+                    process.stop_timeout = defaults.stop_timeout;
+                }
+                if process.restart.is_none() {
+                    process.restart = defaults.restart;
                 }
             }
-            CommandLineConfig::Detailed(config) => {
-                let (program, args) = config.command.program_and_args();
-                Self {
-                    user: config.user,
-                    only_env: config.only_env,
-                    program,
-                    args,
+
+            for command in process.commands_mut() {
+                if command.user.is_none() && command.group.is_none() && command.groups.is_none() {
+                    command.user = defaults.user.clone();
+                    command.group = defaults.group.clone();
+                    command.groups = defaults.groups.clone();
                 }
+                if command.only_env.is_none() && command.inherit_env.is_none() {
+                    command.only_env = defaults.only_env.clone();
+                }
+                command.env_file = env_file.clone();
+                command.root_directory = root_directory.clone();
+                command.cpu_affinity = cpu_affinity.clone();
+                command.oom_score_adj = oom_score_adj;
+                command.limits = limits;
+                command.no_new_privileges = no_new_privileges;
+                command.clear_ambient_capabilities = clear_ambient_capabilities;
             }
         }
     }
-}
 
-#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(untagged)]
-enum CommandLine {
-    CommandString(String),
+    /// Loads the configuration from the file at the given path (or from
+    /// standard input, if the path is `-`, or from the given `http://` or
+    /// `https://` URL, see [`Config::fetch`]), or, if the path is a
+    /// directory, merges every configuration file in the directory (see
+    /// [`Config::merge`]). The format is based on the path if not given,
+    /// and directories only include the files with the extension of the
+    /// format.
+    pub fn load(path: &str, format: Option<ConfigFormat>) -> eyre::Result<Config> {
+        if crate::remote::is_url(path) {
+            return Config::fetch(path, format, None, None);
+        }
 
-    CommandVector(Vec<String>),
-}
+        let format = format.unwrap_or_else(|| ConfigFormat::from_path(path));
+        if path == "-" {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .wrap_err("Failed to read config from standard input")?;
+            return Config::parse(&text, format)
+                .wrap_err("Failed to parse config from standard input");
+        }
 
-impl CommandLine {
-    /// Parse the Command Line into the program to execute, and the
-    /// arguments to that program.
-    fn program_and_args(&self) -> (String, Vec<String>) {
-        match self {
-            CommandLine::CommandString(line) => {
-                // TODO: This won't handle quoted arguments with spaces
-                // (for example), so really we should parse this using a
-                // more correct, shell-like parser. OTOH, we could just
-                // say that anything complicated needs to use the vector
-                // format...
-                let mut elems = line.split(' ');
+        if !Path::new(path).is_dir() {
+            let text = std::fs::read_to_string(path)
+                .wrap_err_with(|| format!("Failed to read config file \"{path}\""))?;
+            return Config::parse(&text, format)
+                .wrap_err_with(|| format!("Failed to parse config file \"{path}\""));
+        }
+
+        let texts = Config::read_dir(path, format)?;
+        Config::merge(texts.iter().map(String::as_str), format)
+            .wrap_err_with(|| format!("Failed to merge config directory \"{path}\""))
+    }
+
+    /// Loads the configurations at the given paths (each of which can be
+    /// anything that [`Config::load`] accepts), and merges them in order
+    /// (see [`Config::merge`]), so that later configurations (such as the
+    /// overlay of a deployment) override and add to the earlier ones
+    /// (such as the base configuration of an image). The format of each
+    /// configuration is based on its path if not given.
+    pub fn load_all<'a>(
+        paths: impl IntoIterator<Item = &'a str>,
+        format: Option<ConfigFormat>,
+    ) -> eyre::Result<Config> {
+        let paths: Vec<&str> = paths.into_iter().collect();
+        if let [path] = paths[..] {
+            return Config::load(path, format);
+        }
+
+        let mut values = Vec::new();
+        for path in paths {
+            let is_url = crate::remote::is_url(path);
+            let format = format.unwrap_or_else(|| {
+                ConfigFormat::from_path(if is_url {
+                    crate::remote::url_path(path)
+                } else {
+                    path
+                })
+            });
+            let texts = if is_url {
+                vec![crate::remote::fetch(path, None, None)?]
+            } else if path == "-" {
+                let mut text = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut text)
+                    .wrap_err("Failed to read config from standard input")?;
+                vec![text]
+            } else if Path::new(path).is_dir() {
+                Config::read_dir(path, format)?
+            } else {
+                vec![std::fs::read_to_string(path)
+                    .wrap_err_with(|| format!("Failed to read config file \"{path}\""))?]
+            };
+
+            for text in texts {
+                values.push(
+                    Config::to_value(&text, format)
+                        .wrap_err_with(|| format!("Failed to parse config \"{path}\""))?,
+                );
+            }
+        }
+
+        Config::merge_values(values).wrap_err("Failed to merge config files")
+    }
 
-                let program = elems
-                    .next()
-                    .expect("Command line must not be empty")
-                    .to_string();
-                let args = elems.map(|s| s.to_string()).collect();
+    /// Reads every configuration file (with the extension of the format)
+    /// in the given directory, in the order of their names.
+    fn read_dir(path: &str, format: ConfigFormat) -> eyre::Result<Vec<String>> {
+        let mut paths = std::fs::read_dir(path)
+            .wrap_err_with(|| format!("Failed to read config directory \"{path}\""))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .wrap_err_with(|| format!("Failed to read config directory \"{path}\""))?;
+        paths.retain(|path| {
+            path.is_file()
+                && path.extension().and_then(|extension| extension.to_str())
+                    == Some(format.extension())
+        });
+        paths.sort();
 
-                (program, args)
+        paths
+            .iter()
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .wrap_err_with(|| format!("Failed to read config file \"{}\"", path.display()))
+            })
+            .collect()
+    }
+
+    /// Fetches the configuration from the given `http://` or `https://`
+    /// URL, and verifies that its SHA-256 checksum (as a hexadecimal
+    /// string) and its `ETag` are the expected ones, if given. The format
+    /// is based on the path of the URL if not given.
+    pub fn fetch(
+        url: &str,
+        format: Option<ConfigFormat>,
+        sha256: Option<&str>,
+        etag: Option<&str>,
+    ) -> eyre::Result<Config> {
+        let format =
+            format.unwrap_or_else(|| ConfigFormat::from_path(crate::remote::url_path(url)));
+        let text = crate::remote::fetch(url, sha256, etag)?;
+        Config::parse(&text, format).wrap_err_with(|| format!("Failed to parse config \"{url}\""))
+    }
+
+    /// Merges the given configurations (which are in the given format)
+    /// into a single configuration: their `processes` are combined (in
+    /// order, with processes replacing the earlier processes that have
+    /// the same name), their `env` and `defaults` tables are merged, and
+    /// every other setting is taken from the last configuration that
+    /// includes it. The overrides in the `GC__*` environment variables
+    /// are then applied to the merged configuration.
+    pub fn merge<'a>(
+        texts: impl IntoIterator<Item = &'a str>,
+        format: ConfigFormat,
+    ) -> eyre::Result<Config> {
+        let values = texts
+            .into_iter()
+            .map(|text| Config::to_value(text, format))
+            .collect::<eyre::Result<Vec<_>>>()?;
+        Config::merge_values(values)
+    }
+
+    /// Merges the given configurations (as their JSON values), as
+    /// [`Config::merge`] does.
+    fn merge_values(values: Vec<serde_json::Value>) -> eyre::Result<Config> {
+        let mut merged = serde_json::Map::new();
+        for (index, value) in values.into_iter().enumerate() {
+            let table = match value {
+                serde_json::Value::Object(table) => table,
+                _ => return Err(eyre::eyre!("Config {} is not a table", index + 1)),
+            };
+
+            for (key, value) in table {
+                match (merged.get_mut(&key), value) {
+                    (
+                        Some(serde_json::Value::Array(processes)),
+                        serde_json::Value::Array(more_processes),
+                    ) if key == "processes" => {
+                        for process in more_processes {
+                            let name = process.get("name");
+                            match processes
+                                .iter_mut()
+                                .find(|existing| name.is_some() && existing.get("name") == name)
+                            {
+                                Some(existing) => *existing = process,
+                                None => processes.push(process),
+                            }
+                        }
+                    }
+                    (
+                        Some(serde_json::Value::Object(table)),
+                        serde_json::Value::Object(more_table),
+                    ) if key == "env" || key == "defaults" => table.extend(more_table),
+                    (_, value) => {
+                        merged.insert(key, value);
+                    }
+                }
+            }
+        }
+
+        let mut merged = serde_json::Value::Object(merged);
+        crate::overrides::apply(&mut merged, &crate::overrides::from_env())?;
+
+        let mut unknown_keys = Vec::new();
+        let mut config = Config::from_deserializer(merged, &mut unknown_keys)?;
+        config.unknown_keys = unknown_keys
+            .iter()
+            .map(|path| unknown_key::describe(path, None))
+            .collect();
+        config.check_unknown_keys()?;
+        config.apply_defaults();
+        Ok(config)
+    }
+
+    /// Checks the parts of the configuration that cannot be checked
+    /// while parsing the configuration (such as the dependencies between
+    /// processes).
+    pub fn validate(&self) -> eyre::Result<()> {
+        let mut names = HashSet::new();
+        if let Some(process) = self
+            .processes
+            .iter()
+            .find(|process| !names.insert(process.name.as_str()))
+        {
+            return Err(eyre::eyre!(
+                "Process name \"{}\" is used by more than one process",
+                process.name
+            ));
+        }
+
+        crate::dependencies::startup_order(&self.processes)?;
+
+        if let Some(address) = &self.healthz {
+            address
+                .parse::<std::net::SocketAddr>()
+                .wrap_err_with(|| format!("Invalid `healthz` address \"{address}\""))?;
+        }
+
+        for process in &self.processes {
+            if process.ready == ReadinessCheck::Healthy && process.healthcheck.is_none() {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" uses the `healthy` readiness check, but does not have a `healthcheck`",
+                    process.name
+                ));
+            }
+
+            if let ReadinessCheck::Http(http) = &process.ready {
+                if !crate::remote::is_url(&http.url) {
+                    return Err(eyre::eyre!(
+                        "Process \"{}\" uses an unsupported readiness URL \"{}\" (only `http://` and `https://` URLs are supported)",
+                        process.name,
+                        http.url
+                    ));
+                }
+            }
+
+            if process.kill_mode != KillMode::Process && !process.new_session {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" uses a group `kill-mode`, but does not have its own session (`new-session = false`)",
+                    process.name
+                ));
+            }
+
+            if let StopMechanism::Steps(steps) = &process.stop {
+                match steps.split_last() {
+                    None => {
+                        return Err(eyre::eyre!(
+                            "Process \"{}\" has an empty list of `stop` steps",
+                            process.name
+                        ))
+                    }
+                    Some((_, steps)) if steps.iter().any(|step| step.timeout().is_none()) => {
+                        return Err(eyre::eyre!(
+                            "Process \"{}\" has a `stop` step without a `timeout` (only the last step can wait forever)",
+                            process.name
+                        ))
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            match process.cpu_affinity.as_deref() {
+                Some([]) => {
+                    return Err(eyre::eyre!(
+                        "Process \"{}\" has an empty `cpu-affinity` (it needs at least one CPU)",
+                        process.name
+                    ))
+                }
+                Some(cpus) => {
+                    if let Some(cpu) = cpus.iter().find(|&&cpu| cpu >= CPU_SETSIZE) {
+                        return Err(eyre::eyre!(
+                            "Process \"{}\" has CPU {cpu} in its `cpu-affinity`, but CPUs are numbered from 0 to {}",
+                            process.name,
+                            CPU_SETSIZE - 1
+                        ));
+                    }
+                }
+                None => {}
+            }
+
+            if let Some(oom_score_adj) = process.oom_score_adj {
+                if !(-1000..=1000).contains(&oom_score_adj) {
+                    return Err(eyre::eyre!(
+                        "Process \"{}\" has an `oom-score-adj` of {oom_score_adj}, but it must be between -1000 and 1000",
+                        process.name
+                    ));
+                }
+            }
+
+            if process.private_tmp && process.root_directory.is_some() {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" has both `private-tmp` and a `root-directory` (which would hide the temporary directory)",
+                    process.name
+                ));
+            }
+
+            if process.cgroup.is_some() && process.name.contains('/') {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" has a `cgroup`, but its name contains a `/` (which cgroup names cannot)",
+                    process.name
+                ));
+            }
+
+            if let Some(signal) = process
+                .forward_signals
+                .iter()
+                .find(|signal| !signal.can_be_forwarded())
+            {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" cannot forward {signal}, which Ground Control handles itself (or cannot handle at all)",
+                    process.name
+                ));
+            }
+
+            if process.pre.is_empty()
+                && process.run.is_none()
+                && process.post.is_empty()
+                && process.post_success.is_empty()
+                && process.post_failure.is_empty()
+            {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" does not have any commands (it needs at least one `pre`, `run`, or `post` command)",
+                    process.name
+                ));
+            }
+
+            if !process.is_daemon()
+                && (process.stop != StopMechanism::default() || process.stop_timeout.is_some())
+            {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" configures how it is stopped, but is not a daemon (only processes with a `run` command are stopped)",
+                    process.name
+                ));
+            }
+
+            if matches!(
+                process.process_type,
+                Some(ProcessType::Simple | ProcessType::Forking)
+            ) && process.run.is_none()
+            {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" is a daemon, but does not have a `run` command",
+                    process.name
+                ));
+            }
+
+            if process.process_type == Some(ProcessType::Forking) && process.pid_file.is_none() {
+                return Err(eyre::eyre!(
+                    "Process \"{}\" is a `forking` daemon, but does not have a `pid-file`",
+                    process.name
+                ));
+            }
+
+            if let ReadinessCheck::LogLine(pattern) = &process.ready {
+                regex::Regex::new(pattern).wrap_err_with(|| {
+                    format!(
+                        "Process \"{}\" has an invalid `log-line` readiness pattern",
+                        process.name
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the configuration can be run on this system (which
+    /// [`Config::validate`] does not): that the users of the commands
+    /// exist, that their programs exist and are executable, and that
+    /// cgroup v2 is available for the processes that have a `cgroup`. Only the
+    /// processes that are enabled by the active profiles are checked.
+    /// Every problem is reported, instead of only the first one.
+    pub fn check(&self) -> eyre::Result<()> {
+        let mut problems = Vec::new();
+        let mut check_command = |owner: &str, command: &CommandConfig| {
+            let (user, group) = command.user_and_group();
+            if let Some(user) = user {
+                if is_numeric_id(user) {
+                    if group.is_none() {
+                        problems.push(format!(
+                            "{owner} runs a command as numeric user \"{user}\" without a group"
+                        ));
+                    }
+                } else if users::get_user_by_name(user).is_none() {
+                    problems.push(format!("{owner} runs a command as unknown user \"{user}\""));
+                }
+            }
+            for group in group
+                .into_iter()
+                .chain(command.groups.iter().flatten().map(String::as_str))
+            {
+                if !is_numeric_id(group) && users::get_group_by_name(group).is_none() {
+                    problems.push(format!(
+                        "{owner} runs a command as unknown group \"{group}\""
+                    ));
+                }
+            }
+
+            if let Err(problem) = check_program(&command.program, command.root_directory.as_deref())
+            {
+                problems.push(format!(
+                    "{owner} runs program \"{}\", which {problem}",
+                    command.program
+                ));
+            }
+
+            for (key, path) in &command.env_from_file {
+                if !Path::new(path).is_file() {
+                    problems.push(format!(
+                        "{owner} reads `{key}` from file \"{path}\", which does not exist"
+                    ));
+                }
+            }
+        };
+
+        if let Some(command) = &self.ready_command {
+            check_command("`ready_command`", command);
+        }
+
+        for process in &self.processes {
+            if !process.is_enabled(&self.profiles) || !process.runs_on_current_platform() {
+                continue;
+            }
+
+            let mut process = process.clone();
+            let owner = format!("Process \"{}\"", process.name);
+            for command in process.commands_mut() {
+                check_command(&owner, command);
+            }
+        }
+
+        for process in &self.processes {
+            if !process.is_enabled(&self.profiles) || !process.runs_on_current_platform() {
+                continue;
+            }
+
+            if let Some(env_file) = &process.env_file {
+                if env_file.if_missing == MissingEnvFilePolicy::Fail
+                    && !Path::new(&env_file.path).is_file()
+                {
+                    problems.push(format!(
+                        "Process \"{}\" reads env file \"{}\", which does not exist",
+                        process.name, env_file.path
+                    ));
+                }
+            }
+
+            if let Some(root_directory) = &process.root_directory {
+                if !Path::new(root_directory).is_dir() {
+                    problems.push(format!(
+                        "Process \"{}\" has root directory \"{root_directory}\", which does not exist",
+                        process.name
+                    ));
+                }
+            }
+
+            if process.cgroup.is_some() && !crate::cgroup::is_available() {
+                problems.push(format!(
+                    "Process \"{}\" has a `cgroup`, but cgroup v2 is not available",
+                    process.name
+                ));
             }
+        }
+
+        match problems.as_slice() {
+            [] => Ok(()),
+            [problem] => Err(eyre::eyre!("{problem}")),
+            problems => Err(eyre::eyre!(
+                "Found {} problems:\n  {}",
+                problems.len(),
+                problems.join("\n  ")
+            )),
+        }
+    }
+
+    /// Replaces the environment variable templates (such as `{{USER}}`)
+    /// in the arguments (and `env` values) of every command with their
+    /// values, as they will be when the command is run (including the
+    /// variables in `env`).
+    pub fn interpolate(&mut self) -> eyre::Result<()> {
+        let interpolate = |command: &mut CommandConfig, env: &HashMap<String, String>| {
+            command.env = crate::command::expand_env(&command.env, env).wrap_err_with(|| {
+                format!(
+                    "Environment variable expansion failed for command \"{}\"",
+                    command.program
+                )
+            })?;
+            let mut env = env.clone();
+            env.extend(command.env.clone());
+            command.args = command
+                .args
+                .iter()
+                .map(|arg| crate::command::substitute_env_var(arg, &env))
+                .collect::<eyre::Result<_>>()
+                .wrap_err_with(|| {
+                    format!(
+                        "Environment variable expansion failed for command \"{}\"",
+                        command.program
+                    )
+                })?;
+            eyre::Result::<()>::Ok(())
+        };
+
+        if let Some(command) = &mut self.ready_command {
+            interpolate(command, &self.env)?;
+        }
+
+        for process in &mut self.processes {
+            let name = process.name.clone();
+            for command in process.commands_mut() {
+                interpolate(command, &self.env)
+                    .wrap_err_with(|| format!("Invalid command in process \"{name}\""))?;
+            }
+        }
+
+        Ok(())
+    }
 
-            CommandLine::CommandVector(v) => {
-                let program = v[0].to_string();
-                let args = v[1..].to_vec();
+    /// Renders the configuration in the given format (in the form in
+    /// which it was resolved: with every default filled in, and every
+    /// replicated process expanded into its instances).
+    pub fn render(&self, format: ConfigFormat) -> eyre::Result<String> {
+        Ok(match format {
+            ConfigFormat::Toml => toml::to_string_pretty(&toml::Value::try_from(self)?)?,
+            ConfigFormat::Json => serde_json::to_string_pretty(self)? + "\n",
+            ConfigFormat::Supervisord | ConfigFormat::Compose => {
+                return Err(eyre::eyre!(
+                    "Configurations can only be rendered as TOML or JSON"
+                ))
+            }
+        })
+    }
 
-                (program, args)
+    /// Returns every signal that is forwarded to at least one process.
+    pub fn forwarded_signals(&self) -> Vec<SignalConfig> {
+        let mut signals: Vec<SignalConfig> = Vec::new();
+        for signal in self
+            .processes
+            .iter()
+            .flat_map(|process| &process.forward_signals)
+        {
+            if !signals.contains(signal) {
+                signals.push(*signal);
             }
         }
+        signals
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(deny_unknown_fields, rename_all = "kebab-case")]
-struct DetailedCommandLine {
+/// Process configuration.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProcessConfig {
+    /// Name of the process (used in logging/monitoring).
+    pub name: String,
+
+    /// Optional number of instances of this process to run, named
+    /// `{name}-0` through `{name}-{replicas - 1}`. Each instance is given
+    /// its index in the `GC_INSTANCE` (and `GROUNDCONTROL_INSTANCE`)
+    /// environment variable, and
+    /// references to `name` (in `depends-on`, `wants`, `after`, and
+    /// `before`) refer to all of the instances.
+    #[serde(default, skip_serializing)]
+    pub replicas: Option<u32>,
+
+    /// Index of this instance of a replicated process (set when the
+    /// `replicas` are expanded).
+    #[serde(skip)]
+    pub instance: Option<u32>,
+
+    /// Optional list of the processes that must be started (and be
+    /// ready, see `ready`) before this process is started, and that are
+    /// stopped after this process. Startup is aborted if any of these
+    /// processes exits before it is ready. Also accepted as `requires`.
+    #[serde(default, alias = "requires")]
+    pub depends_on: Vec<DependencyConfig>,
+
+    /// Same as `depends-on`, except that this process is still started
+    /// if any of these processes exits before it is ready.
     #[serde(default)]
-    user: Option<String>,
+    pub wants: Vec<DependencyConfig>,
 
+    /// Optional name of the startup phase that this process belongs to.
+    /// Phases are started in the order in which they first appear, and
+    /// processes in a phase depend on every process in the previous
+    /// phase (as if they were listed in `depends-on`).
     #[serde(default)]
-    only_env: Option<HashSet<String>>,
+    pub phase: Option<String>,
 
-    command: CommandLine,
-}
+    /// Optional list of the names of processes that must be started
+    /// before this process, without waiting for them to become ready
+    /// (and without aborting the startup if they fail).
+    #[serde(default)]
+    pub after: Vec<String>,
 
-#[cfg(test)]
-#[allow(clippy::unwrap_used)]
-mod tests {
-    use serde::Deserialize;
+    /// Optional list of the names of processes that must be started
+    /// after this process (the opposite of `after`).
+    #[serde(default)]
+    pub before: Vec<String>,
+
+    /// Optional condition that must be met for the process to be
+    /// started; the process is skipped (including its `post` commands)
+    /// otherwise.
+    #[serde(default)]
+    pub condition: Option<ConditionConfig>,
+
+    /// Optional list of the profiles that enable this process; processes
+    /// with profiles are skipped unless one of their profiles is active,
+    /// processes without profiles are always started.
+    #[serde(default)]
+    pub profiles: Vec<String>,
+
+    /// Optional list of the platforms (such as `"linux/amd64"`) that the
+    /// process runs on; the process is skipped on every other platform.
+    #[serde(default)]
+    pub only_on: Vec<PlatformConfig>,
+
+    /// Optional list of the platforms that the process is skipped on.
+    #[serde(default)]
+    pub skip_on: Vec<PlatformConfig>,
+
+    /// Optional delay before the process is started (measured from when
+    /// the previous process finished starting).
+    #[serde(default, with = "option_duration")]
+    pub start_delay: Option<Duration>,
+
+    /// Optional limit on how long the process may take to start (that
+    /// is, for its dependencies to become ready and for its `pre`
+    /// command to complete); startup is aborted if the process has not
+    /// started by then.
+    #[serde(default, with = "option_duration")]
+    pub start_timeout: Option<Duration>,
+
+    /// Optional file of environment variables (in the `.env` format)
+    /// that are set for every command of the process; the file is read
+    /// whenever a command is started.
+    #[serde(default)]
+    pub env_file: Option<EnvFileConfig>,
+
+    /// Optional command that writes secrets to stdout (as `KEY=VALUE`
+    /// lines, in the `.env` format), which are set for the `pre`
+    /// commands, and for the `run` command. The command runs before the
+    /// `pre` commands, and again whenever the `run` command is started
+    /// (or restarted); its stdout is never logged.
+    #[serde(default)]
+    pub secrets_command: Option<CommandConfig>,
+
+    /// Optional directory that every command of the process is confined
+    /// to (with `chroot`), which becomes the root (`/`) of the command.
+    #[serde(default)]
+    pub root_directory: Option<String>,
+
+    /// Optional list of CPUs that every command of the process may run
+    /// on (numbered from 0), otherwise the commands may run on every CPU
+    /// that Ground Control may run on.
+    #[serde(default)]
+    pub cpu_affinity: Option<Vec<usize>>,
+
+    /// Optional adjustment of the OOM score of every command of the
+    /// process (from -1000, which keeps the kernel from killing the
+    /// command when it runs out of memory, to 1000, which makes the
+    /// command the first to be killed).
+    #[serde(default)]
+    pub oom_score_adj: Option<i32>,
+
+    /// Optional resource limits of every command of the process,
+    /// otherwise the commands have the resource limits of Ground Control.
+    #[serde(default)]
+    pub limits: Option<LimitsConfig>,
+
+    /// Optional cgroup (v2) settings of the process, which runs the `run`
+    /// command of the process in a cgroup of its own, otherwise the
+    /// command runs in the cgroup of Ground Control (as the other commands
+    /// of the process always do).
+    #[serde(default)]
+    pub cgroup: Option<CgroupConfig>,
+
+    /// Whether or not every command of the process (and every program
+    /// that it executes) is kept from gaining privileges, such as by
+    /// executing setuid or setgid programs, or programs with file
+    /// capabilities.
+    #[serde(default)]
+    pub no_new_privileges: bool,
+
+    /// Whether or not the ambient capabilities of every command of the
+    /// process are cleared, so that the programs that the command
+    /// executes do not inherit them.
+    #[serde(default)]
+    pub clear_ambient_capabilities: bool,
+
+    /// Whether or not the process gets a temporary directory of its own
+    /// (owned by the user of its `run` command), which is set as the
+    /// `TMPDIR` of every command of the process, and removed once the
+    /// process has stopped.
+    #[serde(default)]
+    pub private_tmp: bool,
+
+    /// Optional list of commands to run (in order) *before* the `run`
+    /// command.
+    #[serde(default, with = "command_list", skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<CommandConfig>,
+
+    /// Optional limit on how long each `pre` command may run; commands
+    /// that exceed the limit are killed (and count as failed).
+    #[serde(default, with = "option_duration")]
+    pub pre_timeout: Option<Duration>,
+
+    /// Optional `run` command; if present, this process is considered a
+    /// "daemon process" and Ground Control will monitor the run
+    /// command, shutting down all of the processes if any run command
+    /// exits.
+    #[serde(default)]
+    pub run: Option<CommandConfig>,
+
+    /// Optional type of the process (otherwise a process with a `run`
+    /// command is a `simple` daemon, and a process without one is a
+    /// one-shot process).
+    #[serde(default, rename = "type")]
+    pub process_type: Option<ProcessType>,
+
+    /// File that a `forking` daemon writes its PID to (required for
+    /// `forking` daemons, and ignored otherwise).
+    #[serde(default)]
+    pub pid_file: Option<String>,
+
+    /// Whether or not the `run` command is started in its own session
+    /// (and thus its own process group); processes that need to share
+    /// Ground Control's controlling terminal can opt out.
+    #[serde(default = "ProcessConfig::default_new_session")]
+    pub new_session: bool,
+
+    /// Optional signal that the `run` command receives if Ground
+    /// Control itself dies (using `PR_SET_PDEATHSIG`), so that the
+    /// daemon does not linger once Ground Control has been killed.
+    #[serde(default)]
+    pub parent_death_signal: Option<SignalConfig>,
+
+    /// Exit codes (in addition to 0) that indicate that the `pre` or
+    /// `run` command succeeded.
+    #[serde(default)]
+    pub allowed_exit_codes: Vec<i32>,
+
+    /// Policy used to restart the `run` command *if this is a daemon
+    /// process* (ignored if the process does not have a `run` command);
+    /// the daemon is never restarted if not provided.
+    #[serde(default)]
+    pub restart: Option<RestartPolicy>,
+
+    /// Optional backoff used to delay restarts of the `run` command
+    /// (otherwise the command is restarted immediately).
+    #[serde(default)]
+    pub restart_backoff: Option<BackoffConfig>,
+
+    /// Optional limit on the number of times that the `run` command will
+    /// be restarted (within `restart-window`, if provided); Ground
+    /// Control gives up and shuts down once the limit is exceeded.
+    #[serde(default)]
+    pub max_restarts: Option<u32>,
+
+    /// Sliding window over which `max-restarts` is counted (otherwise
+    /// every restart counts towards the limit).
+    #[serde(default, with = "option_duration")]
+    pub restart_window: Option<Duration>,
+
+    /// Optional interval after which the `run` command is proactively
+    /// restarted (measured from the most recent start of the command).
+    #[serde(default, with = "option_duration")]
+    pub restart_interval: Option<Duration>,
+
+    /// Whether or not the daemon is critical: Ground Control shuts down
+    /// once a critical daemon exits (and is not restarted), whereas the
+    /// exit of a non-critical daemon is only logged (ignored if the
+    /// process does not have a `run` command).
+    #[serde(default = "ProcessConfig::default_critical")]
+    pub critical: bool,
+
+    /// Optional limit on how long the process may run: daemons are
+    /// stopped once their `run` command exceeds the limit (and may then
+    /// be restarted according to the restart policy), and the `pre`
+    /// command of one-shot processes is killed (aborting startup).
+    #[serde(default, with = "option_duration")]
+    pub max_runtime: Option<Duration>,
+
+    /// Optional health check that is run periodically once the daemon
+    /// has started (ignored if the process does not have a `run`
+    /// command).
+    #[serde(default)]
+    pub healthcheck: Option<HealthCheckConfig>,
+
+    /// Optional watchdog that the daemon must keep satisfying once it
+    /// has started (ignored if the process does not have a `run`
+    /// command).
+    #[serde(default)]
+    pub watchdog: Option<WatchdogConfig>,
+
+    /// Condition that must be met before the daemon is considered ready,
+    /// which processes that depend on this process wait for before they
+    /// are started (ignored if the process does not have a `run`
+    /// command; one-shot processes are ready once they have started).
+    #[serde(default)]
+    pub ready: ReadinessCheck,
+
+    /// Optional command to run when the daemon exits unexpectedly with
+    /// a failure, before the daemon is restarted (or Ground Control
+    /// shuts down). The command is given the exit code or signal of the
+    /// daemon in the `GC_EXIT_CODE` or `GC_SIGNAL` environment variable
+    /// (ignored if the process does not have a `run` command).
+    #[serde(default)]
+    pub on_crash: Option<CommandConfig>,
+
+    /// Optional limit on how long the `on-crash` command may run; the
+    /// command is killed once the limit expires.
+    #[serde(default, with = "option_duration")]
+    pub on_crash_timeout: Option<Duration>,
+
+    /// Optional command to run before the daemon is stopped, perhaps to
+    /// drain connections (ignored if the process does not have a `run`
+    /// command).
+    #[serde(default)]
+    pub pre_stop: Option<CommandConfig>,
+
+    /// Optional limit on how long the `pre-stop` command may run; the
+    /// command is killed once the limit expires, and the daemon is then
+    /// stopped as usual.
+    #[serde(default, with = "option_duration")]
+    pub pre_stop_timeout: Option<Duration>,
+
+    /// Mechanism for stopping the process *if this is a daemon process*
+    /// (ignored if the process does not have a `run` command).
+    #[serde(default)]
+    pub stop: StopMechanism,
+
+    /// Optional limit on how long the daemon may take to stop once its
+    /// `stop` signal has been sent (or its `stop` command has been
+    /// started); daemons (and `stop` commands) that exceed the limit are
+    /// killed with `SIGKILL`.
+    #[serde(default, with = "option_duration")]
+    pub stop_timeout: Option<Duration>,
+
+    /// Which processes the `stop` signal, and the `SIGKILL` of a daemon
+    /// that did not stop in time, are sent to.
+    #[serde(default)]
+    pub kill_mode: KillMode,
+
+    /// Optional mechanism used to tell the daemon to reload its
+    /// configuration without being restarted (ignored if the process
+    /// does not have a `run` command).
+    #[serde(default)]
+    pub reload: Option<ReloadMechanism>,
+
+    /// Optional limit on how long the `reload` command may run; the
+    /// command is killed once the limit expires.
+    #[serde(default, with = "option_duration")]
+    pub reload_timeout: Option<Duration>,
+
+    /// Signals that are forwarded to the daemon when Ground Control
+    /// receives them (ignored if the process does not have a `run`
+    /// command).
+    #[serde(default)]
+    pub forward_signals: Vec<SignalConfig>,
+
+    /// Translates the signals that Ground Control sends to the daemon
+    /// (its `stop` and `reload` signals, and forwarded signals) into
+    /// other signals; for example, `{ SIGTERM = "SIGQUIT" }`.
+    #[serde(default, with = "signal_map")]
+    pub signal_map: HashMap<SignalConfig, SignalConfig>,
+
+    /// Optional list of commands to run after the process has been
+    /// stopped, but only if the process did not fail (that is, it is a
+    /// one-shot process, or a daemon that exited cleanly or was stopped
+    /// when asked). Runs before the `post` commands.
+    #[serde(default, with = "command_list", skip_serializing_if = "Vec::is_empty")]
+    pub post_success: Vec<CommandConfig>,
+
+    /// Optional list of commands to run after the process has been
+    /// stopped, but only if the process failed (that is, the daemon
+    /// exited unexpectedly with a failure, or could not be stopped).
+    /// Runs before the `post` commands.
+    #[serde(default, with = "command_list", skip_serializing_if = "Vec::is_empty")]
+    pub post_failure: Vec<CommandConfig>,
+
+    /// Optional list of commands to run (in order, even if earlier
+    /// commands fail) after the process has been stopped.
+    #[serde(default, with = "command_list", skip_serializing_if = "Vec::is_empty")]
+    pub post: Vec<CommandConfig>,
+
+    /// Optional limit on how long each `post`, `post-success`, or
+    /// `post-failure` command may run; commands that exceed the limit
+    /// are killed (and count as failed).
+    #[serde(default, with = "option_duration")]
+    pub post_timeout: Option<Duration>,
+}
+
+impl ProcessConfig {
+    fn default_critical() -> bool {
+        true
+    }
+
+    fn default_new_session() -> bool {
+        true
+    }
+
+    /// Returns `true` if this is a daemon process (one with a `run`
+    /// command that is not a `oneshot` command).
+    pub(crate) fn is_daemon(&self) -> bool {
+        self.run.is_some() && self.process_type != Some(ProcessType::Oneshot)
+    }
+
+    /// Returns the signal to send to the daemon in place of the given
+    /// signal (see `signal-map`).
+    pub(crate) fn outgoing_signal(&self, signal: SignalConfig) -> Signal {
+        self.signal_map
+            .get(&signal)
+            .copied()
+            .unwrap_or(signal)
+            .into()
+    }
+
+    /// Returns instance `index` of this replicated process.
+    pub(crate) fn instance(&self, index: u32) -> ProcessConfig {
+        let mut instance = self.clone();
+        instance.name = format!("{}-{index}", self.name);
+        instance.instance = Some(index);
+
+        for command in instance.commands_mut() {
+            command
+                .env
+                .insert(String::from("GC_INSTANCE"), index.to_string());
+            command
+                .env
+                .insert(String::from("GROUNDCONTROL_INSTANCE"), index.to_string());
+        }
+
+        instance
+    }
+
+    /// Sets the given environment variable for every command of the
+    /// process that does not set the variable itself.
+    pub(crate) fn set_default_env(&mut self, key: &str, value: &str) {
+        for command in self.commands_mut() {
+            command
+                .env
+                .entry(key.to_owned())
+                .or_insert_with(|| value.to_owned());
+        }
+    }
+
+    /// Returns all of the commands of the process.
+    fn commands_mut(&mut self) -> impl Iterator<Item = &mut CommandConfig> {
+        let condition = match &mut self.condition {
+            Some(ConditionConfig::Command(command)) => Some(command),
+            _ => None,
+        };
+        let stop: Vec<&mut CommandConfig> = match &mut self.stop {
+            StopMechanism::Command(command) => vec![command],
+            StopMechanism::Signal(_) => vec![],
+            StopMechanism::Steps(steps) => steps
+                .iter_mut()
+                .filter_map(|step| match step {
+                    StopStep::Command { command, .. } => Some(command),
+                    StopStep::Signal { .. } => None,
+                })
+                .collect(),
+        };
+        let reload = match &mut self.reload {
+            Some(ReloadMechanism::Command(command)) => Some(command),
+            _ => None,
+        };
+        let ready = match &mut self.ready {
+            ReadinessCheck::Command(ready) => Some(&mut ready.command),
+            _ => None,
+        };
+
+        condition
+            .into_iter()
+            .chain(self.secrets_command.iter_mut())
+            .chain(self.pre.iter_mut())
+            .chain(self.run.iter_mut())
+            .chain(
+                self.healthcheck
+                    .iter_mut()
+                    .map(|healthcheck| &mut healthcheck.command),
+            )
+            .chain(ready)
+            .chain(self.on_crash.iter_mut())
+            .chain(self.pre_stop.iter_mut())
+            .chain(stop)
+            .chain(reload)
+            .chain(self.post_success.iter_mut())
+            .chain(self.post_failure.iter_mut())
+            .chain(self.post.iter_mut())
+    }
+
+    /// Returns `true` if the process is enabled when the given profiles
+    /// are active.
+    pub fn is_enabled(&self, active_profiles: &[String]) -> bool {
+        self.profiles.is_empty()
+            || self
+                .profiles
+                .iter()
+                .any(|profile| active_profiles.contains(profile))
+    }
+
+    /// Returns `true` if the process runs on the platform that Ground
+    /// Control is running on (according to its `only-on` and `skip-on`
+    /// platforms).
+    pub fn runs_on_current_platform(&self) -> bool {
+        (self.only_on.is_empty() || self.only_on.iter().any(PlatformConfig::is_current))
+            && !self.skip_on.iter().any(PlatformConfig::is_current)
+    }
+}
+
+/// Platform that a process runs on (or is skipped on): an operating
+/// system (such as `"linux"`), optionally followed by an architecture
+/// (such as `"linux/arm64"`), using the names that container images use.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PlatformConfig {
+    os: String,
+    arch: Option<String>,
+}
+
+impl PlatformConfig {
+    const OPERATING_SYSTEMS: &'static [&'static str] =
+        &["linux", "darwin", "freebsd", "netbsd", "openbsd"];
+
+    const ARCHITECTURES: &'static [&'static str] = &[
+        "amd64", "arm64", "arm", "386", "ppc64le", "ppc64", "s390x", "riscv64", "mips64le",
+        "loong64",
+    ];
+
+    /// Returns the platform that Ground Control is running on.
+    pub fn current() -> Self {
+        let os = match std::env::consts::OS {
+            "macos" => "darwin",
+            os => os,
+        };
+        let arch = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            "x86" => "386",
+            "powerpc64" if cfg!(target_endian = "little") => "ppc64le",
+            "loongarch64" => "loong64",
+            arch => arch,
+        };
+        Self {
+            os: os.to_string(),
+            arch: Some(arch.to_string()),
+        }
+    }
+
+    /// Returns `true` if this is the platform that Ground Control is
+    /// running on (any architecture matches a platform without one).
+    pub fn is_current(&self) -> bool {
+        let current = Self::current();
+        self.os == current.os && (self.arch.is_none() || self.arch == current.arch)
+    }
+}
+
+impl fmt::Display for PlatformConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.arch {
+            Some(arch) => write!(f, "{}/{arch}", self.os),
+            None => f.write_str(&self.os),
+        }
+    }
+}
+
+impl Serialize for PlatformConfig {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl TryFrom<String> for PlatformConfig {
+    type Error = String;
+
+    fn try_from(platform: String) -> Result<Self, Self::Error> {
+        let (os, arch) = match platform.split_once('/') {
+            Some((os, arch)) => (os, Some(arch)),
+            None => (platform.as_str(), None),
+        };
+        if !Self::OPERATING_SYSTEMS.contains(&os) {
+            return Err(format!(
+                "unknown operating system \"{os}\" in platform \"{platform}\" (expected one of {})",
+                Self::OPERATING_SYSTEMS.join(", ")
+            ));
+        }
+        if let Some(arch) = arch {
+            if !Self::ARCHITECTURES.contains(&arch) {
+                return Err(format!(
+                    "unknown architecture \"{arch}\" in platform \"{platform}\" (expected one of {})",
+                    Self::ARCHITECTURES.join(", ")
+                ));
+            }
+        }
+
+        Ok(Self {
+            os: os.to_string(),
+            arch: arch.map(String::from),
+        })
+    }
+}
+
+/// Dependency of a process on another process (in `depends-on` or
+/// `wants`), given either as the name of the other process, or as a
+/// table with the `name` of the other process and a `propagate` policy.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(from = "DependencyLineConfig")]
+pub struct DependencyConfig {
+    /// Name of the process that this process depends on.
+    pub name: String,
+
+    /// What happens to this process when the other process is restarted
+    /// or exits.
+    pub propagate: PropagationPolicy,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "expected a process name, or a table with a `name`"
+)]
+enum DependencyLineConfig {
+    Name(String),
+
+    Detailed(DetailedDependency),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct DetailedDependency {
+    name: String,
+
+    #[serde(default)]
+    propagate: PropagationPolicy,
+}
+
+impl From<DependencyLineConfig> for DependencyConfig {
+    fn from(config: DependencyLineConfig) -> Self {
+        match config {
+            DependencyLineConfig::Name(name) => Self {
+                name,
+                propagate: PropagationPolicy::default(),
+            },
+            DependencyLineConfig::Detailed(config) => Self {
+                name: config.name,
+                propagate: config.propagate,
+            },
+        }
+    }
+}
+
+/// File of environment variables (in the `.env` format, see `env-file`),
+/// given either as the path of the file, or as a table with the `path` of
+/// the file and what to do `if-missing`.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(from = "EnvFileLineConfig", rename_all = "kebab-case")]
+pub struct EnvFileConfig {
+    /// Path of the file.
+    pub path: String,
+
+    /// What happens when a command is started, but the file does not
+    /// exist.
+    pub if_missing: MissingEnvFilePolicy,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(untagged, expecting = "expected a path, or a table with a `path`")]
+enum EnvFileLineConfig {
+    Path(String),
+
+    Detailed(DetailedEnvFile),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct DetailedEnvFile {
+    path: String,
+
+    #[serde(default)]
+    if_missing: MissingEnvFilePolicy,
+}
+
+impl From<EnvFileLineConfig> for EnvFileConfig {
+    fn from(config: EnvFileLineConfig) -> Self {
+        match config {
+            EnvFileLineConfig::Path(path) => Self {
+                path,
+                if_missing: MissingEnvFilePolicy::default(),
+            },
+            EnvFileLineConfig::Detailed(config) => Self {
+                path: config.path,
+                if_missing: config.if_missing,
+            },
+        }
+    }
+}
+
+/// Policy that decides what happens when a command is started, but the
+/// `env-file` of its process does not exist.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissingEnvFilePolicy {
+    /// The command fails to start.
+    Fail,
+
+    /// The command is started without the variables in the file, and a
+    /// warning is logged.
+    Warn,
+
+    /// The command is started without the variables in the file.
+    Ignore,
+}
+
+impl Default for MissingEnvFilePolicy {
+    fn default() -> Self {
+        MissingEnvFilePolicy::Fail
+    }
+}
+
+/// Resource limits (see `setrlimit(2)`) of the commands of a process;
+/// each limit is both the soft and the hard limit of its resource.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LimitsConfig {
+    /// Maximum size of the address space, in bytes (`as`).
+    #[serde(default, rename = "as", skip_serializing_if = "Option::is_none")]
+    pub address_space: Option<Limit>,
+
+    /// Maximum size of core dumps, in bytes (0 disables core dumps).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core: Option<Limit>,
+
+    /// Maximum CPU time, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<Limit>,
+
+    /// Maximum size of the data segment, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Limit>,
+
+    /// Maximum size of the files that the command writes, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fsize: Option<Limit>,
+
+    /// Maximum size of locked memory, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memlock: Option<Limit>,
+
+    /// Maximum number of open file descriptors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nofile: Option<Limit>,
+
+    /// Maximum number of processes (of the user that runs the command).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nproc: Option<Limit>,
+
+    /// Maximum size of the stack, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Limit>,
+}
+
+/// Resource limit, given either as a number, or as `"unlimited"`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(try_from = "LimitLine", into = "LimitLine")]
+pub enum Limit {
+    /// At most the given amount.
+    Value(u64),
+
+    /// No limit.
+    Unlimited,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(untagged, expecting = "expected a number, or \"unlimited\"")]
+enum LimitLine {
+    Value(u64),
+
+    Keyword(String),
+}
+
+impl TryFrom<LimitLine> for Limit {
+    type Error = String;
+
+    fn try_from(line: LimitLine) -> Result<Self, Self::Error> {
+        match line {
+            LimitLine::Value(value) => Ok(Self::Value(value)),
+            LimitLine::Keyword(keyword) if keyword == "unlimited" => Ok(Self::Unlimited),
+            LimitLine::Keyword(keyword) => Err(format!(
+                "Invalid limit \"{keyword}\" (expected a number, or \"unlimited\")"
+            )),
+        }
+    }
+}
+
+impl From<Limit> for LimitLine {
+    fn from(limit: Limit) -> Self {
+        match limit {
+            Limit::Value(value) => Self::Value(value),
+            Limit::Unlimited => Self::Keyword(String::from("unlimited")),
+        }
+    }
+}
+
+/// cgroup (v2) settings of a process (see the cgroup v2 documentation of
+/// the Linux kernel).
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CgroupConfig {
+    /// Limit on the memory of the commands of the process (`memory.max`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_max: Option<MemoryMax>,
+
+    /// Limit on the CPU time of the commands of the process (`cpu.max`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_max: Option<CpuMax>,
+}
+
+/// Memory limit of a cgroup, given either as a number of bytes, as a
+/// string with a `K`, `M`, `G`, or `T` suffix (such as `"512M"`), or as
+/// `"max"`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(try_from = "LimitLine", into = "LimitLine")]
+pub enum MemoryMax {
+    /// At most the given number of bytes.
+    Bytes(u64),
+
+    /// No limit.
+    Max,
+}
+
+impl TryFrom<LimitLine> for MemoryMax {
+    type Error = String;
+
+    fn try_from(line: LimitLine) -> Result<Self, Self::Error> {
+        let invalid = |size: &str| {
+            format!("Invalid memory size \"{size}\" (expected a number of bytes, with an optional K, M, G, or T suffix, or \"max\")")
+        };
+        match line {
+            LimitLine::Value(bytes) => Ok(Self::Bytes(bytes)),
+            LimitLine::Keyword(keyword) if keyword == "max" => Ok(Self::Max),
+            LimitLine::Keyword(size) => {
+                let (number, multiplier) = match size.char_indices().last() {
+                    Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+                        let exponent = match suffix.to_ascii_uppercase() {
+                            'K' => 1,
+                            'M' => 2,
+                            'G' => 3,
+                            'T' => 4,
+                            _ => return Err(invalid(&size)),
+                        };
+                        (&size[..index], 1024u64.pow(exponent))
+                    }
+                    _ => (size.as_str(), 1),
+                };
+                number
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|number| number.checked_mul(multiplier))
+                    .map(Self::Bytes)
+                    .ok_or_else(|| invalid(&size))
+            }
+        }
+    }
+}
+
+impl From<MemoryMax> for LimitLine {
+    fn from(memory_max: MemoryMax) -> Self {
+        match memory_max {
+            MemoryMax::Bytes(bytes) => Self::Value(bytes),
+            MemoryMax::Max => Self::Keyword(String::from("max")),
+        }
+    }
+}
+
+/// CPU limit of a cgroup, given either as a number of CPUs (such as `0.5`
+/// for half of a CPU), or as `"max"`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(try_from = "CpuMaxLine", into = "CpuMaxLine")]
+pub enum CpuMax {
+    /// At most the given number of microseconds of CPU time in every
+    /// [`CpuMax::PERIOD`].
+    Quota(u64),
+
+    /// No limit.
+    Max,
+}
+
+impl CpuMax {
+    /// Period (in microseconds) of the CPU time quota.
+    pub const PERIOD: u64 = 100_000;
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(untagged, expecting = "expected a number of CPUs, or \"max\"")]
+enum CpuMaxLine {
+    Cpus(f64),
+
+    Keyword(String),
+}
+
+impl TryFrom<CpuMaxLine> for CpuMax {
+    type Error = String;
+
+    fn try_from(line: CpuMaxLine) -> Result<Self, Self::Error> {
+        match line {
+            // The kernel does not accept quotas below 1ms.
+            CpuMaxLine::Cpus(cpus) if cpus * CpuMax::PERIOD as f64 >= 1000.0 => {
+                Ok(Self::Quota((cpus * CpuMax::PERIOD as f64).round() as u64))
+            }
+            CpuMaxLine::Cpus(cpus) => Err(format!(
+                "Invalid CPU limit {cpus} (the limit must be at least 0.01 CPUs)"
+            )),
+            CpuMaxLine::Keyword(keyword) if keyword == "max" => Ok(Self::Max),
+            CpuMaxLine::Keyword(keyword) => Err(format!(
+                "Invalid CPU limit \"{keyword}\" (expected a number of CPUs, or \"max\")"
+            )),
+        }
+    }
+}
+
+impl From<CpuMax> for CpuMaxLine {
+    fn from(cpu_max: CpuMax) -> Self {
+        match cpu_max {
+            CpuMax::Quota(quota) => Self::Cpus(quota as f64 / CpuMax::PERIOD as f64),
+            CpuMax::Max => Self::Keyword(String::from("max")),
+        }
+    }
+}
+
+/// Policy that decides what happens to a daemon process when one of its
+/// dependencies is restarted, or exits (without being restarted).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PropagationPolicy {
+    /// Leave the daemon alone.
+    Ignore,
+
+    /// Stop the daemon while the dependency is restarted, and restart the
+    /// daemon once the dependency is ready again; stop the daemon if the
+    /// dependency exits.
+    Restart,
+
+    /// Stop the daemon (without restarting it) when the dependency is
+    /// restarted or exits.
+    Stop,
+}
+
+impl Default for PropagationPolicy {
+    fn default() -> Self {
+        PropagationPolicy::Ignore
+    }
+}
+
+/// Condition under which a process is started.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ConditionConfig {
+    /// Condition is met if the given environment variable is set to a
+    /// non-empty value other than `0` or `false`.
+    Env(String),
+
+    /// Condition is met if the given command exits successfully.
+    Command(CommandConfig),
+}
+
+/// Whether the `run` command of a process is a daemon or a one-shot
+/// command, and how the `run` command of a daemon starts the daemon.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProcessType {
+    /// The `run` command *is* the daemon.
+    #[serde(alias = "daemon")]
+    Simple,
+
+    /// The `run` command starts the daemon in the background, writes
+    /// the daemon's PID to the `pid-file`, and then exits; the daemon is
+    /// supervised using that PID.
+    Forking,
+
+    /// The `run` command is expected to exit: it is run to completion
+    /// when the process is started (after the `pre` commands), and its
+    /// exit does not trigger a shutdown.
+    #[serde(alias = "one-shot")]
+    Oneshot,
+}
+
+/// Which processes are signalled when a daemon is stopped (or killed).
+/// The group is the process group of the daemon, which every `run`
+/// command gets on its own.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KillMode {
+    /// Only the main process of the daemon is signalled.
+    Process,
+
+    /// Every process in the daemon's process group is signalled, and
+    /// any that are left once the daemon has stopped are killed.
+    Group,
+
+    /// The `stop` signal is sent to the main process, but the `SIGKILL`
+    /// (of a daemon that did not stop in time, and of the processes
+    /// that are left once the daemon has stopped) goes to the whole
+    /// process group.
+    Mixed,
+}
+
+impl Default for KillMode {
+    fn default() -> Self {
+        KillMode::Process
+    }
+}
+
+/// Policy used to decide if a daemon process should be restarted after
+/// its `run` command exits.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Never restart the daemon; any exit begins the shutdown process.
+    Never,
+
+    /// Restart the daemon if it exits with a non-zero exit code (or is
+    /// killed); clean exits begin the shutdown process.
+    OnFailure,
+
+    /// Always restart the daemon, regardless of how it exited.
+    Always,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Never
+    }
+}
+
+/// Exponential backoff applied between restarts of a daemon process.
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackoffConfig {
+    /// Delay before the first restart.
+    #[serde(default = "BackoffConfig::default_initial_delay", with = "duration")]
+    pub initial_delay: Duration,
+
+    /// Factor by which the delay grows after every restart.
+    #[serde(default = "BackoffConfig::default_multiplier")]
+    pub multiplier: f64,
+
+    /// Upper bound on the delay between restarts. The delay is reset
+    /// to `initial-delay` once the daemon stays up for at least this
+    /// long.
+    #[serde(default = "BackoffConfig::default_max_delay", with = "duration")]
+    pub max_delay: Duration,
+}
+
+impl BackoffConfig {
+    fn default_initial_delay() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn default_multiplier() -> f64 {
+        2.0
+    }
+
+    fn default_max_delay() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    /// Returns the delay that follows the given delay.
+    pub fn next_delay(&self, delay: Duration) -> Duration {
+        let next = delay.as_secs_f64() * self.multiplier.max(1.0);
+        Duration::from_secs_f64(next.min(self.max_delay.as_secs_f64()))
+    }
+}
+
+/// Health check that periodically verifies that a daemon process is
+/// working correctly.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HealthCheckConfig {
+    /// Command to run; the check fails if the command exits with a
+    /// non-zero exit code (or cannot be run).
+    pub command: CommandConfig,
+
+    /// Time between checks (also accepted as `period`).
+    #[serde(
+        default = "HealthCheckConfig::default_interval",
+        with = "duration",
+        alias = "period"
+    )]
+    pub interval: Duration,
+
+    /// Additional delay before the first check (the first check always
+    /// waits for one `interval`).
+    #[serde(default, with = "duration")]
+    pub initial_delay: Duration,
+
+    /// Optional limit on how long each check may run; checks that
+    /// exceed the limit are killed (and count as failed).
+    #[serde(default, with = "option_duration")]
+    pub timeout: Option<Duration>,
+
+    /// Number of consecutive successful checks after which a `healthy`
+    /// daemon is considered ready.
+    #[serde(default = "default_threshold")]
+    pub success_threshold: u32,
+
+    /// Number of consecutive failed checks after which the daemon is
+    /// considered unhealthy.
+    #[serde(default = "HealthCheckConfig::default_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// Action to take once the daemon is considered unhealthy.
+    #[serde(default)]
+    pub action: ProbeAction,
+}
+
+impl HealthCheckConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn default_failure_threshold() -> u32 {
+        3
+    }
+}
+
+/// Condition under which a daemon process is considered ready.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(from = "ReadinessCheckConfig", into = "ReadinessCheckConfig")]
+pub enum ReadinessCheck {
+    /// Daemon is ready as soon as it is running.
+    Running,
+
+    /// Daemon is ready once its health check succeeds for the first
+    /// time.
+    Healthy,
+
+    /// Daemon is ready once it creates (or touches) the given file.
+    File(String),
+
+    /// Daemon is ready once the given command succeeds. The daemon is
+    /// only considered to have started once it is ready, and the daemon
+    /// is treated as having failed if the command does not succeed.
+    Command(ReadinessCommandConfig),
+
+    /// Daemon is ready once the given HTTP endpoint responds with the
+    /// expected status code.
+    Http(ReadinessHttpConfig),
+
+    /// Daemon is ready once it writes a line (to stdout or stderr) that
+    /// matches the given regular expression.
+    LogLine(String),
+
+    /// Daemon is ready once it sends `READY=1` to the `sd_notify` socket
+    /// in its `NOTIFY_SOCKET` environment variable.
+    Notify,
+
+    /// Daemon is ready once the given path (usually a file or a Unix
+    /// socket) exists. The daemon is only considered to have started
+    /// once it is ready, and the daemon is treated as having failed if
+    /// the path does not exist before the timeout.
+    Path(ReadinessPathConfig),
+}
+
+impl ReadinessCheck {
+    /// Returns `true` if the daemon is only considered to have started
+    /// (and the processes after it are only started) once it is ready.
+    pub fn gates_startup(&self) -> bool {
+        matches!(self, Self::Command(_) | Self::Path(_))
+    }
+}
+
+impl Default for ReadinessCheck {
+    fn default() -> Self {
+        Self::Running
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(
+    untagged,
+    expecting = "expected `running`, `healthy`, `notify`, or a table with one of `file`, `command`, `http`, `log-line`, or `path`"
+)]
+enum ReadinessCheckConfig {
+    Simple(SimpleReadinessCheck),
+
+    File(FileReadinessCheck),
+
+    Command(ReadinessCommandConfig),
+
+    Http(ReadinessHttpConfig),
+
+    LogLine(LogLineReadinessCheck),
+
+    Path(ReadinessPathConfig),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum SimpleReadinessCheck {
+    Running,
+
+    Healthy,
+
+    Notify,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct FileReadinessCheck {
+    file: String,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct LogLineReadinessCheck {
+    log_line: String,
+}
+
+impl From<ReadinessCheckConfig> for ReadinessCheck {
+    fn from(config: ReadinessCheckConfig) -> Self {
+        match config {
+            ReadinessCheckConfig::Simple(SimpleReadinessCheck::Running) => Self::Running,
+            ReadinessCheckConfig::Simple(SimpleReadinessCheck::Healthy) => Self::Healthy,
+            ReadinessCheckConfig::Simple(SimpleReadinessCheck::Notify) => Self::Notify,
+            ReadinessCheckConfig::File(config) => Self::File(config.file),
+            ReadinessCheckConfig::Command(config) => Self::Command(config),
+            ReadinessCheckConfig::Http(config) => Self::Http(config),
+            ReadinessCheckConfig::LogLine(config) => Self::LogLine(config.log_line),
+            ReadinessCheckConfig::Path(config) => Self::Path(config),
+        }
+    }
+}
+
+impl From<ReadinessCheck> for ReadinessCheckConfig {
+    fn from(check: ReadinessCheck) -> Self {
+        match check {
+            ReadinessCheck::Running => Self::Simple(SimpleReadinessCheck::Running),
+            ReadinessCheck::Healthy => Self::Simple(SimpleReadinessCheck::Healthy),
+            ReadinessCheck::Notify => Self::Simple(SimpleReadinessCheck::Notify),
+            ReadinessCheck::File(file) => Self::File(FileReadinessCheck { file }),
+            ReadinessCheck::Command(config) => Self::Command(config),
+            ReadinessCheck::Http(config) => Self::Http(config),
+            ReadinessCheck::LogLine(log_line) => Self::LogLine(LogLineReadinessCheck { log_line }),
+            ReadinessCheck::Path(config) => Self::Path(config),
+        }
+    }
+}
+
+/// Command that is run (until it succeeds) to check if a daemon process
+/// is ready.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReadinessCommandConfig {
+    /// Command to run; the daemon is ready once the command exits
+    /// successfully.
+    pub command: CommandConfig,
+
+    /// Time between attempts (also accepted as `period`).
+    #[serde(
+        default = "ReadinessCommandConfig::default_interval",
+        with = "duration",
+        alias = "period"
+    )]
+    pub interval: Duration,
+
+    /// Delay before the first attempt.
+    #[serde(default, with = "duration")]
+    pub initial_delay: Duration,
+
+    /// Optional limit on how long each attempt may run; attempts that
+    /// exceed the limit are killed (and count as failed).
+    #[serde(default, with = "option_duration")]
+    pub timeout: Option<Duration>,
+
+    /// Number of consecutive successful attempts after which the daemon
+    /// is considered ready.
+    #[serde(default = "default_threshold")]
+    pub success_threshold: u32,
+
+    /// Number of failed attempts after which the daemon is considered to
+    /// have failed (also accepted as `failure-threshold`).
+    #[serde(
+        default = "ReadinessCommandConfig::default_retries",
+        alias = "failure-threshold"
+    )]
+    pub retries: u32,
+}
+
+impl ReadinessCommandConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn default_retries() -> u32 {
+        30
+    }
+}
+
+/// Default for probe thresholds (other than `failure-threshold` of a
+/// health check, and `retries`): succeed or fail on the first attempt.
+fn default_threshold() -> u32 {
+    1
+}
+
+/// HTTP endpoint that is polled (until it responds with the expected
+/// status code) to check if a daemon process is ready.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReadinessHttpConfig {
+    /// URL to request (an `http://` or `https://` URL).
+    #[serde(rename = "http")]
+    pub url: String,
+
+    /// Status code that indicates that the daemon is ready.
+    #[serde(default = "ReadinessHttpConfig::default_status")]
+    pub status: u16,
+
+    /// Time between requests (also accepted as `period`).
+    #[serde(
+        default = "ReadinessHttpConfig::default_interval",
+        with = "duration",
+        alias = "period"
+    )]
+    pub interval: Duration,
+
+    /// Delay before the first request.
+    #[serde(default, with = "duration")]
+    pub initial_delay: Duration,
+
+    /// Maximum amount of time to wait for each response.
+    #[serde(default = "ReadinessHttpConfig::default_timeout", with = "duration")]
+    pub timeout: Duration,
+
+    /// Number of consecutive successful requests after which the daemon
+    /// is considered ready.
+    #[serde(default = "default_threshold")]
+    pub success_threshold: u32,
+
+    /// Number of consecutive failed requests after which the daemon is
+    /// considered to have failed.
+    #[serde(default = "ReadinessHttpConfig::default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl ReadinessHttpConfig {
+    fn default_status() -> u16 {
+        200
+    }
+
+    fn default_interval() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn default_timeout() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn default_failure_threshold() -> u32 {
+        30
+    }
+}
+
+/// Path that must exist for a daemon process to be ready.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReadinessPathConfig {
+    /// Path to wait for.
+    pub path: String,
+
+    /// Time between checks (also accepted as `period`).
+    #[serde(
+        default = "ReadinessPathConfig::default_interval",
+        with = "duration",
+        alias = "period"
+    )]
+    pub interval: Duration,
+
+    /// Delay before the first check.
+    #[serde(default, with = "duration")]
+    pub initial_delay: Duration,
+
+    /// Maximum amount of time to wait for the path to exist (including
+    /// the `initial-delay`).
+    #[serde(default = "ReadinessPathConfig::default_timeout", with = "duration")]
+    pub timeout: Duration,
+
+    /// Number of consecutive checks that find the path after which the
+    /// daemon is considered ready.
+    #[serde(default = "default_threshold")]
+    pub success_threshold: u32,
+
+    /// Optional number of checks that do not find the path after which
+    /// the daemon is considered to have failed (otherwise only the
+    /// `timeout` limits the wait).
+    #[serde(default)]
+    pub failure_threshold: Option<u32>,
+}
+
+impl ReadinessPathConfig {
+    fn default_interval() -> Duration {
+        Duration::from_millis(100)
+    }
+
+    fn default_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+}
+
+/// Watchdog that requires a daemon process to periodically prove that
+/// it is still making progress by touching (updating the modification
+/// time of) a file. The file is checked whenever the `timeout` elapses,
+/// so watchdogs do not have a `period` (or a `success-threshold`), and
+/// reject those settings.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WatchdogConfig {
+    /// File that the daemon must touch.
+    pub path: String,
+
+    /// Maximum time between touches (measured from the start of the
+    /// daemon for the first touch) after which the daemon is considered
+    /// to be hung.
+    #[serde(with = "duration")]
+    pub timeout: Duration,
+
+    /// Additional time that the daemon is given for the first touch.
+    #[serde(default, with = "duration")]
+    pub initial_delay: Duration,
+
+    /// Number of consecutive timeouts after which the daemon is
+    /// considered hung.
+    #[serde(default = "default_threshold")]
+    pub failure_threshold: u32,
+
+    /// Action to take once the daemon is considered hung.
+    #[serde(default)]
+    pub action: ProbeAction,
+}
+
+/// Action taken when a daemon fails its health check or watchdog.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProbeAction {
+    /// Stop and then restart the daemon.
+    Restart,
+
+    /// Stop the daemon and shut down Ground Control (as if the daemon
+    /// had failed).
+    Shutdown,
+
+    /// Only log the failure, and leave the daemon running.
+    LogOnly,
+}
+
+impl Default for ProbeAction {
+    fn default() -> Self {
+        ProbeAction::Restart
+    }
+}
+
+/// Mechanism used to stop a daemon process.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(
+    untagged,
+    expecting = "expected a signal name, a command, or an array of stop steps"
+)]
+pub enum StopMechanism {
+    /// Stop the process using a signal.
+    Signal(SignalConfig),
+
+    /// Stop the process by running a command.
+    Command(CommandConfig),
+
+    /// Stop the process by taking each step in turn, until the process
+    /// has stopped, and then killing the process (with `SIGKILL`) if it
+    /// is still running once the `timeout` of the last step has elapsed.
+    Steps(Vec<StopStep>),
+}
+
+/// Step of a [`StopMechanism::Steps`] chain.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(
+    untagged,
+    deny_unknown_fields,
+    expecting = "expected a table with a `signal` or a `command` (and an optional `timeout`)"
+)]
+pub enum StopStep {
+    /// Send a signal to the process.
+    Signal {
+        /// Signal to send.
+        signal: SignalConfig,
+
+        /// How long to wait for the process to stop before taking the
+        /// next step (waits forever if not provided, which is only
+        /// allowed for the last step).
+        #[serde(default, with = "option_duration")]
+        timeout: Option<Duration>,
+    },
+
+    /// Run a command.
+    Command {
+        /// Command to run.
+        command: CommandConfig,
+
+        /// How long to wait for the process to stop (including the
+        /// time it takes to run the command) before taking the next
+        /// step (waits forever if not provided, which is only allowed
+        /// for the last step).
+        #[serde(default, with = "option_duration")]
+        timeout: Option<Duration>,
+    },
+}
+
+impl StopStep {
+    /// Returns how long to wait for the process to stop before taking
+    /// the next step.
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        match self {
+            StopStep::Signal { timeout, .. } | StopStep::Command { timeout, .. } => *timeout,
+        }
+    }
+}
+
+impl Default for StopMechanism {
+    fn default() -> Self {
+        StopMechanism::Signal(SignalConfig(Signal::SIGTERM))
+    }
+}
+
+/// Mechanism used to tell a daemon process to reload its configuration.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(untagged, expecting = "expected a signal name, or a command")]
+pub enum ReloadMechanism {
+    /// Reload the process by sending it a signal.
+    Signal(SignalConfig),
+
+    /// Reload the process by running a command.
+    Command(CommandConfig),
+}
+
+/// Signal used to stop (or reload) a daemon process, or that is
+/// forwarded to it: either the name of the signal (such as `"SIGUSR2"`)
+/// or its number (such as `12`).
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+#[serde(try_from = "SignalValue")]
+pub struct SignalConfig(Signal);
+
+impl SignalConfig {
+    /// Returns `false` for the signals that Ground Control handles
+    /// itself (`SIGINT` and `SIGTERM` to shut down, `SIGUSR1` to log the
+    /// status, `SIGUSR2` to reload), and for those that cannot be handled
+    /// at all.
+    pub(crate) fn can_be_forwarded(self) -> bool {
+        !matches!(
+            self.0,
+            Signal::SIGINT
+                | Signal::SIGTERM
+                | Signal::SIGUSR1
+                | Signal::SIGUSR2
+                | Signal::SIGKILL
+                | Signal::SIGSTOP
+                | Signal::SIGILL
+                | Signal::SIGFPE
+                | Signal::SIGSEGV
+        )
+    }
+}
+
+impl fmt::Display for SignalConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+impl Serialize for SignalConfig {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "expected a signal name or number")]
+enum SignalValue {
+    Name(String),
+    Number(i32),
+}
+
+impl TryFrom<SignalValue> for SignalConfig {
+    type Error = String;
+
+    fn try_from(value: SignalValue) -> Result<Self, Self::Error> {
+        match value {
+            SignalValue::Name(name) => match name.parse::<i32>() {
+                Ok(number) => Self::try_from(SignalValue::Number(number)),
+                Err(_) => name
+                    .parse()
+                    .map(Self)
+                    .map_err(|_| format!("unknown signal \"{name}\"")),
+            },
+            SignalValue::Number(number) => Signal::try_from(number)
+                .map(Self)
+                .map_err(|_| format!("unknown signal number {number}")),
+        }
+    }
+}
+
+impl From<Signal> for SignalConfig {
+    fn from(signal: Signal) -> Self {
+        Self(signal)
+    }
+}
+
+impl From<SignalConfig> for Signal {
+    fn from(signal: SignalConfig) -> Self {
+        signal.0
+    }
+}
+
+impl From<&SignalConfig> for Signal {
+    fn from(signal: &SignalConfig) -> Self {
+        signal.0
+    }
+}
+
+/// Configuration for a command, its arguments, and any execution
+/// properties (such as the user under which to run the command, or the
+/// environment variables to pass through to the command).
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(try_from = "CommandLineConfig", into = "CommandLineConfig")]
+pub struct CommandConfig {
+    /// User to run this command as (or `user:group`), otherwise run the
+    /// command as the user that executed Ground Control (most likely
+    /// `root`).
+    pub user: Option<String>,
+
+    /// Group to run this command as, otherwise run the command as the
+    /// primary group of its `user` (or as the group that executed Ground
+    /// Control, if the command does not have a `user` either).
+    pub group: Option<String>,
+
+    /// Supplementary groups to run this command with, otherwise run the
+    /// command with the groups that list its `user` as a member in
+    /// `/etc/group` (or with the supplementary groups of Ground Control,
+    /// if the command does not have a `user`).
+    pub groups: Option<Vec<String>>,
+
+    /// If present, then only the given list of environment variables
+    /// will be passed through to the command (all other variables will
+    /// be removed from the command's environment). Note that `PATH` is
+    /// always allowed. Names that contain `*` are patterns (such as
+    /// `RUST_*`), which allow every variable that matches them. All
+    /// environment variables will be allowed if this value is `None`. If
+    /// provided, but empty, then no variables other than `PATH` will be
+    /// allowed.
+    pub only_env: Option<HashSet<String>>,
+
+    /// Which of Ground Control's environment variables the command
+    /// inherits; defaults to [`InheritEnv::Listed`] if `only_env` is
+    /// provided, and to [`InheritEnv::All`] otherwise.
+    pub inherit_env: Option<InheritEnv>,
+
+    /// Set `HOME`, `USER`, `LOGNAME`, and `SHELL` from the passwd entry
+    /// of the user that runs the command (as a login would), unless the
+    /// command's own environment variables set them.
+    pub login_env: bool,
+
+    /// Niceness to run the command with (from -20, the highest priority,
+    /// to 19, the lowest), otherwise run the command with the niceness
+    /// of Ground Control.
+    pub nice: Option<i32>,
+
+    /// IO scheduling class to run the command with (with the
+    /// `io_priority`, if any), otherwise run the command with the IO
+    /// scheduling class of Ground Control (or in the best-effort class,
+    /// if the command has an `io_priority`).
+    pub io_class: Option<IoClass>,
+
+    /// IO priority within the IO scheduling class (from 0, the highest
+    /// priority, to 7, the lowest), otherwise run the command with
+    /// priority 4 (the idle class does not have priorities).
+    pub io_priority: Option<u8>,
+
+    /// Program to execute.
+    pub program: String,
+
+    /// Arguments to pass to the program.
+    pub args: Vec<String>,
+
+    /// Treat failures of this command as success (only applies to the
+    /// `pre` and `post` commands; failures are still logged).
+    pub ignore_failure: bool,
+
+    /// Additional environment variables to set for the command (such as
+    /// `GC_INSTANCE`, for the instances of replicated processes).
+    pub env: HashMap<String, String>,
+
+    /// Additional environment variables to set for the command, whose
+    /// values are read from the given files (such as mounted secrets)
+    /// when the command is started, without their trailing newline.
+    pub env_from_file: HashMap<String, String>,
+
+    /// File of environment variables to set for the command (the
+    /// `env-file` of its process), which the command's own `env`
+    /// variables override.
+    pub env_file: Option<EnvFileConfig>,
+
+    /// Directory to confine the command to (the `root-directory` of its
+    /// process), which becomes the root (`/`) of the command.
+    pub root_directory: Option<String>,
+
+    /// CPUs that the command may run on (the `cpu-affinity` of its
+    /// process).
+    pub cpu_affinity: Option<Vec<usize>>,
+
+    /// Adjustment of the OOM score of the command (the `oom-score-adj`
+    /// of its process).
+    pub oom_score_adj: Option<i32>,
+
+    /// Resource limits of the command (the `limits` of its process).
+    pub limits: Option<LimitsConfig>,
+
+    /// Whether or not the command is kept from gaining privileges (the
+    /// `no-new-privileges` of its process).
+    pub no_new_privileges: bool,
+
+    /// Whether or not the ambient capabilities of the command are
+    /// cleared (the `clear-ambient-capabilities` of its process).
+    pub clear_ambient_capabilities: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize)]
+#[serde(untagged)]
+enum CommandLineConfig {
+    Simple(CommandLine),
+
+    Detailed(DetailedCommandLine),
+}
+
+/// Picks the form of the command based on the type of the value (instead
+/// of trying each form in turn, as `untagged` does), so that errors in
+/// detailed commands (such as unknown fields) are reported as such.
+impl<'de> Deserialize<'de> for CommandLineConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CommandLineConfigVisitor)
+    }
+}
+
+struct CommandLineConfigVisitor;
+
+impl<'de> serde::de::Visitor<'de> for CommandLineConfigVisitor {
+    type Value = CommandLineConfig;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a command string, an array of strings, or a table with a `command`"
+        )
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(CommandLineConfig::Simple(CommandLine::CommandString(
+            v.to_string(),
+        )))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        Vec::<String>::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))
+            .map(|args| CommandLineConfig::Simple(CommandLine::CommandVector(args)))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        DetailedCommandLine::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+            .map(CommandLineConfig::Detailed)
+    }
+}
+
+impl TryFrom<CommandLineConfig> for CommandConfig {
+    type Error = String;
+
+    fn try_from(config: CommandLineConfig) -> Result<Self, Self::Error> {
+        match config {
+            CommandLineConfig::Simple(config) => {
+                let (program, args) = config.program_and_args(false)?;
+                Ok(Self {
+                    user: None,
+                    group: None,
+                    groups: None,
+                    only_env: None,
+                    inherit_env: None,
+                    login_env: false,
+                    nice: None,
+                    io_class: None,
+                    io_priority: None,
+                    program,
+                    args,
+                    ignore_failure: false,
+                    env: HashMap::new(),
+                    env_from_file: HashMap::new(),
+                    env_file: None,
+                    root_directory: None,
+                    cpu_affinity: None,
+                    oom_score_adj: None,
+                    limits: None,
+                    no_new_privileges: false,
+                    clear_ambient_capabilities: false,
+                })
+            }
+            CommandLineConfig::Detailed(config) => {
+                let (program, args) = config.command.program_and_args(config.shell)?;
+                if config.only_env.is_some()
+                    && matches!(config.inherit_env, Some(InheritEnv::All | InheritEnv::None))
+                {
+                    return Err(String::from(
+                        "`only-env` can only be used with `inherit-env = \"listed\"`",
+                    ));
+                }
+                if let Some(nice) = config.nice {
+                    if !(-20..=19).contains(&nice) {
+                        return Err(format!("`nice` must be between -20 and 19, not {nice}"));
+                    }
+                }
+                if let Some(io_priority) = config.io_priority {
+                    if io_priority > 7 {
+                        return Err(format!(
+                            "`io-priority` must be between 0 and 7, not {io_priority}"
+                        ));
+                    }
+                    if config.io_class == Some(IoClass::Idle) {
+                        return Err(String::from(
+                            "`io-priority` cannot be used with `io-class = \"idle\"`",
+                        ));
+                    }
+                }
+                if config.group.is_some()
+                    && config
+                        .user
+                        .as_ref()
+                        .map_or(false, |user| user.contains(':'))
+                {
+                    return Err(String::from(
+                        "`user` includes a group, but `group` is also set",
+                    ));
+                }
+                if let Some(key) = config
+                    .env_from_file
+                    .keys()
+                    .find(|key| config.env.contains_key(*key))
+                {
+                    return Err(format!("`{key}` is set by both `env` and `env-from-file`"));
+                }
+
+                Ok(Self {
+                    user: config.user,
+                    group: config.group,
+                    groups: config.groups,
+                    only_env: config.only_env,
+                    inherit_env: config.inherit_env,
+                    login_env: config.login_env,
+                    nice: config.nice,
+                    io_class: config.io_class,
+                    io_priority: config.io_priority,
+                    program,
+                    args,
+                    ignore_failure: config.ignore_failure,
+                    env: config.env,
+                    env_from_file: config.env_from_file,
+                    env_file: None,
+                    root_directory: None,
+                    cpu_affinity: None,
+                    oom_score_adj: None,
+                    limits: None,
+                    no_new_privileges: false,
+                    clear_ambient_capabilities: false,
+                })
+            }
+        }
+    }
+}
+
+impl CommandConfig {
+    /// Returns the user and the group to run the command as, splitting a
+    /// `user:group` user.
+    pub fn user_and_group(&self) -> (Option<&str>, Option<&str>) {
+        match self.user.as_deref().and_then(|user| user.split_once(':')) {
+            Some((user, group)) => (Some(user), Some(group)),
+            None => (self.user.as_deref(), self.group.as_deref()),
+        }
+    }
+
+    /// Returns `true` if the command can be written as a command line
+    /// (that is, without a table of options).
+    fn is_simple(&self) -> bool {
+        self.user.is_none()
+            && self.group.is_none()
+            && self.groups.is_none()
+            && self.only_env.is_none()
+            && self.inherit_env.is_none()
+            && !self.login_env
+            && self.nice.is_none()
+            && self.io_class.is_none()
+            && self.io_priority.is_none()
+            && !self.ignore_failure
+            && self.env.is_empty()
+            && self.env_from_file.is_empty()
+    }
+}
+
+/// Which of Ground Control's environment variables a command inherits
+/// (the variables in the command's `env`, and in the `env-file` of its
+/// process, are always set).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InheritEnv {
+    /// Every variable.
+    All,
+
+    /// No variables (not even `PATH`).
+    None,
+
+    /// `PATH`, and the variables in `only-env`.
+    Listed,
+}
+
+/// IO scheduling class of a command (see `ionice(1)`).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// Ahead of every other class (only root can use this class).
+    Realtime,
+
+    /// Shared fairly with the other best-effort commands (the default).
+    BestEffort,
+
+    /// Only when no other command needs the disk.
+    Idle,
+}
+
+/// Writes the command in the simplest form that describes it (shell
+/// commands are written as the `/bin/sh -c` command that runs them).
+impl From<CommandConfig> for CommandLineConfig {
+    fn from(config: CommandConfig) -> Self {
+        if config.is_simple() {
+            Self::Simple(CommandLine::from(config))
+        } else {
+            Self::Detailed(DetailedCommandLine::from(config))
+        }
+    }
+}
+
+impl From<CommandConfig> for DetailedCommandLine {
+    fn from(config: CommandConfig) -> Self {
+        Self {
+            user: config.user.clone(),
+            group: config.group.clone(),
+            groups: config.groups.clone(),
+            only_env: config.only_env.clone(),
+            inherit_env: config.inherit_env,
+            login_env: config.login_env,
+            nice: config.nice,
+            io_class: config.io_class,
+            io_priority: config.io_priority,
+            ignore_failure: config.ignore_failure,
+            shell: false,
+            env: config.env.clone(),
+            env_from_file: config.env_from_file.clone(),
+            command: CommandLine::from(config),
+        }
+    }
+}
+
+impl From<CommandConfig> for CommandLine {
+    fn from(config: CommandConfig) -> Self {
+        Self::CommandVector(std::iter::once(config.program).chain(config.args).collect())
+    }
+}
+
+/// Command line: a string, which is split into arguments like a shell
+/// would (honoring quotes and backslash escapes, but without any other
+/// shell features, such as variables or pipes), or an array of the
+/// program to execute followed by its arguments.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(
+    untagged,
+    expecting = "expected a command string or an array of strings"
+)]
+enum CommandLine {
+    CommandString(String),
+
+    CommandVector(Vec<String>),
+}
+
+impl CommandLine {
+    /// Parse the Command Line into the program to execute, and the
+    /// arguments to that program. Shell command lines must be strings,
+    /// which are run by `/bin/sh -c`.
+    fn program_and_args(self, shell: bool) -> Result<(String, Vec<String>), String> {
+        let mut args = match (self, shell) {
+            (CommandLine::CommandString(line), true) => {
+                vec![String::from("/bin/sh"), String::from("-c"), line]
+            }
+            (CommandLine::CommandVector(_), true) => {
+                return Err(String::from("a `shell` command must be a string"))
+            }
+            (CommandLine::CommandString(line), false) => shell_words::split(&line)
+                .map_err(|err| format!("invalid command string `{line}` ({err})"))?,
+            (CommandLine::CommandVector(args), false) => args,
+        };
+
+        if args.is_empty() {
+            return Err(String::from("command line must not be empty"));
+        }
+        let program = args.remove(0);
+
+        Ok((program, args))
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct DetailedCommandLine {
+    #[serde(default)]
+    user: Option<String>,
+
+    #[serde(default)]
+    group: Option<String>,
+
+    #[serde(default)]
+    groups: Option<Vec<String>>,
+
+    #[serde(default)]
+    only_env: Option<HashSet<String>>,
+
+    #[serde(default)]
+    inherit_env: Option<InheritEnv>,
+
+    #[serde(default)]
+    login_env: bool,
+
+    #[serde(default)]
+    nice: Option<i32>,
+
+    #[serde(default)]
+    io_class: Option<IoClass>,
+
+    #[serde(default)]
+    io_priority: Option<u8>,
+
+    #[serde(default)]
+    ignore_failure: bool,
+
+    #[serde(default, skip_serializing)]
+    shell: bool,
+
+    #[serde(
+        default,
+        serialize_with = "sorted_map::serialize",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    env: HashMap<String, String>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted_map::serialize",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    env_from_file: HashMap<String, String>,
+
+    command: CommandLine,
+}
+
+/// Serde helpers for durations, which can be provided as a number of
+/// seconds, or as a string of one or more numbers with unit suffixes
+/// (`ms`, `s`, `m`, `h`), for example `"500ms"` or `"1m30s"`.
+mod duration {
+    use std::{fmt, time::Duration};
+
+    use serde::{de, Deserializer, Serializer};
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+
+    pub(super) fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format(*duration))
+    }
+
+    struct DurationVisitor;
+
+    impl<'de> de::Visitor<'de> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "a number of seconds or a duration string such as \"30s\""
+            )
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            u64::try_from(v)
+                .map(Duration::from_secs)
+                .map_err(|_| E::custom("duration must not be negative"))
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(Duration::from_secs(v))
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            parse(v).map_err(E::custom)
+        }
+    }
+
+    /// Formats the duration as a duration string (such as `"1m30s"`),
+    /// to the millisecond.
+    pub(super) fn format(duration: Duration) -> String {
+        let millis = duration.as_millis();
+        if millis == 0 {
+            return String::from("0s");
+        }
+
+        let parts = [
+            (millis / (60 * 60 * 1000), "h"),
+            (millis / (60 * 1000) % 60, "m"),
+            (millis / 1000 % 60, "s"),
+            (millis % 1000, "ms"),
+        ];
+        parts
+            .iter()
+            .filter(|(value, _)| *value > 0)
+            .map(|(value, unit)| format!("{value}{unit}"))
+            .collect()
+    }
+
+    /// Parses a duration string.
+    pub(super) fn parse(s: &str) -> Result<Duration, String> {
+        let invalid = || format!("invalid duration \"{s}\"");
+
+        let mut total = Duration::ZERO;
+        let mut rest = s.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+            rest = &rest[digits..];
+
+            let units = rest
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let unit = match &rest[..units] {
+                "ms" => Duration::from_millis(1),
+                "s" => Duration::from_secs(1),
+                "m" => Duration::from_secs(60),
+                "h" => Duration::from_secs(60 * 60),
+                _ => return Err(invalid()),
+            };
+            rest = &rest[units..];
+
+            total += unit * u32::try_from(value).map_err(|_| invalid())?;
+        }
+
+        Ok(total)
+    }
+}
+
+/// Serde helpers that expand processes with `replicas` into their
+/// instances.
+mod replicas {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ProcessConfig;
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Vec<ProcessConfig>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<ProcessConfig>::deserialize(deserializer).map(expand)
+    }
+
+    /// Serializes the processes as they were expanded (that is, every
+    /// instance is its own process).
+    pub(super) fn serialize<S>(
+        processes: &[ProcessConfig],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        processes.serialize(serializer)
+    }
+
+    /// Replaces every process that has `replicas` with its instances,
+    /// and every reference to such a process with references to all of
+    /// its instances.
+    fn expand(processes: Vec<ProcessConfig>) -> Vec<ProcessConfig> {
+        let instances: HashMap<String, Vec<String>> = processes
+            .iter()
+            .filter_map(|process| {
+                let replicas = process.replicas?;
+                let names = (0..replicas)
+                    .map(|index| format!("{}-{index}", process.name))
+                    .collect();
+                Some((process.name.clone(), names))
+            })
+            .collect();
+
+        let expand_names = |names: &mut Vec<String>| {
+            *names = names
+                .drain(..)
+                .flat_map(|name| match instances.get(&name) {
+                    Some(instances) => instances.clone(),
+                    None => vec![name],
+                })
+                .collect();
+        };
+
+        processes
+            .into_iter()
+            .flat_map(|mut process| {
+                for dependencies in [&mut process.depends_on, &mut process.wants] {
+                    *dependencies = dependencies
+                        .drain(..)
+                        .flat_map(|dependency| match instances.get(&dependency.name) {
+                            Some(instances) => instances
+                                .iter()
+                                .map(|name| super::DependencyConfig {
+                                    name: name.clone(),
+                                    propagate: dependency.propagate,
+                                })
+                                .collect(),
+                            None => vec![dependency],
+                        })
+                        .collect();
+                }
+                expand_names(&mut process.after);
+                expand_names(&mut process.before);
+
+                match process.replicas {
+                    Some(replicas) => (0..replicas).map(|index| process.instance(index)).collect(),
+                    None => vec![process],
+                }
+            })
+            .collect()
+    }
+}
+
+/// Helpers that describe the unknown keys in a configuration.
+mod unknown_key {
+    /// Part of the path to an unknown key.
+    pub(super) enum Segment {
+        Key(String),
+        Index(usize),
+    }
+
+    /// Returns the parts of the given path (leaving out the optional and
+    /// newtype wrappers, which do not appear in the configuration).
+    pub(super) fn segments(path: &serde_ignored::Path<'_>) -> Vec<Segment> {
+        let mut segments = match path {
+            serde_ignored::Path::Root => return Vec::new(),
+            serde_ignored::Path::Seq { parent, .. }
+            | serde_ignored::Path::Map { parent, .. }
+            | serde_ignored::Path::Some { parent }
+            | serde_ignored::Path::NewtypeStruct { parent }
+            | serde_ignored::Path::NewtypeVariant { parent } => segments(parent),
+        };
+        match path {
+            serde_ignored::Path::Seq { index, .. } => segments.push(Segment::Index(*index)),
+            serde_ignored::Path::Map { key, .. } => segments.push(Segment::Key(key.clone())),
+            _ => {}
+        }
+        segments
+    }
+
+    /// Describes the unknown key at the given path (such as
+    /// `` `processes[0].runn` at line 3, column 1 ``).
+    pub(super) fn describe(path: &[Segment], location: Option<(usize, usize)>) -> String {
+        let mut key = String::new();
+        for segment in path {
+            match segment {
+                Segment::Key(name) if key.is_empty() => key.push_str(name),
+                Segment::Key(name) => {
+                    key.push('.');
+                    key.push_str(name);
+                }
+                Segment::Index(index) => key.push_str(&format!("[{index}]")),
+            }
+        }
+
+        match location {
+            Some((line, column)) => format!("`{key}` at line {line}, column {column}"),
+            None => format!("`{key}`"),
+        }
+    }
+
+    /// Returns the line and column of the unknown key at the given path
+    /// in the TOML document, if it can be found.
+    pub(super) fn toml_location(text: &str, path: &[Segment]) -> Option<(usize, usize)> {
+        let document = toml_edit::ImDocument::parse(text).ok()?;
+        let (key, parents) = path.split_last()?;
+        let mut item = document.as_item();
+        for segment in parents {
+            item = match segment {
+                Segment::Key(name) => item.get(name.as_str())?,
+                Segment::Index(index) => item.get(*index)?,
+            };
+        }
+        let span = match key {
+            Segment::Key(name) => item.as_table_like()?.key(name)?.span()?,
+            Segment::Index(_) => return None,
+        };
+
+        let before = text.get(..span.start)?;
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        Some((
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        ))
+    }
+}
+
+/// Serializes maps sorted by key, so that rendered configurations do
+/// not depend on the order of a `HashMap`.
+mod sorted_map {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{Serialize, Serializer};
+
+    pub(super) fn serialize<S>(
+        map: &HashMap<String, String>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+    }
+}
+
+/// Serde helpers for optional durations (see [`duration`]).
+mod option_duration {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(with = "super::duration")] Duration);
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<Wrapper>::deserialize(deserializer).map(|duration| duration.map(|Wrapper(d)| d))
+    }
+
+    pub(super) fn serialize<S>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match duration {
+            Some(duration) => super::duration::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Deserializes either a single command, or a list of commands. Note
+/// that an array of strings is a single command (the program and its
+/// arguments); lists of commands must use arrays or tables for each
+/// command.
+mod command_list {
+    use std::fmt;
+
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{
+        CommandConfig, CommandLine, CommandLineConfig, CommandLineConfigVisitor,
+        DetailedCommandLine,
+    };
+
+    /// Converts the deserialized command, reporting invalid command
+    /// lines as deserialization errors.
+    fn command<E: de::Error>(config: CommandLineConfig) -> Result<CommandConfig, E> {
+        CommandConfig::try_from(config).map_err(E::custom)
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Vec<CommandConfig>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CommandListVisitor)
+    }
+
+    /// Element of an array of commands (its strings are arguments if the
+    /// array only contains strings, and command strings otherwise).
+    enum Element {
+        Arg(String),
+        Command(CommandConfig),
+    }
+
+    impl<'de> Deserialize<'de> for Element {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_any(ElementVisitor)
+        }
+    }
+
+    struct ElementVisitor;
+
+    impl<'de> de::Visitor<'de> for ElementVisitor {
+        type Value = Element;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "a string, an array of strings, or a table with a `command`"
+            )
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(Element::Arg(v.to_string()))
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            command(CommandLineConfigVisitor.visit_seq(seq)?).map(Element::Command)
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            command(CommandLineConfigVisitor.visit_map(map)?).map(Element::Command)
+        }
+    }
+
+    struct CommandListVisitor;
+
+    impl<'de> de::Visitor<'de> for CommandListVisitor {
+        type Value = Vec<CommandConfig>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "a command, or an array of commands")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(vec![command(CommandLineConfigVisitor.visit_str::<E>(v)?)?])
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            Ok(vec![command(CommandLineConfigVisitor.visit_map(map)?)?])
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut elements = Vec::new();
+            while let Some(element) = seq.next_element()? {
+                elements.push(element);
+            }
+
+            // An array of strings is a single command (split into
+            // arguments); otherwise, every element is a command.
+            if !elements.is_empty()
+                && elements
+                    .iter()
+                    .all(|element| matches!(element, Element::Arg(_)))
+            {
+                let args = elements
+                    .into_iter()
+                    .filter_map(|element| match element {
+                        Element::Arg(arg) => Some(arg),
+                        Element::Command(_) => None,
+                    })
+                    .collect();
+                return Ok(vec![command(CommandLineConfig::Simple(
+                    CommandLine::CommandVector(args),
+                ))?]);
+            }
+
+            elements
+                .into_iter()
+                .map(|element| match element {
+                    Element::Arg(line) => {
+                        command(CommandLineConfig::Simple(CommandLine::CommandString(line)))
+                    }
+                    Element::Command(command) => Ok(command),
+                })
+                .collect()
+        }
+    }
+
+    /// Serializes the commands as a list, in which every command uses
+    /// the same form (since TOML cannot mix command lines and tables).
+    pub(super) fn serialize<S>(commands: &[CommandConfig], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if commands.iter().all(CommandConfig::is_simple) {
+            commands.serialize(serializer)
+        } else {
+            commands
+                .iter()
+                .cloned()
+                .map(DetailedCommandLine::from)
+                .collect::<Vec<_>>()
+                .serialize(serializer)
+        }
+    }
+}
+
+/// Deserializes a map of signals to signals (TOML keys are always
+/// strings, which are parsed as signal names here).
+mod signal_map {
+    use std::collections::HashMap;
+
+    use serde::{de::IntoDeserializer, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::SignalConfig;
+
+    pub(super) fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<HashMap<SignalConfig, SignalConfig>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        HashMap::<String, SignalConfig>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(incoming, outgoing)| {
+                Ok((
+                    SignalConfig::deserialize(incoming.into_deserializer())?,
+                    outgoing,
+                ))
+            })
+            .collect()
+    }
+
+    pub(super) fn serialize<S>(
+        signals: &HashMap<SignalConfig, SignalConfig>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        signals.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct StopMechanismTest {
+        stop: StopMechanism,
+    }
+
+    #[test]
+    fn supports_signal_names_in_stop() {
+        let toml = r#"stop = "SIGTERM""#;
+        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            StopMechanism::Signal(SignalConfig(Signal::SIGTERM)),
+            decoded.stop
+        );
+
+        let toml = r#"stop = "SIGWINCH""#;
+        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            StopMechanism::Signal(SignalConfig(Signal::SIGWINCH)),
+            decoded.stop
+        );
+
+        let toml = r#"stop = 10"#;
+        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            StopMechanism::Signal(SignalConfig(Signal::SIGUSR1)),
+            decoded.stop
+        );
+
+        let toml = r#"stop = 1000"#;
+        assert!(toml::from_str::<StopMechanismTest>(toml).is_err());
+    }
+
+    #[test]
+    fn supports_stop_steps() {
+        let toml = r#"stop = [ { command = "/usr/sbin/nginx -s quit", timeout = "10s" }, { signal = "SIGTERM", timeout = "5s" } ]"#;
+        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            StopMechanism::Steps(vec![
+                StopStep::Command {
+                    command: CommandConfig {
+                        user: None,
+                        group: None,
+                        groups: None,
+                        program: "/usr/sbin/nginx".into(),
+                        args: vec!["-s".into(), "quit".into()],
+                        only_env: None,
+                        inherit_env: None,
+                        login_env: false,
+                        nice: None,
+                        io_class: None,
+                        io_priority: None,
+                        ignore_failure: false,
+                        env: HashMap::new(),
+                        env_from_file: HashMap::new(),
+                        env_file: None,
+                        root_directory: None,
+                        cpu_affinity: None,
+                        oom_score_adj: None,
+                        limits: None,
+                        no_new_privileges: false,
+                        clear_ambient_capabilities: false,
+                    },
+                    timeout: Some(Duration::from_secs(10)),
+                },
+                StopStep::Signal {
+                    signal: SignalConfig(Signal::SIGTERM),
+                    timeout: Some(Duration::from_secs(5)),
+                },
+            ]),
+            decoded.stop
+        );
+
+        // Arrays of strings are still a single command.
+        let toml = r#"stop = [ "/usr/sbin/nginx", "-s", "quit" ]"#;
+        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert!(matches!(decoded.stop, StopMechanism::Command(_)));
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct CommandConfigTest {
+        run: CommandConfig,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct CommandListTest {
+        #[serde(with = "command_list")]
+        pre: Vec<CommandConfig>,
+    }
+
+    #[test]
+    fn supports_command_lists() {
+        let command = |program: &str, args: &[&str], ignore_failure| CommandConfig {
+            user: None,
+            group: None,
+            groups: None,
+            only_env: None,
+            inherit_env: None,
+            login_env: false,
+            nice: None,
+            io_class: None,
+            io_priority: None,
+            program: String::from(program),
+            args: args.iter().map(|arg| String::from(*arg)).collect(),
+            ignore_failure,
+            env: HashMap::new(),
+            env_from_file: HashMap::new(),
+            env_file: None,
+            root_directory: None,
+            cpu_affinity: None,
+            oom_score_adj: None,
+            limits: None,
+            no_new_privileges: false,
+            clear_ambient_capabilities: false,
+        };
+
+        let toml = r#"pre = ["/app/run-me.sh", "using", "args"]"#;
+        let decoded: CommandListTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            vec![command("/app/run-me.sh", &["using", "args"], false)],
+            decoded.pre
+        );
+
+        let toml = r#"pre = [["/app/first.sh", "arg"], "/app/second.sh", { command = "/app/third.sh", ignore-failure = true }]"#;
+        let decoded: CommandListTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            vec![
+                command("/app/first.sh", &["arg"], false),
+                command("/app/second.sh", &[], false),
+                command("/app/third.sh", &[], true),
+            ],
+            decoded.pre
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct DependencyTest {
+        depends_on: Vec<DependencyConfig>,
+    }
+
+    #[test]
+    fn supports_dependency_propagation() {
+        let dependency = |name: &str, propagate| DependencyConfig {
+            name: String::from(name),
+            propagate,
+        };
+
+        let toml = r#"depends_on = ["db", { name = "cache", propagate = "restart" }, { name = "queue", propagate = "stop" }, { name = "metrics" }]"#;
+        let decoded: DependencyTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            vec![
+                dependency("db", PropagationPolicy::Ignore),
+                dependency("cache", PropagationPolicy::Restart),
+                dependency("queue", PropagationPolicy::Stop),
+                dependency("metrics", PropagationPolicy::Ignore),
+            ],
+            decoded.depends_on
+        );
+    }
+
+    #[test]
+    fn expands_replicas() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            wants = [{ name = "worker", propagate = "restart" }]
+            after = ["db", "worker"]
+
+            [[processes]]
+            name = "worker"
+            replicas = 2
+            run = "/app/worker"
+            "#;
+        let decoded: Config = toml::from_str(toml).expect("Failed to parse test TOML");
+
+        let names: Vec<&str> = decoded
+            .processes
+            .iter()
+            .map(|process| process.name.as_str())
+            .collect();
+        assert_eq!(vec!["app", "worker-0", "worker-1"], names);
+
+        let app = &decoded.processes[0];
+        assert_eq!(
+            vec![
+                DependencyConfig {
+                    name: String::from("worker-0"),
+                    propagate: PropagationPolicy::Restart,
+                },
+                DependencyConfig {
+                    name: String::from("worker-1"),
+                    propagate: PropagationPolicy::Restart,
+                },
+            ],
+            app.wants
+        );
+        assert_eq!(vec!["db", "worker-0", "worker-1"], app.after);
+
+        let worker = &decoded.processes[2];
+        assert_eq!(Some(1), worker.instance);
+        assert_eq!(
+            Some("1"),
+            worker
+                .run
+                .as_ref()
+                .and_then(|run| run.env.get("GC_INSTANCE"))
+                .map(String::as_str)
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct PlatformTest {
+        only_on: Vec<PlatformConfig>,
+    }
+
+    #[test]
+    fn supports_platforms() {
+        let toml = r#"only_on = [ "linux", "linux/arm64", "darwin/amd64" ]"#;
+        let decoded: PlatformTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            vec!["linux", "linux/arm64", "darwin/amd64"],
+            decoded
+                .only_on
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+
+        let current = PlatformConfig::current();
+        assert!(current.is_current());
+        assert!(PlatformConfig::try_from(current.os.clone())
+            .unwrap()
+            .is_current());
+
+        let error = toml::from_str::<PlatformTest>(r#"only_on = [ "linux/x86_64" ]"#).unwrap_err();
+        assert!(error
+            .message()
+            .starts_with("unknown architecture \"x86_64\" in platform \"linux/x86_64\""));
+        assert!(toml::from_str::<PlatformTest>(r#"only_on = [ "windows" ]"#).is_err());
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct ConditionTest {
+        condition: ConditionConfig,
+    }
+
+    #[test]
+    fn supports_conditions() {
+        let toml = r#"condition = { env = "ENABLE_WORKER" }"#;
+        let decoded: ConditionTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ConditionConfig::Env(String::from("ENABLE_WORKER")),
+            decoded.condition
+        );
+
+        let toml = r#"condition = { command = "/bin/check" }"#;
+        let decoded: ConditionTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ConditionConfig::Command(CommandConfig {
+                user: None,
+                group: None,
+                groups: None,
+                only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
+                program: String::from("/bin/check"),
+                args: vec![],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
+            }),
+            decoded.condition
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct RestartPolicyTest {
+        #[serde(default)]
+        restart: RestartPolicy,
+    }
+
+    #[test]
+    fn supports_restart_policies() {
+        let decoded: RestartPolicyTest = toml::from_str("").expect("Failed to parse test TOML");
+        assert_eq!(RestartPolicy::Never, decoded.restart);
+
+        let toml = r#"restart = "on-failure""#;
+        let decoded: RestartPolicyTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(RestartPolicy::OnFailure, decoded.restart);
+
+        let toml = r#"restart = "always""#;
+        let decoded: RestartPolicyTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(RestartPolicy::Always, decoded.restart);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(Ok(Duration::from_millis(500)), duration::parse("500ms"));
+        assert_eq!(Ok(Duration::from_secs(30)), duration::parse("30s"));
+        assert_eq!(Ok(Duration::from_secs(90)), duration::parse("1m30s"));
+        assert_eq!(Ok(Duration::from_secs(7200)), duration::parse("2h"));
+        assert!(duration::parse("").is_err());
+        assert!(duration::parse("30").is_err());
+        assert!(duration::parse("5 minutes").is_err());
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!("0s", duration::format(Duration::ZERO));
+        assert_eq!("500ms", duration::format(Duration::from_millis(500)));
+        assert_eq!("1m30s", duration::format(Duration::from_secs(90)));
+        assert_eq!("2h1s", duration::format(Duration::from_secs(7201)));
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct ReloadMechanismTest {
+        reload: ReloadMechanism,
+    }
+
+    #[test]
+    fn supports_signals_and_commands_in_reload() {
+        let toml = r#"reload = "SIGHUP""#;
+        let decoded: ReloadMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ReloadMechanism::Signal(SignalConfig(Signal::SIGHUP)),
+            decoded.reload
+        );
+
+        let toml = r#"reload = "/usr/sbin/nginx -s reload""#;
+        let decoded: ReloadMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ReloadMechanism::Command(CommandConfig {
+                user: None,
+                group: None,
+                groups: None,
+                program: "/usr/sbin/nginx".into(),
+                args: vec!["-s".into(), "reload".into()],
+                only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
+            }),
+            decoded.reload
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "kebab-case")]
+    struct SignalMapTest {
+        #[serde(with = "signal_map")]
+        signal_map: HashMap<SignalConfig, SignalConfig>,
+    }
+
+    #[test]
+    fn supports_signal_maps() {
+        let toml = r#"signal-map = { SIGTERM = "SIGQUIT", 1 = "SIGUSR1" }"#;
+        let decoded: SignalMapTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            HashMap::from([
+                (SignalConfig(Signal::SIGTERM), SignalConfig(Signal::SIGQUIT)),
+                (SignalConfig(Signal::SIGHUP), SignalConfig(Signal::SIGUSR1)),
+            ]),
+            decoded.signal_map
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct ProcessTypeTest {
+        #[serde(rename = "type")]
+        process_type: ProcessType,
+    }
+
+    #[test]
+    fn supports_process_types() {
+        for (toml, process_type) in [
+            (r#"type = "simple""#, ProcessType::Simple),
+            (r#"type = "daemon""#, ProcessType::Simple),
+            (r#"type = "forking""#, ProcessType::Forking),
+            (r#"type = "oneshot""#, ProcessType::Oneshot),
+            (r#"type = "one-shot""#, ProcessType::Oneshot),
+        ] {
+            let decoded: ProcessTypeTest = toml::from_str(toml).expect("Failed to parse test TOML");
+            assert_eq!(process_type, decoded.process_type);
+        }
+
+        let toml = r#"type = "notify""#;
+        assert!(toml::from_str::<ProcessTypeTest>(toml).is_err());
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "kebab-case")]
+    struct BackoffConfigTest {
+        restart_backoff: BackoffConfig,
+    }
+
+    #[test]
+    fn supports_restart_backoff() {
+        let toml = r#"restart-backoff = { initial-delay = "500ms", max-delay = 10 }"#;
+        let decoded: BackoffConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            BackoffConfig {
+                initial_delay: Duration::from_millis(500),
+                multiplier: 2.0,
+                max_delay: Duration::from_secs(10),
+            },
+            decoded.restart_backoff
+        );
+
+        let backoff = decoded.restart_backoff;
+        assert_eq!(
+            Duration::from_secs(1),
+            backoff.next_delay(backoff.initial_delay)
+        );
+        assert_eq!(
+            Duration::from_secs(10),
+            backoff.next_delay(Duration::from_secs(8))
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct HealthCheckConfigTest {
+        healthcheck: HealthCheckConfig,
+    }
+
+    #[test]
+    fn supports_healthchecks() {
+        let toml = r#"healthcheck = { command = "/app/check.sh" }"#;
+        let decoded: HealthCheckConfigTest =
+            toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            HealthCheckConfig {
+                command: CommandConfig {
+                    user: None,
+                    group: None,
+                    groups: None,
+                    only_env: None,
+                    inherit_env: None,
+                    login_env: false,
+                    nice: None,
+                    io_class: None,
+                    io_priority: None,
+                    program: String::from("/app/check.sh"),
+                    args: vec![],
+                    ignore_failure: false,
+                    env: HashMap::new(),
+                    env_from_file: HashMap::new(),
+                    env_file: None,
+                    root_directory: None,
+                    cpu_affinity: None,
+                    oom_score_adj: None,
+                    limits: None,
+                    no_new_privileges: false,
+                    clear_ambient_capabilities: false,
+                },
+                interval: Duration::from_secs(10),
+                initial_delay: Duration::ZERO,
+                timeout: None,
+                success_threshold: 1,
+                failure_threshold: 3,
+                action: ProbeAction::Restart,
+            },
+            decoded.healthcheck
+        );
+
+        let toml = r#"healthcheck = { command = { user = "app", command = "/app/check.sh" }, interval = "1s", failure-threshold = 1, action = "shutdown" }"#;
+        let decoded: HealthCheckConfigTest =
+            toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            HealthCheckConfig {
+                command: CommandConfig {
+                    user: Some(String::from("app")),
+                    group: None,
+                    groups: None,
+                    only_env: None,
+                    inherit_env: None,
+                    login_env: false,
+                    nice: None,
+                    io_class: None,
+                    io_priority: None,
+                    program: String::from("/app/check.sh"),
+                    args: vec![],
+                    ignore_failure: false,
+                    env: HashMap::new(),
+                    env_from_file: HashMap::new(),
+                    env_file: None,
+                    root_directory: None,
+                    cpu_affinity: None,
+                    oom_score_adj: None,
+                    limits: None,
+                    no_new_privileges: false,
+                    clear_ambient_capabilities: false,
+                },
+                interval: Duration::from_secs(1),
+                initial_delay: Duration::ZERO,
+                timeout: None,
+                success_threshold: 1,
+                failure_threshold: 1,
+                action: ProbeAction::Shutdown,
+            },
+            decoded.healthcheck
+        );
+
+        // Kubernetes-style probe settings.
+        let toml = r#"healthcheck = { command = "/app/check.sh", initial-delay = "5s", period = "2s", timeout = "1s", success-threshold = 2, failure-threshold = 4 }"#;
+        let decoded: HealthCheckConfigTest =
+            toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(Duration::from_secs(2), decoded.healthcheck.interval);
+        assert_eq!(Duration::from_secs(5), decoded.healthcheck.initial_delay);
+        assert_eq!(Some(Duration::from_secs(1)), decoded.healthcheck.timeout);
+        assert_eq!(2, decoded.healthcheck.success_threshold);
+        assert_eq!(4, decoded.healthcheck.failure_threshold);
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct WatchdogConfigTest {
+        watchdog: WatchdogConfig,
+    }
+
+    #[test]
+    fn supports_watchdogs() {
+        let toml = r#"watchdog = { path = "/run/app/heartbeat", timeout = "30s" }"#;
+        let decoded: WatchdogConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            WatchdogConfig {
+                path: String::from("/run/app/heartbeat"),
+                timeout: Duration::from_secs(30),
+                initial_delay: Duration::ZERO,
+                failure_threshold: 1,
+                action: ProbeAction::Restart,
+            },
+            decoded.watchdog
+        );
+
+        let toml =
+            r#"watchdog = { path = "/run/app/heartbeat", timeout = "30s", action = "log-only" }"#;
+        let decoded: WatchdogConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(ProbeAction::LogOnly, decoded.watchdog.action);
+
+        let toml = r#"watchdog = { path = "/run/app/heartbeat" }"#;
+        assert!(toml::from_str::<WatchdogConfigTest>(toml).is_err());
+
+        let toml = r#"watchdog = { path = "/run/app/heartbeat", timeout = "30s", period = "5s" }"#;
+        let error = toml::from_str::<WatchdogConfigTest>(toml).unwrap_err();
+        assert!(error.to_string().contains("unknown field `period`"));
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct ReadinessCheckTest {
+        ready: ReadinessCheck,
+    }
+
+    #[test]
+    fn supports_readiness_checks() {
+        let toml = r#"ready = "running""#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(ReadinessCheck::Running, decoded.ready);
+
+        let toml = r#"ready = "healthy""#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(ReadinessCheck::Healthy, decoded.ready);
+
+        let toml = r#"ready = "notify""#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(ReadinessCheck::Notify, decoded.ready);
+
+        let toml = r#"ready = { file = "/run/app/ready" }"#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ReadinessCheck::File(String::from("/run/app/ready")),
+            decoded.ready
+        );
+
+        let toml = r#"ready = { command = "/app/ping", interval = "250ms", retries = 4 }"#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ReadinessCheck::Command(ReadinessCommandConfig {
+                command: CommandConfig {
+                    user: None,
+                    group: None,
+                    groups: None,
+                    only_env: None,
+                    inherit_env: None,
+                    login_env: false,
+                    nice: None,
+                    io_class: None,
+                    io_priority: None,
+                    program: String::from("/app/ping"),
+                    args: vec![],
+                    ignore_failure: false,
+                    env: HashMap::new(),
+                    env_from_file: HashMap::new(),
+                    env_file: None,
+                    root_directory: None,
+                    cpu_affinity: None,
+                    oom_score_adj: None,
+                    limits: None,
+                    no_new_privileges: false,
+                    clear_ambient_capabilities: false,
+                },
+                interval: Duration::from_millis(250),
+                initial_delay: Duration::ZERO,
+                timeout: None,
+                success_threshold: 1,
+                retries: 4,
+            }),
+            decoded.ready
+        );
+
+        let toml = r#"ready = { command = "/app/ping", period = "2s", failure-threshold = 5 }"#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        match decoded.ready {
+            ReadinessCheck::Command(ready) => {
+                assert_eq!(Duration::from_secs(2), ready.interval);
+                assert_eq!(5, ready.retries);
+            }
+            ready => panic!("Unexpected readiness check {ready:?}"),
+        }
+
+        let toml =
+            r#"ready = { http = "http://127.0.0.1:8080/healthz", status = 204, timeout = "2s" }"#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ReadinessCheck::Http(ReadinessHttpConfig {
+                url: String::from("http://127.0.0.1:8080/healthz"),
+                status: 204,
+                interval: Duration::from_secs(1),
+                initial_delay: Duration::ZERO,
+                timeout: Duration::from_secs(2),
+                success_threshold: 1,
+                failure_threshold: 30,
+            }),
+            decoded.ready
+        );
 
-    use super::*;
+        let toml = r#"ready = { log-line = "Listening on port \\d+" }"#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ReadinessCheck::LogLine(String::from(r"Listening on port \d+")),
+            decoded.ready
+        );
 
-    #[derive(Debug, Deserialize, PartialEq)]
-    struct StopMechanismTest {
-        stop: StopMechanism,
-    }
+        let toml = r#"ready = { path = "/run/app.sock", timeout = "5s" }"#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ReadinessCheck::Path(ReadinessPathConfig {
+                path: String::from("/run/app.sock"),
+                interval: Duration::from_millis(100),
+                initial_delay: Duration::ZERO,
+                timeout: Duration::from_secs(5),
+                success_threshold: 1,
+                failure_threshold: None,
+            }),
+            decoded.ready
+        );
 
-    #[test]
-    fn supports_signal_names_in_stop() {
-        let toml = r#"stop = "SIGTERM""#;
-        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
-        assert_eq!(StopMechanism::Signal(SignalConfig::SIGTERM), decoded.stop);
-    }
+        let toml = r#"ready = { path = "/run/app.sock", period = "1s", initial-delay = "2s", success-threshold = 2, failure-threshold = 10 }"#;
+        let decoded: ReadinessCheckTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            ReadinessCheck::Path(ReadinessPathConfig {
+                path: String::from("/run/app.sock"),
+                interval: Duration::from_secs(1),
+                initial_delay: Duration::from_secs(2),
+                timeout: Duration::from_secs(30),
+                success_threshold: 2,
+                failure_threshold: Some(10),
+            }),
+            decoded.ready
+        );
 
-    #[derive(Debug, Deserialize, PartialEq)]
-    struct CommandConfigTest {
-        run: CommandConfig,
+        let toml = r#"ready = "spawned""#;
+        assert!(toml::from_str::<ReadinessCheckTest>(toml).is_err());
+
+        let toml = r#"ready = { file = "/run/app/ready", retries = 4 }"#;
+        assert!(toml::from_str::<ReadinessCheckTest>(toml).is_err());
     }
 
     #[test]
@@ -241,16 +4162,123 @@ mod tests {
         assert_eq!(
             CommandConfig {
                 user: None,
+                group: None,
+                groups: None,
                 only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
                 program: String::from("/app/run-me.sh"),
                 args: vec![
                     String::from("using"),
                     String::from("these"),
                     String::from("args"),
-                ]
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
+            },
+            decoded.run
+        );
+    }
+
+    #[test]
+    fn supports_quoted_command_lines() {
+        let toml = r#"run = "/app/run-me.sh 'using these' \"args too\" with\\ escapes""#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: None,
+                group: None,
+                groups: None,
+                only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
+                program: String::from("/app/run-me.sh"),
+                args: vec![
+                    String::from("using these"),
+                    String::from("args too"),
+                    String::from("with escapes"),
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
+            },
+            decoded.run
+        );
+
+        let toml = r#"run = "/app/run-me.sh 'unterminated""#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!(
+            indoc::indoc! {r#"
+                TOML parse error at line 1, column 7
+                  |
+                1 | run = "/app/run-me.sh 'unterminated"
+                  |       ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+                invalid command string `/app/run-me.sh 'unterminated` (missing closing quote)
+            "#},
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn supports_shell_commands() {
+        let toml = r#"run = { shell = true, command = "/app/run-me.sh | tee \"$LOG_FILE\"" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: None,
+                group: None,
+                groups: None,
+                only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
+                program: String::from("/bin/sh"),
+                args: vec![
+                    String::from("-c"),
+                    String::from("/app/run-me.sh | tee \"$LOG_FILE\""),
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
             },
             decoded.run
         );
+
+        let toml = r#"run = { shell = true, command = ["/app/run-me.sh", "args"] }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("a `shell` command must be a string"));
     }
 
     #[test]
@@ -260,13 +4288,30 @@ mod tests {
         assert_eq!(
             CommandConfig {
                 user: None,
+                group: None,
+                groups: None,
                 only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
                 program: String::from("/app/run-me.sh"),
                 args: vec![
                     String::from("using"),
                     String::from("these"),
                     String::from("args"),
-                ]
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
             },
             decoded.run
         );
@@ -279,13 +4324,30 @@ mod tests {
         assert_eq!(
             CommandConfig {
                 user: None,
+                group: None,
+                groups: None,
                 only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
                 program: String::from("/app/run-me.sh"),
                 args: vec![
                     String::from("using"),
                     String::from("these"),
                     String::from("args"),
-                ]
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
             },
             decoded.run
         );
@@ -295,13 +4357,30 @@ mod tests {
         assert_eq!(
             CommandConfig {
                 user: Some(String::from("app")),
+                group: None,
+                groups: None,
                 only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
                 program: String::from("/app/run-me.sh"),
                 args: vec![
                     String::from("using"),
                     String::from("these"),
                     String::from("args"),
-                ]
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
             },
             decoded.run
         );
@@ -314,13 +4393,30 @@ mod tests {
         assert_eq!(
             CommandConfig {
                 user: None,
+                group: None,
+                groups: None,
                 only_env: None,
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
                 program: String::from("/app/run-me.sh"),
                 args: vec![
                     String::from("using"),
                     String::from("these"),
                     String::from("args"),
-                ]
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
             },
             decoded.run
         );
@@ -330,13 +4426,30 @@ mod tests {
         assert_eq!(
             CommandConfig {
                 user: Some(String::from("app")),
+                group: None,
+                groups: None,
                 only_env: Some(HashSet::new()),
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
                 program: String::from("/app/run-me.sh"),
                 args: vec![
                     String::from("using"),
                     String::from("these"),
                     String::from("args"),
-                ]
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
             },
             decoded.run
         );
@@ -346,26 +4459,831 @@ mod tests {
         assert_eq!(
             CommandConfig {
                 user: Some(String::from("app")),
+                group: None,
+                groups: None,
                 only_env: Some(HashSet::from(["USER".into(), "HOME".into()])),
+                inherit_env: None,
+                login_env: false,
+                nice: None,
+                io_class: None,
+                io_priority: None,
                 program: String::from("/app/run-me.sh"),
                 args: vec![
                     String::from("using"),
                     String::from("these"),
                     String::from("args"),
-                ]
+                ],
+                ignore_failure: false,
+                env: HashMap::new(),
+                env_from_file: HashMap::new(),
+                env_file: None,
+                root_directory: None,
+                cpu_affinity: None,
+                oom_score_adj: None,
+                limits: None,
+                no_new_privileges: false,
+                clear_ambient_capabilities: false,
             },
             decoded.run
         );
     }
 
+    #[test]
+    fn supports_inherit_env() {
+        let toml = r#"run = { inherit-env = "none", command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(Some(InheritEnv::None), decoded.run.inherit_env);
+
+        let toml = r#"run = { inherit-env = "listed", only-env = ["RUST_*"], command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(Some(InheritEnv::Listed), decoded.run.inherit_env);
+
+        let toml =
+            r#"run = { inherit-env = "all", only-env = ["RUST_*"], command = "/app/run-me.sh" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("`only-env` can only be used with `inherit-env = \"listed\"`"));
+    }
+
+    #[test]
+    fn supports_env_from_file() {
+        let toml = r#"run = { env-from-file = { DB_PASSWORD = "/run/secrets/db_password" }, command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            HashMap::from([(
+                String::from("DB_PASSWORD"),
+                String::from("/run/secrets/db_password")
+            )]),
+            decoded.run.env_from_file
+        );
+
+        let toml = r#"run = { env = { DB_PASSWORD = "" }, env-from-file = { DB_PASSWORD = "/run/secrets/db_password" }, command = "/app/run-me.sh" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("`DB_PASSWORD` is set by both `env` and `env-from-file`"));
+    }
+
+    #[test]
+    fn supports_groups() {
+        let toml = r#"run = { user = "app:staff", command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!((Some("app"), Some("staff")), decoded.run.user_and_group());
+
+        let toml = r#"run = { user = "app", group = "staff", command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!((Some("app"), Some("staff")), decoded.run.user_and_group());
+
+        let toml = r#"run = { group = "staff", command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!((None, Some("staff")), decoded.run.user_and_group());
+
+        let toml =
+            r#"run = { user = "app", groups = ["audio", "video"], command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            Some(vec![String::from("audio"), String::from("video")]),
+            decoded.run.groups
+        );
+
+        let toml = r#"run = { user = "app:staff", group = "wheel", command = "/app/run-me.sh" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("`user` includes a group, but `group` is also set"));
+    }
+
+    #[test]
+    fn supports_nice() {
+        let toml = r#"run = { nice = 10, command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(Some(10), decoded.run.nice);
+
+        let toml = r#"run = { nice = 20, command = "/app/run-me.sh" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("`nice` must be between -20 and 19, not 20"));
+    }
+
+    #[test]
+    fn supports_io_priority() {
+        let toml =
+            r#"run = { io-class = "best-effort", io-priority = 7, command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(Some(IoClass::BestEffort), decoded.run.io_class);
+        assert_eq!(Some(7), decoded.run.io_priority);
+
+        let toml = r#"run = { io-priority = 8, command = "/app/run-me.sh" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("`io-priority` must be between 0 and 7, not 8"));
+
+        let toml = r#"run = { io-class = "idle", io-priority = 7, command = "/app/run-me.sh" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("`io-priority` cannot be used with `io-class = \"idle\"`"));
+    }
+
     #[test]
     fn requires_command_in_detailed_command() {
         let toml = r#"run = { }"#;
         let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
-        assert_eq!("data did not match any variant of untagged enum CommandLineConfig for key `run` at line 1 column 1", error.to_string(),);
+        assert_eq!(
+            indoc::indoc! {r#"
+                TOML parse error at line 1, column 7
+                  |
+                1 | run = { }
+                  |       ^^^
+                missing field `command`
+            "#},
+            error.to_string()
+        );
 
         let toml = r#"run = { user = "app" }"#;
         let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
-        assert_eq!("data did not match any variant of untagged enum CommandLineConfig for key `run` at line 1 column 1", error.to_string(),);
+        assert_eq!(
+            indoc::indoc! {r#"
+                TOML parse error at line 1, column 7
+                  |
+                1 | run = { user = "app" }
+                  |       ^^^^^^^^^^^^^^^^
+                missing field `command`
+            "#},
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn reports_the_location_of_errors_in_command_lists() {
+        let toml = indoc::indoc! {r#"
+            [[processes]]
+            name = "app"
+            pre = [ "/bin/true", { command = "/bin/false", ignore-failure = "yes" } ]
+        "#};
+        let error = Config::parse(toml, ConfigFormat::Toml).unwrap_err();
+        assert_eq!(
+            indoc::indoc! {r#"
+                TOML parse error at line 3, column 65
+                  |
+                3 | pre = [ "/bin/true", { command = "/bin/false", ignore-failure = "yes" } ]
+                  |                                                                 ^^^^^
+                invalid type: string "yes", expected a boolean
+            "#},
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys_unless_not_strict() {
+        let toml = indoc::indoc! {r#"
+            [[processes]]
+            name = "app"
+            runn = "/app/server"
+            pre = [ "/bin/true", { command = "/bin/false", ignore-falure = true } ]
+        "#};
+        let error = Config::parse(toml, ConfigFormat::Toml).unwrap_err();
+        assert_eq!(
+            "Unknown config keys `processes[0].runn` at line 3, column 1, `processes[0].pre[1].ignore-falure` at line 4, column 48 (set `strict = false` to ignore unknown keys)",
+            error.to_string()
+        );
+
+        let json = r#"{ "processes": [ { "name": "app", "run": "/app/server", "nice": 10 } ] }"#;
+        let error = Config::parse(json, ConfigFormat::Json).unwrap_err();
+        assert_eq!(
+            "Unknown config key `processes[0].nice` (set `strict = false` to ignore unknown keys)",
+            error.to_string()
+        );
+
+        let toml = format!("strict = false\n{toml}");
+        let mut config = Config::parse(&toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            vec![
+                "`processes[0].runn` at line 4, column 1",
+                "`processes[0].pre[1].ignore-falure` at line 5, column 48"
+            ],
+            config.unknown_keys
+        );
+        assert!(!config.processes[0].pre[1].ignore_failure);
+
+        config.strict = true;
+        assert!(config.check_unknown_keys().is_err());
+    }
+
+    #[test]
+    fn supports_json() {
+        let toml = r#"
+            shutdown-timeout = "30s"
+
+            [[processes]]
+            name = "app"
+            pre = "/app/migrate"
+            run = [ "/app/server", "--port", "8080" ]
+            stop = [ { signal = "SIGQUIT", timeout = 10 }, { signal = 15 } ]
+            signal-map = { SIGHUP = 10 }
+            replicas = 2
+            "#;
+        let json = r#"{
+            "shutdown-timeout": "30s",
+            "processes": [
+                {
+                    "name": "app",
+                    "pre": "/app/migrate",
+                    "run": [ "/app/server", "--port", "8080" ],
+                    "stop": [ { "signal": "SIGQUIT", "timeout": 10 }, { "signal": 15 } ],
+                    "signal-map": { "SIGHUP": 10 },
+                    "replicas": 2
+                }
+            ]
+        }"#;
+
+        let from_toml = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        let from_json = Config::parse(json, ConfigFormat::Json).unwrap();
+        assert_eq!(from_toml.shutdown_timeout, from_json.shutdown_timeout);
+        assert_eq!(2, from_json.processes.len());
+        for (from_toml, from_json) in from_toml.processes.iter().zip(&from_json.processes) {
+            assert_eq!(from_toml.name, from_json.name);
+            assert_eq!(from_toml.pre, from_json.pre);
+            assert_eq!(from_toml.run, from_json.run);
+            assert_eq!(from_toml.stop, from_json.stop);
+            assert_eq!(from_toml.signal_map, from_json.signal_map);
+        }
+
+        assert_eq!(ConfigFormat::Json, ConfigFormat::from_path("gc.json"));
+        assert_eq!(ConfigFormat::Toml, ConfigFormat::from_path("gc.toml"));
+    }
+
+    #[test]
+    fn applies_overrides() {
+        let toml = r#"
+            [env]
+            RUST_LOG = "info"
+
+            [[processes]]
+            name = "web-app"
+            run = "/app/web"
+
+            [[processes]]
+            name = "worker"
+            run = "/app/worker"
+            stop-timeout = "10s"
+            "#;
+        let overrides = [
+            ("GC__ENV__RUST_LOG", "debug"),
+            ("GC__ENV__Proxy_Url", "http://proxy"),
+            ("GC__SHUTDOWN_TIMEOUT", "1m"),
+            ("GC__PROCESSES__WEB_APP__RUN", "/app/web --port 8080"),
+            ("GC__PROCESSES__1__STOP_TIMEOUT", "30"),
+            ("GC__PROCESSES__worker__CRITICAL", "true"),
+            ("GC__DEFAULTS__USER", "app"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let config = Config::parse_with_overrides(toml, ConfigFormat::Toml, &overrides).unwrap();
+
+        assert_eq!(
+            HashMap::from([
+                ("RUST_LOG".to_string(), "debug".to_string()),
+                ("Proxy_Url".to_string(), "http://proxy".to_string()),
+            ]),
+            config.env
+        );
+        assert_eq!(Some(Duration::from_secs(60)), config.shutdown_timeout);
+        assert_eq!(Some("app"), config.defaults.user.as_deref());
+
+        let web = config.processes[0].run.as_ref().unwrap();
+        assert_eq!(vec!["--port", "8080"], web.args);
+
+        let worker = &config.processes[1];
+        assert_eq!(Some(Duration::from_secs(30)), worker.stop_timeout);
+        assert!(worker.critical);
+
+        let overrides = [("GC__PROCESSES__db__RUN".to_string(), "/db".to_string())];
+        assert_eq!(
+            "Override `GC__PROCESSES__db__RUN` does not match the configuration (at `db`)",
+            Config::parse_with_overrides(toml, ConfigFormat::Toml, &overrides)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn merges_configs() {
+        let base = r#"
+            shutdown-timeout = "30s"
+            env = { REGION = "us", TIER = "dev" }
+
+            [[processes]]
+            name = "db"
+            run = "/usr/bin/db"
+            "#;
+        let app = r#"
+            shutdown-timeout = "1m"
+            env = { TIER = "prod" }
+
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            depends-on = [ "db" ]
+            "#;
+
+        let config = Config::merge([base, app], ConfigFormat::Toml).unwrap();
+        assert_eq!(Some(Duration::from_secs(60)), config.shutdown_timeout);
+        assert_eq!(
+            HashMap::from([
+                ("REGION".to_string(), "us".to_string()),
+                ("TIER".to_string(), "prod".to_string()),
+            ]),
+            config.env
+        );
+        assert_eq!(
+            vec!["db", "app"],
+            config
+                .processes
+                .iter()
+                .map(|process| process.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        config.validate().unwrap();
+
+        let overlay = r#"
+            [[processes]]
+            name = "db"
+            run = "/usr/bin/db --replica"
+
+            [[processes]]
+            name = "cache"
+            run = "/usr/bin/cache"
+            "#;
+        let config = Config::merge([base, app, overlay], ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            vec!["db", "app", "cache"],
+            config
+                .processes
+                .iter()
+                .map(|process| process.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        let db = config.processes[0].run.as_ref().unwrap();
+        assert_eq!(vec!["--replica"], db.args);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn loads_overlays() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let base = dir.path().join("base.toml");
+        std::fs::write(
+            &base,
+            r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            "#,
+        )
+        .unwrap();
+        let overlay = dir.path().join("overlay.json");
+        std::fs::write(
+            &overlay,
+            r#"{ "processes": [ { "name": "app", "run": "/app/server --debug" } ] }"#,
+        )
+        .unwrap();
+
+        let config =
+            Config::load_all([base.to_str().unwrap(), overlay.to_str().unwrap()], None).unwrap();
+        assert_eq!(1, config.processes.len());
+        let app = config.processes[0].run.as_ref().unwrap();
+        assert_eq!(vec!["--debug"], app.args);
+    }
+
+    #[test]
+    fn applies_defaults() {
+        let toml = r#"
+            [defaults]
+            user = "app"
+            group = "staff"
+            only-env = [ "DATABASE_URL" ]
+            stop-timeout = "5s"
+            restart = "on-failure"
+
+            [[processes]]
+            name = "init"
+            pre = "/app/migrate"
+
+            [[processes]]
+            name = "web"
+            pre = { user = "root", command = "/bin/mkdir -p /run/web" }
+            run = { only-env = [], command = "/app/web" }
+            stop-timeout = "30s"
+
+            [[processes]]
+            name = "worker"
+            run = "/app/worker"
+            restart = "always"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        config.validate().unwrap();
+
+        let (init, web, worker) = (
+            &config.processes[0],
+            &config.processes[1],
+            &config.processes[2],
+        );
+        assert_eq!(Some("app"), init.pre[0].user.as_deref());
+        assert_eq!(Some("staff"), init.pre[0].group.as_deref());
+        assert_eq!(None, init.stop_timeout);
+        assert_eq!(None, init.restart);
+
+        assert_eq!(Some("root"), web.pre[0].user.as_deref());
+        assert_eq!(None, web.pre[0].group.as_deref());
+        assert_eq!(
+            Some(&HashSet::from(["DATABASE_URL".to_string()])),
+            web.pre[0].only_env.as_ref()
+        );
+        let run = web.run.as_ref().unwrap();
+        assert_eq!(Some("app"), run.user.as_deref());
+        assert_eq!(Some(&HashSet::new()), run.only_env.as_ref());
+        assert_eq!(Some(Duration::from_secs(30)), web.stop_timeout);
+        assert_eq!(Some(RestartPolicy::OnFailure), web.restart);
+
+        assert_eq!(Some(Duration::from_secs(5)), worker.stop_timeout);
+        assert_eq!(Some(RestartPolicy::Always), worker.restart);
+    }
+
+    #[test]
+    fn rejects_duplicate_process_names() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            pre = "/bin/true"
+
+            [[processes]]
+            name = "app"
+            run = "/bin/sleep 1"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process name \"app\" is used by more than one process",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_processes_without_commands() {
+        let toml = r#"
+            [[processes]]
+            name = "nothing"
+            critical = false
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process \"nothing\" does not have any commands (it needs at least one `pre`, `run`, or `post` command)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        // Shutdown-only processes are fine.
+        let toml = r#"
+            [[processes]]
+            name = "final-backup"
+            post = "/app/backup --final"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_cpu_affinity() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            cpu-affinity = []
+            run = "/app/server"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process \"app\" has an empty `cpu-affinity` (it needs at least one CPU)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            cpu-affinity = [ 0, 1024 ]
+            run = "/app/server"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process \"app\" has CPU 1024 in its `cpu-affinity`, but CPUs are numbered from 0 to 1023",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            cpu-affinity = [ 0, 1 ]
+            pre = "/app/migrate"
+            run = "/app/server"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        config.validate().unwrap();
+        assert_eq!(Some(vec![0, 1]), config.processes[0].pre[0].cpu_affinity);
+    }
+
+    #[test]
+    fn rejects_invalid_oom_score_adj() {
+        let toml = r#"
+            [[processes]]
+            name = "sidecar"
+            oom-score-adj = 1001
+            run = "/app/sidecar"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process \"sidecar\" has an `oom-score-adj` of 1001, but it must be between -1000 and 1000",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn parses_limits() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            limits = { nofile = 65536, core = 0, stack = "unlimited", as = 1073741824 }
+            run = "/app/server"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            Some(LimitsConfig {
+                address_space: Some(Limit::Value(1073741824)),
+                core: Some(Limit::Value(0)),
+                nofile: Some(Limit::Value(65536)),
+                stack: Some(Limit::Unlimited),
+                ..LimitsConfig::default()
+            }),
+            config.processes[0].run.as_ref().unwrap().limits
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            limits = { nofile = "many" }
+            run = "/app/server"
+            "#;
+        let error = Config::parse(toml, ConfigFormat::Toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Invalid limit \"many\" (expected a number, or \"unlimited\")"));
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            limits = { open-files = 1024 }
+            run = "/app/server"
+            "#;
+        let error = Config::parse(toml, ConfigFormat::Toml).unwrap_err();
+        assert!(error.to_string().contains("unknown field `open-files`"));
+    }
+
+    #[test]
+    fn parses_cgroups() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            cgroup = { memory-max = "512M", cpu-max = 1.5 }
+            run = "/app/server"
+
+            [[processes]]
+            name = "worker"
+            cgroup = { memory-max = 1048576, cpu-max = "max" }
+            run = "/app/worker"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            Some(CgroupConfig {
+                memory_max: Some(MemoryMax::Bytes(512 * 1024 * 1024)),
+                cpu_max: Some(CpuMax::Quota(150_000)),
+            }),
+            config.processes[0].cgroup
+        );
+        assert_eq!(
+            Some(CgroupConfig {
+                memory_max: Some(MemoryMax::Bytes(1048576)),
+                cpu_max: Some(CpuMax::Max),
+            }),
+            config.processes[1].cgroup
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            cgroup = { memory-max = "lots" }
+            run = "/app/server"
+            "#;
+        let error = Config::parse(toml, ConfigFormat::Toml).unwrap_err();
+        assert!(error.to_string().contains(
+            "Invalid memory size \"lots\" (expected a number of bytes, with an optional K, M, G, or T suffix, or \"max\")"
+        ));
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            cgroup = { cpu-max = 0.001 }
+            run = "/app/server"
+            "#;
+        let error = Config::parse(toml, ConfigFormat::Toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Invalid CPU limit 0.001 (the limit must be at least 0.01 CPUs)"));
+    }
+
+    #[test]
+    fn instances_have_their_own_cgroups() {
+        let toml = r#"
+            [[processes]]
+            name = "worker"
+            replicas = 2
+            cgroup = { memory-max = "1G" }
+            run = "/app/worker"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+
+        // The cgroup of a process is named after the process, so every
+        // instance (which has a name of its own) has its own cgroup.
+        let instance = config.processes[0].instance(3);
+        assert_eq!("worker-1", config.processes[1].name);
+        assert_eq!("worker-0-3", instance.name);
+        assert_eq!(config.processes[1].cgroup, instance.cgroup);
+        assert!(instance.cgroup.is_some());
+    }
+
+    #[test]
+    fn rejects_private_tmp_with_root_directory() {
+        let toml = r#"
+            [[processes]]
+            name = "converter"
+            private-tmp = true
+            root-directory = "/srv/jail"
+            run = "/bin/convert-daemon"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process \"converter\" has both `private-tmp` and a `root-directory` (which would hide the temporary directory)",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_cgroups_with_slashes_in_names() {
+        let toml = r#"
+            [[processes]]
+            name = "app/server"
+            cgroup = { memory-max = "1G" }
+            run = "/app/server"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process \"app/server\" has a `cgroup`, but its name contains a `/` (which cgroup names cannot)",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_stop_without_run() {
+        let toml = r#"
+            [[processes]]
+            name = "init"
+            pre = "/bin/true"
+            stop = "SIGINT"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process \"init\" configures how it is stopped, but is not a daemon (only processes with a `run` command are stopped)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "init"
+            type = "oneshot"
+            run = "/bin/true"
+            stop-timeout = "5s"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            "Process \"init\" configures how it is stopped, but is not a daemon (only processes with a `run` command are stopped)",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn checks_users_and_programs() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            pre = "sh -c true"
+            run = "/bin/sleep 1"
+
+            [[processes]]
+            name = "missing"
+            profiles = [ "never" ]
+            run = "/does/not/exist"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        config.check().unwrap();
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            pre = [
+                { user = "no-such-user", groups = [ "no-such-group" ], command = "/bin/true" },
+                { user = "10001:10001", groups = [ "10002" ], command = "/bin/true" },
+                { user = "10001", command = "/bin/true" },
+            ]
+            run = "/does/not/exist"
+            stop = "/etc/passwd"
+            post = "no-such-program"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            indoc::indoc! {r#"
+                Found 6 problems:
+                  Process "app" runs a command as unknown user "no-such-user"
+                  Process "app" runs a command as unknown group "no-such-group"
+                  Process "app" runs a command as numeric user "10001" without a group
+                  Process "app" runs program "/does/not/exist", which does not exist
+                  Process "app" runs program "/etc/passwd", which is not executable
+                  Process "app" runs program "no-such-program", which was not found in the `PATH`"#},
+            config.check().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn checks_programs_in_root_directories() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            root-directory = "/"
+            run = "/bin/sh -c true"
+
+            [[processes]]
+            name = "jailed"
+            root-directory = "/does/not/exist"
+            pre = "/bin/sh -c true"
+            run = "sh -c true"
+            "#;
+        let config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        let jailed = &config.processes[1];
+        assert_eq!(
+            Some("/does/not/exist"),
+            jailed.pre[0].root_directory.as_deref()
+        );
+        assert_eq!(
+            indoc::indoc! {r#"
+                Found 3 problems:
+                  Process "jailed" runs program "/bin/sh", which does not exist
+                  Process "jailed" runs program "sh", which was not found in the `PATH`
+                  Process "jailed" has root directory "/does/not/exist", which does not exist"#},
+            config.check().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn renders_configs_that_parse_to_the_same_config() {
+        let toml = r#"
+            shutdown_timeout = "1m"
+            env = { GREETING = "hello" }
+
+            [[processes]]
+            name = "init"
+            pre = [ [ "/bin/echo", "{{GREETING}}", "world" ], { command = "/bin/false", ignore-failure = true } ]
+
+            [[processes]]
+            name = "app"
+            run = { user = "root", command = "/app/server --port 8080" }
+            replicas = 2
+            depends-on = [ "init" ]
+            stop = [ { signal = "SIGQUIT", timeout = "10s" }, { command = "/app/stop" } ]
+            signal-map = { SIGHUP = "SIGUSR1" }
+            ready = { http = "http://localhost:8080/ready" }
+            healthcheck = { command = "/app/check", interval = "500ms" }
+            "#;
+
+        let mut config = Config::parse(toml, ConfigFormat::Toml).unwrap();
+        config.interpolate().unwrap();
+        assert_eq!(vec!["hello", "world"], config.processes[0].pre[0].args);
+
+        for format in [ConfigFormat::Toml, ConfigFormat::Json] {
+            let rendered = config.render(format).unwrap();
+            let reparsed = Config::parse(&rendered, format).unwrap();
+            assert_eq!(rendered, reparsed.render(format).unwrap());
+            assert_eq!(3, reparsed.processes.len());
+            assert_eq!("app-1", reparsed.processes[2].name);
+            assert_eq!(config.processes[2].run, reparsed.processes[2].run);
+            assert_eq!(config.processes[2].stop, reparsed.processes[2].stop);
+            assert_eq!(config.processes[2].ready, reparsed.processes[2].ready);
+        }
     }
 }
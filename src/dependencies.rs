@@ -0,0 +1,231 @@
+//! Resolves the `depends-on` relationships (and `after`/`before`
+//! ordering constraints, and phases) between processes.
+
+use std::collections::HashMap;
+
+use color_eyre::eyre::{self, eyre};
+
+use crate::config::{ProcessConfig, PropagationPolicy};
+
+/// Returns the indices of the given processes in the order in which they
+/// must be started: every process starts after the processes that it
+/// depends on (or is ordered after), and otherwise in the order in which
+/// the processes were listed. Unknown (or ambiguous) process names and
+/// cycles are errors.
+pub(crate) fn startup_order(processes: &[ProcessConfig]) -> eyre::Result<Vec<usize>> {
+    let predecessors = predecessors(processes)?;
+
+    // Number of not-yet-started predecessors of each process, and the
+    // successors of each process.
+    let mut remaining: Vec<usize> = predecessors.iter().map(Vec::len).collect();
+    let mut successors = vec![Vec::new(); processes.len()];
+    for (index, predecessors) in predecessors.iter().enumerate() {
+        for &predecessor in predecessors {
+            successors[predecessor].push(index);
+        }
+    }
+
+    // Repeatedly start the first (in file order) process whose
+    // predecessors have all been started.
+    let mut order = Vec::with_capacity(processes.len());
+    let mut started = vec![false; processes.len()];
+    while order.len() < processes.len() {
+        let index = (0..processes.len())
+            .find(|&index| !started[index] && remaining[index] == 0)
+            .ok_or_else(|| {
+                let cycle: Vec<&str> = find_cycle(&predecessors, &started)
+                    .into_iter()
+                    .map(|index| processes[index].name.as_str())
+                    .collect();
+                eyre!("Dependency cycle: \"{}\"", cycle.join("\" -> \""))
+            })?;
+
+        started[index] = true;
+        order.push(index);
+        for &successor in &successors[index] {
+            remaining[successor] -= 1;
+        }
+    }
+
+    Ok(order)
+}
+
+/// Returns a cycle (as a list of process indices, starting and ending
+/// with the same process, where every process must be started after the
+/// next one) among the processes that could not be started.
+fn find_cycle(predecessors: &[Vec<usize>], started: &[bool]) -> Vec<usize> {
+    // Every process that could not be started is waiting for at least
+    // one other process that could not be started, so following those
+    // processes must eventually lead back to a process that has already
+    // been visited.
+    let mut path: Vec<usize> = Vec::new();
+    let mut next = started.iter().position(|started| !started);
+    while let Some(index) = next {
+        if let Some(position) = path.iter().position(|&visited| visited == index) {
+            path.drain(..position);
+            path.push(index);
+            break;
+        }
+
+        path.push(index);
+        next = predecessors[index]
+            .iter()
+            .copied()
+            .find(|&predecessor| !started[predecessor]);
+    }
+
+    path
+}
+
+/// Returns the level of each of the given processes in the dependency
+/// graph, given the startup order returned by [`startup_order`]:
+/// processes without predecessors are at level zero, and every other
+/// process is one level above the highest of its predecessors.
+pub(crate) fn levels(processes: &[ProcessConfig], order: &[usize]) -> Vec<usize> {
+    let predecessors = match predecessors(processes) {
+        Ok(predecessors) => predecessors,
+        Err(_) => return vec![0; processes.len()],
+    };
+
+    let mut levels = vec![0; processes.len()];
+    for &index in order {
+        levels[index] = predecessors[index]
+            .iter()
+            .map(|&predecessor| levels[predecessor] + 1)
+            .max()
+            .unwrap_or(0);
+    }
+
+    levels
+}
+
+/// Returns, for each of the given processes, the indices of the
+/// processes that must be started before it: its dependencies, the
+/// processes it is ordered `after`, the processes that are ordered
+/// `before` it, and the processes in the previous phase.
+fn predecessors(processes: &[ProcessConfig]) -> eyre::Result<Vec<Vec<usize>>> {
+    let mut predecessors = vec![Vec::new(); processes.len()];
+    for edge in edges(processes)? {
+        predecessors[edge.to].push(edge.from);
+    }
+
+    Ok(predecessors)
+}
+
+/// Relationship between two processes that orders their startup.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum EdgeKind {
+    /// `depends-on` (or `requires`).
+    Requires,
+
+    /// `wants`.
+    Wants,
+
+    /// `after` (or `before`, in the other direction).
+    After,
+
+    /// Process is in the phase after the other process's phase.
+    Phase,
+}
+
+/// Edge in the startup graph: the process at index `from` must be
+/// started before the process at index `to`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Edge {
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    pub(crate) kind: EdgeKind,
+
+    /// What happens to the process at index `to` when the process at
+    /// index `from` is restarted or exits (always
+    /// [`PropagationPolicy::Ignore`] for ordering and phase edges).
+    pub(crate) propagate: PropagationPolicy,
+}
+
+/// Returns all of the edges in the startup graph of the given processes.
+/// Unknown (or ambiguous) process names are errors.
+pub(crate) fn edges(processes: &[ProcessConfig]) -> eyre::Result<Vec<Edge>> {
+    let mut indices: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, process) in processes.iter().enumerate() {
+        indices.entry(&process.name).or_default().push(index);
+    }
+
+    let find = |process: &ProcessConfig, relationship: &str, name: &str| {
+        let process_name = &process.name;
+        match indices.get(name).map(Vec::as_slice).unwrap_or_default() {
+            [index] => Ok(*index),
+            [] => Err(eyre!(
+                "Process \"{process_name}\" {relationship} unknown process \"{name}\""
+            )),
+            _ => Err(eyre!(
+                "Process \"{process_name}\" {relationship} \"{name}\", but multiple processes have that name"
+            )),
+        }
+    };
+
+    // Every process in a phase depends on every process in the previous
+    // phase (phases are ordered by their first appearance).
+    let mut phases: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (index, process) in processes.iter().enumerate() {
+        if let Some(phase) = &process.phase {
+            match phases.iter_mut().find(|(name, _)| name == phase) {
+                Some((_, members)) => members.push(index),
+                None => phases.push((phase, vec![index])),
+            }
+        }
+    }
+
+    let mut edges = Vec::new();
+    for pair in phases.windows(2) {
+        for &from in &pair[0].1 {
+            for &to in &pair[1].1 {
+                edges.push(Edge {
+                    from,
+                    to,
+                    kind: EdgeKind::Phase,
+                    propagate: PropagationPolicy::Ignore,
+                });
+            }
+        }
+    }
+
+    for (index, process) in processes.iter().enumerate() {
+        for dependency in &process.depends_on {
+            edges.push(Edge {
+                from: find(process, "depends on", &dependency.name)?,
+                to: index,
+                kind: EdgeKind::Requires,
+                propagate: dependency.propagate,
+            });
+        }
+
+        for dependency in &process.wants {
+            edges.push(Edge {
+                from: find(process, "wants", &dependency.name)?,
+                to: index,
+                kind: EdgeKind::Wants,
+                propagate: dependency.propagate,
+            });
+        }
+
+        for name in &process.after {
+            edges.push(Edge {
+                from: find(process, "is ordered after", name)?,
+                to: index,
+                kind: EdgeKind::After,
+                propagate: PropagationPolicy::Ignore,
+            });
+        }
+
+        for name in &process.before {
+            edges.push(Edge {
+                from: index,
+                to: find(process, "is ordered before", name)?,
+                kind: EdgeKind::After,
+                propagate: PropagationPolicy::Ignore,
+            });
+        }
+    }
+
+    Ok(edges)
+}
@@ -40,7 +40,7 @@ impl GroundControlFormatter {
     /// processes).
     pub fn from_config(config: &Config) -> Self {
         // Assign a style to every phase of every daemon process.
-        let styles = vec![
+        let styles = [
             Style::new().green().bold(),
             Style::new().blue().bold(),
             Style::new().yellow().bold(),
@@ -50,18 +50,26 @@ impl GroundControlFormatter {
         let mut styles = styles.iter().cycle();
 
         let mut daemon_styles: HashMap<String, Style> = Default::default();
-        for process in config.processes.iter().filter(|p| p.run.is_some()) {
+        for process in config.processes.iter().filter(|p| p.is_daemon()) {
             // Get the next style from the iterator.
             let style = styles
                 .next()
                 .expect("iterator should be infinite in length");
 
-            // Add styles for all four process phases of this process to
-            // the list.
+            // Add styles for all of the process phases of this process
+            // to the list.
             daemon_styles.extend([
+                (format!("{}[condition]", process.name), style.clone()),
                 (format!("{}[pre]", process.name), style.clone()),
                 (process.name.to_string(), style.clone()),
+                (format!("{}[ready]", process.name), style.clone()),
+                (format!("{}[healthcheck]", process.name), style.clone()),
+                (format!("{}[on-crash]", process.name), style.clone()),
+                (format!("{}[reload]", process.name), style.clone()),
+                (format!("{}[pre-stop]", process.name), style.clone()),
                 (format!("{}[stop]", process.name), style.clone()),
+                (format!("{}[post-success]", process.name), style.clone()),
+                (format!("{}[post-failure]", process.name), style.clone()),
                 (format!("{}[post]", process.name), style.clone()),
             ]);
         }
@@ -154,13 +162,15 @@ struct EventVisitor {
 
 impl Visit for EventVisitor {
     fn record_str(&mut self, field: &Field, value: &str) {
-        write!(self.fields, " {}={}", field.name(), value).unwrap();
+        write!(self.fields, " {}={}", field.name(), value)
+            .expect("writing to a String should not fail");
     }
 
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
         match field.name() {
             "message" => self.message = format!(" {value:?}"),
-            _ => write!(self.fields, " {}={:?}", field.name(), value).unwrap(),
+            _ => write!(self.fields, " {}={:?}", field.name(), value)
+                .expect("writing to a String should not fail"),
         }
     }
 }
@@ -177,14 +187,16 @@ impl Visit for ConsoleOutputVisitor {
         match field.name() {
             "process" => self.process = value.to_string(),
             "output" => self.message = format!(" {value}"),
-            _ => write!(self.fields, " {}={}", field.name(), value).unwrap(),
+            _ => write!(self.fields, " {}={}", field.name(), value)
+                .expect("writing to a String should not fail"),
         }
     }
 
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
         match field.name() {
             "message" => self.message = format!(" {value:?}"),
-            _ => write!(self.fields, " {}={:?}", field.name(), value).unwrap(),
+            _ => write!(self.fields, " {}={:?}", field.name(), value)
+                .expect("writing to a String should not fail"),
         }
     }
 }
@@ -0,0 +1,109 @@
+//! Renders the startup graph of a Ground Control specification.
+
+use std::{fmt::Write, str::FromStr};
+
+use color_eyre::eyre;
+
+use crate::{
+    config::Config,
+    dependencies::{self, Edge, EdgeKind},
+};
+
+/// Output format for the startup graph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    Dot,
+
+    /// Mermaid flowchart.
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = UnknownGraphFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "mermaid" => Ok(Self::Mermaid),
+            _ => Err(UnknownGraphFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Error returned when parsing an unknown [`GraphFormat`].
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown graph format \"{0}\" (expected \"dot\" or \"mermaid\")")]
+pub struct UnknownGraphFormat(String);
+
+/// Renders the resolved startup graph of the given configuration: every
+/// process (in startup order), and an edge from each process to the
+/// processes that are started after it because of a `depends-on`,
+/// `wants`, `after`, or `before` relationship (or because they are in
+/// the next phase). Returns an error if the dependencies are invalid.
+pub fn render(config: &Config, format: GraphFormat) -> eyre::Result<String> {
+    let order = dependencies::startup_order(&config.processes)?;
+    let edges = dependencies::edges(&config.processes)?;
+    let names: Vec<&str> = config
+        .processes
+        .iter()
+        .map(|process| process.name.as_str())
+        .collect();
+
+    Ok(match format {
+        GraphFormat::Dot => render_dot(&names, &order, &edges),
+        GraphFormat::Mermaid => render_mermaid(&names, &order, &edges),
+    })
+}
+
+fn render_dot(names: &[&str], order: &[usize], edges: &[Edge]) -> String {
+    let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let mut output = String::from("digraph groundcontrol {\n");
+    for &index in order {
+        let _ = writeln!(output, "    {};", quote(names[index]));
+    }
+
+    for edge in edges {
+        let attributes = match edge.kind {
+            EdgeKind::Requires => "label=\"requires\"",
+            EdgeKind::Wants => "label=\"wants\", style=dashed",
+            EdgeKind::After => "label=\"after\", style=dotted",
+            EdgeKind::Phase => "label=\"phase\", style=bold",
+        };
+        let _ = writeln!(
+            output,
+            "    {} -> {} [{attributes}];",
+            quote(names[edge.from]),
+            quote(names[edge.to])
+        );
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+fn render_mermaid(names: &[&str], order: &[usize], edges: &[Edge]) -> String {
+    // Mermaid node IDs cannot contain arbitrary characters, so the nodes
+    // are identified by their index, and labelled with their name.
+    let mut output = String::from("flowchart TD\n");
+    for &index in order {
+        let _ = writeln!(
+            output,
+            "    p{index}[\"{}\"]",
+            names[index].replace('"', "#quot;")
+        );
+    }
+
+    for edge in edges {
+        let arrow = match edge.kind {
+            EdgeKind::Requires => "-->|requires|",
+            EdgeKind::Wants => "-.->|wants|",
+            EdgeKind::After => "-.->|after|",
+            EdgeKind::Phase => "==>|phase|",
+        };
+        let _ = writeln!(output, "    p{} {arrow} p{}", edge.from, edge.to);
+    }
+
+    output
+}
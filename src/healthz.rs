@@ -0,0 +1,213 @@
+//! Built-in HTTP endpoint that reports the aggregated status of every
+//! process, so that container orchestrators can health-check everything
+//! that Ground Control runs with a single probe.
+
+use std::{fmt::Write, time::Duration};
+
+use color_eyre::eyre::{self, WrapErr};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    task::JoinHandle,
+};
+
+use crate::process::{Process, Readiness};
+
+/// Most bytes read from a request (the request line and headers); the
+/// rest of the request is ignored.
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+/// How long a client gets to send its request and read the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Overall state of Ground Control.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Stage {
+    /// Processes are still being started.
+    Starting,
+
+    /// Every process has been started.
+    Running,
+
+    /// Processes are being stopped.
+    Stopping,
+}
+
+#[derive(Debug)]
+struct Status {
+    stage: Stage,
+    processes: Vec<Readiness>,
+}
+
+/// Status reported by the `/healthz` endpoint (if enabled).
+#[derive(Debug)]
+pub(crate) struct Healthz {
+    status: watch::Sender<Status>,
+    server: Option<JoinHandle<()>>,
+}
+
+impl Healthz {
+    /// Starts serving the status on the given address (if any).
+    pub(crate) async fn start(address: Option<&str>) -> eyre::Result<Self> {
+        let (status, receiver) = watch::channel(Status {
+            stage: Stage::Starting,
+            processes: Vec::new(),
+        });
+
+        let server = match address {
+            Some(address) => {
+                let listener = TcpListener::bind(address).await.wrap_err_with(|| {
+                    format!("Failed to listen on `healthz` address \"{address}\"")
+                })?;
+                tracing::info!(%address, "Serving aggregated status at /healthz");
+                Some(tokio::spawn(serve(listener, receiver)))
+            }
+            None => None,
+        };
+
+        Ok(Self { status, server })
+    }
+
+    /// Updates the reported stage and (started) processes.
+    pub(crate) fn update(&self, stage: Stage, processes: &[Process]) {
+        self.status.send_replace(Status {
+            stage,
+            processes: processes.iter().map(Process::readiness).collect(),
+        });
+    }
+}
+
+impl Drop for Healthz {
+    fn drop(&mut self) {
+        if let Some(server) = &self.server {
+            server.abort();
+        }
+    }
+}
+
+/// Accepts connections until the server task is aborted.
+async fn serve(listener: TcpListener, status: watch::Receiver<Status>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let status = status.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, &status)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(err)) => {
+                            tracing::debug!(?err, "Error responding to `healthz` request");
+                        }
+                        Err(_) => {
+                            tracing::debug!(timeout = ?REQUEST_TIMEOUT, "`healthz` request timed out");
+                        }
+                    }
+                });
+            }
+            Err(err) => tracing::warn!(?err, "Error accepting `healthz` connection"),
+        }
+    }
+}
+
+/// Reads the request (up to [`MAX_REQUEST_SIZE`] bytes of it), and
+/// responds with the status (for `GET /healthz`) or a 404.
+async fn respond(mut stream: TcpStream, status: &watch::Receiver<Status>) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_SIZE));
+
+    // Only the request line ("GET /healthz HTTP/1.1") matters, but the
+    // headers are read so that the client sees a clean close.
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut header = String::new();
+    while reader.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let path = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => target.split('?').next().unwrap_or_default(),
+        _ => "",
+    };
+
+    let (healthy, body) = if path == "/healthz" {
+        let (healthy, body) = render(&status.borrow());
+        (Some(healthy), body)
+    } else {
+        (None, String::from("{\"error\":\"not found\"}"))
+    };
+
+    let status_line = match healthy {
+        Some(true) => "200 OK",
+        Some(false) => "503 Service Unavailable",
+        None => "404 Not Found",
+    };
+
+    let response = format!(
+        "HTTP/1.0 {status_line}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+/// Renders the status as JSON, and returns `true` if everything is
+/// healthy: every process has been started, and every daemon is running
+/// and ready.
+fn render(status: &Status) -> (bool, String) {
+    let mut healthy = status.stage == Stage::Running;
+    let mut processes = Vec::with_capacity(status.processes.len());
+    for process in &status.processes {
+        let running = !process.has_exited();
+        let ready = process.is_ready();
+        healthy &= running && ready;
+
+        let mut json = format!(
+            "{{\"name\":{},\"type\":\"{}\",\"ready\":{ready}",
+            quote(process.process_name()),
+            if process.restarts().is_some() {
+                "daemon"
+            } else {
+                "one-shot"
+            }
+        );
+        if let Some(restarts) = process.restarts() {
+            let _ = write!(json, ",\"running\":{running},\"restarts\":{restarts}");
+        }
+        json.push('}');
+        processes.push(json);
+    }
+
+    let overall = match status.stage {
+        Stage::Starting => "starting",
+        Stage::Running if healthy => "ok",
+        Stage::Running => "unhealthy",
+        Stage::Stopping => "stopping",
+    };
+
+    (
+        healthy,
+        format!(
+            "{{\"status\":\"{overall}\",\"processes\":[{}]}}",
+            processes.join(",")
+        ),
+    )
+}
+
+/// Returns the given string as a JSON string.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
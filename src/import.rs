@@ -0,0 +1,433 @@
+//! Imports the configurations of other process managers (or, rather,
+//! the common subset of their settings) as Ground Control configurations.
+
+use std::collections::HashMap;
+
+use color_eyre::eyre::{self, WrapErr};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Converts a `supervisord.conf` file into a configuration (as the JSON
+/// value of the configuration).
+///
+/// Every `[program:x]` section becomes a daemon, in the order of their
+/// `priority`, with the `command` as its `run` command (along with the
+/// `user` and `environment` of the program). `autorestart` becomes the
+/// `restart` policy, and the daemons are not critical, since
+/// supervisord does not stop when a program exits. All of the other
+/// sections and settings are ignored.
+pub(crate) fn supervisord(text: &str) -> eyre::Result<Value> {
+    let mut programs = Vec::new();
+    for section in parse_ini(text)? {
+        let name = match section.name.strip_prefix("program:") {
+            Some(name) => name.trim(),
+            None => continue,
+        };
+        let setting = |key: &str| -> eyre::Result<Option<String>> {
+            section
+                .settings
+                .iter()
+                .rev()
+                .find(|(setting, _)| setting == key)
+                .map(|(_, value)| expand_supervisord(value, name))
+                .transpose()
+        };
+
+        let command = setting("command")?
+            .ok_or_else(|| eyre::eyre!("Program \"{name}\" does not have a `command`"))?;
+        let mut run = json!({ "command": command });
+        if let Some(user) = setting("user")? {
+            run["user"] = json!(user);
+        }
+        if let Some(environment) = setting("environment")? {
+            run["env"] = json!(parse_environment(&environment).wrap_err_with(|| {
+                format!("Program \"{name}\" has an invalid `environment`")
+            })?);
+        }
+
+        let restart = match setting("autorestart")?
+            .unwrap_or_else(|| String::from("unexpected"))
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "true" | "yes" | "on" | "1" => "always",
+            "false" | "no" | "off" | "0" => "never",
+            "unexpected" => "on-failure",
+            other => {
+                return Err(eyre::eyre!(
+                    "Program \"{name}\" has an invalid `autorestart` value \"{other}\""
+                ))
+            }
+        };
+
+        let priority = setting("priority")?
+            .map(|priority| priority.parse::<i64>())
+            .transpose()
+            .wrap_err_with(|| format!("Program \"{name}\" has an invalid `priority`"))?
+            .unwrap_or(999);
+
+        programs.push((
+            priority,
+            json!({
+                "name": name,
+                "run": run,
+                "restart": restart,
+                "critical": false,
+            }),
+        ));
+    }
+
+    // Programs with a lower priority start first (and the sort is
+    // stable, so programs with the same priority stay in order).
+    programs.sort_by_key(|(priority, _)| *priority);
+
+    Ok(json!({
+        "processes": programs.into_iter().map(|(_, program)| program).collect::<Vec<_>>(),
+    }))
+}
+
+/// Converts the `services` of a Compose file (such as
+/// `docker-compose.yml`) into a configuration (as the JSON value of the
+/// configuration).
+///
+/// Every service becomes a daemon, with its `command` as its `run`
+/// command (along with the `user` and `environment` of the service).
+/// `depends_on` becomes `depends-on`, `restart` becomes the `restart`
+/// policy, and the daemons are not critical, since the other services
+/// keep running when a service exits. Variables (such as `${VAR}`) are
+/// replaced with their values, as Compose does when it loads the file.
+/// All of the other keys are ignored.
+pub(crate) fn compose(text: &str) -> eyre::Result<Value> {
+    #[derive(Deserialize)]
+    struct ComposeFile {
+        #[serde(default)]
+        services: serde_yaml::Mapping,
+    }
+
+    #[derive(Deserialize)]
+    struct Service {
+        command: Option<ServiceCommand>,
+        user: Option<serde_yaml::Value>,
+        environment: Option<ServiceEnvironment>,
+        depends_on: Option<ServiceDependencies>,
+        restart: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(
+        untagged,
+        expecting = "expected a command string or an array of strings"
+    )]
+    enum ServiceCommand {
+        String(String),
+        Vector(Vec<String>),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(
+        untagged,
+        expecting = "expected a map of variables or an array of `KEY=value` strings"
+    )]
+    enum ServiceEnvironment {
+        Map(HashMap<String, serde_yaml::Value>),
+        List(Vec<String>),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(
+        untagged,
+        expecting = "expected an array of service names or a map of services"
+    )]
+    enum ServiceDependencies {
+        List(Vec<String>),
+        Map(serde_yaml::Mapping),
+    }
+
+    let file: ComposeFile = serde_yaml::from_str(text)?;
+
+    let mut services = Vec::new();
+    for (name, service) in file.services {
+        let name = yaml_scalar(&name).ok_or_else(|| eyre::eyre!("Invalid service name"))?;
+        let service: Service = serde_yaml::from_value(service)
+            .wrap_err_with(|| format!("Service \"{name}\" is invalid"))?;
+        let expand = |value: &str| {
+            expand_compose(value)
+                .wrap_err_with(|| format!("Service \"{name}\" uses an invalid variable"))
+        };
+
+        let mut run = json!({
+            "command": match service.command {
+                Some(ServiceCommand::String(command)) => json!(expand(&command)?),
+                Some(ServiceCommand::Vector(command)) => json!(command
+                    .iter()
+                    .map(|arg| expand(arg))
+                    .collect::<eyre::Result<Vec<_>>>()?),
+                None => {
+                    return Err(eyre::eyre!(
+                        "Service \"{name}\" does not have a `command` (the command of its image cannot be imported)"
+                    ))
+                }
+            },
+        });
+        if let Some(user) = service.user.as_ref().and_then(yaml_scalar) {
+            run["user"] = json!(expand(&user)?);
+        }
+
+        // Variables without a value are passed through from the
+        // environment, which every command inherits anyway.
+        let env = match service.environment {
+            Some(ServiceEnvironment::Map(env)) => env
+                .iter()
+                .filter_map(|(key, value)| yaml_scalar(value).map(|value| (key.clone(), value)))
+                .collect(),
+            Some(ServiceEnvironment::List(env)) => env
+                .iter()
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            None => Vec::new(),
+        };
+        if !env.is_empty() {
+            run["env"] = json!(env
+                .into_iter()
+                .map(|(key, value)| Ok((key, expand(&value)?)))
+                .collect::<eyre::Result<HashMap<_, _>>>()?);
+        }
+
+        let depends_on = match service.depends_on {
+            Some(ServiceDependencies::List(names)) => names,
+            Some(ServiceDependencies::Map(services)) => {
+                services.keys().filter_map(yaml_scalar).collect()
+            }
+            None => Vec::new(),
+        };
+
+        let restart = match service.restart.as_deref().unwrap_or("no") {
+            "no" => "never",
+            "always" | "unless-stopped" => "always",
+            restart if restart.split(':').next() == Some("on-failure") => "on-failure",
+            other => {
+                return Err(eyre::eyre!(
+                    "Service \"{name}\" has an invalid `restart` value \"{other}\""
+                ))
+            }
+        };
+
+        services.push(json!({
+            "name": name,
+            "run": run,
+            "depends-on": depends_on,
+            "restart": restart,
+            "critical": false,
+        }));
+    }
+
+    Ok(json!({ "processes": services }))
+}
+
+/// Returns the string form of a YAML scalar (strings, numbers, and
+/// booleans), or `None` for other values (including `null`).
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(value) => Some(value.clone()),
+        serde_yaml::Value::Number(value) => Some(value.to_string()),
+        serde_yaml::Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Replaces Compose's variables in the given value with the values of
+/// the environment variables: `$VAR` and `${VAR}` (empty if the variable
+/// is not set), `${VAR:-default}` and `${VAR-default}` (the default if
+/// the variable is unset or empty, or only if it is unset),
+/// `${VAR:?error}` and `${VAR?error}` (an error instead of the default),
+/// and `$$` (a literal `$`).
+fn expand_compose(value: &str) -> eyre::Result<String> {
+    static VARIABLE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(
+            r"\$\$|\$([A-Za-z_][A-Za-z0-9_]*)|\$\{([A-Za-z_][A-Za-z0-9_]*)(?:(:?[-?])([^}]*))?\}",
+        )
+        .expect("regex should be valid")
+    });
+
+    let mut expanded = String::with_capacity(value.len());
+    let mut last = 0;
+    for captures in VARIABLE.captures_iter(value) {
+        let variable = captures.get(0).expect("capture 0 should always be present");
+        expanded.push_str(&value[last..variable.start()]);
+        last = variable.end();
+
+        let name = match captures.get(1).or_else(|| captures.get(2)) {
+            Some(name) => name.as_str(),
+            None => {
+                expanded.push('$');
+                continue;
+            }
+        };
+        let var = std::env::var(name).ok();
+        let value = match captures.get(3).map(|operator| operator.as_str()) {
+            None => var.unwrap_or_default(),
+            Some(operator) => {
+                let unset = if operator.starts_with(':') {
+                    var.as_deref().map_or(true, str::is_empty)
+                } else {
+                    var.is_none()
+                };
+                let operand = captures.get(4).map_or("", |operand| operand.as_str());
+                match (unset, operator.ends_with('?')) {
+                    (false, _) => var.unwrap_or_default(),
+                    (true, false) => operand.to_string(),
+                    (true, true) => {
+                        return Err(eyre::eyre!("Variable `{name}` is not set ({operand})"))
+                    }
+                }
+            }
+        };
+        expanded.push_str(&value);
+    }
+    expanded.push_str(&value[last..]);
+
+    Ok(expanded)
+}
+
+/// Section of an INI file, with its settings (in order, and with
+/// lowercase keys).
+#[derive(Debug)]
+struct Section<'a> {
+    name: &'a str,
+    settings: Vec<(String, String)>,
+}
+
+/// Parses the INI file that supervisord uses: `[section]` headers,
+/// followed by `key = value` (or `key: value`) settings, whose values
+/// can continue on indented lines. Lines that start with `;` or `#`, and
+/// anything after a ` ;`, are comments.
+fn parse_ini(text: &str) -> eyre::Result<Vec<Section<'_>>> {
+    let mut sections: Vec<Section<'_>> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = trimmed
+            .strip_prefix('[')
+            .and_then(|name| name.strip_suffix(']'))
+        {
+            sections.push(Section {
+                name: name.trim(),
+                settings: Vec::new(),
+            });
+            continue;
+        }
+
+        let section = sections
+            .last_mut()
+            .ok_or_else(|| eyre::eyre!("Line {} is not in a section", index + 1))?;
+        let value = match trimmed.find(" ;") {
+            Some(comment) => trimmed[..comment].trim_end(),
+            None => trimmed,
+        };
+
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, previous)) = section.settings.last_mut() {
+                previous.push(' ');
+                previous.push_str(value);
+                continue;
+            }
+        }
+
+        let (key, value) = value
+            .split_once(['=', ':'])
+            .ok_or_else(|| eyre::eyre!("Line {} is not a `key = value` setting", index + 1))?;
+        section
+            .settings
+            .push((key.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    Ok(sections)
+}
+
+/// Replaces supervisord's `%(name)s` expansions in the given value:
+/// `%(ENV_X)s` becomes the value of the `X` environment variable (as
+/// supervisord reads it when it loads its configuration), and
+/// `%(program_name)s` the name of the program.
+fn expand_supervisord(value: &str, program_name: &str) -> eyre::Result<String> {
+    static EXPANSION: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"%%|%\((\w+)\)s").expect("regex should be valid"));
+
+    let mut expanded = String::with_capacity(value.len());
+    let mut last = 0;
+    for captures in EXPANSION.captures_iter(value) {
+        let expansion = captures.get(0).expect("capture 0 should always be present");
+        expanded.push_str(&value[last..expansion.start()]);
+        last = expansion.end();
+
+        match captures.get(1).map(|name| name.as_str()) {
+            None => expanded.push('%'),
+            Some("program_name") => expanded.push_str(program_name),
+            Some(name) => match name.strip_prefix("ENV_") {
+                Some(var) => expanded.push_str(&std::env::var(var).map_err(|_| {
+                    eyre::eyre!(
+                        "Program \"{program_name}\" uses the `{var}` environment variable, which is not set"
+                    )
+                })?),
+                None => {
+                    return Err(eyre::eyre!(
+                        "Program \"{program_name}\" uses the `%({name})s` expansion, which cannot be imported"
+                    ))
+                }
+            },
+        }
+    }
+    expanded.push_str(&value[last..]);
+
+    Ok(expanded)
+}
+
+/// Parses supervisord's `environment` setting: a comma-separated list of
+/// `KEY=value` pairs, whose values can be quoted (in order to include
+/// commas).
+fn parse_environment(text: &str) -> eyre::Result<HashMap<String, String>> {
+    let mut pairs = Vec::new();
+    let mut pair = String::new();
+    let mut quote = None;
+    for c in text.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                pair.push(c);
+            }
+            (c, Some(open)) if c == open => {
+                quote = None;
+                pair.push(c);
+            }
+            (',', None) => pairs.push(std::mem::take(&mut pair)),
+            _ => pair.push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(eyre::eyre!("Unterminated quote in \"{text}\""));
+    }
+    pairs.push(pair);
+
+    pairs
+        .iter()
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| eyre::eyre!("\"{}\" is not a `KEY=value` pair", pair.trim()))?;
+            let value = value.trim();
+            let value = match (value.chars().next(), value.chars().last()) {
+                (Some(open @ ('"' | '\'')), Some(close)) if value.len() > 1 && open == close => {
+                    &value[1..value.len() - 1]
+                }
+                _ => value,
+            };
+            Ok((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,248 @@
+//! Duties of Ground Control when it is the init process (PID 1) of a
+//! container: every orphaned process in the container becomes a child
+//! of Ground Control, whether or not Ground Control started it.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use nix::{
+    sys::{
+        signal::{kill, Signal},
+        wait::{waitpid, WaitPidFlag},
+    },
+    unistd::Pid,
+};
+use once_cell::sync::Lazy;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::JoinHandle,
+};
+
+/// PIDs of the commands that Ground Control started (and waits for)
+/// itself, which must not be reaped as orphans.
+static COMMANDS: Lazy<Mutex<HashSet<Pid>>> = Lazy::new(Default::default);
+
+/// Returns `true` if Ground Control is the init process.
+pub(crate) fn is_init() -> bool {
+    std::process::id() == 1
+}
+
+/// Locks and returns the PIDs of the commands that Ground Control
+/// waits for itself. The lock must be held while a command is started
+/// (until its PID has been added), so that the command cannot be reaped
+/// as an orphan in the meantime.
+pub(crate) fn commands() -> MutexGuard<'static, HashSet<Pid>> {
+    COMMANDS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Reaps every orphaned process that exits (once `SIGCHLD` is received),
+/// until the returned handle is dropped.
+pub(crate) fn reap_orphans() -> Reaper {
+    let mut sigchld = match signal(SignalKind::child()) {
+        Ok(sigchld) => sigchld,
+        Err(err) => {
+            tracing::warn!(
+                ?err,
+                "Unable to listen for `SIGCHLD`; not reaping orphaned processes"
+            );
+            return Reaper(None);
+        }
+    };
+
+    Reaper(Some(tokio::spawn(async move {
+        loop {
+            reap_zombies();
+            if sigchld.recv().await.is_none() {
+                break;
+            }
+        }
+    })))
+}
+
+/// Reaps orphaned processes until it is dropped.
+#[derive(Debug)]
+pub(crate) struct Reaper(Option<JoinHandle<()>>);
+
+impl Drop for Reaper {
+    fn drop(&mut self) {
+        if let Some(task) = &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// Reaps every exited child process that is not one of the commands
+/// (whose exit status is collected by the command's monitor).
+fn reap_zombies() {
+    let commands = commands();
+    for pid in zombies(Pid::this()) {
+        if commands.contains(&pid) {
+            continue;
+        }
+
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(status) => tracing::debug!(%pid, ?status, "Reaped orphaned process"),
+            Err(err) => tracing::debug!(%pid, ?err, "Error reaping orphaned process"),
+        }
+    }
+}
+
+/// Kills (with `SIGKILL`) every process that is still running below
+/// Ground Control, so that stray grandchildren (daemons that were not
+/// stopped along with their parent, for example) cannot keep the
+/// container running.
+pub(crate) fn kill_descendants() {
+    let descendants = descendants(Pid::this());
+    if descendants.is_empty() {
+        return;
+    }
+
+    tracing::warn!(count = %descendants.len(), "Killing processes that are still running after the shutdown");
+    for pid in descendants {
+        if let Err(err) = kill(pid, Signal::SIGKILL) {
+            tracing::debug!(%pid, ?err, "Error killing process");
+        }
+    }
+}
+
+/// Returns every (transitive) child of the given process, according to
+/// `/proc`.
+fn descendants(root: Pid) -> Vec<Pid> {
+    let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for process in processes() {
+        children
+            .entry(process.parent)
+            .or_default()
+            .push(process.pid);
+    }
+
+    let mut descendants = Vec::new();
+    let mut pending = vec![root];
+    while let Some(pid) = pending.pop() {
+        if let Some(children) = children.remove(&pid) {
+            descendants.extend(&children);
+            pending.extend(children);
+        }
+    }
+
+    descendants
+}
+
+/// Returns the (exited, but not yet reaped) zombie children of the given
+/// process, according to `/proc`.
+fn zombies(parent: Pid) -> Vec<Pid> {
+    processes()
+        .into_iter()
+        .filter(|process| process.zombie && process.parent == parent)
+        .map(|process| process.pid)
+        .collect()
+}
+
+/// Process listed in `/proc`.
+#[derive(Debug)]
+struct ProcessStat {
+    pid: Pid,
+    parent: Pid,
+    zombie: bool,
+}
+
+/// Returns every process in `/proc`.
+fn processes() -> Vec<ProcessStat> {
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(err) => {
+            tracing::warn!(?err, "Unable to list processes");
+            return Vec::new();
+        }
+    };
+
+    entries
+        .filter_map(|entry| {
+            let pid: i32 = entry.ok()?.file_name().to_str()?.parse().ok()?;
+            let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            let (state, parent) = parse_stat(&stat)?;
+            Some(ProcessStat {
+                pid: Pid::from_raw(pid),
+                parent,
+                zombie: state == "Z",
+            })
+        })
+        .collect()
+}
+
+/// Returns the state and parent PID from the contents of
+/// `/proc/<pid>/stat`, which are the first two fields after the
+/// (parenthesized, and possibly space-containing) command name.
+fn parse_stat(stat: &str) -> Option<(&str, Pid)> {
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+    let state = fields.next()?;
+    let ppid = fields.next()?.parse().ok()?;
+    Some((state, Pid::from_raw(ppid)))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_is_parsed_after_command_name() {
+        assert_eq!(
+            Some(("S", Pid::from_raw(42))),
+            parse_stat("1234 (my (odd) program) S 42 1234 1234 0 -1")
+        );
+        assert_eq!(None, parse_stat("garbage"));
+    }
+
+    #[test]
+    fn descendants_include_grandchildren() {
+        let mut child = std::process::Command::new("/bin/sh")
+            .args(["-c", "sleep 5 & echo $!; wait"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut grandchild = String::new();
+        std::io::BufRead::read_line(
+            &mut std::io::BufReader::new(child.stdout.take().unwrap()),
+            &mut grandchild,
+        )
+        .unwrap();
+        let child_pid = Pid::from_raw(child.id() as i32);
+        let grandchild_pid = Pid::from_raw(grandchild.trim().parse().unwrap());
+
+        let descendants = descendants(Pid::this());
+        let _ = kill(grandchild_pid, Signal::SIGKILL);
+        let _ = child.wait();
+
+        assert!(descendants.contains(&child_pid));
+        assert!(descendants.contains(&grandchild_pid));
+    }
+
+    #[test]
+    #[allow(clippy::zombie_processes)]
+    fn zombies_are_reaped_unless_they_are_commands() {
+        let spawn_zombie = || {
+            let child = std::process::Command::new("/bin/true").spawn().unwrap();
+            let pid = Pid::from_raw(child.id() as i32);
+            while !zombies(Pid::this()).contains(&pid) {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            pid
+        };
+
+        let orphan = spawn_zombie();
+        let command = spawn_zombie();
+        commands().insert(command);
+
+        reap_zombies();
+        let zombies = zombies(Pid::this());
+        commands().remove(&command);
+        let _ = waitpid(command, None);
+
+        assert!(!zombies.contains(&orphan));
+        assert!(zombies.contains(&command));
+    }
+}
@@ -14,16 +14,34 @@
     clippy::unwrap_used
 )]
 
+use std::{collections::HashMap, sync::Arc};
+
 use color_eyre::eyre;
-use config::Config;
+use config::{CommandConfig, Config, PlatformConfig, ProcessConfig, SignalConfig};
 use tokio::sync::mpsc;
 
-use crate::process::Process;
+use crate::{
+    command::ExitStatus,
+    dependencies::EdgeKind,
+    healthz::{Healthz, Stage},
+    process::{Dependency, Killer, Process, ProcessPhase, Readiness},
+    systemd::Systemd,
+};
 
+mod cgroup;
 mod command;
 pub mod config;
+mod dependencies;
 pub mod formatter;
+pub mod graph;
+mod healthz;
+mod import;
+mod init;
+mod overrides;
+mod probe;
 mod process;
+mod remote;
+mod systemd;
 
 /// Errors generated by Ground Control.
 #[derive(Debug, thiserror::Error)]
@@ -33,26 +51,126 @@ pub enum Error {
     StartupAborted(#[from] eyre::Report),
 
     /// A long-running daemon exited with a non-zero exit code.
-    #[error("Daemon process exited with a non-zero exit code")]
-    AbnormalShutdown,
+    #[error("Daemon process \"{process}\" exited with a non-zero exit code")]
+    AbnormalShutdown {
+        /// Name of the daemon.
+        process: String,
+
+        /// Exit code of the daemon (or, if the daemon was killed by a
+        /// signal, 128 plus the number of the signal, as is customary
+        /// for shells). Never zero.
+        exit_code: i32,
+    },
+}
+
+/// Reason for a clean shutdown of Ground Control.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Shutdown {
+    /// The shutdown was requested (by a signal, or a
+    /// [`Control::Shutdown`] message).
+    Requested,
+
+    /// A long-running daemon exited cleanly, which triggered the
+    /// shutdown.
+    DaemonExited {
+        /// Name of the daemon.
+        process: String,
+
+        /// Exit code of the daemon (which is one of its
+        /// `allowed-exit-codes`, if not zero).
+        exit_code: i32,
+    },
+}
+
+/// Messages used to control Ground Control while it is running.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Control {
+    /// Stop all of the processes and shut down.
+    Shutdown,
+
+    /// Tell the named daemon process to reload using its `reload`
+    /// signal/command, or every daemon process that has a `reload`
+    /// signal/command if no name is given.
+    Reload(Option<String>),
+
+    /// Start or stop instances of the named replicated process (see
+    /// `replicas`) until it has the given number of instances. New
+    /// instances are started in order of their index; instances are
+    /// stopped starting with the highest index.
+    Scale {
+        /// Name of the replicated process (without an instance index).
+        name: String,
+
+        /// Number of instances to run.
+        replicas: u32,
+    },
+
+    /// Forward the signal to every daemon process that lists it in its
+    /// `forward-signals`.
+    Signal(SignalConfig),
+
+    /// Log the status (state, PID, uptime, and restart count) of every
+    /// process.
+    Status,
+
+    /// Apply a new configuration to the running processes: stop the
+    /// processes that were removed (or are no longer enabled), restart
+    /// the processes whose configuration changed, and start the
+    /// processes that were added, in dependency order. Invalid
+    /// configurations are logged and ignored.
+    Apply(Box<Config>),
 }
 
+/// Why a daemon exited (for the last time).
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum ShutdownReason {
-    /// Graceful shutdown was triggered by an external signal.
-    GracefulShutdown,
+    /// Daemon exited cleanly, with the given exit status.
+    DaemonExited(ExitStatus),
 
-    /// Daemon exited cleanly.
-    DaemonExited,
+    /// Daemon failed (non-zero exit code), with the given exit status.
+    DaemonFailed(ExitStatus),
+}
+
+/// Sent by the supervisor of a (critical) daemon that exited on its own,
+/// which triggers the shutdown.
+#[derive(Debug)]
+struct DaemonStopped {
+    process: String,
+    reason: ShutdownReason,
+}
+
+/// Runs a Ground Control specification, returning only when all of the
+/// processes have stopped (either because one process triggered a
+/// shutdown, or because the `shutdown` signal was triggered), and
+/// returns the reason for the shutdown.
+pub async fn run(
+    config: Config,
+    mut shutdown: mpsc::UnboundedReceiver<()>,
+) -> Result<Shutdown, Error> {
+    let (control_sender, control_receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        // Both sending the shutdown signal, *and dropping the sender,*
+        // trigger a shutdown (which closes the control channel). Sending
+        // the shutdown signal again forces the shutdown.
+        while shutdown.recv().await.is_some() {
+            if control_sender.send(Control::Shutdown).is_err() {
+                break;
+            }
+        }
+    });
 
-    /// Daemon failed (non-zero exit code).
-    DaemonFailed,
+    run_with_control(config, control_receiver).await
 }
 
 /// Runs a Ground Control specification, returning only when all of the
 /// processes have stopped (either because one process triggered a
-/// shutdown, or because the `shutdown` signal was triggered).
-pub async fn run(config: Config, mut shutdown: mpsc::UnboundedReceiver<()>) -> Result<(), Error> {
+/// shutdown, or because a [`Control::Shutdown`] message was received).
+/// [`Control`] messages received during startup are handled once all of
+/// the processes have started. Returns the reason for the shutdown.
+pub async fn run_with_control(
+    mut config: Config,
+    mut control: mpsc::UnboundedReceiver<Control>,
+) -> Result<Shutdown, Error> {
     tracing::info!("Ground Control starting.");
 
     // Create the shutdown channel, which will be used to initiate the
@@ -60,7 +178,17 @@ pub async fn run(config: Config, mut shutdown: mpsc::UnboundedReceiver<()>) -> R
     // triggered by a shutdown signal, a clean shutdown of a daemon
     // process, or an unexpected shutdown caused by the failure of a
     // daemon process.
-    let (shutdown_sender, mut shutdown_receiver) = mpsc::unbounded_channel::<ShutdownReason>();
+    let (shutdown_sender, mut shutdown_receiver) = mpsc::unbounded_channel::<DaemonStopped>();
+
+    // Take over the systemd notification socket (if Ground Control is a
+    // systemd service) before any process can inherit it.
+    let systemd = Systemd::from_env().map(Arc::new);
+    let keep_alive = systemd.as_ref().map(Systemd::keep_alive);
+
+    // As the init process, reap the orphaned processes that are
+    // reparented to Ground Control (such as those left behind by
+    // double-forking tools), so that they do not linger as zombies.
+    let _reaper = init::is_init().then(init::reap_orphans);
 
     // Set extra environment variables.
     for (key, value) in &config.env {
@@ -68,81 +196,680 @@ pub async fn run(config: Config, mut shutdown: mpsc::UnboundedReceiver<()>) -> R
     }
 
     // Start every process in the order they were found in the config
-    // file.
-    let mut running: Vec<Process> = Vec::with_capacity(config.processes.len());
-    for process_config in config.processes.into_iter() {
-        let process = match process::start_process(process_config, shutdown_sender.clone()).await {
-            Ok(process) => process,
-            Err(err) => {
-                tracing::error!(?err, "Failed to start process; aborting startup procedure");
-
-                // Stop all of the daemon processes that have already
-                // started (otherwise they will block Ground Control
-                // from exiting and thus the container from shutting
-                // down).
-                while let Some(process) = running.pop() {
-                    if let Err(err) = process.stop_process().await {
-                        tracing::error!(?err, "Error stopping process after aborted startup");
-                    }
-                }
+    // file, except that processes are always started after the
+    // processes they depend on (and once those processes are ready).
+    config.validate()?;
+    let order = dependencies::startup_order(&config.processes)?;
+    let levels = dependencies::levels(&config.processes, &order);
+    let edges = dependencies::edges(&config.processes)?;
+    let healthz = Healthz::start(config.healthz.as_deref()).await?;
+    let mut processes: Vec<Option<ProcessConfig>> =
+        config.processes.iter().cloned().map(Some).collect();
 
-                // Manually drop `shutdown_sender` here, and then drain
-                // all of the receiver signals. If we let the channel
-                // auto-drop (which happens at the entrance to this
-                // match arm), then stopping the already-started
-                // processes will generate a bunch of spurious errors,
-                // since they will be unable to send their shutdown
-                // signals. That also generates out-of-order log lines,
-                // since the warnings about those signals may not show
-                // up until *after* Ground Control itself thinks it has
-                // stopped.
-                drop(shutdown_sender);
-                while shutdown_receiver.recv().await.is_some() {}
-
-                // Return the original error, now that everything has
-                // been stopped.
-                return Err(Error::StartupAborted(err));
-            }
+    // Processes that have been started, and their level in the
+    // dependency graph (for parallel shutdowns).
+    let mut running: Vec<Process> = Vec::with_capacity(processes.len());
+    let mut running_levels: Vec<usize> = Vec::with_capacity(processes.len());
+    let mut readiness: Vec<Option<Readiness>> = vec![None; processes.len()];
+    for index in order {
+        let process_config = match processes[index].take() {
+            Some(process_config) => process_config,
+            None => continue,
         };
 
+        // Skipped processes are not started (or stopped), and processes
+        // that depend on them do not wait for them.
+        if !process_config.is_enabled(&config.profiles) {
+            tracing::info!(process = %process_config.name, "Process is not in an active profile; skipping process");
+            continue;
+        }
+
+        if !process_config.runs_on_current_platform() {
+            tracing::info!(process = %process_config.name, platform = %PlatformConfig::current(), "Process does not run on this platform; skipping process");
+            continue;
+        }
+
+        if let Err(reason) = process::check_condition(&process_config).await {
+            tracing::info!(process = %process_config.name, ?reason, "Condition not met; skipping process");
+            continue;
+        }
+
+        let dependencies = dependencies_of(index, &edges, &readiness);
+        let process =
+            match process::start_process(process_config, dependencies, shutdown_sender.clone())
+                .await
+            {
+                Ok(process) => process,
+                Err(err) => {
+                    tracing::error!(?err, "Failed to start process; aborting startup procedure");
+
+                    // Stop all of the daemon processes that have already
+                    // started (otherwise they will block Ground Control
+                    // from exiting and thus the container from shutting
+                    // down).
+                    while let Some(process) = running.pop() {
+                        if let Err(err) = process.stop_process().await {
+                            tracing::error!(?err, "Error stopping process after aborted startup");
+                        }
+                    }
+
+                    // Manually drop `shutdown_sender` here, and then drain
+                    // all of the receiver signals. If we let the channel
+                    // auto-drop (which happens at the entrance to this
+                    // match arm), then stopping the already-started
+                    // processes will generate a bunch of spurious errors,
+                    // since they will be unable to send their shutdown
+                    // signals. That also generates out-of-order log lines,
+                    // since the warnings about those signals may not show
+                    // up until *after* Ground Control itself thinks it has
+                    // stopped.
+                    drop(shutdown_sender);
+                    while shutdown_receiver.recv().await.is_some() {}
+
+                    if init::is_init() {
+                        init::kill_descendants();
+                    }
+
+                    // Return the original error, now that everything has
+                    // been stopped.
+                    return Err(Error::StartupAborted(err));
+                }
+            };
+
+        readiness[index] = Some(process.readiness());
         running.push(process);
+        running_levels.push(levels[index]);
+        healthz.update(Stage::Starting, &running);
     }
 
-    // Convert an external shutdown signal into a shutdown message.
-    let external_shutdown_sender = shutdown_sender.clone();
-    tokio::spawn(async move {
-        // Both sending the shutdown signal, *and dropping the sender,*
-        // trigger a shutdown.
-        let _ = shutdown.recv().await;
-        let _ = external_shutdown_sender.send(ShutdownReason::GracefulShutdown);
-    });
-
     tracing::info!("Startup phase completed; waiting for shutdown signal or any process to exit.");
+    healthz.update(Stage::Running, &running);
+    let announcer = tokio::spawn(announce_ready(
+        running.iter().map(Process::readiness).collect(),
+        config.ready_file.clone(),
+        config.ready_command.clone(),
+        systemd.clone(),
+    ));
 
-    let shutdown_reason = shutdown_receiver
-        .recv()
-        .await
-        .expect("All shutdown senders closed without sending a shutdown signal.");
+    // Replicated processes can be scaled once they have started.
+    let mut templates = replica_templates(&running);
+
+    // From now on, the systemd watchdog (if any) is pinged by the loop
+    // that handles the control messages and process exits, so that
+    // systemd restarts Ground Control if that loop gets stuck.
+    drop(keep_alive);
+    let mut watchdog = systemd
+        .as_ref()
+        .and_then(|systemd| systemd.watchdog_interval())
+        .map(tokio::time::interval);
+
+    // Set unless the shutdown was requested.
+    let stopped = loop {
+        tokio::select! {
+            _ = tick(&mut watchdog) => {
+                if let Some(systemd) = &systemd {
+                    systemd.notify("WATCHDOG=1").await;
+                }
+            }
+            stopped = shutdown_receiver.recv() => {
+                break Some(stopped
+                    .expect("All shutdown senders closed without sending a shutdown signal."));
+            }
+            message = control.recv() => match message {
+                // Both sending the shutdown message, *and dropping the
+                // sender,* trigger a shutdown.
+                Some(Control::Shutdown) | None => break None,
+                Some(Control::Reload(name)) => reload_processes(&running, name.as_deref()),
+                Some(Control::Signal(signal)) => forward_signal(&running, signal),
+                Some(Control::Status) => log_status(&running).await,
+                Some(Control::Apply(new_config)) => {
+                    match apply_config(&new_config, &mut running, &mut running_levels, &shutdown_sender).await {
+                        Ok(()) => {
+                            config = *new_config;
+                            templates = replica_templates(&running);
+                        }
+                        Err(err) => {
+                            tracing::error!(?err, "Invalid configuration; keeping the current configuration");
+                        }
+                    }
+                    healthz.update(Stage::Running, &running);
+                }
+                Some(Control::Scale { name, replicas }) => match templates.get(&name) {
+                    Some(template) => {
+                        scale_process(
+                            &config,
+                            template,
+                            replicas,
+                            &mut running,
+                            &mut running_levels,
+                            &shutdown_sender,
+                        )
+                        .await;
+                        healthz.update(Stage::Running, &running);
+                    }
+                    None => {
+                        tracing::warn!(process = %name, "Scale requested, but there is no (started) replicated process with that name");
+                    }
+                },
+            },
+        }
+    };
 
     // Either one process exited or we received a stop signal; stop all
     // of the processes in the *reverse* order in which they were
-    // started. Note that "stop" means both `stop` (*if* the process is
-    // a daemon process that is still running) and `post`.
+    // started (which stops dependents before their dependencies). Note
+    // that "stop" means both `stop` (*if* the process is a daemon
+    // process that is still running) and `post`.
     tracing::info!("Completion signal triggered; shutting down all processes");
+    healthz.update(Stage::Stopping, &running);
+    announcer.abort();
+    if let Some(systemd) = &systemd {
+        systemd.notify("STOPPING=1").await;
+    }
+    let _keep_alive = systemd.as_ref().map(Systemd::keep_alive);
+    if let Some(ready_file) = &config.ready_file {
+        match tokio::fs::remove_file(ready_file).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => tracing::warn!(path = %ready_file, ?err, "Error removing ready file"),
+        }
+    }
 
-    while let Some(process) = running.pop() {
-        if let Err(err) = process.stop_process().await {
-            tracing::error!(?err, "Error stopping process");
+    // Processes that are still being stopped once the `shutdown_timeout`
+    // has elapsed, or once the shutdown is requested again, are killed
+    // (along with their `pre-stop`, `stop`, and `post` commands, the
+    // rest of which are skipped), which ends the shutdown.
+    let killers: Vec<Killer> = running.iter().map(Process::killer).collect();
+    let kill_all = || {
+        for killer in &killers {
+            killer.kill();
+        }
+    };
+    let stop_all = async {
+        if config.parallel_shutdown {
+            stop_processes_by_level(running, running_levels).await;
+        } else {
+            while let Some(process) = running.pop() {
+                if let Err(err) = process.stop_process().await {
+                    tracing::error!(?err, "Error stopping process");
+                }
+            }
+        }
+    };
+    tokio::pin!(stop_all);
+
+    let timeout = async {
+        match config.shutdown_timeout {
+            Some(shutdown_timeout) => {
+                tokio::time::sleep(shutdown_timeout).await;
+                shutdown_timeout
+            }
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(timeout);
+
+    // The shutdown was already requested once, unless a daemon triggered
+    // it.
+    let mut shutdown_requested = stopped.is_none();
+    let mut control_closed = false;
+    let mut killed = false;
+    loop {
+        tokio::select! {
+            () = &mut stop_all => break,
+            shutdown_timeout = &mut timeout, if !killed => {
+                tracing::warn!(elapsed = ?shutdown_timeout, "Shutdown exceeded its time limit; killing remaining processes");
+                kill_all();
+                killed = true;
+            }
+            message = control.recv(), if !killed && !control_closed => match message {
+                Some(Control::Shutdown) if shutdown_requested => {
+                    tracing::warn!("Shutdown requested again; killing remaining processes");
+                    kill_all();
+                    killed = true;
+                }
+                Some(Control::Shutdown) => shutdown_requested = true,
+                Some(message) => tracing::debug!(?message, "Ignoring control message during shutdown"),
+                None => control_closed = true,
+            },
         }
     }
 
-    tracing::info!("All processes have exited; Ground Control shutting down.");
+    // As the init process, make sure that nothing is left running.
+    if init::is_init() {
+        init::kill_descendants();
+    }
 
     // Clean shutdowns (a daemon that exited with a non-error exit code,
     // or a graceful shutdown request) are success, abnormal shutdowns
     // are errors.
-    match shutdown_reason {
-        ShutdownReason::GracefulShutdown | ShutdownReason::DaemonExited => Ok(()),
-        ShutdownReason::DaemonFailed => Err(Error::AbnormalShutdown),
+    let result = match stopped {
+        None => Ok(Shutdown::Requested),
+        Some(DaemonStopped {
+            process,
+            reason: ShutdownReason::DaemonExited(exit_status),
+        }) => Ok(Shutdown::DaemonExited {
+            process,
+            exit_code: exit_status.exit_code(),
+        }),
+        // Daemons can fail with an exit code of zero (if zero is not one
+        // of their `allowed-exit-codes`), which must still be reported
+        // as a failure.
+        Some(DaemonStopped {
+            process,
+            reason: ShutdownReason::DaemonFailed(exit_status),
+        }) => Err(Error::AbnormalShutdown {
+            process,
+            exit_code: exit_status.exit_code().max(1),
+        }),
+    };
+
+    tracing::info!(reason = ?result, "All processes have exited; Ground Control shutting down.");
+    result
+}
+
+/// Returns the dependencies of the process at the given index, given the
+/// readiness of the processes that have been started. Processes only
+/// wait for their dependencies (including the processes in the previous
+/// phase), not for the processes they are merely ordered after.
+fn dependencies_of(
+    index: usize,
+    edges: &[dependencies::Edge],
+    readiness: &[Option<Readiness>],
+) -> Vec<Dependency> {
+    edges
+        .iter()
+        .filter(|edge| edge.to == index)
+        .filter_map(|edge| {
+            let required = match edge.kind {
+                EdgeKind::Requires | EdgeKind::Phase => true,
+                EdgeKind::Wants => false,
+                EdgeKind::After => return None,
+            };
+
+            readiness[edge.from].clone().map(|readiness| Dependency {
+                readiness,
+                required,
+                propagate: edge.propagate,
+            })
+        })
+        .collect()
+}
+
+/// Returns the templates from which new instances of the replicated
+/// processes are created (that is, the instances that were started,
+/// without their index).
+fn replica_templates(running: &[Process]) -> HashMap<String, ProcessConfig> {
+    let mut templates: HashMap<String, ProcessConfig> = HashMap::new();
+    for process in running {
+        let process_config = process.config();
+        if let Some(index) = process_config.instance {
+            if let Some(name) = process_config.name.strip_suffix(&format!("-{index}")) {
+                let mut template = process_config.clone();
+                template.name = name.to_owned();
+                template.instance = None;
+                templates.entry(name.to_owned()).or_insert(template);
+            }
+        }
+    }
+    templates
+}
+
+/// Applies a new configuration to the running processes. Processes
+/// that are no longer in the configuration (or no longer enabled), and
+/// processes whose configuration changed, are stopped (dependents
+/// first); then the processes that are not running are started in the
+/// startup order of the new configuration, waiting for their
+/// dependencies as they do during startup. Processes that depend on a
+/// restarted process are not restarted themselves (unless they changed
+/// too). Returns an error, without changing anything, if the
+/// configuration is invalid; failures to start or stop processes are
+/// logged, but do not trigger a shutdown. Logs a summary of the processes
+/// that were added, changed, and removed.
+async fn apply_config(
+    config: &Config,
+    running: &mut Vec<Process>,
+    running_levels: &mut Vec<usize>,
+    process_stopped: &mpsc::UnboundedSender<DaemonStopped>,
+) -> eyre::Result<()> {
+    config.validate()?;
+    let order = dependencies::startup_order(&config.processes)?;
+    let levels = dependencies::levels(&config.processes, &order);
+    let edges = dependencies::edges(&config.processes)?;
+    let find = |name: &str| {
+        config
+            .processes
+            .iter()
+            .position(|process| process.name == name)
+    };
+    let is_enabled = |process: &ProcessConfig| {
+        process.is_enabled(&config.profiles) && process.runs_on_current_platform()
+    };
+
+    tracing::info!("Applying new configuration");
+    for (key, value) in &config.env {
+        std::env::set_var(key, value);
+    }
+
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    let mut added = Vec::new();
+    for position in (0..running.len()).rev() {
+        let name = running[position].name().to_owned();
+        match find(&name).map(|index| &config.processes[index]) {
+            Some(process) if is_enabled(process) && process == running[position].config() => {
+                continue
+            }
+            Some(process) if is_enabled(process) => {
+                tracing::info!(process = %name, "Process changed; stopping process");
+                changed.push(name);
+            }
+            _ => {
+                tracing::info!(process = %name, "Process was removed; stopping process");
+                removed.push(name);
+            }
+        }
+
+        let process = running.remove(position);
+        running_levels.remove(position);
+        if let Err(err) = process.stop_process().await {
+            tracing::error!(?err, "Error stopping process");
+        }
+    }
+
+    let mut readiness: Vec<Option<Readiness>> = config
+        .processes
+        .iter()
+        .map(|process_config| {
+            running
+                .iter()
+                .find(|process| process.name() == process_config.name)
+                .map(Process::readiness)
+        })
+        .collect();
+    for &index in &order {
+        let process_config = &config.processes[index];
+        if readiness[index].is_some() || !is_enabled(process_config) {
+            continue;
+        }
+
+        if let Err(reason) = process::check_condition(process_config).await {
+            tracing::info!(process = %process_config.name, ?reason, "Condition not met; skipping process");
+            continue;
+        }
+
+        let dependencies = dependencies_of(index, &edges, &readiness);
+        match process::start_process(
+            process_config.clone(),
+            dependencies,
+            process_stopped.clone(),
+        )
+        .await
+        {
+            Ok(process) => {
+                readiness[index] = Some(process.readiness());
+                running.push(process);
+                if !changed.contains(&process_config.name) {
+                    added.push(process_config.name.clone());
+                }
+            }
+            Err(err) => tracing::error!(?err, "Failed to start process"),
+        }
+    }
+
+    // Keep the processes in the startup order of the new configuration
+    // (so that they are stopped in the right order during shutdown).
+    running.sort_by_key(|process| {
+        find(process.name()).and_then(|index| order.iter().position(|&i| i == index))
+    });
+    *running_levels = running
+        .iter()
+        .map(|process| find(process.name()).map_or(0, |index| levels[index]))
+        .collect();
+
+    removed.reverse();
+    changed.reverse();
+    tracing::info!(
+        ?added,
+        ?changed,
+        ?removed,
+        "Applied new configuration ({} added, {} changed, {} removed)",
+        added.len(),
+        changed.len(),
+        removed.len()
+    );
+    Ok(())
+}
+
+/// Waits for the next tick of the interval, or forever if there is no
+/// interval.
+async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Creates the ready file, and runs the ready command, of the
+/// specification (if any), and notifies systemd (if Ground Control is a
+/// systemd service), once every started process is ready. Failures are
+/// logged, but do not trigger a shutdown.
+async fn announce_ready(
+    readiness: Vec<Readiness>,
+    ready_file: Option<String>,
+    ready_command: Option<CommandConfig>,
+    systemd: Option<Arc<Systemd>>,
+) {
+    if ready_file.is_none() && ready_command.is_none() && systemd.is_none() {
+        return;
+    }
+
+    for readiness in readiness {
+        if let Err(err) = readiness.wait().await {
+            tracing::warn!(?err, "Not all processes became ready");
+            return;
+        }
+    }
+
+    tracing::info!("All processes are ready");
+    if let Some(ready_file) = ready_file {
+        if let Err(err) = tokio::fs::write(&ready_file, "").await {
+            tracing::warn!(path = %ready_file, ?err, "Error creating ready file");
+        }
+    }
+
+    if let Some(systemd) = systemd {
+        systemd.notify("READY=1").await;
+    }
+
+    if let Some(ready_command) = ready_command {
+        if let Err(err) =
+            process::run_process_command("groundcontrol", ProcessPhase::Ready, &ready_command, None)
+                .await
+        {
+            tracing::warn!(?err, "Ready command failed");
+        }
+    }
+}
+
+/// Stops the processes one dependency level at a time, starting with the
+/// highest level (the processes that nothing else depends on); all of
+/// the processes in a level are stopped concurrently.
+async fn stop_processes_by_level(processes: Vec<Process>, levels: Vec<usize>) {
+    let mut remaining: Vec<(usize, Process)> = levels.into_iter().zip(processes).collect();
+
+    while let Some(level) = remaining.iter().map(|(level, _)| *level).max() {
+        let (stopping, rest): (Vec<_>, Vec<_>) = remaining
+            .into_iter()
+            .partition(|(process_level, _)| *process_level == level);
+        remaining = rest;
+
+        tracing::debug!(%level, processes = %stopping.len(), "Stopping dependency level");
+
+        let tasks: Vec<_> = stopping
+            .into_iter()
+            .map(|(_, process)| tokio::spawn(process.stop_process()))
+            .collect();
+
+        for task in tasks {
+            match task.await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => tracing::error!(?err, "Error stopping process"),
+                Err(err) => tracing::error!(?err, "Process stop task failed"),
+            }
+        }
+    }
+}
+
+/// Stops the instances of the replicated process above the given number
+/// of replicas (highest index first), then starts the missing instances
+/// next to the existing ones (so that they are stopped in the right
+/// order during shutdown). New instances wait for their dependencies,
+/// but processes that depend on the replicated process do not depend on
+/// the new instances. Failures are logged, but do not trigger a
+/// shutdown.
+async fn scale_process(
+    config: &Config,
+    template: &ProcessConfig,
+    replicas: u32,
+    running: &mut Vec<Process>,
+    running_levels: &mut Vec<usize>,
+    process_stopped: &mpsc::UnboundedSender<DaemonStopped>,
+) {
+    let instance_of = |process: &Process| {
+        process
+            .config()
+            .instance
+            .filter(|index| process.name() == format!("{}-{index}", template.name))
+    };
+
+    tracing::info!(process = %template.name, %replicas, "Scaling replicated process");
+
+    // Instances all have the same dependencies, and so are all at the
+    // level of the instances in the configuration (even if none of them
+    // are running).
+    let level = dependencies::startup_order(&config.processes)
+        .ok()
+        .and_then(|order| {
+            let levels = dependencies::levels(&config.processes, &order);
+            config
+                .processes
+                .iter()
+                .position(|process| {
+                    process.instance.map_or(false, |index| {
+                        process.name == format!("{}-{index}", template.name)
+                    })
+                })
+                .map(|index| levels[index])
+        })
+        .unwrap_or_default();
+
+    while let Some((position, _)) = running
+        .iter()
+        .enumerate()
+        .filter_map(|(position, process)| Some((position, instance_of(process)?)))
+        .filter(|(_, index)| *index >= replicas)
+        .max_by_key(|(_, index)| *index)
+    {
+        let process = running.remove(position);
+        running_levels.remove(position);
+
+        if let Err(err) = process.stop_process().await {
+            tracing::error!(?err, "Error stopping process");
+        }
+    }
+
+    for index in 0..replicas {
+        if running
+            .iter()
+            .any(|process| instance_of(process) == Some(index))
+        {
+            continue;
+        }
+
+        let config = template.instance(index);
+        let dependencies = config
+            .depends_on
+            .iter()
+            .map(|dependency| (dependency, true))
+            .chain(config.wants.iter().map(|dependency| (dependency, false)))
+            .filter_map(|(dependency, required)| {
+                let process = running
+                    .iter()
+                    .find(|process| process.name() == dependency.name)?;
+                Some(Dependency {
+                    readiness: process.readiness(),
+                    required,
+                    propagate: dependency.propagate,
+                })
+            })
+            .collect();
+
+        // New instances go after the running instances (or, if none are
+        // running, after the last process at or below their level).
+        let position = running
+            .iter()
+            .rposition(|process| instance_of(process).is_some())
+            .or_else(|| running_levels.iter().rposition(|&other| other <= level))
+            .map_or(0, |position| position + 1);
+
+        match process::start_process(config, dependencies, process_stopped.clone()).await {
+            Ok(process) => {
+                running.insert(position, process);
+                running_levels.insert(position, level);
+            }
+            Err(err) => {
+                tracing::error!(?err, "Failed to start new instance of replicated process");
+                break;
+            }
+        }
+    }
+}
+
+/// Forwards the signal to every process that forwards it.
+fn forward_signal(running: &[Process], signal: SignalConfig) {
+    for process in running {
+        process.forward_signal(signal);
+    }
+}
+
+/// Logs the status of every process, in the order in which they were
+/// started.
+async fn log_status(running: &[Process]) {
+    tracing::info!(count = %running.len(), "Status of all processes");
+    for process in running {
+        process.log_status().await;
+    }
+}
+
+/// Reloads the named process, or every reloadable process if no name is
+/// given. The reloads finish in the background (so that other control
+/// messages can be handled in the meantime); reload failures are logged,
+/// but do not trigger a shutdown.
+fn reload_processes(running: &[Process], name: Option<&str>) {
+    let processes: Vec<&Process> = match name {
+        Some(name) => running
+            .iter()
+            .filter(|process| process.name() == name)
+            .collect(),
+        None => running
+            .iter()
+            .filter(|process| process.is_reloadable())
+            .collect(),
+    };
+
+    if processes.is_empty() {
+        tracing::warn!(process = ?name, "Reload requested, but there are no matching processes");
+    }
+
+    for process in processes {
+        let reload = process.reload_process();
+        tokio::spawn(async move {
+            if let Err(err) = reload.await {
+                tracing::error!(?err, "Error reloading process");
+            }
+        });
     }
 }
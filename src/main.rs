@@ -14,9 +14,15 @@
     clippy::unwrap_used
 )]
 
+use std::{sync::Arc, time::Duration};
+
 use clap::Parser;
 use color_eyre::eyre::{self, WrapErr};
-use groundcontrol::config::Config;
+use groundcontrol::{
+    config::{Config, ConfigFormat},
+    graph::GraphFormat,
+    Control,
+};
 use tokio::{
     signal::unix::{signal, SignalKind},
     sync::mpsc,
@@ -25,14 +31,77 @@ use tokio::{
 #[derive(Parser)]
 #[clap(about, long_about = None)]
 struct Cli {
-    /// Check the configuration file for errors, but do not start any
-    /// processes.
+    /// Check the configuration file for errors (including that its users
+    /// and programs exist), but do not start any processes.
     #[clap(long)]
     check: bool,
 
-    config_file: String,
+    /// Print the startup graph of the configuration file (as `dot` or
+    /// `mermaid`), but do not start any processes.
+    #[clap(long, value_name = "FORMAT")]
+    graph: Option<GraphFormat>,
+
+    /// Print the effective configuration (after merging, expanding
+    /// replicas, filling in defaults, and expanding environment
+    /// variables) as `toml` (the default) or `json` (with
+    /// `--print-config=json`), but do not start any processes.
+    #[clap(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "toml"
+    )]
+    print_config: Option<ConfigFormat>,
+
+    /// Reject unknown keys in the configuration, even if the
+    /// configuration sets `strict = false`.
+    #[clap(long)]
+    strict: bool,
+
+    /// Watch the configuration files (or directories) for changes, and apply
+    /// them to the running processes: added processes are started,
+    /// removed processes are stopped, and changed processes are
+    /// restarted. Only local files can be watched.
+    #[clap(long)]
+    watch: bool,
+
+    /// Activate the given profile (can be repeated). Overrides the
+    /// `GROUNDCONTROL_PROFILES` environment variable (a comma-separated
+    /// list of profiles), which overrides the `profiles` in the
+    /// configuration file.
+    #[clap(long = "profile", value_name = "PROFILE")]
+    profiles: Vec<String>,
+
+    /// Format of the configuration file (`toml`, `json`, or `supervisord`
+    /// or `compose` to import a `supervisord.conf` or Compose file);
+    /// defaults to JSON for `.json` files, to Compose for files with the
+    /// standard Compose names, and to TOML otherwise (including for
+    /// directories, which merge every file in the format).
+    #[clap(long, value_name = "FORMAT")]
+    format: Option<ConfigFormat>,
+
+    /// Expected SHA-256 checksum (in hexadecimal) of a configuration that
+    /// is fetched from a URL.
+    #[clap(long, value_name = "CHECKSUM")]
+    config_sha256: Option<String>,
+
+    /// Expected `ETag` of a configuration that is fetched from a URL.
+    #[clap(long, value_name = "ETAG")]
+    config_etag: Option<String>,
+
+    /// Configuration file, directory of configuration files, `http://` or
+    /// `https://` URL to fetch the configuration from, or `-` to read the
+    /// configuration from standard input. Multiple configurations are
+    /// merged in order, with later configurations overriding (and adding
+    /// to) the earlier ones. Defaults to the configuration in the
+    /// `GROUNDCONTROL_CONFIG` environment variable.
+    config_files: Vec<String>,
 }
 
+// The `tokio::main` expansion `expect`s the runtime, which trips
+// `unwrap_in_result` on the generated `main`.
+#[allow(clippy::unwrap_in_result)]
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     // Install color-eyre hooks.
@@ -49,14 +118,43 @@ async fn main() -> eyre::Result<()> {
     // Parse the command line arguments.
     let cli = Cli::parse();
 
-    // Read and parse the config file.
-    let config_file = tokio::fs::read_to_string(cli.config_file)
-        .await
-        .wrap_err("Failed to read config file")?;
-    let config: Config = toml::from_str(&config_file).wrap_err("Failed to parse config file")?;
+    if cli.watch && (cli.config_files.is_empty() || reads_stdin(&cli)) {
+        return Err(eyre::eyre!(
+            "`--watch` needs a config file (or directory) to watch"
+        ));
+    }
+    if cli.watch
+        && cli.config_files.iter().any(|config_file| {
+            config_file.starts_with("https://") || config_file.starts_with("http://")
+        })
+    {
+        return Err(eyre::eyre!(
+            "`--watch` can only watch local config files (send `SIGHUP` to reload a config URL)"
+        ));
+    }
+
+    // Read and parse the config files (or directories), or else the
+    // config in the environment.
+    let mut config = load_config(&cli)?;
 
     // We're done if this was only a config file check.
     if cli.check {
+        for key in &config.unknown_keys {
+            eprintln!("Warning: ignoring unknown config key {key}");
+        }
+        return config.check().wrap_err("Invalid config file");
+    }
+
+    // Or if we only needed to print the effective configuration.
+    if let Some(format) = cli.print_config {
+        config.interpolate()?;
+        print!("{}", config.render(format)?);
+        return Ok(());
+    }
+
+    // Or if we only needed to print the startup graph.
+    if let Some(format) = cli.graph {
+        print!("{}", groundcontrol::graph::render(&config, format)?);
         return Ok(());
     }
 
@@ -74,42 +172,184 @@ async fn main() -> eyre::Result<()> {
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    // Create the external shutdown signal (used to shut down Ground
-    // Control on UNIX signals).
-    let (shutdown_sender, mut shutdown_receiver) = mpsc::unbounded_channel();
+    for key in &config.unknown_keys {
+        tracing::warn!("Ignoring unknown config key {key}");
+    }
+
+    // Create the external control channel (used to shut down Ground
+    // Control, or reload its processes, on UNIX signals).
+    let (control_sender, mut control_receiver) = mpsc::unbounded_channel();
+
+    // A second shutdown signal (received while the processes are being
+    // stopped) kills the remaining daemons.
+    let sigint_control_sender = control_sender.clone();
+    tokio::spawn(async move {
+        let mut sigint =
+            signal(SignalKind::interrupt()).expect("Failed to register SIGINT handler");
+        while sigint.recv().await.is_some() {
+            if sigint_control_sender.send(Control::Shutdown).is_err() {
+                break;
+            }
+        }
+    });
+
+    let sigterm_control_sender = control_sender.clone();
+    tokio::spawn(async move {
+        let mut sigterm =
+            signal(SignalKind::terminate()).expect("Failed to register SIGTERM handler");
+        while sigterm.recv().await.is_some() {
+            if sigterm_control_sender.send(Control::Shutdown).is_err() {
+                break;
+            }
+        }
+    });
 
-    let sigint_shutdown_sender = shutdown_sender.clone();
+    let sigusr1_control_sender = control_sender.clone();
     tokio::spawn(async move {
-        signal(SignalKind::interrupt())
-            .expect("Failed to register SIGINT handler")
-            .recv()
-            .await;
-        let _ = sigint_shutdown_sender.send(());
+        let mut sigusr1 =
+            signal(SignalKind::user_defined1()).expect("Failed to register SIGUSR1 handler");
+        while sigusr1.recv().await.is_some() {
+            if sigusr1_control_sender.send(Control::Status).is_err() {
+                break;
+            }
+        }
     });
 
-    let sigterm_shutdown_sender = shutdown_sender.clone();
+    let sigusr2_control_sender = control_sender.clone();
     tokio::spawn(async move {
-        signal(SignalKind::terminate())
-            .expect("Failed to register SIGTERM handler")
-            .recv()
-            .await;
-        let _ = sigterm_shutdown_sender.send(());
+        let mut sigusr2 =
+            signal(SignalKind::user_defined2()).expect("Failed to register SIGUSR2 handler");
+        while sigusr2.recv().await.is_some() {
+            if sigusr2_control_sender.send(Control::Reload(None)).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Reload the config file on SIGHUP (as daemons usually do), which
+    // applies its changes to the running processes.
+    let cli = Arc::new(cli);
+    let sighup_control_sender = control_sender.clone();
+    let sighup_cli = Arc::clone(&cli);
+    tokio::spawn(async move {
+        let mut sighup = signal(SignalKind::hangup()).expect("Failed to register SIGHUP handler");
+        while sighup.recv().await.is_some() {
+            if reads_stdin(&sighup_cli) {
+                tracing::warn!(
+                    "Reload requested, but the config was read from standard input; ignoring"
+                );
+                continue;
+            }
+
+            tracing::info!("Reload requested; reloading config");
+            match load_config_blocking(&sighup_cli).await {
+                Ok(config) => {
+                    if sighup_control_sender
+                        .send(Control::Apply(Box::new(config)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(error = %format!("{err:#}"), "Invalid config; keeping the current configuration")
+                }
+            }
+        }
     });
 
+    // Apply the changes to the config file (checked every few seconds).
+    if cli.watch {
+        let watch_control_sender = control_sender.clone();
+        let mut current = config.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            let mut last_error = None;
+            loop {
+                interval.tick().await;
+                match load_config_blocking(&cli).await {
+                    Ok(new) if new == current => {}
+                    Ok(new) => {
+                        last_error = None;
+                        current = new.clone();
+                        tracing::info!("Config file changed");
+                        if watch_control_sender
+                            .send(Control::Apply(Box::new(new)))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        let error = format!("{err:#}");
+                        if last_error.as_ref() != Some(&error) {
+                            tracing::error!(%error, "Config file changed, but is invalid; keeping the current configuration");
+                            last_error = Some(error);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    // Forward the signals that the processes ask for.
+    for forwarded in config.forwarded_signals() {
+        let signal_control_sender = control_sender.clone();
+        let mut signals = signal(SignalKind::from_raw(
+            nix::sys::signal::Signal::from(forwarded) as i32,
+        ))
+        .wrap_err_with(|| format!("Failed to register {forwarded} handler"))?;
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                if signal_control_sender
+                    .send(Control::Signal(forwarded))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+
     // Run the Ground Control specification, *unless* we are in
     // break-glass mode, in which case we freeze startup and just wait
     // for the shutdown signal. (this gives the admin a chance to SSH
     // into a machine that is in a startup-crash loop, perhaps due to an
     // issue on an attached, persistent storage volume)
     if std::env::var_os("BREAK_GLASS").is_none() {
-        groundcontrol::run(config, shutdown_receiver).await?;
+        if let Err(err) = groundcontrol::run_with_control(config, control_receiver).await {
+            // Exit with the exit code of the daemon that caused the
+            // shutdown, so that orchestrators can tell why it failed.
+            if let groundcontrol::Error::AbnormalShutdown { exit_code, .. } = &err {
+                tracing::error!(%exit_code, "{err}");
+                std::process::exit(*exit_code);
+            }
+
+            return Err(err.into());
+        }
     } else {
         tracing::info!("BREAK GLASS MODE: no processes will be started");
 
-        shutdown_receiver
-            .recv()
-            .await
-            .expect("All shutdown senders closed without sending a shutdown signal.");
+        loop {
+            match control_receiver
+                .recv()
+                .await
+                .expect("All control senders closed without sending a shutdown signal.")
+            {
+                Control::Shutdown => break,
+                Control::Reload(_) => tracing::info!("BREAK GLASS MODE: ignoring reload request"),
+                Control::Scale { .. } => {
+                    tracing::info!("BREAK GLASS MODE: ignoring scale request")
+                }
+                Control::Signal(signal) => {
+                    tracing::info!(%signal, "BREAK GLASS MODE: not forwarding signal")
+                }
+                Control::Status => tracing::info!("BREAK GLASS MODE: no processes are running"),
+                Control::Apply(_) => {
+                    tracing::info!("BREAK GLASS MODE: ignoring new configuration")
+                }
+            }
+        }
 
         tracing::info!(
             "Shutdown signal triggered (make sure to clear the `BREAK_GLASS` environment variable)"
@@ -118,3 +358,76 @@ async fn main() -> eyre::Result<()> {
 
     Ok(())
 }
+
+/// How often the config file is checked for changes (with `--watch`).
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Returns `true` if (one of) the config files is read from standard
+/// input, and so cannot be read again.
+fn reads_stdin(cli: &Cli) -> bool {
+    cli.config_files
+        .iter()
+        .any(|config_file| config_file == "-")
+}
+
+/// Same as [`load_config`], but on a blocking thread (reading the config
+/// files, or fetching the config URL, blocks), for use once Ground
+/// Control is running.
+async fn load_config_blocking(cli: &Arc<Cli>) -> eyre::Result<Config> {
+    let cli = Arc::clone(cli);
+    tokio::task::spawn_blocking(move || load_config(&cli))
+        .await
+        .wrap_err("Failed to load config")?
+}
+
+/// Loads and validates the config files (or directories), or else the
+/// config in the environment, and selects the active profiles.
+fn load_config(cli: &Cli) -> eyre::Result<Config> {
+    let is_url = matches!(cli.config_files.as_slice(), [config_file]
+        if config_file.starts_with("https://") || config_file.starts_with("http://"));
+    if !is_url && (cli.config_sha256.is_some() || cli.config_etag.is_some()) {
+        return Err(eyre::eyre!(
+            "`--config-sha256` and `--config-etag` can only be used with a single config URL"
+        ));
+    }
+
+    let mut config = match cli.config_files.as_slice() {
+        [url] if is_url => Config::fetch(
+            url,
+            cli.format,
+            cli.config_sha256.as_deref(),
+            cli.config_etag.as_deref(),
+        )?,
+        [] => match std::env::var("GROUNDCONTROL_CONFIG") {
+            Ok(config) => Config::parse(&config, cli.format.unwrap_or(ConfigFormat::Toml))
+                .wrap_err("Failed to parse `GROUNDCONTROL_CONFIG`")?,
+            Err(_) => {
+                return Err(eyre::eyre!(
+                    "No config file was given, and `GROUNDCONTROL_CONFIG` is not set"
+                ))
+            }
+        },
+        config_files => Config::load_all(config_files.iter().map(String::as_str), cli.format)?,
+    };
+    if cli.strict {
+        config.strict = true;
+        config
+            .check_unknown_keys()
+            .wrap_err("Invalid config file")?;
+    }
+    config.validate().wrap_err("Invalid config file")?;
+
+    // Select the active profiles.
+    if !cli.profiles.is_empty() {
+        config.profiles = cli.profiles.clone();
+    } else if let Ok(profiles) = std::env::var("GROUNDCONTROL_PROFILES") {
+        config.profiles = profiles
+            .split(',')
+            .map(str::trim)
+            .filter(|profile| !profile.is_empty())
+            .map(String::from)
+            .collect();
+    }
+
+    Ok(config)
+}
@@ -0,0 +1,129 @@
+//! Overrides individual configuration values with environment variables
+//! (such as `GC__PROCESSES__worker__RUN`), so that deployment tooling can
+//! tweak a configuration without templating it.
+
+use color_eyre::eyre;
+use serde_json::{Map, Value};
+
+/// Prefix of the environment variables that override configuration
+/// values.
+const PREFIX: &str = "GC__";
+
+/// Returns the overrides in the environment (the variables that start
+/// with `GC__`), sorted by name.
+pub(crate) fn from_env() -> Vec<(String, String)> {
+    let mut overrides: Vec<(String, String)> = std::env::vars()
+        .filter(|(name, _)| name.starts_with(PREFIX))
+        .collect();
+    overrides.sort();
+    overrides
+}
+
+/// Applies the overrides to the configuration (as its JSON value).
+///
+/// The name of every override is the path to the value, after the
+/// `GC__` prefix, with `__` between the keys. Keys are matched ignoring
+/// case and the difference between `-` and `_` (except in `env` tables),
+/// and elements of arrays are given by their index, or by their `name`
+/// (so `GC__PROCESSES__0__RUN` and `GC__PROCESSES__worker__RUN` both set
+/// the `run` command of the first process, if it is named `worker`).
+/// Missing keys are added. Values are parsed as TOML values (such as
+/// `30`, `true`, or `["a", "b"]`), and are strings otherwise.
+pub(crate) fn apply(config: &mut Value, overrides: &[(String, String)]) -> eyre::Result<()> {
+    for (name, value) in overrides {
+        let path = name.strip_prefix(PREFIX).unwrap_or(name);
+        let mut target = &mut *config;
+        let mut in_env = false;
+        for (depth, key) in path.split("__").enumerate() {
+            if key.is_empty() {
+                return Err(eyre::eyre!("Override `{name}` has an empty key"));
+            }
+
+            let style = match (depth, in_env) {
+                (_, true) => KeyStyle::Verbatim,
+                (0, false) => KeyStyle::SnakeCase,
+                (_, false) => KeyStyle::KebabCase,
+            };
+            in_env = same_key(key, "env");
+            target = child(target, key, style).ok_or_else(|| {
+                eyre::eyre!("Override `{name}` does not match the configuration (at `{key}`)")
+            })?;
+        }
+
+        *target = parse_value(value);
+    }
+
+    Ok(())
+}
+
+/// How keys that are not in the configuration yet are named.
+#[derive(Copy, Clone, Debug)]
+enum KeyStyle {
+    /// As given (for the names of environment variables).
+    Verbatim,
+
+    /// In lowercase, with `_` between words (for the top-level settings).
+    SnakeCase,
+
+    /// In lowercase, with `-` between words (for all other settings).
+    KebabCase,
+}
+
+/// Returns the value at the given key of the table (adding the key if
+/// needed), or the element of the array with the given index or name.
+/// Missing tables are created.
+fn child<'a>(value: &'a mut Value, key: &str, style: KeyStyle) -> Option<&'a mut Value> {
+    if value.is_null() {
+        *value = Value::Object(Map::new());
+    }
+
+    match value {
+        Value::Array(elements) => {
+            let index = match key.parse::<usize>() {
+                Ok(index) => index,
+                Err(_) => elements.iter().position(|element| {
+                    element
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .map_or(false, |name| same_key(name, key))
+                })?,
+            };
+            elements.get_mut(index)
+        }
+        Value::Object(table) => {
+            let existing = table
+                .keys()
+                .find(|existing| match style {
+                    KeyStyle::Verbatim => existing.as_str() == key,
+                    KeyStyle::SnakeCase | KeyStyle::KebabCase => same_key(existing, key),
+                })
+                .cloned();
+            let key = existing.unwrap_or_else(|| match style {
+                KeyStyle::Verbatim => key.to_owned(),
+                KeyStyle::SnakeCase => key.to_lowercase().replace('-', "_"),
+                KeyStyle::KebabCase => key.to_lowercase().replace('_', "-"),
+            });
+            Some(table.entry(key).or_insert(Value::Null))
+        }
+        _ => None,
+    }
+}
+
+/// Returns `true` if the keys are the same, ignoring case and the
+/// difference between `-` and `_`.
+fn same_key(a: &str, b: &str) -> bool {
+    let normalize = |c: char| match c {
+        '-' => '_',
+        c => c.to_ascii_lowercase(),
+    };
+    a.len() == b.len() && a.chars().map(normalize).eq(b.chars().map(normalize))
+}
+
+/// Parses the value of an override as a TOML value, or else as a string.
+fn parse_value(value: &str) -> Value {
+    toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .and_then(|value| serde_json::to_value(value).ok())
+        .unwrap_or_else(|| Value::String(value.to_owned()))
+}
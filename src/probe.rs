@@ -0,0 +1,591 @@
+//! Periodically probes running daemons.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use regex::Regex;
+use tokio::{
+    net::UnixDatagram,
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+
+use crate::{
+    config::{
+        HealthCheckConfig, ProbeAction, ProcessConfig, ReadinessCheck, ReadinessCommandConfig,
+        ReadinessHttpConfig, ReadinessPathConfig, WatchdogConfig,
+    },
+    process::{run_process_command, ProcessPhase},
+};
+
+/// Runs a daemon's probes (health check, watchdog, and readiness check)
+/// in the background, reporting when the daemon becomes ready, and when
+/// any of the probes has failed. The probes are stopped when this handle
+/// is dropped.
+#[derive(Debug)]
+pub(crate) struct Probes {
+    failed: mpsc::Receiver<ProbeAction>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Probes {
+    /// Starts all of the probes configured for the given process, given
+    /// the baseline from just before the daemon was started (see
+    /// [`prepare`]).
+    pub(crate) fn start(
+        config: &ProcessConfig,
+        ready: &Arc<watch::Sender<bool>>,
+        baseline: Baseline,
+    ) -> Self {
+        let (sender, failed) = mpsc::channel(1);
+        let mut tasks = Vec::new();
+
+        if let Some(healthcheck) = &config.healthcheck {
+            tasks.push(tokio::spawn(run_healthcheck(
+                config.name.clone(),
+                healthcheck.clone(),
+                sender.clone(),
+                Some(ready.clone()).filter(|_| config.ready == ReadinessCheck::Healthy),
+            )));
+        }
+
+        if let ReadinessCheck::File(path) = &config.ready {
+            tasks.push(tokio::spawn(wait_for_ready_file(
+                config.name.clone(),
+                path.clone(),
+                baseline.ready_file_modified,
+                ready.clone(),
+            )));
+        }
+
+        if let (ReadinessCheck::LogLine(pattern), Some(output)) = (&config.ready, baseline.output) {
+            match Regex::new(pattern) {
+                Ok(pattern) => tasks.push(tokio::spawn(wait_for_ready_log_line(
+                    config.name.clone(),
+                    pattern,
+                    output,
+                    ready.clone(),
+                ))),
+                Err(err) => {
+                    tracing::error!(process = %config.name, ?err, "Invalid `log-line` readiness pattern");
+                }
+            }
+        }
+
+        if let ReadinessCheck::Command(command) = &config.ready {
+            tasks.push(tokio::spawn(wait_for_ready_command(
+                config.name.clone(),
+                command.clone(),
+                sender.clone(),
+                ready.clone(),
+            )));
+        }
+
+        if let Some(socket) = baseline.notify {
+            tasks.push(tokio::spawn(wait_for_ready_notification(
+                config.name.clone(),
+                socket,
+                ready.clone(),
+            )));
+        }
+
+        if let ReadinessCheck::Path(path) = &config.ready {
+            tasks.push(tokio::spawn(wait_for_ready_path(
+                config.name.clone(),
+                path.clone(),
+                sender.clone(),
+                ready.clone(),
+            )));
+        }
+
+        if let ReadinessCheck::Http(http) = &config.ready {
+            tasks.push(tokio::spawn(wait_for_ready_http(
+                config.name.clone(),
+                http.clone(),
+                sender.clone(),
+                ready.clone(),
+            )));
+        }
+
+        if let Some(watchdog) = &config.watchdog {
+            tasks.push(tokio::spawn(run_watchdog(
+                config.name.clone(),
+                watchdog.clone(),
+                sender,
+            )));
+        }
+
+        Self { failed, tasks }
+    }
+
+    /// Waits for any of the probes to fail, then returns the action
+    /// configured for that probe. Waits forever if there are no probes.
+    pub(crate) async fn failed(&mut self) -> ProbeAction {
+        match self.failed.recv().await {
+            Some(action) => action,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for Probes {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Runs the health check command every `interval` (after the
+/// `initial-delay`), reporting a failure once `failure-threshold`
+/// consecutive checks have failed, and (if given a `ready` sender)
+/// reporting that the daemon is ready once `success-threshold`
+/// consecutive checks have succeeded.
+async fn run_healthcheck(
+    process_name: String,
+    config: HealthCheckConfig,
+    failed: mpsc::Sender<ProbeAction>,
+    ready: Option<Arc<watch::Sender<bool>>>,
+) {
+    let mut failures: u32 = 0;
+    let mut successes: u32 = 0;
+
+    tokio::time::sleep(config.initial_delay).await;
+
+    loop {
+        tokio::time::sleep(config.interval).await;
+
+        match run_process_command(
+            &process_name,
+            ProcessPhase::HealthCheck,
+            &config.command,
+            config.timeout,
+        )
+        .await
+        {
+            Ok(()) => {
+                failures = 0;
+                successes = successes.saturating_add(1);
+                if let Some(ready) = ready
+                    .as_ref()
+                    .filter(|_| successes >= config.success_threshold)
+                {
+                    report_ready(&process_name, ready);
+                }
+            }
+            Err(err) => {
+                successes = 0;
+                failures += 1;
+                tracing::warn!(process = %process_name, ?err, %failures, "Health check failed");
+            }
+        }
+
+        if failures >= config.failure_threshold {
+            failures = 0;
+
+            if failed.send(config.action).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// What the probes of a daemon need from just before the daemon was
+/// started.
+#[derive(Debug)]
+pub(crate) struct Baseline {
+    /// Modification time of the process's readiness file (if the process
+    /// has one, and the file exists).
+    ready_file_modified: Option<SystemTime>,
+
+    /// Lines that the daemon writes to stdout and stderr (only captured
+    /// for the `log-line` readiness check).
+    output: Option<mpsc::UnboundedReceiver<String>>,
+
+    /// Socket that the daemon sends its notifications to (only for the
+    /// `notify` readiness check).
+    notify: Option<NotifySocket>,
+}
+
+/// What the daemon must be started with for its probes to work.
+#[derive(Debug, Default)]
+pub(crate) struct ProbeHooks {
+    /// Environment variables to set for the daemon.
+    pub(crate) env: Vec<(&'static str, String)>,
+
+    /// Channel that the daemon's output must be sent to.
+    pub(crate) output: Option<mpsc::UnboundedSender<String>>,
+}
+
+/// Returns the baseline for the probes of the given process, and what
+/// the daemon must be started with. Must be called before the daemon is
+/// started, so that files left behind by a previous instance of the
+/// daemon are not mistaken for the daemon being ready.
+pub(crate) fn prepare(config: &ProcessConfig) -> eyre::Result<(Baseline, ProbeHooks)> {
+    let ready_file_modified = match &config.ready {
+        ReadinessCheck::File(path) => std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok(),
+        _ => None,
+    };
+
+    let (output_sender, output) = match &config.ready {
+        ReadinessCheck::LogLine(_) => {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            (Some(sender), Some(receiver))
+        }
+        _ => (None, None),
+    };
+
+    let mut env = Vec::new();
+    let notify = match &config.ready {
+        ReadinessCheck::Notify => {
+            let socket = NotifySocket::bind().wrap_err_with(|| {
+                format!(
+                    "Error creating notification socket for process \"{}\"",
+                    config.name
+                )
+            })?;
+            env.push(("NOTIFY_SOCKET", socket.path.display().to_string()));
+            Some(socket)
+        }
+        _ => None,
+    };
+
+    let baseline = Baseline {
+        ready_file_modified,
+        output,
+        notify,
+    };
+    let hooks = ProbeHooks {
+        env,
+        output: output_sender,
+    };
+    Ok((baseline, hooks))
+}
+
+/// `sd_notify`-compatible datagram socket, which is removed when it is
+/// dropped.
+#[derive(Debug)]
+struct NotifySocket {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl NotifySocket {
+    /// Binds a new socket in the temporary directory.
+    fn bind() -> std::io::Result<Self> {
+        static NEXT_SOCKET: AtomicU64 = AtomicU64::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "groundcontrol-{}-{}.sock",
+            std::process::id(),
+            NEXT_SOCKET.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let socket = UnixDatagram::bind(&path)?;
+        Ok(Self { socket, path })
+    }
+}
+
+impl Drop for NotifySocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Waits for the daemon to send `READY=1` to its notification socket,
+/// then reports that the daemon is ready. Other notifications are
+/// ignored.
+async fn wait_for_ready_notification(
+    process_name: String,
+    socket: NotifySocket,
+    ready: Arc<watch::Sender<bool>>,
+) {
+    let mut buffer = vec![0; 4096];
+    while let Ok(length) = socket.socket.recv(&mut buffer).await {
+        let message = String::from_utf8_lossy(&buffer[..length]);
+        if message.lines().any(|line| line == "READY=1") {
+            report_ready(&process_name, &ready);
+            break;
+        }
+    }
+}
+
+/// Waits for the daemon to write a line that matches the given pattern
+/// (to stdout or stderr), then reports that the daemon is ready.
+async fn wait_for_ready_log_line(
+    process_name: String,
+    pattern: Regex,
+    mut output: mpsc::UnboundedReceiver<String>,
+    ready: Arc<watch::Sender<bool>>,
+) {
+    while let Some(line) = output.recv().await {
+        if pattern.is_match(&line) {
+            report_ready(&process_name, &ready);
+            break;
+        }
+    }
+}
+
+/// Waits for the readiness file to be created, or for its modification
+/// time to change (from the time before the daemon was started), then
+/// reports that the daemon is ready.
+async fn wait_for_ready_file(
+    process_name: String,
+    path: String,
+    previously_modified: Option<SystemTime>,
+    ready: Arc<watch::Sender<bool>>,
+) {
+    loop {
+        let touched = tokio::fs::metadata(&path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .map_or(false, |modified| Some(modified) != previously_modified);
+
+        if touched {
+            report_ready(&process_name, &ready);
+            break;
+        }
+
+        tokio::time::sleep(READY_FILE_POLL_INTERVAL).await;
+    }
+}
+
+/// Checks for the readiness path (every `interval`, after the
+/// `initial-delay`) until it has been found `success-threshold` times in
+/// a row, then reports that the daemon is ready. Reports a failure (which
+/// stops the daemon) if the path was not found `failure-threshold` times
+/// (if given), or if the daemon is not ready before the timeout.
+async fn wait_for_ready_path(
+    process_name: String,
+    config: ReadinessPathConfig,
+    failed: mpsc::Sender<ProbeAction>,
+    ready: Arc<watch::Sender<bool>>,
+) {
+    let mut failures: u32 = 0;
+    let mut successes: u32 = 0;
+
+    let checks = async {
+        tokio::time::sleep(config.initial_delay).await;
+
+        loop {
+            if tokio::fs::metadata(&config.path).await.is_ok() {
+                successes += 1;
+                if successes >= config.success_threshold {
+                    return true;
+                }
+            } else {
+                successes = 0;
+                failures += 1;
+                if config
+                    .failure_threshold
+                    .map_or(false, |failure_threshold| failures >= failure_threshold)
+                {
+                    return false;
+                }
+            }
+
+            tokio::time::sleep(config.interval).await;
+        }
+    };
+
+    if tokio::time::timeout(config.timeout, checks).await == Ok(true) {
+        report_ready(&process_name, &ready);
+    } else {
+        tracing::error!(process = %process_name, path = %config.path, timeout = ?config.timeout, %failures, "Daemon did not become ready");
+        let _ = failed.send(ProbeAction::Shutdown).await;
+    }
+}
+
+/// Runs the readiness command (every `interval`, after the
+/// `initial-delay`) until it has succeeded `success-threshold` times in
+/// a row, then reports that the daemon is ready. Reports a failure
+/// (which stops the daemon) once the command has failed `retries` times.
+async fn wait_for_ready_command(
+    process_name: String,
+    config: ReadinessCommandConfig,
+    failed: mpsc::Sender<ProbeAction>,
+    ready: Arc<watch::Sender<bool>>,
+) {
+    let mut failures: u32 = 0;
+    let mut successes: u32 = 0;
+
+    tokio::time::sleep(config.initial_delay).await;
+
+    loop {
+        match run_process_command(
+            &process_name,
+            ProcessPhase::Ready,
+            &config.command,
+            config.timeout,
+        )
+        .await
+        {
+            Ok(()) => {
+                successes += 1;
+                if successes >= config.success_threshold {
+                    report_ready(&process_name, &ready);
+                    return;
+                }
+            }
+            Err(err) => {
+                successes = 0;
+                failures += 1;
+                tracing::debug!(process = %process_name, ?err, %failures, "Readiness check failed");
+
+                if failures >= config.retries {
+                    break;
+                }
+            }
+        }
+
+        tokio::time::sleep(config.interval).await;
+    }
+
+    tracing::error!(process = %process_name, retries = %config.retries, "Daemon did not become ready");
+    let _ = failed.send(ProbeAction::Shutdown).await;
+}
+
+/// Polls the readiness URL (every `interval`, after the `initial-delay`)
+/// until it has responded with the expected status code
+/// `success-threshold` times in a row, then reports that the daemon is
+/// ready. Reports a failure (which stops the daemon) once
+/// `failure-threshold` consecutive requests have failed.
+async fn wait_for_ready_http(
+    process_name: String,
+    config: ReadinessHttpConfig,
+    failed: mpsc::Sender<ProbeAction>,
+    ready: Arc<watch::Sender<bool>>,
+) {
+    let mut failures: u32 = 0;
+    let mut successes: u32 = 0;
+
+    tokio::time::sleep(config.initial_delay).await;
+
+    loop {
+        let succeeded = match http_status(&config.url, config.timeout).await {
+            Ok(status) if status == config.status => true,
+            Ok(status) => {
+                tracing::debug!(process = %process_name, url = %config.url, %status, "Daemon is not ready yet");
+                false
+            }
+            Err(err) => {
+                tracing::debug!(process = %process_name, url = %config.url, ?err, "Readiness request failed");
+                false
+            }
+        };
+
+        if succeeded {
+            failures = 0;
+            successes += 1;
+            if successes >= config.success_threshold {
+                report_ready(&process_name, &ready);
+                return;
+            }
+        } else {
+            successes = 0;
+            failures += 1;
+            if failures >= config.failure_threshold {
+                break;
+            }
+        }
+
+        tokio::time::sleep(config.interval).await;
+    }
+
+    tracing::error!(process = %process_name, url = %config.url, %failures, "Daemon did not become ready");
+    let _ = failed.send(ProbeAction::Shutdown).await;
+}
+
+/// Sends a `GET` request to the given URL (without following
+/// redirects), and returns the status code of the response. The request
+/// (including reading the response) fails after the given timeout.
+async fn http_status(url: &str, timeout: Duration) -> eyre::Result<u16> {
+    let url = url.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let response = ureq::AgentBuilder::new()
+            .timeout(timeout)
+            .redirects(0)
+            .build()
+            .get(&url)
+            .call();
+        match response {
+            Ok(response) => Ok(response.status()),
+            Err(ureq::Error::Status(status, _)) => Ok(status),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await
+    .wrap_err("Readiness request panicked")?
+}
+
+/// How often to check for the readiness file (or path).
+const READY_FILE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Marks the daemon as ready (if it was not already).
+fn report_ready(process_name: &str, ready: &watch::Sender<bool>) {
+    if !ready.send_replace(true) {
+        tracing::info!(process = %process_name, "Daemon is ready");
+    }
+}
+
+/// Waits for the watchdog file to go without being touched for longer
+/// than the watchdog timeout (plus the `initial-delay`, for the first
+/// touch) `failure-threshold` times in a row, then reports a failure.
+async fn run_watchdog(
+    process_name: String,
+    config: WatchdogConfig,
+    failed: mpsc::Sender<ProbeAction>,
+) {
+    let mut last_activity = SystemTime::now();
+    let mut grace_period = config.initial_delay;
+    let mut timeouts: u32 = 0;
+
+    loop {
+        let deadline = last_activity + grace_period + config.timeout;
+        let remaining = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO);
+        tokio::time::sleep(remaining).await;
+
+        // Touches from before the daemon was started (from a previous
+        // instance of the daemon, for example) do not count.
+        let touched = tokio::fs::metadata(&config.path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .filter(|modified| *modified > last_activity);
+
+        grace_period = Duration::ZERO;
+
+        match touched {
+            Some(modified) => {
+                last_activity = modified;
+                timeouts = 0;
+            }
+            None => {
+                timeouts += 1;
+                tracing::warn!(process = %process_name, path = %config.path, timeout = ?config.timeout, %timeouts, "Watchdog timeout expired");
+
+                if timeouts >= config.failure_threshold {
+                    timeouts = 0;
+
+                    if failed.send(config.action).await.is_err() {
+                        break;
+                    }
+                }
+
+                last_activity = SystemTime::now();
+            }
+        }
+    }
+}
@@ -1,121 +1,1720 @@
 //! Starts and stops processes.
 
+use std::{
+    collections::VecDeque,
+    fs,
+    future::Future,
+    io,
+    os::unix::fs::DirBuilderExt,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use color_eyre::eyre::{self, eyre, WrapErr};
-use tokio::sync::{mpsc, oneshot};
+use nix::{
+    sys::signal::Signal,
+    unistd::{Gid, Pid, Uid},
+};
+use tokio::sync::{mpsc, oneshot, watch};
 
 use crate::{
-    command::{self, CommandControl, ExitStatus},
-    config::{CommandConfig, ProcessConfig, StopMechanism},
-    ShutdownReason,
+    cgroup,
+    command::{self, CommandControl, CommandMonitor, ExitStatus, SpawnContext},
+    config::{
+        BackoffConfig, CgroupConfig, CommandConfig, ConditionConfig, KillMode, ProbeAction,
+        ProcessConfig, ProcessType, PropagationPolicy, ReadinessCheck, ReloadMechanism,
+        RestartPolicy, SignalConfig, StopMechanism, StopStep,
+    },
+    probe::{self, Baseline, Probes},
+    DaemonStopped, ShutdownReason,
 };
 
 /// Process being managed by Ground Control.
 #[derive(Debug)]
 pub(crate) struct Process {
     config: ProcessConfig,
+
+    /// Configuration of the process as it was given, without what Ground
+    /// Control adds to it (such as the `TMPDIR` of `private-tmp`).
+    declared_config: Box<ProcessConfig>,
+
     handle: ProcessHandle,
+    readiness: Readiness,
+    private_tmp: Option<PrivateTmp>,
+
+    /// Set once the process has been killed (see [`Killer`]).
+    killed: Arc<watch::Sender<bool>>,
+}
+
+/// Temporary directory of a process with `private-tmp`, which is removed
+/// (with everything in it) when it is dropped.
+#[derive(Debug)]
+struct PrivateTmp(PathBuf);
+
+impl PrivateTmp {
+    /// Creates the temporary directory of the given process, owned by the
+    /// user of the process's `run` command (or of its first `pre`
+    /// command, if it does not have a `run` command).
+    fn create(config: &ProcessConfig) -> eyre::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "groundcontrol-{}-{}",
+            std::process::id(),
+            config.name.replace('/', "_")
+        ));
+
+        // Remove whatever is left of the directory of an earlier Ground
+        // Control with the same pid (such as in a restarted container).
+        match fs::remove_dir_all(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err).wrap_err_with(|| format!("Failed to remove {path:?}")),
+        }
+        fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&path)
+            .wrap_err_with(|| format!("Failed to create {path:?}"))?;
+        let private_tmp = Self(path);
+
+        if let Some(command) = config.run.as_ref().or_else(|| config.pre.first()) {
+            let (uid, gid) = command::owner(command)?;
+            if uid.is_some() || gid.is_some() {
+                nix::unistd::chown(
+                    &private_tmp.0,
+                    uid.map(Uid::from_raw),
+                    gid.map(Gid::from_raw),
+                )
+                .wrap_err_with(|| format!("Failed to change the owner of {:?}", private_tmp.0))?;
+            }
+        }
+
+        Ok(private_tmp)
+    }
+}
+
+impl Drop for PrivateTmp {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.0) {
+            tracing::warn!(path = ?self.0, ?err, "Error removing private temporary directory");
+        }
+    }
+}
+
+/// Readiness of a process, which processes that depend on the process
+/// wait for before they are started.
+#[derive(Clone, Debug)]
+pub(crate) struct Readiness {
+    process_name: String,
+    ready: watch::Receiver<bool>,
+
+    /// Number of times the process has been restarted (`None` for
+    /// one-shot processes); closed once the process has exited for the
+    /// last time.
+    restarts: Option<watch::Receiver<u32>>,
+}
+
+impl Readiness {
+    /// Returns the name of the process.
+    pub(crate) fn process_name(&self) -> &str {
+        &self.process_name
+    }
+
+    /// Returns `true` if the process is currently ready.
+    pub(crate) fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Returns the number of times the process has been restarted
+    /// (`None` for one-shot processes).
+    pub(crate) fn restarts(&self) -> Option<u32> {
+        self.restarts.as_ref().map(|restarts| *restarts.borrow())
+    }
+
+    /// Returns `true` if this is a daemon process that has exited for
+    /// the last time.
+    pub(crate) fn has_exited(&self) -> bool {
+        self.restarts
+            .as_ref()
+            .map_or(false, |restarts| restarts.has_changed().is_err())
+    }
+
+    /// Waits for the process to become ready, returning an error if the
+    /// process exits before it is ready.
+    pub(crate) async fn wait(mut self) -> eyre::Result<()> {
+        while !*self.ready.borrow_and_update() {
+            if self.ready.changed().await.is_err() {
+                return Err(eyre!(
+                    "Process \"{}\" exited before it was ready",
+                    self.process_name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Kills a process (for example, because it did not stop within the
+/// `shutdown_timeout`), even while it is being stopped: the daemon is
+/// killed with `SIGKILL`, as is whichever of its `pre-stop`, `stop`, or
+/// `post` commands is running, and the rest of those commands are
+/// skipped.
+#[derive(Clone, Debug)]
+pub(crate) struct Killer {
+    process_name: String,
+    killed: Arc<watch::Sender<bool>>,
+}
+
+impl Killer {
+    /// Kills the process, unless it has already been killed.
+    pub(crate) fn kill(&self) {
+        if !self.killed.send_replace(true) {
+            tracing::warn!(process = %self.process_name, "Killing process");
+        }
+    }
+}
+
+/// Process that has to be ready before another process is started.
+#[derive(Debug)]
+pub(crate) struct Dependency {
+    /// Readiness of the dependency.
+    pub(crate) readiness: Readiness,
+
+    /// `true` if the dependency is required (`depends-on`), `false` if
+    /// the dependency is only wanted (`wants`) and the other process can
+    /// be started without it.
+    pub(crate) required: bool,
+
+    /// What happens to the other process when the dependency is
+    /// restarted or exits.
+    pub(crate) propagate: PropagationPolicy,
+}
+
+/// Restart or exit of a dependency that is propagated to a daemon.
+#[derive(Debug)]
+struct DependencyEvent {
+    dependency: Readiness,
+    propagate: PropagationPolicy,
+
+    /// `true` if the dependency is being restarted, `false` if it has
+    /// exited for the last time.
+    restarted: bool,
 }
 
 #[derive(Debug)]
 enum ProcessHandle {
-    Daemon(CommandControl, oneshot::Receiver<ExitStatus>),
+    Daemon(DaemonHandle),
     OneShot,
 }
 
-/// Starts the process and returns a handle to the process.
+/// Handle to the task that supervises (monitors, restarts, and stops) a
+/// daemon process.
+#[derive(Debug)]
+struct DaemonHandle {
+    /// Asks the supervisor to stop the daemon; the supervisor replies
+    /// with the result of the `stop` operation.
+    stop_request: oneshot::Sender<oneshot::Sender<eyre::Result<()>>>,
+
+    /// Asks the supervisor to reload the daemon; the supervisor replies
+    /// with the result of the `reload` operation.
+    reload_request: mpsc::UnboundedSender<oneshot::Sender<eyre::Result<()>>>,
+
+    /// Asks the supervisor to send a (forwarded) signal to the daemon.
+    signal_request: mpsc::UnboundedSender<Signal>,
+
+    /// Asks the supervisor for the current status of the daemon.
+    status_request: mpsc::UnboundedSender<oneshot::Sender<DaemonStatus>>,
+
+    /// Receives the exit status of the daemon once it has exited for
+    /// the last time (that is, without being restarted).
+    exited: oneshot::Receiver<DaemonExit>,
+}
+
+/// Final exit of a daemon process.
+#[derive(Copy, Clone, Debug)]
+struct DaemonExit {
+    exit_status: ExitStatus,
+
+    /// `true` if the daemon exited unexpectedly and with a failure (as
+    /// opposed to exiting cleanly, or being stopped when asked).
+    failed: bool,
+
+    /// `true` if the daemon did not stop within its `stop-timeout`, and
+    /// was killed with `SIGKILL`.
+    escalated: bool,
+}
+
+/// Snapshot of a daemon process, reported by its supervisor.
+#[derive(Copy, Clone, Debug)]
+struct DaemonStatus {
+    state: ProcessState,
+
+    /// PID of the daemon, and how long it has been running (since it was
+    /// last started), if it is running.
+    running: Option<(Pid, Duration)>,
+}
+
+/// What a process is currently doing (as logged by the status dump).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ProcessState {
+    /// One-shot process that has run to completion.
+    Completed,
+
+    /// Daemon that is running.
+    Running,
+
+    /// Daemon that is being stopped (or restarted).
+    Stopping,
+
+    /// Daemon that is waiting to be restarted.
+    Restarting,
+
+    /// Daemon that has exited for the last time.
+    Exited,
+}
+
+/// Checks the process's `condition` (if any), returning an error that
+/// describes why the condition is not met.
+pub(crate) async fn check_condition(config: &ProcessConfig) -> eyre::Result<()> {
+    match &config.condition {
+        None => Ok(()),
+        Some(ConditionConfig::Env(name)) => match std::env::var(name) {
+            Ok(value) if !matches!(value.to_lowercase().as_str(), "" | "0" | "false") => Ok(()),
+            Ok(value) => Err(eyre!(
+                "Environment variable \"{name}\" is set to \"{value}\""
+            )),
+            Err(_) => Err(eyre!("Environment variable \"{name}\" is not set")),
+        },
+        Some(ConditionConfig::Command(command)) => {
+            run_process_command(&config.name, ProcessPhase::Condition, command, None).await
+        }
+    }
+}
+
+/// Starts the process (once all of its dependencies are ready, or in the
+/// case of wanted dependencies, have exited without becoming ready) and
+/// returns a handle to the process.
 pub(crate) async fn start_process(
-    config: ProcessConfig,
-    process_stopped: mpsc::UnboundedSender<ShutdownReason>,
+    mut config: ProcessConfig,
+    dependencies: Vec<Dependency>,
+    process_stopped: mpsc::UnboundedSender<DaemonStopped>,
 ) -> eyre::Result<Process> {
+    // Wait for the dependencies to become ready, which counts towards
+    // the `start-timeout`.
+    let start_timeout = config.start_timeout;
+    let waiting_started_at = Instant::now();
+    let has_dependencies = !dependencies.is_empty();
+    let mut propagated = Vec::new();
+
+    for Dependency {
+        readiness,
+        required,
+        propagate,
+    } in dependencies
+    {
+        let dependency_name = readiness.process_name.clone();
+        tracing::debug!(process = %config.name, dependency = %dependency_name, %required, "Waiting for dependency to be ready");
+
+        let result = match start_timeout {
+            Some(start_timeout) => tokio::time::timeout(
+                start_timeout.saturating_sub(waiting_started_at.elapsed()),
+                readiness.clone().wait(),
+            )
+            .await
+            .map_err(|_| StartProcessError::StartTimeout {
+                process_name: config.name.clone(),
+                start_timeout,
+                waiting_for: Some(format!("dependency \"{dependency_name}\" was not ready")),
+            })?,
+            None => readiness.clone().wait().await,
+        };
+
+        match result {
+            Ok(()) if propagate != PropagationPolicy::Ignore => {
+                propagated.push((readiness, propagate));
+            }
+            Ok(()) => {}
+            Err(err) if !required => {
+                tracing::warn!(process = %config.name, dependency = %dependency_name, ?err, "Wanted dependency is not available; starting process anyway");
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    let waited = if has_dependencies {
+        waiting_started_at.elapsed()
+    } else {
+        Duration::ZERO
+    };
+
+    if let Some(start_delay) = config.start_delay {
+        tracing::info!(process = %config.name, ?start_delay, "Delaying start of process");
+        tokio::time::sleep(start_delay).await;
+    }
+
     tracing::info!("Starting process {}", config.name);
+    let declared_config = Box::new(config.clone());
+
+    // Create the private temporary directory of the process (if asked
+    // to), which every command of the process gets as its `TMPDIR`
+    // (unless the command sets `TMPDIR` itself). The directory is
+    // removed if the process fails to start.
+    let private_tmp = if config.private_tmp {
+        let private_tmp = PrivateTmp::create(&config).wrap_err_with(|| {
+            format!(
+                "Failed to create the private temporary directory of process \"{}\"",
+                config.name
+            )
+        })?;
+        config.set_default_env("TMPDIR", &private_tmp.0.to_string_lossy());
+        Some(private_tmp)
+    } else {
+        None
+    };
+
+    // Perform the pre-run actions, if provided, followed by the `run`
+    // command of a `oneshot` process. These commands are limited (in
+    // total) by `start-timeout`, and for one-shot processes, by
+    // `max-runtime` (which, for daemon processes, applies to the `run`
+    // command instead).
+    let max_runtime = config.max_runtime.filter(|_| !config.is_daemon());
+    let mut elapsed = Duration::ZERO;
 
-    // Perform the pre-run action, if provided.
-    if let Some(pre_run) = &config.pre {
-        run_process_command(&config.name, ProcessPhase::PreRun, pre_run).await?;
+    // These commands get the secrets of the process (if there are any
+    // such commands).
+    let oneshot_run = config.run.iter().filter(|_| !config.is_daemon());
+    let secrets = if config.pre.is_empty() && oneshot_run.clone().next().is_none() {
+        Vec::new()
+    } else {
+        fetch_secrets(&config).await?
+    };
+    let secrets: Vec<(&str, String)> = secrets
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.clone()))
+        .collect();
+    for (phase, pre_run) in config
+        .pre
+        .iter()
+        .map(|pre| (ProcessPhase::PreRun, pre))
+        .chain(oneshot_run.map(|run| (ProcessPhase::Run, run)))
+    {
+        let timeout = [
+            start_timeout.map(|start_timeout| start_timeout.saturating_sub(waited)),
+            max_runtime,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|limit| limit.saturating_sub(elapsed))
+        .chain(config.pre_timeout.filter(|_| phase == ProcessPhase::PreRun))
+        .min();
+
+        let command_started_at = Instant::now();
+        let result = run_process_command_allowing(
+            &config.name,
+            phase,
+            pre_run,
+            timeout,
+            &config.allowed_exit_codes,
+            &secrets,
+            None,
+            config
+                .cgroup
+                .as_ref()
+                .filter(|_| phase == ProcessPhase::Run),
+        )
+        .await;
+        elapsed += command_started_at.elapsed();
+
+        let err = match result {
+            Ok(()) => continue,
+            Err(err) => err,
+        };
+
+        match start_timeout {
+            Some(start_timeout) if waited + elapsed >= start_timeout => {
+                return Err(err.wrap_err(StartProcessError::StartTimeout {
+                    process_name: config.name.clone(),
+                    start_timeout,
+                    waiting_for: None,
+                }));
+            }
+            _ if max_runtime.map_or(false, |max_runtime| elapsed >= max_runtime) => {
+                return Err(err);
+            }
+            _ if pre_run.ignore_failure => {
+                tracing::warn!(process = %config.name, ?err, "Ignoring failed `{phase}` command");
+            }
+            _ => return Err(err),
+        }
     }
 
     // Run the process itself (if this is a daemon process with a `run`
     // command).
-    let handle = if let Some(run) = &config.run {
+    let (killed, killed_receiver) = watch::channel(false);
+    let (handle, ready, restarts) = if let Some(run) =
+        config.run.as_ref().filter(|_| config.is_daemon())
+    {
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        let (signal_sender, signal_receiver) = mpsc::unbounded_channel();
+        let (status_sender, status_receiver) = mpsc::unbounded_channel();
+        let (reload_sender, reload_receiver) = mpsc::unbounded_channel();
         let (daemon_sender, daemon_receiver) = oneshot::channel();
 
-        let (control, monitor) = command::run(&config.name, run)
+        let (baseline, control, monitor) = start_daemon(&config, run)
+            .await
             .wrap_err_with(|| format!("`run` command failed for process \"{}\"", config.name))?;
 
-        // Spawn a task to wait for the command to exit, then notify
-        // both ourselves (to allow `stop` to return) and the shutdown
-        // listener that our daemon process has exited.
-        let process_name = config.name.clone();
-        tokio::spawn(async move {
-            let exit_status = monitor.wait().await;
-
-            // TODO: Should this ever really happen? I would prefer to
-            // just `expect` here if it is not possible. *But,* we need
-            // to verify that, during some sort of startup/shutdown
-            // failure, that we do not drop things too early and then
-            // the receiver is gone.
-            if daemon_sender.send(exit_status).is_err() {
-                tracing::error!(process = %process_name, "Daemon receiver dropped before receiving exit signal.");
-            }
-
-            let shutdown_reason = match exit_status {
-                ExitStatus::Exited(0) => ShutdownReason::DaemonExited,
-                ExitStatus::Exited(_) | ExitStatus::Killed => ShutdownReason::DaemonFailed,
+        // Daemons that do not have a readiness check are ready as soon
+        // as they are running; the probes report readiness otherwise.
+        let (ready_sender, ready) = watch::channel(config.ready == ReadinessCheck::Running);
+        let (restarts_sender, restarts) = watch::channel(0);
+
+        // Forward the restarts (and exits) of the dependencies that
+        // propagate to this daemon to the supervisor.
+        let (dependency_sender, dependency_events) = mpsc::unbounded_channel();
+        for (dependency, propagate) in propagated {
+            tokio::spawn(watch_dependency(
+                dependency,
+                propagate,
+                dependency_sender.clone(),
+            ));
+        }
+
+        // Spawn a task to supervise the daemon: it restarts the daemon
+        // (if so configured), stops the daemon when asked, and then
+        // notifies both ourselves (to allow `stop` to return) and the
+        // shutdown listener once the daemon has exited for good.
+        tokio::spawn(supervise_daemon(
+            config.clone(),
+            run.clone(),
+            control,
+            monitor,
+            stop_receiver,
+            reload_receiver,
+            killed_receiver,
+            signal_receiver,
+            status_receiver,
+            daemon_sender,
+            process_stopped,
+            Arc::new(ready_sender),
+            baseline,
+            restarts_sender,
+            dependency_events,
+        ));
+
+        let handle = ProcessHandle::Daemon(DaemonHandle {
+            stop_request: stop_sender,
+            reload_request: reload_sender,
+            signal_request: signal_sender,
+            status_request: status_sender,
+            exited: daemon_receiver,
+        });
+        (handle, ready, Some(restarts))
+    } else {
+        // One-shot processes are ready as soon as they have started
+        // (dropping the sender does not change that), and are never
+        // restarted.
+        (ProcessHandle::OneShot, watch::channel(true).1, None)
+    };
+
+    let readiness = Readiness {
+        process_name: config.name.clone(),
+        ready,
+        restarts,
+    };
+
+    let process = Process {
+        config,
+        declared_config,
+        handle,
+        readiness,
+        private_tmp,
+        killed: Arc::new(killed),
+    };
+
+    // Daemons with a readiness command (or path) have not started until
+    // they are ready, which counts towards the `start-timeout` (the
+    // daemon is stopped if it is not ready in time).
+    if process.config.ready.gates_startup() {
+        let ready = process.readiness().wait();
+        match start_timeout {
+            Some(start_timeout) => {
+                match tokio::time::timeout(start_timeout.saturating_sub(waited + elapsed), ready).await
+                {
+                    Ok(result) => result,
+                    Err(_) => {
+                        // `stop_process` is boxed, since it would otherwise make this
+                        // (already large) future much larger.
+                        let name = process.config.name.clone();
+                        if let Err(err) = Box::pin(process.stop_process()).await {
+                            tracing::warn!(process = %name, ?err, "Error stopping process that was not ready in time");
+                        }
+
+                        return Err(StartProcessError::StartTimeout {
+                            process_name: name,
+                            start_timeout,
+                            waiting_for: Some(String::from("process was not ready")),
+                        }
+                        .into());
+                    }
+                }
+            }
+            None => ready.await,
+        }
+        .wrap_err_with(|| {
+            format!(
+                "Readiness check failed for process \"{}\"",
+                process.config.name
+            )
+        })?;
+    }
+
+    Ok(process)
+}
+
+/// Starts the daemon using its `run` command (as appropriate for the
+/// daemon's `type`, and with the secrets of the process), and returns
+/// the baseline for its probes, and the control and monitor handles for
+/// the daemon.
+async fn start_daemon(
+    config: &ProcessConfig,
+    run: &CommandConfig,
+) -> eyre::Result<(Baseline, CommandControl, CommandMonitor)> {
+    let secrets = fetch_secrets(config).await?;
+    let (baseline, hooks) = probe::prepare(config)?;
+    let env: Vec<(&str, String)> = secrets
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.clone()))
+        .chain(hooks.env)
+        .collect();
+
+    // Only the `run` command runs in the cgroup of the process.
+    let context = SpawnContext {
+        process_name: Some(&config.name),
+        cgroup: config.cgroup.as_ref(),
+    };
+    let (control, monitor) = match (config.process_type, &config.pid_file) {
+        (Some(ProcessType::Forking), Some(pid_file)) => {
+            command::run_forking(
+                &config.name,
+                run,
+                context,
+                &env,
+                hooks.output,
+                pid_file,
+                config.new_session,
+            )
+            .await?
+        }
+        _ => command::run(
+            &config.name,
+            run,
+            context,
+            &env,
+            hooks.output,
+            config.new_session,
+            config.parent_death_signal.map(Signal::from),
+        )?,
+    };
+
+    Ok((baseline, control, monitor))
+}
+
+/// Runs the `secrets-command` of the process (if any), and returns the
+/// secrets that it writes. The command is limited to the `start-timeout`
+/// of the process.
+async fn fetch_secrets(config: &ProcessConfig) -> eyre::Result<Vec<(String, String)>> {
+    match &config.secrets_command {
+        Some(secrets_command) => command::capture_env(
+            &format!("{}[secrets-command]", config.name),
+            secrets_command,
+            SpawnContext {
+                process_name: Some(&config.name),
+                cgroup: None,
+            },
+            config.start_timeout,
+        )
+        .await
+        .wrap_err_with(|| format!("`secrets-command` failed for process \"{}\"", config.name)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Supervises a running daemon until it exits for the last time.
+#[allow(clippy::too_many_arguments)]
+async fn supervise_daemon(
+    config: ProcessConfig,
+    run: CommandConfig,
+    mut control: CommandControl,
+    mut monitor: CommandMonitor,
+    mut stop_request: oneshot::Receiver<oneshot::Sender<eyre::Result<()>>>,
+    mut reload_request: mpsc::UnboundedReceiver<oneshot::Sender<eyre::Result<()>>>,
+    killed: watch::Receiver<bool>,
+    mut signal_request: mpsc::UnboundedReceiver<Signal>,
+    mut status_request: mpsc::UnboundedReceiver<oneshot::Sender<DaemonStatus>>,
+    daemon_sender: oneshot::Sender<DaemonExit>,
+    process_stopped: mpsc::UnboundedSender<DaemonStopped>,
+    ready: Arc<watch::Sender<bool>>,
+    baseline: Baseline,
+    restarts_sender: watch::Sender<u32>,
+    mut dependency_events: mpsc::UnboundedReceiver<DependencyEvent>,
+) {
+    // Both a stop request *and* dropping the stop sender disable
+    // restarts; the former also stops the daemon.
+    let mut stopping = false;
+
+    // Track how long the daemon stays up, both for scheduled restarts
+    // and so that the restart delay can be reset if the daemon had been
+    // running for a while.
+    let mut started_at = Instant::now();
+    let mut scheduled_restart_at = config
+        .restart_interval
+        .map(|interval| started_at + interval);
+    let mut runtime_limit_at = config
+        .max_runtime
+        .map(|max_runtime| started_at + max_runtime);
+    let mut restarts = RestartTracker::new(&config);
+
+    // Set when the supervisor itself stopped the daemon (for example,
+    // because the daemon failed its health check); determines what
+    // happens once the daemon exits.
+    let mut intervention: Option<Intervention> = None;
+
+    // Set when the daemon was stopped because a dependency is being
+    // restarted; the daemon is restarted once that dependency is ready.
+    let mut restarted_dependency: Option<Readiness> = None;
+
+    // Set when the daemon was stopped for good because a dependency was
+    // restarted or exited. Those exits do not trigger a shutdown.
+    let mut stopped_by_dependency = false;
+
+    // Set once the daemon has been asked to stop: when to kill the
+    // daemon if it has not stopped by then (see `stop-timeout`), when to
+    // take the next `stop` step (along with the current step), and
+    // whether the daemon had to be killed.
+    let mut kill_at: Option<Instant> = None;
+    let mut step_at: Option<(usize, Instant)> = None;
+    let mut escalated = false;
+
+    // The `stop` and `reload` operations that are in progress, whether
+    // they were requested or the supervisor started them on its own. The
+    // operations run alongside everything else, so that the daemon can
+    // still exit, time out, or be killed in the meantime.
+    let mut stop_operation: Option<Operation> = None;
+    let mut reload_operation: Option<Operation> = None;
+
+    // Set once the process has been killed (see [`Killer`]).
+    let mut force_killed = false;
+
+    let mut probes = Some(Probes::start(&config, &ready, baseline));
+
+    let (exit_status, shutdown_reason) = loop {
+        let exit_status = tokio::select! {
+            exit_status = monitor.wait() => exit_status,
+            request = &mut stop_request, if !stopping => {
+                stopping = true;
+                kill_at = kill_deadline(&config);
+                step_at = step_deadline(&config, 0);
+
+                // The daemon may already be stopping on behalf of the
+                // supervisor, in which case the request is answered once
+                // that is done.
+                if let Ok(reply) = request {
+                    match &mut stop_operation {
+                        Some(operation) => operation.reply = Some(reply),
+                        None => stop_operation = Some(Operation::stop(&config, &control, &killed, Some(reply))),
+                    }
+                }
+
+                continue;
+            }
+            result = in_progress(&mut stop_operation) => {
+                if let Some(Operation { reply, .. }) = stop_operation.take() {
+                    match reply {
+                        Some(reply) => {
+                            let _ = reply.send(result);
+                        }
+                        None => {
+                            if let Err(err) = result {
+                                tracing::warn!(process = %config.name, ?err, ?intervention, "Error stopping daemon.");
+
+                                // Leave it to the next check to stop
+                                // the daemon instead.
+                                intervention = None;
+                                restarted_dependency = None;
+                            }
+                        }
+                    }
+                }
+
+                continue;
+            }
+            result = in_progress(&mut reload_operation) => {
+                if let Some(Operation { reply: Some(reply), .. }) = reload_operation.take() {
+                    let _ = reply.send(result);
+                }
+
+                continue;
+            }
+            () = wait_killed(killed.clone()), if !force_killed => {
+                // Killed daemons are never restarted.
+                force_killed = true;
+                stopping = true;
+
+                if let Err(err) = kill_daemon(&config, &control) {
+                    tracing::warn!(process = %config.name, ?err, "Error killing daemon");
+                }
+
+                continue;
+            }
+            Some(reply) = reload_request.recv(), if !stopping && reload_operation.is_none() => {
+                let (config, control, killed) = (config.clone(), control.clone(), killed.clone());
+                reload_operation = Some(Operation {
+                    operation: Box::pin(async move { reload_daemon(&config, &control, &killed).await }),
+                    reply: Some(reply),
+                });
+
+                continue;
+            }
+            Some(signal) = signal_request.recv(), if !stopping => {
+                tracing::debug!(process = %config.name, %signal, "Forwarding signal to daemon");
+                if let Err(err) = control.kill(signal) {
+                    tracing::warn!(process = %config.name, ?err, "Error forwarding signal to daemon");
+                }
+                continue;
+            }
+            Some(reply) = status_request.recv() => {
+                let _ = reply.send(DaemonStatus {
+                    state: if stopping || intervention.is_some() || stop_operation.is_some() {
+                        ProcessState::Stopping
+                    } else {
+                        ProcessState::Running
+                    },
+                    running: Some((control.pid(), started_at.elapsed())),
+                });
+                continue;
+            }
+            action = probe_failed(&mut probes), if !stopping && intervention.is_none() && stop_operation.is_none() => {
+                let action = match Intervention::for_probe_action(action) {
+                    Some(action) => action,
+                    None => {
+                        tracing::warn!(process = %config.name, "Daemon is unhealthy; leaving it running");
+                        continue;
+                    }
+                };
+
+                tracing::warn!(process = %config.name, "Daemon is unhealthy; stopping daemon");
+
+                kill_at = kill_deadline(&config);
+                step_at = step_deadline(&config, 0);
+                intervention = Some(action);
+                stop_operation = Some(Operation::stop(&config, &control, &killed, None));
+
+                continue;
+            }
+            Some(event) = dependency_events.recv(), if !stopping && intervention.is_none() && stop_operation.is_none() => {
+                let dependency = event.dependency.process_name.clone();
+                let action = match event.propagate {
+                    PropagationPolicy::Restart if event.restarted => {
+                        tracing::info!(process = %config.name, %dependency, "Dependency is restarting; restarting daemon");
+                        restarted_dependency = Some(event.dependency);
+                        Intervention::DependencyRestart
+                    }
+                    _ if event.restarted => {
+                        tracing::warn!(process = %config.name, %dependency, "Dependency is restarting; stopping daemon");
+                        Intervention::Stop
+                    }
+                    _ => {
+                        tracing::warn!(process = %config.name, %dependency, "Dependency exited; stopping daemon");
+                        Intervention::Stop
+                    }
+                };
+
+                kill_at = kill_deadline(&config);
+                step_at = step_deadline(&config, 0);
+                intervention = Some(action);
+                stop_operation = Some(Operation::stop(&config, &control, &killed, None));
+
+                continue;
+            }
+            _ = deadline(scheduled_restart_at), if !stopping && intervention.is_none() && stop_operation.is_none() => {
+                tracing::info!(process = %config.name, "Restart interval elapsed; stopping daemon");
+
+                // Schedule the next restart now, in case the daemon
+                // does not actually stop.
+                scheduled_restart_at = config.restart_interval.map(|interval| Instant::now() + interval);
+
+                kill_at = kill_deadline(&config);
+                step_at = step_deadline(&config, 0);
+                intervention = Some(Intervention::ScheduledRestart);
+                stop_operation = Some(Operation::stop(&config, &control, &killed, None));
+
+                continue;
+            }
+            _ = deadline(step_at.map(|(_, at)| at)) => {
+                let step = step_at.take().map_or(0, |(step, _)| step) + 1;
+                match stop_steps(&config).get(step) {
+                    Some(next) => {
+                        tracing::info!(process = %config.name, %step, "Daemon did not stop after the previous `stop` step; taking the next step");
+                        step_at = step_deadline(&config, step);
+                        if let Err(err) = take_stop_step(&config, &control, next, &killed).await {
+                            tracing::warn!(process = %config.name, ?err, "Error taking `stop` step");
+                        }
+                    }
+                    None => {
+                        kill_at = None;
+                        escalated = true;
+
+                        tracing::warn!(process = %config.name, "Daemon did not stop after its last `stop` step; killing daemon");
+                        if let Err(err) = kill_daemon(&config, &control) {
+                            tracing::warn!(process = %config.name, ?err, "Error killing daemon");
+                        }
+                    }
+                }
+
+                continue;
+            }
+            _ = deadline(kill_at) => {
+                kill_at = None;
+                step_at = None;
+                escalated = true;
+
+                tracing::warn!(process = %config.name, timeout = ?config.stop_timeout, "Daemon did not stop within its `stop-timeout`; killing daemon");
+                if let Err(err) = kill_daemon(&config, &control) {
+                    tracing::warn!(process = %config.name, ?err, "Error killing daemon");
+                }
+
+                continue;
+            }
+            _ = deadline(runtime_limit_at), if !stopping && intervention.is_none() && stop_operation.is_none() => {
+                // Only try to stop the daemon once; what happens after
+                // the daemon exits is up to the restart policy.
+                runtime_limit_at = None;
+
+                tracing::warn!(process = %config.name, elapsed = ?started_at.elapsed(), "Daemon exceeded its maximum runtime; stopping daemon");
+
+                kill_at = kill_deadline(&config);
+                step_at = step_deadline(&config, 0);
+                stop_operation = Some(Operation::stop(&config, &control, &killed, None));
+
+                continue;
+            }
+        };
+
+        // Stop probing the daemon now that it has exited.
+        drop(probes.take());
+        kill_at = None;
+        step_at = None;
+
+        // Kill whatever is left of the daemon's process group (and of its
+        // cgroup, which also has the processes that left the process
+        // group) if we stopped the daemon (there is usually nothing left,
+        // in which case there is nothing to kill).
+        if (stopping || intervention.is_some()) && config.kill_mode != KillMode::Process {
+            let _ = control.kill_group(Signal::SIGKILL);
+            if config.cgroup.is_some() {
+                if let Err(err) = cgroup::kill(&config.name) {
+                    tracing::warn!(process = %config.name, ?err, "Error killing the cgroup of daemon");
+                }
+            }
+        }
+
+        let shutdown_reason = if is_success(exit_status, &config.allowed_exit_codes) {
+            ShutdownReason::DaemonExited(exit_status)
+        } else {
+            ShutdownReason::DaemonFailed(exit_status)
+        };
+
+        // Run the `on-crash` command if the daemon failed on its own
+        // (and not because we stopped it), before deciding whether or
+        // not to restart the daemon.
+        if !stopping
+            && intervention.is_none()
+            && matches!(shutdown_reason, ShutdownReason::DaemonFailed(_))
+        {
+            if let Some(on_crash) = &config.on_crash {
+                run_on_crash(&config, on_crash, exit_status, &killed).await;
+            }
+        }
+
+        // Killed daemons are never restarted, even if they were killed
+        // while the `on-crash` command was running.
+        if *killed.borrow() {
+            stopping = true;
+        }
+
+        if stopping {
+            break (exit_status, shutdown_reason);
+        }
+
+        // Finish stopping the daemon (if it exited before the supervisor
+        // was done stopping it) before deciding what to do next.
+        if let Some(Operation { operation, .. }) = stop_operation.take() {
+            if let Err(err) = operation.await {
+                tracing::warn!(process = %config.name, ?err, ?intervention, "Error stopping daemon.");
+            }
+        }
+
+        // Scheduled restarts are not failures, and so they are neither
+        // delayed nor counted towards `max-restarts`.
+        let scheduled = match intervention.take() {
+            Some(Intervention::ScheduledRestart | Intervention::DependencyRestart) => true,
+            Some(Intervention::Restart) => false,
+            Some(Intervention::Shutdown) => {
+                break (exit_status, ShutdownReason::DaemonFailed(exit_status))
+            }
+            Some(Intervention::Stop) => {
+                stopped_by_dependency = true;
+                break (exit_status, ShutdownReason::DaemonExited(exit_status));
+            }
+            None if should_restart(config.restart.unwrap_or_default(), shutdown_reason) => false,
+            None => break (exit_status, shutdown_reason),
+        };
+
+        let delay = if scheduled {
+            tracing::info!(process = %config.name, ?exit_status, "Restarting daemon");
+            Duration::ZERO
+        } else {
+            // Give up on the daemon if it has been restarted too many
+            // times (within the restart window, if provided).
+            let delay = match restarts.record(started_at.elapsed()) {
+                Some(delay) => delay,
+                None => {
+                    tracing::error!(process = %config.name, ?exit_status, restarts = %restarts.total, "Daemon restarted too many times; giving up");
+                    break (exit_status, ShutdownReason::DaemonFailed(exit_status));
+                }
             };
 
-            if let Err(err) = process_stopped.send(shutdown_reason) {
-                tracing::error!(
-                    process = %process_name,
-                    ?err,
-                    "Shutdown receiver dropped before all processes have exited."
-                );
+            if delay.is_zero() {
+                tracing::warn!(process = %config.name, ?exit_status, restarts = %restarts.total, "Daemon exited; restarting");
+            } else {
+                tracing::warn!(process = %config.name, ?exit_status, restarts = %restarts.total, ?delay, "Daemon exited; restarting after delay");
             }
-        });
 
-        ProcessHandle::Daemon(control, daemon_receiver)
-    } else {
-        ProcessHandle::OneShot
+            delay
+        };
+
+        // The daemon is not ready again until it has been restarted, and
+        // its dependents are told about the restart right away (so that
+        // they can be stopped while the daemon is down).
+        ready.send_replace(false);
+        restarts_sender.send_modify(|restarts| *restarts += 1);
+
+        // Wait before restarting the daemon, if a backoff has been
+        // configured, or for the dependency that caused the restart to
+        // be ready again. Stop requests received during the wait abort
+        // the restart.
+        if !delay.is_zero() || restarted_dependency.is_some() {
+            let wait = async {
+                tokio::time::sleep(delay).await;
+                match restarted_dependency.take() {
+                    Some(dependency) => dependency.wait().await,
+                    None => Ok(()),
+                }
+            };
+            tokio::pin!(wait);
+
+            let result = loop {
+                tokio::select! {
+                    result = &mut wait => break result,
+                    result = in_progress(&mut reload_operation) => {
+                        if let Some(Operation { reply: Some(reply), .. }) = reload_operation.take() {
+                            let _ = reply.send(result);
+                        }
+                    }
+                    request = &mut stop_request => {
+                        if let Ok(reply) = request {
+                            let _ = reply.send(Ok(()));
+                        }
+
+                        stopping = true;
+                        break Ok(());
+                    }
+                    Some(reply) = reload_request.recv() => {
+                        let _ = reply.send(Err(eyre!(
+                            "Process \"{}\" is waiting to be restarted and cannot be reloaded",
+                            config.name
+                        )));
+                    }
+                    Some(signal) = signal_request.recv() => {
+                        tracing::debug!(process = %config.name, %signal, "Daemon is waiting to be restarted; not forwarding signal");
+                    }
+                    Some(reply) = status_request.recv() => {
+                        let _ = reply.send(DaemonStatus {
+                            state: ProcessState::Restarting,
+                            running: None,
+                        });
+                    }
+                }
+            };
+
+            if stopping {
+                break (exit_status, shutdown_reason);
+            }
+
+            if let Err(err) = result {
+                tracing::warn!(process = %config.name, ?err, "Dependency did not become ready; not restarting daemon");
+                stopped_by_dependency = true;
+                break (exit_status, ShutdownReason::DaemonExited(exit_status));
+            }
+        }
+
+        match start_daemon(&config, &run).await {
+            Ok((baseline, new_control, new_monitor)) => {
+                escalated = false;
+                control = new_control;
+                monitor = new_monitor;
+                started_at = Instant::now();
+                scheduled_restart_at = config
+                    .restart_interval
+                    .map(|interval| started_at + interval);
+                runtime_limit_at = config
+                    .max_runtime
+                    .map(|max_runtime| started_at + max_runtime);
+                if config.ready == ReadinessCheck::Running {
+                    ready.send_replace(true);
+                }
+                probes = Some(Probes::start(&config, &ready, baseline));
+            }
+            Err(err) => {
+                tracing::error!(process = %config.name, ?err, "Failed to restart daemon");
+                break (exit_status, ShutdownReason::DaemonFailed(exit_status));
+            }
+        }
     };
 
-    Ok(Process { config, handle })
+    // Finish the `stop` and `reload` operations (if the daemon exited
+    // before they were done), so that their results are still reported.
+    for Operation { operation, reply } in [stop_operation.take(), reload_operation.take()]
+        .into_iter()
+        .flatten()
+    {
+        let result = operation.await;
+        if let Some(reply) = reply {
+            let _ = reply.send(result);
+        }
+    }
+
+    // Remove the cgroup of the daemon now that it has exited for good.
+    if config.cgroup.is_some() {
+        remove_cgroup(&config.name).await;
+    }
+
+    // TODO: Should this ever really happen? I would prefer to just
+    // `expect` here if it is not possible. *But,* we need to verify
+    // that, during some sort of startup/shutdown failure, that we do
+    // not drop things too early and then the receiver is gone.
+    let daemon_exit = DaemonExit {
+        exit_status,
+        escalated,
+        failed: !stopping && matches!(shutdown_reason, ShutdownReason::DaemonFailed(_)),
+    };
+    if daemon_sender.send(daemon_exit).is_err() {
+        tracing::error!(process = %config.name, "Daemon receiver dropped before receiving exit signal.");
+    }
+
+    // Daemons that were stopped when asked (during shutdown, or when
+    // scaling down a replicated process) do not need to notify anyone.
+    // Neither daemons stopped because of their dependencies, nor
+    // non-critical daemons, trigger a shutdown when they exit on their
+    // own.
+    if stopping {
+        tracing::debug!(process = %config.name, ?exit_status, "Daemon stopped");
+    } else if stopped_by_dependency {
+        tracing::warn!(process = %config.name, ?exit_status, "Daemon stopped because of its dependency; continuing without it");
+    } else if !config.critical {
+        match shutdown_reason {
+            ShutdownReason::DaemonFailed(_) => {
+                tracing::error!(process = %config.name, ?exit_status, "Non-critical daemon failed; continuing without it")
+            }
+            _ => {
+                tracing::warn!(process = %config.name, ?exit_status, "Non-critical daemon exited; continuing without it")
+            }
+        }
+    } else if let Err(err) = process_stopped.send(DaemonStopped {
+        process: config.name.clone(),
+        reason: shutdown_reason,
+    }) {
+        tracing::error!(
+            process = %config.name,
+            ?err,
+            "Shutdown receiver dropped before all processes have exited."
+        );
+    }
+}
+
+/// Removes the cgroup of the process, waiting a moment for the processes
+/// that were just killed in the cgroup to exit.
+async fn remove_cgroup(process_name: &str) {
+    let mut attempts = 0;
+    loop {
+        match cgroup::remove(process_name) {
+            Ok(()) => return,
+            Err(err) if err.raw_os_error() == Some(libc::EBUSY) && attempts < 20 => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Err(err) => {
+                tracing::warn!(process = %process_name, ?err, "Error removing the cgroup of daemon");
+                return;
+            }
+        }
+    }
+}
+
+/// `stop` or `reload` operation that is in progress, along with where to
+/// send its result (`None` if the supervisor started the operation on its
+/// own).
+struct Operation {
+    operation: Pin<Box<dyn Future<Output = eyre::Result<()>> + Send>>,
+    reply: Option<oneshot::Sender<eyre::Result<()>>>,
+}
+
+impl Operation {
+    /// Starts stopping the daemon (see [`stop_daemon`]).
+    fn stop(
+        config: &ProcessConfig,
+        control: &CommandControl,
+        killed: &watch::Receiver<bool>,
+        reply: Option<oneshot::Sender<eyre::Result<()>>>,
+    ) -> Self {
+        let (config, control, killed) = (config.clone(), control.clone(), killed.clone());
+        Self {
+            operation: Box::pin(async move { stop_daemon(&config, &control, &killed).await }),
+            reply,
+        }
+    }
+}
+
+/// Waits for the operation to finish, returning its result (waits forever
+/// if there is no operation in progress).
+async fn in_progress(operation: &mut Option<Operation>) -> eyre::Result<()> {
+    match operation {
+        Some(Operation { operation, .. }) => operation.await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Action taken by the supervisor after it has stopped a daemon on its
+/// own accord.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Intervention {
+    /// Restart the daemon (regardless of the restart policy).
+    Restart,
+
+    /// Restart the daemon immediately, without counting the restart
+    /// as a failure.
+    ScheduledRestart,
+
+    /// Do not restart the daemon, and shut down as if it had failed.
+    Shutdown,
+
+    /// Restart the daemon (without counting the restart as a failure)
+    /// once the dependency that is being restarted is ready again.
+    DependencyRestart,
+
+    /// Do not restart the daemon, and continue without it.
+    Stop,
+}
+
+impl Intervention {
+    /// Returns the intervention for the given probe action (`None` if
+    /// the failure is only logged).
+    fn for_probe_action(action: ProbeAction) -> Option<Self> {
+        match action {
+            ProbeAction::Restart => Some(Intervention::Restart),
+            ProbeAction::Shutdown => Some(Intervention::Shutdown),
+            ProbeAction::LogOnly => None,
+        }
+    }
+}
+
+/// Counts the restarts of a daemon, for crash-loop detection and to
+/// compute the backoff delay before each restart.
+#[derive(Debug)]
+struct RestartTracker {
+    max_restarts: Option<u32>,
+    restart_window: Option<Duration>,
+    backoff: Option<BackoffConfig>,
+
+    /// Total number of restarts (for logging purposes).
+    total: u32,
+
+    /// Times of the restarts that count towards `max-restarts`.
+    recent: VecDeque<Instant>,
+
+    /// Delay before the next restart.
+    delay: Duration,
+}
+
+impl RestartTracker {
+    fn new(config: &ProcessConfig) -> Self {
+        Self {
+            max_restarts: config.max_restarts,
+            restart_window: config.restart_window,
+            backoff: config.restart_backoff,
+            total: 0,
+            recent: VecDeque::new(),
+            delay: config
+                .restart_backoff
+                .map(|backoff| backoff.initial_delay)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Records a restart of a daemon that was up for `uptime`, returning
+    /// the delay to wait before restarting the daemon, or `None` if the
+    /// daemon has been restarted too many times.
+    fn record(&mut self, uptime: Duration) -> Option<Duration> {
+        if let Some(max_restarts) = self.max_restarts {
+            let now = Instant::now();
+            if let Some(window) = self.restart_window {
+                while self
+                    .recent
+                    .front()
+                    .map_or(false, |restart| now.duration_since(*restart) > window)
+                {
+                    self.recent.pop_front();
+                }
+            }
+
+            if self.recent.len() >= max_restarts as usize {
+                return None;
+            }
+
+            self.recent.push_back(now);
+        }
+
+        self.total += 1;
+
+        let backoff = match &self.backoff {
+            Some(backoff) => backoff,
+            None => return Some(Duration::ZERO),
+        };
+
+        if uptime >= backoff.max_delay {
+            self.delay = backoff.initial_delay;
+        }
+
+        let delay = self.delay;
+        self.delay = backoff.next_delay(delay);
+        Some(delay)
+    }
+}
+
+/// Stops the daemon using the process's stop mechanism, after first
+/// running the `pre-stop` command (if any). Failures of the `pre-stop`
+/// command are logged, but do not prevent the daemon from being stopped.
+/// The commands are killed (or skipped) once the process is killed.
+async fn stop_daemon(
+    config: &ProcessConfig,
+    control: &CommandControl,
+    killed: &watch::Receiver<bool>,
+) -> eyre::Result<()> {
+    if let Some(pre_stop) = &config.pre_stop {
+        if let Err(err) = run_killable_process_command(
+            &config.name,
+            ProcessPhase::PreStop,
+            pre_stop,
+            config.pre_stop_timeout,
+            &daemon_env(control),
+            killed,
+        )
+        .await
+        {
+            tracing::warn!(process = %config.name, ?err, "`pre-stop` command failed; stopping daemon anyway");
+        }
+    }
+
+    match &config.stop {
+        StopMechanism::Signal(signal) => send_stop_signal(config, control, *signal),
+        StopMechanism::Command(command) => {
+            run_killable_process_command(
+                &config.name,
+                ProcessPhase::Stop,
+                command,
+                config.stop_timeout,
+                &daemon_env(control),
+                killed,
+            )
+            .await
+        }
+        StopMechanism::Steps(steps) => match steps.first() {
+            Some(step) => take_stop_step(config, control, step, killed).await,
+            None => Ok(()),
+        },
+    }
+}
+
+/// Returns the environment variables that identify the (running) daemon
+/// to the commands that act on it: `pre-stop`, `stop`, and `reload`.
+fn daemon_env(control: &CommandControl) -> [(&'static str, String); 1] {
+    [("GROUNDCONTROL_MAIN_PID", control.pid().to_string())]
+}
+
+/// Sends the `stop` signal to the daemon (and, depending on the
+/// process's `kill-mode`, to the rest of its process group).
+fn send_stop_signal(
+    config: &ProcessConfig,
+    control: &CommandControl,
+    signal: SignalConfig,
+) -> eyre::Result<()> {
+    match config.kill_mode {
+        KillMode::Group => control.kill_group(config.outgoing_signal(signal)),
+        KillMode::Process | KillMode::Mixed => control.kill(config.outgoing_signal(signal)),
+    }
+}
+
+/// Takes one of the daemon's `stop` steps. Step commands are limited to
+/// the `timeout` of their step (and killed once the process is killed).
+async fn take_stop_step(
+    config: &ProcessConfig,
+    control: &CommandControl,
+    step: &StopStep,
+    killed: &watch::Receiver<bool>,
+) -> eyre::Result<()> {
+    match step {
+        StopStep::Signal { signal, .. } => send_stop_signal(config, control, *signal),
+        StopStep::Command { command, timeout } => {
+            run_killable_process_command(
+                &config.name,
+                ProcessPhase::Stop,
+                command,
+                *timeout,
+                &daemon_env(control),
+                killed,
+            )
+            .await
+        }
+    }
+}
+
+/// Returns the `stop` steps of the daemon (if it is stopped in steps).
+fn stop_steps(config: &ProcessConfig) -> &[StopStep] {
+    match &config.stop {
+        StopMechanism::Steps(steps) => steps,
+        StopMechanism::Signal(_) | StopMechanism::Command(_) => &[],
+    }
+}
+
+/// Returns the `stop` step that is being taken now, along with when to
+/// take the step after it (if the daemon has not stopped by then).
+fn step_deadline(config: &ProcessConfig, step: usize) -> Option<(usize, Instant)> {
+    let timeout = stop_steps(config).get(step)?.timeout()?;
+    Some((step, Instant::now() + timeout))
+}
+
+/// Kills the daemon with `SIGKILL` (along with the rest of its process
+/// group, depending on the process's `kill-mode`).
+fn kill_daemon(config: &ProcessConfig, control: &CommandControl) -> eyre::Result<()> {
+    match config.kill_mode {
+        KillMode::Process => control.kill(Signal::SIGKILL),
+        KillMode::Group | KillMode::Mixed => control.kill_group(Signal::SIGKILL),
+    }
+}
+
+/// Returns when to kill a daemon that is being stopped now, if it has
+/// not stopped by then (`None` if the process does not have a
+/// `stop-timeout`).
+fn kill_deadline(config: &ProcessConfig) -> Option<Instant> {
+    config.stop_timeout.map(|timeout| Instant::now() + timeout)
+}
+
+/// Runs the `on-crash` command, providing the exit code or signal of the
+/// crashed daemon via environment variables. The command is limited to
+/// the `on-crash-timeout` (and killed once the process is killed).
+/// Failures are logged, but otherwise do not change what happens to the
+/// daemon.
+async fn run_on_crash(
+    config: &ProcessConfig,
+    on_crash: &CommandConfig,
+    exit_status: ExitStatus,
+    killed: &watch::Receiver<bool>,
+) {
+    let env = match exit_status {
+        ExitStatus::Exited(exit_code) => vec![("GC_EXIT_CODE", exit_code.to_string())],
+        ExitStatus::Killed(Some(signal)) => vec![("GC_SIGNAL", signal.as_str().to_owned())],
+        ExitStatus::Killed(None) => vec![],
+    };
+
+    if let Err(err) = run_killable_process_command(
+        &config.name,
+        ProcessPhase::OnCrash,
+        on_crash,
+        config.on_crash_timeout,
+        &env,
+        killed,
+    )
+    .await
+    {
+        tracing::warn!(process = %config.name, ?err, "`on-crash` command failed");
+    }
+}
+
+/// Forwards the restarts of the dependency (and its final exit) to the
+/// supervisor of a daemon that depends on it, until either of them has
+/// exited.
+async fn watch_dependency(
+    dependency: Readiness,
+    propagate: PropagationPolicy,
+    events: mpsc::UnboundedSender<DependencyEvent>,
+) {
+    let mut restarts = match dependency.restarts.clone() {
+        Some(restarts) => restarts,
+        None => return,
+    };
+
+    // Only restarts that happen from now on are propagated.
+    restarts.borrow_and_update();
+
+    loop {
+        let restarted = restarts.changed().await.is_ok();
+        let event = DependencyEvent {
+            dependency: dependency.clone(),
+            propagate,
+            restarted,
+        };
+
+        if events.send(event).is_err() || !restarted {
+            return;
+        }
+    }
+}
+
+/// Tells the daemon to reload using the process's reload mechanism. The
+/// `reload` command is limited to the `reload-timeout` (and killed once
+/// the process is killed).
+async fn reload_daemon(
+    config: &ProcessConfig,
+    control: &CommandControl,
+    killed: &watch::Receiver<bool>,
+) -> eyre::Result<()> {
+    tracing::info!(process = %config.name, "Reloading daemon");
+
+    match &config.reload {
+        Some(ReloadMechanism::Signal(signal)) => control.kill(config.outgoing_signal(*signal)),
+        Some(ReloadMechanism::Command(command)) => {
+            run_killable_process_command(
+                &config.name,
+                ProcessPhase::Reload,
+                command,
+                config.reload_timeout,
+                &daemon_env(control),
+                killed,
+            )
+            .await
+        }
+        None => Err(eyre!(
+            "Process \"{}\" does not have a `reload` mechanism",
+            config.name
+        )),
+    }
+}
+
+/// Waits for the process to be killed (see [`Killer`]); waits forever if
+/// it never is.
+async fn wait_killed(mut killed: watch::Receiver<bool>) {
+    while !*killed.borrow_and_update() {
+        if killed.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Waits until the given deadline (waits forever if there is no
+/// deadline).
+async fn deadline(at: Option<Instant>) {
+    match at {
+        Some(at) => tokio::time::sleep_until(at.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for one of the daemon's probes to fail, returning the action
+/// to take (waits forever if the daemon is no longer being probed).
+async fn probe_failed(probes: &mut Option<Probes>) -> ProbeAction {
+    match probes {
+        Some(probes) => probes.failed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns `true` if the restart policy calls for the daemon to be
+/// restarted after exiting for the given reason.
+fn should_restart(policy: RestartPolicy, reason: ShutdownReason) -> bool {
+    match policy {
+        RestartPolicy::Never => false,
+        RestartPolicy::OnFailure => matches!(reason, ShutdownReason::DaemonFailed(_)),
+        RestartPolicy::Always => true,
+    }
+}
+
+/// Returns `true` if the exit status is a clean exit, or an exit with
+/// one of the allowed exit codes.
+fn is_success(exit_status: ExitStatus, allowed_exit_codes: &[i32]) -> bool {
+    match exit_status {
+        ExitStatus::Exited(0) => true,
+        ExitStatus::Exited(exit_code) => allowed_exit_codes.contains(&exit_code),
+        ExitStatus::Killed(_) => false,
+    }
 }
 
 impl Process {
+    /// Returns the name of the process.
+    pub(crate) fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Returns the configuration of the process (as it was given, which
+    /// is what changed configurations are compared against, and what
+    /// replicas are created from).
+    pub(crate) fn config(&self) -> &ProcessConfig {
+        &self.declared_config
+    }
+
+    /// Returns the readiness of the process.
+    pub(crate) fn readiness(&self) -> Readiness {
+        self.readiness.clone()
+    }
+
+    /// Returns a [`Killer`] for the process.
+    pub(crate) fn killer(&self) -> Killer {
+        Killer {
+            process_name: self.config.name.clone(),
+            killed: Arc::clone(&self.killed),
+        }
+    }
+
+    /// Forwards the signal to the daemon, if it is one of the daemon's
+    /// `forward-signals` (and the daemon is still running).
+    pub(crate) fn forward_signal(&self, signal: SignalConfig) {
+        if let ProcessHandle::Daemon(DaemonHandle { signal_request, .. }) = &self.handle {
+            if self.config.forward_signals.contains(&signal) {
+                let _ = signal_request.send(self.config.outgoing_signal(signal));
+            }
+        }
+    }
+
+    /// Logs the current status of the process: its state and, for
+    /// daemons, the PID, uptime, and restart count.
+    pub(crate) async fn log_status(&self) {
+        let status = match &self.handle {
+            ProcessHandle::Daemon(DaemonHandle { status_request, .. }) => {
+                let (reply_sender, reply_receiver) = oneshot::channel();
+                let _ = status_request.send(reply_sender);
+                reply_receiver.await.unwrap_or(DaemonStatus {
+                    state: ProcessState::Exited,
+                    running: None,
+                })
+            }
+            ProcessHandle::OneShot => DaemonStatus {
+                state: ProcessState::Completed,
+                running: None,
+            },
+        };
+
+        let pid = status.running.map(|(pid, _)| pid.as_raw());
+        let uptime = status.running.map(|(_, uptime)| uptime);
+        tracing::info!(process = %self.config.name, state = ?status.state, ?pid, ?uptime, restarts = ?self.readiness.restarts(), "Process status");
+    }
+
+    /// Returns `true` if this is a daemon process that can be reloaded.
+    pub(crate) fn is_reloadable(&self) -> bool {
+        matches!(self.handle, ProcessHandle::Daemon(_)) && self.config.reload.is_some()
+    }
+
+    /// Tells the daemon to reload using its `reload` signal/command,
+    /// returning a future that resolves to the result of the reload
+    /// (which does not hold on to the process). One-shot processes, and
+    /// daemons that have already exited, cannot be reloaded.
+    pub(crate) fn reload_process(&self) -> impl Future<Output = eyre::Result<()>> + Send + 'static {
+        let name = self.config.name.clone();
+        let reply_receiver = match &self.handle {
+            ProcessHandle::Daemon(DaemonHandle { reload_request, .. }) => {
+                let (reply_sender, reply_receiver) = oneshot::channel();
+                Ok(reload_request
+                    .send(reply_sender)
+                    .ok()
+                    .map(|()| reply_receiver))
+            }
+            ProcessHandle::OneShot => Err(eyre!(
+                "Process \"{}\" is not a daemon process and cannot be reloaded",
+                name
+            )),
+        };
+
+        async move {
+            let not_running =
+                || eyre!("Process \"{}\" is not running and cannot be reloaded", name);
+            match reply_receiver? {
+                Some(reply_receiver) => reply_receiver.await.map_err(|_| not_running())?,
+                None => Err(not_running()),
+            }
+        }
+    }
+
     /// Stops the process: executes the `stop` command/signal if this is
-    /// a daemon process; waits for the process to exit; runs the `post`
-    /// command (if present).
+    /// a daemon process; waits for the process to exit; runs the
+    /// `post-success` or `post-failure` command (depending on whether
+    /// the daemon failed) and then the `post` command (if present).
     pub(crate) async fn stop_process(self) -> eyre::Result<()> {
         tracing::info!("Stopping process {}", self.config.name);
 
         // Stop the process (which is only required for daemon
-        // processes; one-shot processes never "started").
-        match self.handle {
-            ProcessHandle::Daemon(control, mut daemon_receiver) => {
+        // processes; one-shot processes never "started"), noting if the
+        // daemon failed (crashed, or could not be stopped).
+        let failed = match self.handle {
+            ProcessHandle::Daemon(DaemonHandle {
+                stop_request,
+                mut exited,
+                ..
+            }) => {
                 // Has the daemon already shut down? If so, we do not
                 // need to stop it (we just need to run the `post`
                 // command, if any). Note that, if the `stop` operation
                 // fails, we will *not* wait for the daemon to exit,
                 // since it probably did not get our stop signal.
-                if daemon_receiver.try_recv().is_ok() {
+                if let Ok(daemon_exit) = exited.try_recv() {
                     tracing::debug!(process = %self.config.name, "Process already exited; no need to `stop` it.");
-                } else if let Err(err) = match self.config.stop {
-                    StopMechanism::Signal(signal) => control.kill(signal.into()),
-                    StopMechanism::Command(command) => {
-                        run_process_command(&self.config.name, ProcessPhase::Stop, &command).await
-                    }
-                } {
+                    daemon_exit.failed
+                } else if let Err(err) = request_stop(stop_request).await {
                     tracing::warn!(process = %self.config.name, ?err, "Error stopping process.");
+                    true
                 } else {
                     // Wait for the daemon to stop.
-                    match daemon_receiver.await {
-                        Ok(ExitStatus::Exited(0)) => {
+                    match exited.await {
+                        Ok(DaemonExit {
+                            escalated: true,
+                            failed,
+                            ..
+                        }) => {
+                            tracing::warn!(process = %self.config.name, "Process did not stop in time and was killed");
+                            failed
+                        }
+                        Ok(DaemonExit {
+                            exit_status: ExitStatus::Exited(0),
+                            failed,
+                            ..
+                        }) => {
                             tracing::debug!(process = %self.config.name, "Process exited cleanly");
+                            failed
                         }
-                        Ok(ExitStatus::Exited(exit_code)) => {
+                        Ok(DaemonExit {
+                            exit_status: ExitStatus::Exited(exit_code),
+                            failed,
+                            ..
+                        }) => {
                             tracing::warn!(process = %self.config.name, %exit_code, "Process exited with non-zero exit code");
+                            failed
                         }
-                        Ok(ExitStatus::Killed) => {
-                            tracing::warn!(process = %self.config.name, "Process was killed");
+                        Ok(DaemonExit {
+                            exit_status: ExitStatus::Killed(signal),
+                            failed,
+                            ..
+                        }) => {
+                            tracing::warn!(process = %self.config.name, ?signal, "Process was killed");
+                            failed
                         }
                         Err(_) => {
                             // TODO: Should this ever really happen? I
@@ -125,65 +1724,307 @@ impl Process {
                             // startup/shutdown failure, that we do not
                             // drop things too early and then receiver
                             // is gone.
-                            tracing::error!("Daemon sender dropped before delivering exit signal.")
+                            tracing::error!("Daemon sender dropped before delivering exit signal.");
+                            true
                         }
                     }
                 }
             }
-            ProcessHandle::OneShot => {}
+            ProcessHandle::OneShot => false,
         };
 
-        // Execute the `post`(-run) command.
-        if let Some(post_run) = &self.config.post {
-            run_process_command(&self.config.name, ProcessPhase::PostRun, post_run).await?;
-        }
+        // Execute the `post-success` or `post-failure` commands, then
+        // the `post`(-run) commands, even if the former failed (unless
+        // the process is killed).
+        let killed = self.killed.subscribe();
+        let outcome_result = if failed {
+            run_post_commands(
+                &self.config.name,
+                ProcessPhase::PostFailure,
+                &self.config.post_failure,
+                self.config.post_timeout,
+                &killed,
+            )
+            .await
+        } else {
+            run_post_commands(
+                &self.config.name,
+                ProcessPhase::PostSuccess,
+                &self.config.post_success,
+                self.config.post_timeout,
+                &killed,
+            )
+            .await
+        };
+
+        let post_result = run_post_commands(
+            &self.config.name,
+            ProcessPhase::PostRun,
+            &self.config.post,
+            self.config.post_timeout,
+            &killed,
+        )
+        .await;
+
+        // Remove the private temporary directory of the process (if any)
+        // now that none of its commands are running.
+        drop(self.private_tmp);
 
         // The process has been stopped.
-        Ok(())
+        post_result?;
+        outcome_result
+    }
+}
+
+/// Runs every one of the given `post` commands, in order, even if some
+/// of them fail. Failures (other than those of commands that ignore
+/// failures) are reported together once all of the commands have run.
+/// Once the process is killed, the running command is killed and the
+/// remaining commands are skipped.
+async fn run_post_commands(
+    process_name: &str,
+    process_phase: ProcessPhase,
+    commands: &[CommandConfig],
+    timeout: Option<Duration>,
+    killed: &watch::Receiver<bool>,
+) -> eyre::Result<()> {
+    let mut errors = Vec::new();
+
+    for command in commands {
+        if *killed.borrow() {
+            return Err(eyre!(
+                "`{process_phase}` commands were skipped for process \"{process_name}\" (process was killed)"
+            ));
+        }
+
+        if let Err(err) =
+            run_killable_process_command(process_name, process_phase, command, timeout, &[], killed)
+                .await
+        {
+            if command.ignore_failure {
+                tracing::warn!(process = %process_name, phase = %process_phase, ?err, "Ignoring failed command");
+            } else {
+                tracing::error!(process = %process_name, phase = %process_phase, ?err, "Command failed; continuing with the remaining commands");
+                errors.push(err);
+            }
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        failures => Err(eyre!(
+            "{failures} `{process_phase}` commands failed for process \"{process_name}\": {}",
+            errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>()
+                .join("; ")
+        )),
+    }
+}
+
+/// Asks the daemon supervisor to stop the daemon, returning the result
+/// of the `stop` operation. A supervisor that has already gone away is
+/// not an error: it only does so once the daemon has exited on its own.
+async fn request_stop(
+    stop_request: oneshot::Sender<oneshot::Sender<eyre::Result<()>>>,
+) -> eyre::Result<()> {
+    let (reply_sender, reply_receiver) = oneshot::channel();
+    if stop_request.send(reply_sender).is_err() {
+        return Ok(());
     }
+
+    reply_receiver.await.unwrap_or(Ok(()))
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-enum ProcessPhase {
+pub(crate) enum ProcessPhase {
+    Condition,
     PreRun,
+    Run,
+    Ready,
+    HealthCheck,
+    OnCrash,
+    Reload,
+    PreStop,
     Stop,
+    PostSuccess,
+    PostFailure,
     PostRun,
 }
 
 impl std::fmt::Display for ProcessPhase {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            ProcessPhase::Condition => write!(f, "condition"),
             ProcessPhase::PreRun => write!(f, "pre"),
+            ProcessPhase::Run => write!(f, "run"),
+            ProcessPhase::Ready => write!(f, "ready"),
+            ProcessPhase::HealthCheck => write!(f, "healthcheck"),
+            ProcessPhase::OnCrash => write!(f, "on-crash"),
+            ProcessPhase::Reload => write!(f, "reload"),
+            ProcessPhase::PreStop => write!(f, "pre-stop"),
             ProcessPhase::Stop => write!(f, "stop"),
+            ProcessPhase::PostSuccess => write!(f, "post-success"),
+            ProcessPhase::PostFailure => write!(f, "post-failure"),
             ProcessPhase::PostRun => write!(f, "post"),
         }
     }
 }
 
-/// Runs one of a process's "phase" commands -- `pre`, `stop`, or
-/// `post`, but crucially, not `run` -- and returns the success or
-/// failure of the command.
-async fn run_process_command(
+/// Error returned when a process could not be started.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum StartProcessError {
+    /// The process did not start within its `start-timeout` (because of
+    /// what it was waiting for, if given).
+    #[error(
+        "Process \"{process_name}\" did not start within {start_timeout:?}{}",
+        .waiting_for.as_ref().map_or(String::new(), |waiting_for| format!(" ({waiting_for})"))
+    )]
+    StartTimeout {
+        process_name: String,
+        start_timeout: Duration,
+        waiting_for: Option<String>,
+    },
+}
+
+/// Error returned when a phase command was killed because it did not
+/// finish within its timeout.
+#[derive(Debug, thiserror::Error)]
+#[error("`{process_phase}` command timed out for process \"{process_name}\" (after {timeout:?})")]
+pub(crate) struct CommandTimedOut {
+    process_name: String,
+    process_phase: ProcessPhase,
+    timeout: Duration,
+}
+
+/// Runs one of a process's "phase" commands -- `condition`, `pre`,
+/// `ready`, `healthcheck`, `on-crash`, `reload`, `pre-stop`, `stop`, or one of the
+/// `post` commands, but crucially, not `run` -- and returns the success or
+/// failure of the command. The command (along with its process group) is killed if
+/// it does not finish within the (optional) timeout, in which case the error is a
+/// [`CommandTimedOut`].
+pub(crate) async fn run_process_command(
     process_name: &str,
     process_phase: ProcessPhase,
     command: &CommandConfig,
+    timeout: Option<Duration>,
 ) -> eyre::Result<()> {
-    let (_control, monitor) = command::run(&format!("{process_name}[{process_phase}]"), command)
-        .wrap_err_with(|| {
-            format!("`{process_phase}` command failed for process \"{process_name}\"")
-        })?;
+    run_process_command_allowing(
+        process_name,
+        process_phase,
+        command,
+        timeout,
+        &[],
+        &[],
+        None,
+        None,
+    )
+    .await
+}
+
+/// Same as [`run_process_command`], but also sets the given environment
+/// variables for the command, which is killed (or not run at all) once
+/// the process is killed (see [`Killer`]).
+async fn run_killable_process_command(
+    process_name: &str,
+    process_phase: ProcessPhase,
+    command: &CommandConfig,
+    timeout: Option<Duration>,
+    extra_env: &[(&str, String)],
+    killed: &watch::Receiver<bool>,
+) -> eyre::Result<()> {
+    run_process_command_allowing(
+        process_name,
+        process_phase,
+        command,
+        timeout,
+        &[],
+        extra_env,
+        Some(killed),
+        None,
+    )
+    .await
+}
 
-    match monitor.wait().await {
-        ExitStatus::Exited(0) => Ok(()),
+/// Same as [`run_process_command`], but also treats the given exit codes
+/// as success, sets the given environment variables for the command,
+/// kills the command once the process is killed (if given), and runs the
+/// command in the cgroup of the process (if given).
+#[allow(clippy::too_many_arguments)]
+async fn run_process_command_allowing(
+    process_name: &str,
+    process_phase: ProcessPhase,
+    command: &CommandConfig,
+    timeout: Option<Duration>,
+    allowed_exit_codes: &[i32],
+    extra_env: &[(&str, String)],
+    killed: Option<&watch::Receiver<bool>>,
+    cgroup: Option<&CgroupConfig>,
+) -> eyre::Result<()> {
+    let was_killed =
+        || eyre!("`{process_phase}` command was killed for process \"{process_name}\"");
+    if killed.map_or(false, |killed| *killed.borrow()) {
+        return Err(was_killed());
+    }
+
+    let (control, mut monitor) = command::run(
+        &format!("{process_name}[{process_phase}]"),
+        command,
+        SpawnContext {
+            process_name: Some(process_name),
+            cgroup,
+        },
+        extra_env,
+        None,
+        true,
+        None,
+    )
+    .wrap_err_with(|| format!("`{process_phase}` command failed for process \"{process_name}\""))?;
+
+    let killed = async {
+        match killed {
+            Some(killed) => wait_killed(killed.clone()).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    // Phase commands run in their own session, so killing their process
+    // group also kills whatever the command started (which could
+    // otherwise keep running, and keep its output open).
+    let exit_status = tokio::select! {
+        exit_status = monitor.wait() => exit_status,
+        () = deadline(timeout.map(|timeout| Instant::now() + timeout)) => {
+            tracing::warn!(process = %process_name, phase = %process_phase, elapsed = ?timeout, "Command exceeded its time limit; killing command");
+            if let Err(err) = control.kill_group(Signal::SIGKILL) {
+                tracing::warn!(process = %process_name, phase = %process_phase, ?err, "Error killing command");
+            }
+
+            return Err(CommandTimedOut {
+                process_name: process_name.to_owned(),
+                process_phase,
+                timeout: timeout.unwrap_or_default(),
+            }
+            .into());
+        }
+        () = killed => {
+            tracing::warn!(process = %process_name, phase = %process_phase, "Process was killed; killing command");
+            if let Err(err) = control.kill_group(Signal::SIGKILL) {
+                tracing::warn!(process = %process_name, phase = %process_phase, ?err, "Error killing command");
+            }
+
+            return Err(was_killed());
+        }
+    };
+
+    match exit_status {
+        exit_status if is_success(exit_status, allowed_exit_codes) => Ok(()),
         ExitStatus::Exited(exit_code) => {
             Err(eyre!(
                 "`{process_phase}` command failed for process \"{process_name}\" (exit code {exit_code})",
             ))
         }
-        ExitStatus::Killed => {
-            Err(eyre!(
-                "`{process_phase}` command was killed for process \"{process_name}\"",
-            ))
-        }
+        ExitStatus::Killed(_) => Err(was_killed()),
     }
 }
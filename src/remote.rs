@@ -0,0 +1,79 @@
+//! Fetches configurations from `http://` and `https://` URLs, so that
+//! images can pull their specification when they start instead of
+//! baking it in.
+
+use std::time::Duration;
+
+use color_eyre::eyre::{self, WrapErr};
+use sha2::{Digest, Sha256};
+
+/// How long to wait for the configuration server (to connect, and then to
+/// send the whole configuration).
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns `true` if the given path is a URL that can be fetched.
+pub(crate) fn is_url(path: &str) -> bool {
+    path.starts_with("https://") || path.starts_with("http://")
+}
+
+/// Returns the path of the URL (without its query or fragment), which
+/// determines the format of the configuration.
+pub(crate) fn url_path(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
+
+/// Fetches the configuration at the given URL, verifying that its
+/// SHA-256 checksum (as a hexadecimal string) and its `ETag` header are
+/// the expected ones, if given.
+pub(crate) fn fetch(url: &str, sha256: Option<&str>, etag: Option<&str>) -> eyre::Result<String> {
+    let response = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .build()
+        .get(url)
+        .call()
+        .wrap_err_with(|| format!("Failed to fetch config \"{url}\""))?;
+
+    if let Some(expected) = etag {
+        match response.header("ETag") {
+            Some(actual) if unquote(actual) == unquote(expected) => {}
+            Some(actual) => {
+                return Err(eyre::eyre!(
+                    "Config \"{url}\" has the ETag {actual}, but {expected} was expected"
+                ))
+            }
+            None => {
+                return Err(eyre::eyre!(
+                    "Config \"{url}\" does not have an ETag, but {expected} was expected"
+                ))
+            }
+        }
+    }
+
+    let text = response
+        .into_string()
+        .wrap_err_with(|| format!("Failed to read config \"{url}\""))?;
+
+    if let Some(expected) = sha256 {
+        let actual: String = Sha256::digest(text.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(eyre::eyre!(
+                "Config \"{url}\" has the SHA-256 checksum {actual}, but {expected} was expected"
+            ));
+        }
+    }
+
+    Ok(text)
+}
+
+/// Removes the weak prefix and the quotes from an `ETag`, so that
+/// `W/"abc"`, `"abc"`, and `abc` are the same tag.
+fn unquote(etag: &str) -> &str {
+    let etag = etag.trim();
+    let etag = etag.strip_prefix("W/").unwrap_or(etag);
+    etag.strip_prefix('"')
+        .and_then(|etag| etag.strip_suffix('"'))
+        .unwrap_or(etag)
+}
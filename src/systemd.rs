@@ -0,0 +1,111 @@
+//! Notifications to systemd, for when Ground Control itself is a
+//! systemd service (with `Type=notify`).
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use tokio::{net::UnixDatagram, task::JoinHandle};
+
+/// Notification socket inherited from systemd.
+#[derive(Debug)]
+pub(crate) struct Systemd {
+    socket: UnixDatagram,
+    path: PathBuf,
+
+    /// How often to send `WATCHDOG=1` (half of the watchdog timeout), if
+    /// the systemd watchdog is enabled for Ground Control.
+    watchdog_interval: Option<Duration>,
+}
+
+impl Systemd {
+    /// Returns the notification socket named by `NOTIFY_SOCKET` (if
+    /// any), and the watchdog timeout in `WATCHDOG_USEC`, and removes
+    /// those variables from the environment so that the processes do not
+    /// inherit them and notify systemd themselves.
+    pub(crate) fn from_env() -> Option<Self> {
+        let watchdog_usec = std::env::var("WATCHDOG_USEC").ok();
+        let watchdog_pid = std::env::var("WATCHDOG_PID").ok();
+        std::env::remove_var("WATCHDOG_USEC");
+        std::env::remove_var("WATCHDOG_PID");
+
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        std::env::remove_var("NOTIFY_SOCKET");
+
+        // The watchdog is meant for another process if `WATCHDOG_PID` is
+        // set to something other than our PID.
+        let watchdog_interval = watchdog_usec
+            .filter(|_| {
+                watchdog_pid.map_or(true, |pid| pid == std::process::id().to_string())
+            })
+            .and_then(|usec| match usec.parse::<u64>() {
+                Ok(usec) if usec > 0 => Some(Duration::from_micros(usec) / 2),
+                _ => {
+                    tracing::warn!(%usec, "Invalid `WATCHDOG_USEC`; not pinging the systemd watchdog");
+                    None
+                }
+            });
+
+        // Sockets in the abstract namespace cannot be addressed by path.
+        let path = PathBuf::from(path);
+        if path.to_string_lossy().starts_with('@') {
+            tracing::warn!(
+                ?path,
+                "Abstract `NOTIFY_SOCKET` addresses are not supported; not notifying systemd"
+            );
+            return None;
+        }
+
+        match UnixDatagram::unbound() {
+            Ok(socket) => Some(Self {
+                socket,
+                path,
+                watchdog_interval,
+            }),
+            Err(err) => {
+                tracing::warn!(?err, "Error creating socket; not notifying systemd");
+                None
+            }
+        }
+    }
+
+    /// Sends the given state (such as `READY=1`) to systemd. Failures
+    /// are logged, but are otherwise ignored.
+    pub(crate) async fn notify(&self, state: &str) {
+        if let Err(err) = self.socket.send_to(state.as_bytes(), &self.path).await {
+            tracing::warn!(?err, %state, "Error notifying systemd");
+        }
+    }
+
+    /// Returns how often to ping the systemd watchdog (if it is enabled).
+    pub(crate) fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval
+    }
+
+    /// Pings the systemd watchdog (if it is enabled) from a background
+    /// task, until the returned handle is dropped. Used while Ground
+    /// Control is starting and stopping processes, which can take as
+    /// long as those processes take.
+    pub(crate) fn keep_alive(self: &Arc<Self>) -> KeepAlive {
+        let systemd = Arc::clone(self);
+        KeepAlive(self.watchdog_interval.map(|interval| {
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    interval.tick().await;
+                    systemd.notify("WATCHDOG=1").await;
+                }
+            })
+        }))
+    }
+}
+
+/// Pings the systemd watchdog until it is dropped.
+#[derive(Debug)]
+pub(crate) struct KeepAlive(Option<JoinHandle<()>>);
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        if let Some(task) = &self.0 {
+            task.abort();
+        }
+    }
+}
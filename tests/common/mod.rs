@@ -1,8 +1,11 @@
 //! Helper functions for Ground Control integration tests
 
-use std::{future::Future, time::Duration};
+use std::{future::Future, path::Path, time::Duration};
 
-use groundcontrol::config::Config;
+use groundcontrol::{
+    config::{Config, ConfigFormat},
+    Control,
+};
 use nix::unistd::Pid;
 use tempfile::TempDir;
 use tokio::sync::{
@@ -42,18 +45,47 @@ use tokio::sync::{
 ///   the next process. (without that, test results would be
 ///   inconsistent depending on which process got to run first, and for
 ///   how long).
+#[allow(dead_code)]
 pub async fn start(
     config: &str,
 ) -> (
-    impl Future<Output = Result<(), groundcontrol::Error>>,
+    impl Future<Output = Result<groundcontrol::Shutdown, groundcontrol::Error>>,
     UnboundedSender<()>,
     TempDir,
 ) {
+    let (config, dir) = prepare(config).await;
+
+    // Start Ground Control and return the handles.
+    let (tx, rx) = mpsc::unbounded_channel();
+    let gc = groundcontrol::run(config, rx);
+    (gc, tx, dir)
+}
+
+/// Same as [`start`], but returns a [`Control`] sender instead of the
+/// shutdown handle.
+#[allow(dead_code)]
+pub async fn start_with_control(
+    config: &str,
+) -> (
+    impl Future<Output = Result<groundcontrol::Shutdown, groundcontrol::Error>>,
+    UnboundedSender<Control>,
+    TempDir,
+) {
+    let (config, dir) = prepare(config).await;
+
+    // Start Ground Control and return the handles.
+    let (tx, rx) = mpsc::unbounded_channel();
+    let gc = groundcontrol::run_with_control(config, rx);
+    (gc, tx, dir)
+}
+
+/// Prepares the test directory and parses the test configuration (see
+/// [`start`] for the details).
+async fn prepare(config: &str) -> (Config, TempDir) {
     // Create a temp directory into which we can write output from the
     // commands, as a simple way of verifying that the commands are in
     // fact run in the proper order.
     let dir = TempDir::new().unwrap();
-    let result_path = dir.path().join("results.txt").to_str().unwrap().to_string();
 
     // Write the test scripts into the temp directory.
     let test_daemon_path = dir
@@ -79,29 +111,36 @@ pub async fn start(
     .await
     .unwrap();
 
-    // Parse the test configuration, replacing our template variables
-    // before passing the config to the parser.
-    let config: Config = toml::from_str(
+    let config = parse_config(config, dir.path());
+    (config, dir)
+}
+
+/// Parses the test configuration, replacing the template variables (see
+/// [`start`]) with the paths in the given test directory before passing
+/// the config to the parser.
+#[allow(dead_code)]
+pub fn parse_config(config: &str, dir: &Path) -> Config {
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    Config::parse(
         &config
-            .replace("{result_path}", &result_path)
-            .replace("{temp_path}", dir.path().to_str().unwrap())
-            .replace("{test-daemon.sh}", &test_daemon_path)
-            .replace("{wait-daemon-start.sh}", &wait_daemon_start_path),
+            .replace("{result_path}", &path("results.txt"))
+            .replace("{temp_path}", dir.to_str().unwrap())
+            .replace("{test-daemon.sh}", &path("test-daemon.sh"))
+            .replace("{wait-daemon-start.sh}", &path("wait-daemon-start.sh")),
+        ConfigFormat::Toml,
     )
-    .unwrap();
-
-    // Start Ground Control and return the handles.
-    let (tx, rx) = mpsc::unbounded_channel();
-    let gc = groundcontrol::run(config, rx);
-    (gc, tx, dir)
+    .unwrap()
 }
 
 /// Waits for Ground Control to stop, then collects the contents of the
 /// result file.
 pub async fn stop(
-    gc: impl Future<Output = Result<(), groundcontrol::Error>>,
+    gc: impl Future<Output = Result<groundcontrol::Shutdown, groundcontrol::Error>>,
     dir: TempDir,
-) -> (Result<(), groundcontrol::Error>, String) {
+) -> (
+    Result<groundcontrol::Shutdown, groundcontrol::Error>,
+    String,
+) {
     // Wait for Ground Control to stop.
     let result = gc.await;
 
@@ -152,7 +191,10 @@ pub fn spawn_daemon_waiter(dir: &TempDir, daemon_name: &str) -> oneshot::Receive
 /// Asserts that the Ground Control result is the `StartupAborted` error
 /// and that the error report matches the expected text.
 #[allow(dead_code)]
-pub fn assert_startup_aborted(expected: &str, result: Result<(), groundcontrol::Error>) {
+pub fn assert_startup_aborted(
+    expected: &str,
+    result: Result<groundcontrol::Shutdown, groundcontrol::Error>,
+) {
     match result {
         Err(groundcontrol::Error::StartupAborted(report)) => {
             let report_text: String = report.chain().map(|r| format!("{r}\n")).collect();
@@ -161,3 +203,35 @@ pub fn assert_startup_aborted(expected: &str, result: Result<(), groundcontrol::
         Ok(_) | Err(_) => panic!("Expected StartupAborted error."),
     };
 }
+
+/// Asserts that the process with the given PID has exited (shortly
+/// after this is called), or at least that it is a zombie that has not
+/// been reaped yet.
+#[allow(dead_code)]
+pub async fn assert_exited(pid: Pid) {
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).unwrap_or_default();
+    assert!(
+        !status
+            .lines()
+            .any(|line| line.starts_with("State:") && !line.contains('Z')),
+        "Process {pid} is still running"
+    );
+}
+
+/// Waits for the result file to contain the given line.
+#[allow(dead_code)]
+pub async fn wait_for_result_line(dir: &Path, line: &str) {
+    let result_path = dir.join("results.txt");
+
+    loop {
+        match tokio::fs::read_to_string(&result_path).await {
+            Ok(text) if text.lines().any(|l| l == line) => break,
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => panic!("Unable to read result file: {err}"),
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
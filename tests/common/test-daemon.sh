@@ -29,13 +29,27 @@ do_stop() {
 }
 trap 'do_stop' INT
 
+# Trap the SIGHUP reload signal (which logs a reload-requested message
+# and keeps the daemon running).
+RELOADED=
+do_reload() {
+    echo ${DAEMON_NAME}:reload-requested >> ${RESULT_PATH}
+    RELOADED=1
+}
+trap 'do_reload' HUP
+
 # Write out the PID of the *this* process, which the test code can use
 # to determine that the daemon has started, and to stop the daemon.
 echo $$ > ${PID_BASE_PATH}/${DAEMON_NAME}.pid
 
 # Wait for sleep to exit/be stopped, and ignore its exit code (otherwise
-# the test-daemon script will fail).
+# the test-daemon script will fail). Reload requests interrupt the
+# wait, so go back to waiting after every reload.
 wait ${SLEEP_PID} || true
+while [ -n "${RELOADED}" ]; do
+    RELOADED=
+    wait ${SLEEP_PID} || true
+done
 
 # Clean exit.
 echo ${DAEMON_NAME}:stopped >> ${RESULT_PATH}
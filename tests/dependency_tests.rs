@@ -0,0 +1,983 @@
+//! Tests that verify the `depends-on` relationships between processes,
+//! which override the order in which processes are started and stopped.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{
+    assert_startup_aborted, spawn_daemon_waiter, start, stop, wait_for_result_line,
+};
+
+mod common;
+
+/// Processes start after the processes they depend on (even if they are
+/// listed first), and are stopped before those processes.
+#[test_log::test(tokio::test)]
+async fn dependencies_start_first_and_stop_last() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        depends-on = [ "db", "cache" ]
+        run = [ "/bin/sh", "{test-daemon.sh}", "app", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "db"
+        pre = [ "/bin/sh", "-c", "echo db-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo db-post >> {result_path}" ]
+
+        [[processes]]
+        name = "cache"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo cache-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo cache-post >> {result_path}" ]
+
+        [[processes]]
+        name = "unrelated"
+        pre = [
+            [ "/bin/sh", "{wait-daemon-start.sh}", "app", "{temp_path}" ],
+            [ "/bin/sh", "-c", "echo unrelated-pre >> {result_path}" ],
+        ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "app");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db-pre
+            cache-pre
+            app:started
+            unrelated-pre
+            app:shutdown-requested
+            app:stopped
+            cache-post
+            db-post
+        "#},
+        output
+    );
+}
+
+/// Dependencies on unknown processes abort the startup before any
+/// processes are started.
+#[test_log::test(tokio::test)]
+async fn unknown_dependency_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        depends-on = [ "nope" ]
+        pre = [ "/bin/sh", "-c", "echo b-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        "Process \"b\" depends on unknown process \"nope\"\n",
+        result,
+    );
+    assert_eq!("", output);
+}
+
+/// Dependency cycles abort the startup before any processes are
+/// started.
+#[test_log::test(tokio::test)]
+async fn dependency_cycle_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        depends-on = [ "b" ]
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        depends-on = [ "a" ]
+        pre = [ "/bin/sh", "-c", "echo b-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "c"
+        pre = [ "/bin/sh", "-c", "echo c-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted("Dependency cycle: \"a\" -> \"b\" -> \"a\"\n", result);
+    assert_eq!("", output);
+}
+
+/// Dependents wait for their dependencies to create their readiness
+/// file (instead of only waiting for them to be running).
+#[test_log::test(tokio::test)]
+async fn dependents_wait_for_ready_file() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "sleep 0.3; exec /bin/sh {test-daemon.sh} db {result_path} {temp_path}" ]
+        ready = { file = "{temp_path}/db.pid" }
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "db");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            app-pre
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Dependents wait for their dependencies to pass their health check
+/// when the dependencies use the `healthy` readiness check.
+#[test_log::test(tokio::test)]
+async fn dependents_wait_for_healthy_dependencies() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "sleep 0.3; exec /bin/sh {test-daemon.sh} db {result_path} {temp_path}" ]
+        healthcheck = { command = [ "/bin/sh", "-c", "test -f {temp_path}/db.pid" ], interval = "50ms", failure-threshold = 20 }
+        ready = "healthy"
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "db");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            app-pre
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons with a readiness command are not started until the command
+/// succeeds, so the processes after them wait for them to be ready.
+#[test_log::test(tokio::test)]
+async fn ready_command_delays_startup() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "sleep 0.3; exec /bin/sh {test-daemon.sh} db {result_path} {temp_path}" ]
+        ready = { command = [ "/bin/sh", "-c", "test -f {temp_path}/db.pid" ], interval = "50ms", retries = 20 }
+
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "db");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            app-pre
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons with a readiness path are not started until the path exists.
+#[test_log::test(tokio::test)]
+async fn ready_path_delays_startup() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "sleep 0.3; exec /bin/sh {test-daemon.sh} db {result_path} {temp_path}" ]
+        ready = { path = "{temp_path}/db.pid" }
+
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "db");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            app-pre
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons whose readiness path does not exist before the timeout are
+/// stopped, and abort the startup.
+#[test_log::test(tokio::test)]
+async fn missing_ready_path_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "{test-daemon.sh}", "db", "{result_path}", "{temp_path}" ]
+        ready = { path = "{temp_path}/db.sock", timeout = "200ms" }
+
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            Readiness check failed for process "db"
+            Process "db" exited before it was ready
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons whose readiness path was not found by `failure-threshold`
+/// checks are stopped (before their `timeout`), and abort the startup.
+#[test_log::test(tokio::test)]
+async fn ready_path_failure_threshold_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "{test-daemon.sh}", "db", "{result_path}", "{temp_path}" ]
+        ready = { path = "{temp_path}/db.sock", period = "10ms", failure-threshold = 3, timeout = "1h" }
+
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            Readiness check failed for process "db"
+            Process "db" exited before it was ready
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons with the `notify` readiness check are ready once they send
+/// `READY=1` to their `NOTIFY_SOCKET`.
+#[test_log::test(tokio::test)]
+async fn dependents_wait_for_ready_notification() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "echo $NOTIFY_SOCKET > {temp_path}/db.notify; exec /bin/sh {test-daemon.sh} db {result_path} {temp_path}" ]
+        ready = "notify"
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    // Send the notification (on behalf of the daemon) once the daemon
+    // has started, then stop Ground Control once `app` has started.
+    let daemon_waiter = spawn_daemon_waiter(&dir, "db");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let mut results = std::fs::OpenOptions::new()
+            .append(true)
+            .open(result_dir.join("results.txt"))
+            .unwrap();
+        std::io::Write::write_all(&mut results, b"db:notifying\n").unwrap();
+
+        let notify_socket = std::fs::read_to_string(result_dir.join("db.notify")).unwrap();
+        let socket = std::os::unix::net::UnixDatagram::unbound().unwrap();
+        socket
+            .send_to(b"STATUS=Starting\nREADY=1\n", notify_socket.trim())
+            .unwrap();
+
+        wait_for_result_line(&result_dir, "app-pre").await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            db:notifying
+            app-pre
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons with an HTTP readiness check are ready once their endpoint
+/// responds with the expected status code.
+#[test_log::test(tokio::test)]
+async fn dependents_wait_for_ready_http() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "sleep 0.3; exec /bin/sh {test-daemon.sh} db {result_path} {temp_path}" ]
+        ready = { http = "http://127.0.0.1:{port}/healthz", interval = "50ms" }
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##
+    .replace("{port}", &port.to_string());
+
+    let (gc, tx, dir) = start(&config).await;
+
+    // The endpoint only succeeds once the daemon has started.
+    let pid_path = dir.path().join("db.pid");
+    tokio::task::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut request).await;
+
+            let response: &[u8] = if pid_path.exists() {
+                b"HTTP/1.0 200 OK\r\n\r\n"
+            } else {
+                b"HTTP/1.0 503 Service Unavailable\r\n\r\n"
+            };
+            let _ = tokio::io::AsyncWriteExt::write_all(&mut stream, response).await;
+        }
+    });
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "db");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            app-pre
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons with a `log-line` readiness check are ready once they write a
+/// matching line to stdout or stderr.
+#[test_log::test(tokio::test)]
+async fn dependents_wait_for_ready_log_line() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "echo Starting; sleep 0.3; echo db-listening >> {result_path}; echo Listening on port 5432 >&2; exec sleep 5" ]
+        ready = { log-line = "^Listening on port \\d+$" }
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        wait_for_result_line(&result_dir, "app-pre").await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db-listening
+            app-pre
+        "#},
+        output
+    );
+}
+
+/// Daemons whose readiness command never succeeds are stopped, and
+/// abort the startup.
+#[test_log::test(tokio::test)]
+async fn failed_ready_command_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "{test-daemon.sh}", "db", "{result_path}", "{temp_path}" ]
+        ready = { command = [ "/bin/sh", "-c", "exit 1" ], interval = "50ms", retries = 3 }
+
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            Readiness check failed for process "db"
+            Process "db" exited before it was ready
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Dependencies that exit before they are ready abort the startup.
+#[test_log::test(tokio::test)]
+async fn dependency_exiting_before_ready_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "echo db >> {result_path}" ]
+        ready = { file = "{temp_path}/db.ready" }
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted("Process \"db\" exited before it was ready\n", result);
+    assert_eq!("db\n", output);
+}
+
+/// Waiting for dependencies to become ready counts towards the
+/// dependent's `start-timeout`.
+#[test_log::test(tokio::test)]
+async fn start_timeout_limits_waiting_for_dependencies() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "{test-daemon.sh}", "db", "{result_path}", "{temp_path}" ]
+        ready = { file = "{temp_path}/db.ready" }
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        start-timeout = "300ms"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        "Process \"app\" did not start within 300ms (dependency \"db\" was not ready)\n",
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// `parallel_shutdown` stops the processes at each level of the
+/// dependency graph concurrently, starting with the processes that
+/// nothing depends on.
+#[test_log::test(tokio::test)]
+async fn parallel_shutdown_stops_levels_concurrently() {
+    let config = r##"
+        parallel_shutdown = true
+
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "echo b-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "sleep 0.3; echo b-post >> {result_path}" ]
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "a", "b" ]
+        run = [ "/bin/sh", "{test-daemon.sh}", "app", "{result_path}", "{temp_path}" ]
+        post = [ "/bin/sh", "-c", "echo app-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "app");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    // `b` is stopped before `a` in a sequential shutdown, but since its
+    // `post` command takes longer, `a` finishes first when they are
+    // stopped concurrently.
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            b-pre
+            app:started
+            app:shutdown-requested
+            app:stopped
+            app-post
+            a-post
+            b-post
+        "#},
+        output
+    );
+}
+
+/// `after` and `before` only change the order in which processes are
+/// started (and stopped).
+#[test_log::test(tokio::test)]
+async fn after_and_before_order_processes() {
+    let config = r##"
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "echo b-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo b-post >> {result_path}" ]
+
+        [[processes]]
+        name = "a"
+        before = [ "b" ]
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "c"
+        after = [ "d" ]
+        pre = [ "/bin/sh", "-c", "echo c-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo c-post >> {result_path}" ]
+
+        [[processes]]
+        name = "d"
+        pre = [ "/bin/sh", "-c", "echo d-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo d-post >> {result_path}" ]
+
+        [[processes]]
+        name = "e"
+        run = [ "/bin/sh", "-c", "echo e >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            b-pre
+            d-pre
+            c-pre
+            e
+            c-post
+            d-post
+            b-post
+            a-post
+        "#},
+        output
+    );
+}
+
+/// Processes that are ordered `after` a daemon do not wait for the
+/// daemon to become ready.
+#[test_log::test(tokio::test)]
+async fn after_does_not_wait_for_readiness() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        after = [ "db" ]
+        start-timeout = "300ms"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "{test-daemon.sh}", "db", "{result_path}", "{temp_path}" ]
+        ready = { file = "{temp_path}/db.ready" }
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        wait_for_result_line(&result_dir, "app-pre").await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(output.lines().any(|line| line == "app-pre"));
+}
+
+/// Processes are started even if the processes they `want` exit before
+/// they are ready (unlike processes they require).
+#[test_log::test(tokio::test)]
+async fn wanted_dependency_failure_is_tolerated() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        pre = [ "/bin/sh", "-c", "echo db-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "exporter"
+        run = [ "/bin/sh", "-c", "echo exporter >> {result_path}" ]
+        ready = { file = "{temp_path}/exporter.ready" }
+
+        [[processes]]
+        name = "app"
+        requires = [ "db" ]
+        wants = [ "exporter" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo app-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db-pre
+            exporter
+            app-pre
+            app-post
+        "#},
+        output
+    );
+}
+
+/// Dependency cycle errors list exactly the processes in the cycle (and
+/// not the processes that are only blocked by the cycle), no matter how
+/// the processes in the cycle are related.
+#[test_log::test(tokio::test)]
+async fn dependency_cycle_error_lists_cycle() {
+    let config = r##"
+        [[processes]]
+        name = "blocked"
+        depends-on = [ "b" ]
+        pre = [ "/bin/sh", "-c", "echo blocked-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "a"
+        wants = [ "c" ]
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        after = [ "a" ]
+        pre = [ "/bin/sh", "-c", "echo b-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "c"
+        before = [ "a" ]
+        depends-on = [ "b" ]
+        pre = [ "/bin/sh", "-c", "echo c-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        "Dependency cycle: \"b\" -> \"a\" -> \"c\" -> \"b\"\n",
+        result,
+    );
+    assert_eq!("", output);
+}
+
+/// Every process in a phase must be ready before any process in the
+/// next phase is started; processes without a phase are not affected.
+#[test_log::test(tokio::test)]
+async fn phases_are_barriers() {
+    let config = r##"
+        [[processes]]
+        name = "migrate"
+        phase = "init"
+        pre = [ "/bin/sh", "-c", "echo migrate >> {result_path}" ]
+
+        [[processes]]
+        name = "unphased"
+        pre = [ "/bin/sh", "-c", "echo unphased >> {result_path}" ]
+
+        [[processes]]
+        name = "db"
+        phase = "services"
+        run = [ "/bin/sh", "-c", "sleep 0.2; exec /bin/sh {test-daemon.sh} db {result_path} {temp_path}" ]
+        ready = { file = "{temp_path}/db.pid" }
+
+        [[processes]]
+        name = "web"
+        phase = "services"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "db", "{temp_path}" ]
+        run = [ "/bin/sh", "{test-daemon.sh}", "web", "{result_path}", "{temp_path}" ]
+        ready = { file = "{temp_path}/web.pid" }
+
+        [[processes]]
+        name = "warm-cache"
+        phase = "finalize"
+        pre = [ "/bin/sh", "-c", "echo warm-cache >> {result_path}" ]
+
+        [[processes]]
+        name = "seed"
+        phase = "init"
+        pre = [ "/bin/sh", "-c", "echo seed >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        wait_for_result_line(&result_dir, "warm-cache").await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    // `web` waits for `db` itself (in its `pre` command), since both are
+    // in the same phase. `warm-cache` is only started once both are
+    // ready, and is stopped first.
+    assert_eq!(
+        indoc! {r#"
+            migrate
+            unphased
+            seed
+            db:started
+            web:started
+            warm-cache
+            web:shutdown-requested
+            web:stopped
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Dependents with `propagate = "restart"` are stopped while their
+/// dependency is being restarted, and are restarted once the dependency
+/// is ready again.
+#[test_log::test(tokio::test)]
+async fn restarts_propagate_to_dependents() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        depends-on = [ { name = "db", propagate = "restart" } ]
+        run = [ "/bin/sh", "-c", "if [ -f {temp_path}/app1.pid ]; then exec /bin/sh {test-daemon.sh} app2 {result_path} {temp_path}; else exec /bin/sh {test-daemon.sh} app1 {result_path} {temp_path}; fi" ]
+
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "if [ -f {temp_path}/db.ran ]; then exec /bin/sh {test-daemon.sh} db {result_path} {temp_path}; fi; touch {temp_path}/db.ran {temp_path}/db.pid; /bin/sh {wait-daemon-start.sh} app1 {temp_path}; exit 1" ]
+        ready = { file = "{temp_path}/db.pid" }
+        restart = "on-failure"
+        restart-backoff = { initial-delay = "200ms" }
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "app2");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            app1:started
+            app1:shutdown-requested
+            app1:stopped
+            db:started
+            app2:started
+            app2:shutdown-requested
+            app2:stopped
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Dependents with `propagate = "stop"` are stopped when their
+/// dependency exits, without triggering a shutdown.
+#[test_log::test(tokio::test)]
+async fn exits_propagate_to_dependents() {
+    let config = r##"
+        [[processes]]
+        name = "other"
+        run = [ "/bin/sh", "{test-daemon.sh}", "other", "{result_path}", "{temp_path}" ]
+        ready = { file = "{temp_path}/other.pid" }
+
+        [[processes]]
+        name = "app"
+        wants = [ "other" ]
+        depends-on = [ { name = "db", propagate = "stop" } ]
+        run = [ "/bin/sh", "{test-daemon.sh}", "app", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "/bin/sh {wait-daemon-start.sh} app {temp_path}; echo db-exited >> {result_path}" ]
+        critical = false
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    // Ground Control is still running after `app` was stopped.
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        wait_for_result_line(&result_dir, "app:stopped").await;
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        let mut results = std::fs::OpenOptions::new()
+            .append(true)
+            .open(result_dir.join("results.txt"))
+            .unwrap();
+        std::io::Write::write_all(&mut results, b"still-running\n").unwrap();
+
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            other:started
+            app:started
+            db-exited
+            app:shutdown-requested
+            app:stopped
+            still-running
+            other:shutdown-requested
+            other:stopped
+        "#},
+        output
+    );
+}
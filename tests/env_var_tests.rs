@@ -3,8 +3,9 @@
 
 use indoc::indoc;
 use pretty_assertions::assert_eq;
+use tempfile::TempDir;
 
-use crate::common::{assert_startup_aborted, start, stop};
+use crate::common::{assert_startup_aborted, spawn_daemon_waiter, start, stop};
 
 mod common;
 
@@ -200,6 +201,421 @@ async fn allow_additional_vars() {
     );
 }
 
+/// Commands can set their own variables (which can use Ground Control
+/// environment expansion syntax), even if they use `only-env`.
+#[test_log::test(tokio::test)]
+async fn command_vars() {
+    std::env::set_var("PATH", "im_the_path");
+    std::env::set_var("TESTVAR1", "one");
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        pre = { env = { TESTVAR2 = "two" }, command = [ "/bin/sh", "-c", "echo pre: $TESTVAR1 $TESTVAR2 >> {result_path}" ] }
+        run = { only-env = [], env = { DATABASE_URL = "db://{{TESTVAR1}}/app" }, command = [ "/bin/sh", "-c", "echo run: $TESTVAR1 $DATABASE_URL {{DATABASE_URL}} >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            pre: one two
+            run: db://one/app db://one/app
+        "#},
+        output
+    );
+}
+
+/// Processes can read variables from an env file, which the commands'
+/// own variables override.
+#[test_log::test(tokio::test)]
+async fn env_file() {
+    let env_dir = TempDir::new().unwrap();
+    let env_file = env_dir.path().join("app.env");
+    std::fs::write(
+        &env_file,
+        indoc! {r#"
+            # Settings for the app.
+            TESTVAR5=five
+            export TESTVAR6="six six"
+        "#},
+    )
+    .unwrap();
+
+    let config = r##"
+        [[processes]]
+        name = "optional"
+        env-file = { path = "{env_file}.missing", if-missing = "ignore" }
+        pre = [ "/bin/sh", "-c", "echo optional: $TESTVAR5 >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        env-file = "{env_file}"
+        pre = { only-env = [], command = [ "/bin/sh", "-c", "echo pre: $TESTVAR5 $TESTVAR6 >> {result_path}" ] }
+        run = { env = { TESTVAR6 = "six" }, command = [ "/bin/sh", "-c", "echo run: $TESTVAR5 $TESTVAR6 >> {result_path}" ] }
+        "##
+    .replace("{env_file}", env_file.to_str().unwrap());
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            optional:
+            pre: five six six
+            run: five six
+        "#},
+        output
+    );
+}
+
+/// Commands can read the values of variables from files (such as
+/// secrets), without the trailing newline.
+#[test_log::test(tokio::test)]
+async fn env_from_file() {
+    let secrets_dir = TempDir::new().unwrap();
+    let secret_file = secrets_dir.path().join("db_password");
+    std::fs::write(&secret_file, "hunter2\n").unwrap();
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = { only-env = [], env-from-file = { DB_PASSWORD = "{secret_file}" }, command = [ "/bin/sh", "-c", "echo \"[$DB_PASSWORD]\" >> {result_path}" ] }
+        "##
+    .replace("{secret_file}", secret_file.to_str().unwrap());
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            [hunter2]
+        "#},
+        output
+    );
+}
+
+/// Processes can get secrets from a command, which are set for their
+/// `pre` and `run` commands.
+#[test_log::test(tokio::test)]
+async fn secrets_command() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        secrets-command = [ "/bin/sh", "-c", "echo DB_PASSWORD=hunter2; echo 'API_KEY=\"abc def\"'" ]
+        pre = [ "/bin/sh", "-c", "echo pre: $DB_PASSWORD >> {result_path}" ]
+        run = { only-env = [], command = [ "/bin/sh", "-c", "echo run: $DB_PASSWORD $API_KEY >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            pre: hunter2
+            run: hunter2 abc def
+        "#},
+        output
+    );
+}
+
+/// Processes do not start if their secrets command fails.
+#[test_log::test(tokio::test)]
+async fn secrets_command_must_succeed() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        secrets-command = [ "/bin/sh", "-c", "exit 3" ]
+        run = [ "/bin/sh", "-c", "echo $DB_PASSWORD >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, _output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `run` command failed for process "daemon"
+            `secrets-command` failed for process "daemon"
+            Command "/bin/sh" failed (exit status: 3)
+        "#},
+        result,
+    );
+}
+
+/// Secrets commands are killed if they do not finish within the
+/// `start-timeout` of the process, which then does not start.
+#[test_log::test(tokio::test)]
+async fn secrets_command_is_limited_to_start_timeout() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        start-timeout = "500ms"
+        secrets-command = [ "/bin/sh", "-c", "/bin/sleep 5; echo DB_PASSWORD=hunter2" ]
+        run = [ "/bin/sh", "-c", "echo $DB_PASSWORD >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let started_at = std::time::Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(5));
+    assert_startup_aborted(
+        indoc! {r#"
+            `run` command failed for process "daemon"
+            `secrets-command` failed for process "daemon"
+            Command "/bin/sh" did not finish within 500ms; killed it
+        "#},
+        result,
+    );
+    assert_eq!("", output);
+}
+
+/// Env files must exist, unless their processes allow them to be
+/// missing.
+#[test_log::test(tokio::test)]
+async fn env_file_requires_file_to_exist() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        env-file = "{temp_path}/missing.env"
+        run = [ "/bin/sh", "-c", "echo $TESTVAR5 >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let temp_path = dir.path().to_str().unwrap().to_string();
+    let (result, _output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        &format!(
+            "`run` command failed for process \"daemon\"\nEnv file \"{temp_path}/missing.env\" does not exist\n"
+        ),
+        result,
+    );
+}
+
+/// Allowed variables can be given as patterns, which allow every
+/// variable that matches them (even if no variables match them).
+#[test_log::test(tokio::test)]
+async fn allow_var_patterns() {
+    std::env::set_var("PATH", "im_the_path");
+    std::env::set_var("GLOBVAR_ONE", "one");
+    std::env::set_var("GLOBVAR_TWO", "two");
+    std::env::set_var("THREE_GLOBTAIL", "three");
+    std::env::set_var("OTHERVAR", "other");
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = { only-env = ["GLOBVAR_*", "*_GLOBTAIL", "MISSING_*"], command = [ "/bin/sh", "-c", "echo $PATH $GLOBVAR_ONE $GLOBVAR_TWO $THREE_GLOBTAIL $OTHERVAR >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            im_the_path one two three
+        "#},
+        output
+    );
+}
+
+/// Commands can choose to inherit every variable, no variables, or only
+/// the listed variables (and `PATH`), regardless of the default
+/// `only-env`.
+#[test_log::test(tokio::test)]
+async fn inherit_env() {
+    std::env::set_var("PATH", "im_the_path");
+    std::env::set_var("TESTVAR1", "one");
+
+    let config = r##"
+        [defaults]
+        only-env = []
+
+        [[processes]]
+        name = "daemon"
+        pre = [
+            { inherit-env = "none", env = { TESTVAR7 = "seven" }, command = [ "/bin/sh", "-c", "echo none: $TESTVAR1 $TESTVAR7 >> {result_path}" ] },
+            { inherit-env = "listed", command = [ "/bin/sh", "-c", "echo listed: $PATH $TESTVAR1 >> {result_path}" ] },
+        ]
+        run = { inherit-env = "all", command = [ "/bin/sh", "-c", "echo all: $PATH $TESTVAR1 >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            none: seven
+            listed: im_the_path
+            all: im_the_path one
+        "#},
+        output
+    );
+}
+
+/// Commands can set the login variables of the user that runs them
+/// (unless their own variables set them).
+#[test_log::test(tokio::test)]
+async fn login_env() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = { login-env = true, env = { HOME = "/app" }, command = [ "/bin/sh", "-c", "echo $HOME $USER $LOGNAME >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let user = users::get_current_username().unwrap();
+    assert_eq!(format!("/app {0} {0}\n", user.to_string_lossy()), output);
+}
+
+/// References to variables (such as `${PORT}`) in the arguments of
+/// commands (in either form) are replaced with the variables of the
+/// command's environment; references to unknown variables are left as
+/// is, and `$${VAR}` escapes a reference.
+#[test_log::test(tokio::test)]
+async fn arg_var_references() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        pre = { env = { PORT = "8080" }, command = "/bin/sh -c 'echo pre: \"$@\" >> {result_path}' sh --port=${PORT} ${GROUNDCONTROL_PROCESS_NAME} $${PORT} ${GC_TEST_UNKNOWN_VAR}" }
+        run = { inherit-env = "none", env = { PORT = "9090" }, command = [ "/bin/sh", "-c", "echo run: \"$@\" >> {result_path}", "sh", "--port=${PORT}", "${PATH}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            pre: --port=8080 daemon ${PORT} ${GC_TEST_UNKNOWN_VAR}
+            run: --port=9090 ${PATH}
+        "#},
+        output
+    );
+}
+
+/// References to variables in the script of a shell command are left to
+/// the shell, so the values of the variables are not parsed as shell code.
+#[test_log::test(tokio::test)]
+async fn shell_script_var_references() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        pre = { env = { NAME = "x; /bin/touch {temp_path}/pwned" }, shell = true, command = "echo pre: ${NAME} >> {result_path}" }
+        run = { env = { NAME = "y; /bin/touch {temp_path}/pwned" }, command = [ "/bin/sh", "-ec", "echo run: ${NAME} $1 >> {result_path}; if [ -e {temp_path}/pwned ]; then echo pwned >> {result_path}; fi", "sh", "${NAME}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let temp_path = dir.path().to_str().unwrap().to_string();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            pre: x; /bin/touch {temp_path}/pwned
+            run: y; /bin/touch {temp_path}/pwned y; /bin/touch {temp_path}/pwned
+        "#}
+        .replace("{temp_path}", &temp_path),
+        output
+    );
+}
+
+/// Commands get the pid of Ground Control (not that of the daemon), and
+/// the name and instance of their process (even if they use `only-env`).
+#[test_log::test(tokio::test)]
+async fn metadata_vars() {
+    let config = r##"
+        [[processes]]
+        name = "worker"
+        replicas = 1
+        pre = [ "/bin/sh", "-c", "echo pre: $GROUNDCONTROL_PROCESS_NAME $GROUNDCONTROL_INSTANCE $GROUNDCONTROL_SUPERVISOR_PID >> {result_path}" ]
+        run = { only-env = [], command = [ "/bin/sh", "-c", "echo run: $GROUNDCONTROL_PROCESS_NAME $GROUNDCONTROL_INSTANCE $GROUNDCONTROL_SUPERVISOR_PID >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let pid = std::process::id();
+    assert_eq!(
+        format!("pre: worker-0 0 {pid}\nrun: worker-0 0 {pid}\n"),
+        output
+    );
+}
+
+/// Commands of processes that are not replicated are instance 0 (and
+/// Ground Control's pid is not passed on as `GROUNDCONTROL_PID`).
+#[test_log::test(tokio::test)]
+async fn metadata_vars_without_replicas() {
+    let config = r##"
+        [[processes]]
+        name = "worker"
+        pre = [ "/bin/sh", "-c", "echo pre: $GROUNDCONTROL_PROCESS_NAME $GROUNDCONTROL_INSTANCE $GROUNDCONTROL_SUPERVISOR_PID >> {result_path}" ]
+        run = { inherit-env = "none", command = [ "/bin/sh", "-c", "echo run: $GROUNDCONTROL_PROCESS_NAME $GROUNDCONTROL_INSTANCE $GROUNDCONTROL_SUPERVISOR_PID ${GROUNDCONTROL_PID:-unset} >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let pid = std::process::id();
+    assert_eq!(
+        format!("pre: worker 0 {pid}\nrun: worker 0 {pid} unset\n"),
+        output
+    );
+}
+
+/// The `pre-stop` and `stop` commands of a daemon get the pid of the
+/// daemon.
+#[test_log::test(tokio::test)]
+async fn main_pid_var() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/daemon.pid; exec /bin/sleep 10" ]
+        pre-stop = [ "/bin/sh", "-c", "echo pre-stop: $GROUNDCONTROL_MAIN_PID >> {result_path}" ]
+        stop = [ "/bin/sh", "-c", "echo stop: $GROUNDCONTROL_MAIN_PID >> {result_path}; kill $GROUNDCONTROL_MAIN_PID" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let (pid_sender, pid) = tokio::sync::oneshot::channel();
+    tokio::task::spawn(async move {
+        pid_sender.send(daemon_waiter.await.unwrap()).unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let pid = pid.await.unwrap();
+    assert_eq!(format!("pre-stop: {pid}\nstop: {pid}\n"), output);
+}
+
 /// Allowed environment variables must exist in the environment.
 #[test_log::test(tokio::test)]
 async fn allowed_vars_requires_variable_to_exist() {
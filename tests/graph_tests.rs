@@ -0,0 +1,79 @@
+//! Tests that verify the rendering of the startup graph.
+
+use groundcontrol::{
+    config::Config,
+    graph::{render, GraphFormat},
+};
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+const CONFIG: &str = r##"
+    [[processes]]
+    name = "app"
+    depends-on = [ "db" ]
+    wants = [ "exporter" ]
+    run = "/app/server"
+
+    [[processes]]
+    name = "db"
+    run = "/usr/bin/postgres"
+
+    [[processes]]
+    name = "exporter"
+    before = [ "app" ]
+    run = "/usr/bin/exporter"
+    "##;
+
+/// Graphviz output lists the processes in startup order, then the edges
+/// between them.
+#[test]
+fn renders_dot() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            digraph groundcontrol {
+                "db";
+                "exporter";
+                "app";
+                "db" -> "app" [label="requires"];
+                "exporter" -> "app" [label="wants", style=dashed];
+                "exporter" -> "app" [label="after", style=dotted];
+            }
+        "#},
+        render(&config, GraphFormat::Dot).unwrap()
+    );
+}
+
+/// Mermaid output identifies the processes by their index in the config.
+#[test]
+fn renders_mermaid() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            flowchart TD
+                p1["db"]
+                p2["exporter"]
+                p0["app"]
+                p1 -->|requires| p0
+                p2 -.->|wants| p0
+                p2 -.->|after| p0
+        "#},
+        render(&config, GraphFormat::Mermaid).unwrap()
+    );
+}
+
+/// Invalid dependencies cannot be rendered.
+#[test]
+fn rejects_invalid_dependencies() {
+    let config: Config = toml::from_str(indoc! {r#"
+        [[processes]]
+        name = "app"
+        depends-on = [ "nope" ]
+        run = "/app/server"
+    "#})
+    .unwrap();
+
+    assert!(render(&config, GraphFormat::Dot).is_err());
+}
@@ -0,0 +1,198 @@
+//! Tests that verify the aggregated `/healthz` endpoint.
+
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Returns an address on which nothing is listening (yet).
+fn unused_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Requests the given path, and returns the response (without the
+/// headers, but with the status line).
+async fn get(address: &str, path: &str) -> (String, String) {
+    let mut stream = loop {
+        match TcpStream::connect(address).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+
+    let request = format!("GET {path} HTTP/1.1\r\nHost: {address}\r\n\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let status_line = response.lines().next().unwrap().to_owned();
+    let body = response.split("\r\n\r\n").nth(1).unwrap().to_owned();
+    (status_line, body)
+}
+
+/// The endpoint reports every process once they have all started.
+#[test_log::test(tokio::test)]
+async fn healthz_reports_started_processes() {
+    let address = unused_address();
+    let config = r##"
+        healthz = "{address}"
+
+        [[processes]]
+        name = "init"
+        pre = [ "/bin/sh", "-c", "echo init >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        "##
+    .replace("{address}", &address);
+
+    let (gc, tx, dir) = start(&config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let request = tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+
+        // Wait for Ground Control to report that startup has completed.
+        let response = loop {
+            let response = get(&address, "/healthz").await;
+            if !response.1.contains("\"starting\"") {
+                break response;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        let not_found = get(&address, "/").await;
+
+        tx.send(()).unwrap();
+        (response, not_found)
+    });
+
+    let (result, _) = stop(gc, dir).await;
+    let (response, not_found) = request.await.unwrap();
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        (
+            String::from("HTTP/1.0 200 OK"),
+            String::from(concat!(
+                r#"{"status":"ok","processes":["#,
+                r#"{"name":"init","type":"one-shot","ready":true},"#,
+                r#"{"name":"daemon","type":"daemon","ready":true,"running":true,"restarts":0}"#,
+                r#"]}"#
+            ))
+        ),
+        response
+    );
+    assert_eq!("HTTP/1.0 404 Not Found", not_found.0);
+}
+
+/// The endpoint reports an error while processes are still starting.
+#[test_log::test(tokio::test)]
+async fn healthz_reports_starting_processes() {
+    let address = unused_address();
+    let config = r##"
+        healthz = "{address}"
+
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "{test-daemon.sh}", "db", "{result_path}", "{temp_path}" ]
+        ready = { file = "{temp_path}/db.ready" }
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##
+    .replace("{address}", &address);
+
+    let (gc, tx, dir) = start(&config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "db");
+    let ready_path = dir.path().join("db.ready");
+    let request = tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        let response = get(&address, "/healthz").await;
+
+        // Let the startup finish, so that the shutdown is handled.
+        std::fs::write(ready_path, "").unwrap();
+        tx.send(()).unwrap();
+        response
+    });
+
+    let (result, _) = stop(gc, dir).await;
+    let response = request.await.unwrap();
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        (
+            String::from("HTTP/1.0 503 Service Unavailable"),
+            String::from(concat!(
+                r#"{"status":"starting","processes":["#,
+                r#"{"name":"db","type":"daemon","ready":false,"running":true,"restarts":0}"#,
+                r#"]}"#
+            ))
+        ),
+        response
+    );
+}
+
+/// Clients that never finish their request are disconnected (once the
+/// request times out), without holding up the other clients.
+#[test_log::test(tokio::test)]
+async fn healthz_disconnects_stalled_clients() {
+    let address = unused_address();
+    let config = r##"
+        healthz = "{address}"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        "##
+    .replace("{address}", &address);
+
+    let (gc, tx, dir) = start(&config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let request = tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+
+        // Send the request line, but never the end of the headers.
+        let mut stalled = TcpStream::connect(&address).await.unwrap();
+        stalled
+            .write_all(b"GET /healthz HTTP/1.1\r\n")
+            .await
+            .unwrap();
+
+        let response = loop {
+            let response = get(&address, "/healthz").await;
+            if !response.1.contains("\"starting\"") {
+                break response;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+
+        let mut stalled_response = String::new();
+        stalled.read_to_string(&mut stalled_response).await.unwrap();
+
+        tx.send(()).unwrap();
+        (response.0, stalled_response)
+    });
+
+    let (result, _) = stop(gc, dir).await;
+    let (status_line, stalled_response) = request.await.unwrap();
+
+    assert!(result.is_ok());
+
+    assert_eq!("HTTP/1.0 200 OK", status_line);
+    assert_eq!("", stalled_response);
+}
@@ -0,0 +1,182 @@
+//! Tests that verify the import of the configurations of other process
+//! managers.
+
+use std::collections::HashMap;
+
+use groundcontrol::config::{Config, ConfigFormat, RestartPolicy};
+use pretty_assertions::assert_eq;
+
+/// Programs are imported in the order of their priority, with their
+/// command, user, environment, and restart policy.
+#[test]
+fn imports_supervisord_programs() {
+    let conf = r#"
+[supervisord]
+nodaemon = true
+
+; The worker starts after the web server.
+[program:worker]
+command = /app/worker --queue "%(ENV_SUPERVISORD_QUEUE)s" --name %(program_name)s ; comment
+user = app
+environment = RUST_LOG="info,app=debug",
+    PORT=8080
+stdout_logfile = /dev/stdout
+
+[program:web]
+command=/app/web
+priority=10
+autorestart=false
+"#;
+
+    std::env::set_var("SUPERVISORD_QUEUE", "jobs");
+    let config = Config::parse(conf, ConfigFormat::Supervisord).unwrap();
+    config.validate().unwrap();
+
+    let web = &config.processes[0];
+    assert_eq!("web", web.name);
+    assert_eq!(Some(RestartPolicy::Never), web.restart);
+    assert!(!web.critical);
+    let run = web.run.as_ref().unwrap();
+    assert_eq!("/app/web", run.program);
+    assert!(run.args.is_empty());
+
+    let worker = &config.processes[1];
+    assert_eq!("worker", worker.name);
+    assert_eq!(Some(RestartPolicy::OnFailure), worker.restart);
+    let run = worker.run.as_ref().unwrap();
+    assert_eq!("/app/worker", run.program);
+    assert_eq!(vec!["--queue", "jobs", "--name", "worker"], run.args);
+    assert_eq!(Some("app"), run.user.as_deref());
+    assert_eq!(
+        HashMap::from([
+            ("RUST_LOG".to_string(), "info,app=debug".to_string()),
+            ("PORT".to_string(), "8080".to_string()),
+        ]),
+        run.env
+    );
+}
+
+/// Programs must have a command, and can only use the expansions that
+/// Ground Control can reproduce.
+#[test]
+fn rejects_unsupported_supervisord_programs() {
+    let conf = r#"
+[program:app]
+user = app
+"#;
+    assert_eq!(
+        "Program \"app\" does not have a `command`",
+        Config::parse(conf, ConfigFormat::Supervisord)
+            .unwrap_err()
+            .to_string()
+    );
+
+    let conf = r#"
+[program:app]
+command = /app/server --host %(host_node_name)s
+"#;
+    assert_eq!(
+        "Program \"app\" uses the `%(host_node_name)s` expansion, which cannot be imported",
+        Config::parse(conf, ConfigFormat::Supervisord)
+            .unwrap_err()
+            .to_string()
+    );
+}
+
+/// Services are imported with their command, user, environment,
+/// dependencies, and restart policy (and with their variables
+/// replaced).
+#[test]
+fn imports_compose_services() {
+    let compose = r#"
+version: "3.8"
+services:
+  web:
+    image: example/web
+    command: /app/web --port ${COMPOSE_PORT:-8080}
+    user: "1000"
+    environment:
+      DATABASE_URL: postgres://db/${COMPOSE_DATABASE}
+      WORKERS: 4
+      PASSED_THROUGH:
+    depends_on:
+      db:
+        condition: service_healthy
+    restart: unless-stopped
+  db:
+    command: [ "/usr/bin/postgres", "-D", "/data" ]
+    environment:
+      - PGDATA=/data
+      - PASSED_THROUGH
+volumes:
+  data: {}
+"#;
+
+    std::env::set_var("COMPOSE_DATABASE", "app");
+    let config = Config::parse(compose, ConfigFormat::Compose).unwrap();
+    config.validate().unwrap();
+
+    let web = &config.processes[0];
+    assert_eq!("web", web.name);
+    assert_eq!(Some(RestartPolicy::Always), web.restart);
+    assert!(!web.critical);
+    assert_eq!(
+        vec!["db"],
+        web.depends_on
+            .iter()
+            .map(|dependency| dependency.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    let run = web.run.as_ref().unwrap();
+    assert_eq!("/app/web", run.program);
+    assert_eq!(vec!["--port", "8080"], run.args);
+    assert_eq!(Some("1000"), run.user.as_deref());
+    assert_eq!(
+        HashMap::from([
+            ("DATABASE_URL".to_string(), "postgres://db/app".to_string()),
+            ("WORKERS".to_string(), "4".to_string()),
+        ]),
+        run.env
+    );
+
+    let db = &config.processes[1];
+    assert_eq!("db", db.name);
+    assert_eq!(Some(RestartPolicy::Never), db.restart);
+    let run = db.run.as_ref().unwrap();
+    assert_eq!("/usr/bin/postgres", run.program);
+    assert_eq!(vec!["-D", "/data"], run.args);
+    assert_eq!(
+        HashMap::from([("PGDATA".to_string(), "/data".to_string())]),
+        run.env
+    );
+}
+
+/// Services must have a command (since the command of their image is not
+/// available), and required variables must be set.
+#[test]
+fn rejects_unsupported_compose_services() {
+    let compose = r#"
+services:
+  app:
+    image: example/app
+"#;
+    assert_eq!(
+        "Service \"app\" does not have a `command` (the command of its image cannot be imported)",
+        Config::parse(compose, ConfigFormat::Compose)
+            .unwrap_err()
+            .to_string()
+    );
+
+    let compose = r#"
+services:
+  app:
+    command: /app/server --token ${COMPOSE_MISSING_TOKEN:?the token is required}
+"#;
+    assert_eq!(
+        "Variable `COMPOSE_MISSING_TOKEN` is not set (the token is required)",
+        Config::parse(compose, ConfigFormat::Compose)
+            .unwrap_err()
+            .root_cause()
+            .to_string()
+    );
+}
@@ -168,3 +168,329 @@ async fn multiple_daemons_first_one_exits() {
         output
     );
 }
+
+/// Non-critical daemons that exit do not trigger a shutdown; they are
+/// still considered to have failed once Ground Control shuts down.
+#[test_log::test(tokio::test)]
+async fn non_critical_daemon_exit_is_ignored() {
+    let config = r##"
+        [[processes]]
+        name = "shipper"
+        critical = false
+        run = [ "/bin/sh", "-c", "echo shipper >> {result_path}; exit 1" ]
+        post-failure = [ "/bin/sh", "-c", "echo shipper-post-failure >> {result_path}" ]
+
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "until grep -q shipper {result_path}; do sleep 0.01; done" ]
+        run = [ "/bin/sh", "{test-daemon.sh}", "app", "{result_path}", "{temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "app");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+
+        // Give Ground Control a chance to (incorrectly) shut down because
+        // of the failed daemon.
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    // A shutdown triggered by the failed daemon would be an abnormal
+    // shutdown.
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            shipper
+            app:started
+            app:shutdown-requested
+            app:stopped
+            shipper-post-failure
+        "#},
+        output
+    );
+}
+
+/// `forking` daemons are supervised using the PID that they write to
+/// their `pid-file` (after their `run` command has exited): they are
+/// stopped by signalling that PID.
+#[test_log::test(tokio::test)]
+async fn forking_daemon_is_stopped_by_pid() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo forked >> {result_path}; /bin/sh {test-daemon.sh} daemon {result_path} {temp_path} &" ]
+        type = "forking"
+        pid-file = "{temp_path}/daemon.pid"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "echo b-pre >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            forked
+            daemon:started
+            b-pre
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// The result describes why Ground Control shut down: because it was
+/// asked to, or because a daemon exited.
+#[test_log::test(tokio::test)]
+async fn shutdown_reason_is_reported() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, _) = stop(gc, dir).await;
+    assert_eq!(groundcontrol::Shutdown::Requested, result.unwrap());
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "exit 3" ]
+        allowed-exit-codes = [ 3 ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, _) = stop(gc, dir).await;
+    assert_eq!(
+        groundcontrol::Shutdown::DaemonExited {
+            process: String::from("daemon"),
+            exit_code: 3
+        },
+        result.unwrap()
+    );
+}
+
+/// The exit code of the daemon that failed is reported in the error.
+#[test_log::test(tokio::test)]
+async fn failed_daemon_exit_code_is_reported() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "exit 42" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, _) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { process, exit_code: 42 }) if process == "daemon"
+    ));
+}
+
+/// Daemons that are killed by a signal are reported with 128 plus the
+/// number of the signal (as shells do), so that, for example, OOM kills
+/// can be told apart from crashes.
+#[test_log::test(tokio::test)]
+async fn killed_daemon_exit_code_is_reported() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "kill -KILL $$" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, _) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { exit_code: 137, .. })
+    ));
+}
+
+/// `forking` daemons that exit on their own trigger a shutdown (and are
+/// considered to have failed, because their exit status is not known).
+#[test_log::test(tokio::test)]
+async fn forking_daemon_exit_triggers_shutdown() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "/bin/sh {test-daemon.sh} daemon {result_path} {temp_path} &" ]
+        type = "forking"
+        pid-file = "{temp_path}/daemon.pid"
+        post-failure = [ "/bin/sh", "-c", "echo daemon-post-failure >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        let pid = daemon_waiter.await.unwrap();
+        nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGTERM).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post-failure
+        "#},
+        output
+    );
+}
+
+/// `run` commands are started in their own session, unless the process
+/// opts out with `new-session = false`.
+#[test_log::test(tokio::test)]
+async fn run_commands_get_their_own_session() {
+    let config = r##"
+        [[processes]]
+        name = "own"
+        run = [ "/bin/sh", "-c", "[ $(cut -d' ' -f6 /proc/$$/stat) = $$ ] && echo own:leader >> {result_path}; exec /bin/sh {test-daemon.sh} own {result_path} {temp_path}" ]
+        ready = { file = "{temp_path}/own.pid" }
+
+        [[processes]]
+        name = "shared"
+        depends-on = [ "own" ]
+        run = [ "/bin/sh", "-c", "[ $(cut -d' ' -f6 /proc/$$/stat) = $$ ] || echo shared:not-leader >> {result_path}; exec /bin/sh {test-daemon.sh} shared {result_path} {temp_path}" ]
+        new-session = false
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "shared");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            own:leader
+            own:started
+            shared:not-leader
+            shared:started
+            shared:shutdown-requested
+            shared:stopped
+            own:shutdown-requested
+            own:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons with a `parent-death-signal` start and stop as usual (the
+/// signal is only sent if Ground Control dies).
+#[test_log::test(tokio::test)]
+async fn parent_death_signal_does_not_affect_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        parent-death-signal = "SIGKILL"
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// The `run` command of a `oneshot` process runs to completion before
+/// the next process is started, and its exit does not trigger a
+/// shutdown.
+#[test_log::test(tokio::test)]
+async fn oneshot_run_command_runs_to_completion() {
+    let config = r##"
+        [[processes]]
+        name = "migrate"
+        type = "oneshot"
+        pre = [ "/bin/sh", "-c", "echo migrate-pre >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "echo migrate >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo migrate-post >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            migrate-pre
+            migrate
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            migrate-post
+        "#},
+        output
+    );
+}
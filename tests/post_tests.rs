@@ -4,7 +4,7 @@
 
 use indoc::indoc;
 
-use crate::common::{start, stop};
+use crate::common::{spawn_daemon_waiter, start, stop};
 
 mod common;
 
@@ -142,3 +142,193 @@ async fn not_found_post_continues_shutdown() {
         output
     );
 }
+
+/// `post-success` runs (before `post`) for processes that did not fail:
+/// one-shot processes, and daemons that were stopped during shutdown.
+#[test_log::test(tokio::test)]
+async fn post_success_after_graceful_stop() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post-success = [ "/bin/sh", "-c", "echo a-post-success >> {result_path}" ]
+        post-failure = [ "/bin/sh", "-c", "echo a-post-failure >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        post-success = [ "/bin/sh", "-c", "echo daemon-post-success >> {result_path}" ]
+        post-failure = [ "/bin/sh", "-c", "echo daemon-post-failure >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post-success
+            daemon-post
+            a-post-success
+        "#},
+        output
+    );
+}
+
+/// `post-failure` runs (before `post`) for daemons that crashed; the
+/// other processes are still considered successful.
+#[test_log::test(tokio::test)]
+async fn post_failure_after_crash() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post-success = [ "/bin/sh", "-c", "echo a-post-success >> {result_path}" ]
+        post-failure = [ "/bin/sh", "-c", "echo a-post-failure >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; exit 1" ]
+        post-success = [ "/bin/sh", "-c", "echo daemon-post-success >> {result_path}" ]
+        post-failure = [ "/bin/sh", "-c", "echo daemon-post-failure >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            daemon
+            daemon-post-failure
+            daemon-post
+            a-post-success
+        "#},
+        output
+    );
+}
+
+/// Multiple `post` commands run in order, and each command runs even if
+/// the ones before it failed.
+#[test_log::test(tokio::test)]
+async fn multiple_post_commands_all_run() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [
+            [ "/bin/sh", "-c", "echo a-post-1 >> {result_path}; exit 1" ],
+            [ "/bin/sh", "-c", "echo a-post-2 >> {result_path}" ],
+            [ "/bin/sh", "-c", "echo a-post-3 >> {result_path}; exit 2" ],
+        ]
+
+        [[processes]]
+        name = "b"
+        run = [ "/bin/sh", "-c", "echo b >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            b
+            a-post-1
+            a-post-2
+            a-post-3
+        "#},
+        output
+    );
+}
+
+/// `post` commands that exceed the `post-timeout` are killed, and the
+/// shutdown continues.
+#[test_log::test(tokio::test)]
+async fn post_timeout_kills_post() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        run = [ "/bin/sh", "-c", "echo b >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "sleep 5; echo b-post >> {result_path}" ]
+        post-timeout = "100ms"
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            b
+            a-post
+        "#},
+        output
+    );
+}
+
+/// Processes with only a `post` command do nothing during startup, and
+/// run their `post` command in their place in the (reverse) shutdown
+/// order.
+#[test_log::test(tokio::test)]
+async fn shutdown_only_process() {
+    let config = r##"
+        [[processes]]
+        name = "final-backup"
+        post = [ "/bin/sh", "-c", "echo final-backup >> {result_path}" ]
+
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        run = [ "/bin/sh", "-c", "echo b >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo b-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            b
+            b-post
+            a-post
+            final-backup
+        "#},
+        output
+    );
+}
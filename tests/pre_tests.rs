@@ -35,6 +35,31 @@ async fn pre_before_run() {
     );
 }
 
+/// A `shell` command runs through `/bin/sh`, so it can use pipes and
+/// shell variables.
+#[test_log::test(tokio::test)]
+async fn shell_pre_supports_pipelines() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        pre = { shell = true, command = "GREETING=hello; echo $GREETING | tr a-z A-Z >> {result_path}" }
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            HELLO
+            daemon
+        "#},
+        output
+    );
+}
+
 /// Verifies that a failed `pre` execution aborts all subsequent command
 /// executions *and* runs stop/post commands for anything that was
 /// started.
@@ -160,6 +185,226 @@ async fn not_found_pre_aborts_startup() {
     );
 }
 
+/// Verifies that a `pre` command with an unknown group aborts startup.
+#[test_log::test(tokio::test)]
+async fn unknown_group_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = { group = "no-such-group", command = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `pre` command failed for process "a"
+            Unknown group "no-such-group"
+        "#},
+        result,
+    );
+
+    assert_eq!("", output);
+}
+
+/// Verifies that a `pre` command with a numeric user, but without a
+/// group, aborts startup (instead of running as the root group).
+#[test_log::test(tokio::test)]
+async fn numeric_user_without_group_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = { user = "10001", command = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `pre` command failed for process "a"
+            Numeric user "10001" needs a group (such as "10001:10001")
+        "#},
+        result,
+    );
+
+    assert_eq!("", output);
+}
+
+/// Verifies that the `pre` and `run` commands run with their niceness.
+#[test_log::test(tokio::test)]
+async fn commands_run_with_nice() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        pre = { nice = 10, command = [ "/bin/sh", "-c", "nice >> {result_path}" ] }
+        run = { nice = 15, command = [ "/bin/sh", "-c", "nice >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            10
+            15
+        "#},
+        output
+    );
+}
+
+/// Verifies that the `pre` and `run` commands run with their IO
+/// scheduling class and priority.
+#[test_log::test(tokio::test)]
+async fn commands_run_with_io_priority() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        pre = [
+            { io-class = "idle", command = [ "/bin/sh", "-c", "ionice -p $$ >> {result_path}" ] },
+            { io-priority = 7, command = [ "/bin/sh", "-c", "ionice -p $$ >> {result_path}" ] },
+        ]
+        run = { io-class = "best-effort", command = [ "/bin/sh", "-c", "ionice -p $$ >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            idle
+            best-effort: prio 7
+            best-effort: prio 4
+        "#},
+        output
+    );
+}
+
+/// Verifies that the commands of a process only run on the CPUs in its
+/// `cpu-affinity`.
+#[test_log::test(tokio::test)]
+async fn commands_run_with_cpu_affinity() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        cpu-affinity = [ 0 ]
+        pre = [ "/bin/sh", "-c", "grep Cpus_allowed_list /proc/self/status >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "grep Cpus_allowed_list /proc/self/status >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!("Cpus_allowed_list:\t0\nCpus_allowed_list:\t0\n", output);
+}
+
+/// Verifies that the OOM score of the commands of a process is adjusted
+/// by its `oom-score-adj` (right after the commands start).
+#[test_log::test(tokio::test)]
+async fn commands_run_with_oom_score_adj() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        oom-score-adj = 500
+        pre = [ "/bin/sh", "-c", "sleep 0.5; cat /proc/$$/oom_score_adj >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "sleep 0.5; cat /proc/$$/oom_score_adj >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            500
+            500
+        "#},
+        output
+    );
+}
+
+/// Verifies that the commands of a process run with its resource
+/// limits (as both the soft and the hard limits).
+#[test_log::test(tokio::test)]
+async fn commands_run_with_limits() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        limits = { nofile = 256, core = 0 }
+        pre = [ "/bin/sh", "-c", "echo pre: $(ulimit -Sn) $(ulimit -Hn) $(ulimit -c) >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "echo run: $(ulimit -Sn) $(ulimit -Hn) $(ulimit -c) >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            pre: 256 256 0
+            run: 256 256 0
+        "#},
+        output
+    );
+}
+
+/// Verifies that the commands of a process with `private-tmp` share a
+/// private `TMPDIR`, which is removed once the process has stopped.
+#[test_log::test(tokio::test)]
+async fn commands_run_with_private_tmp() {
+    let config = r##"
+        [[processes]]
+        name = "private-tmp"
+        private-tmp = true
+        pre = [ "/bin/sh", "-c", "touch $TMPDIR/pre; stat -c %a $TMPDIR >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "ls $TMPDIR >> {result_path}; echo $TMPDIR >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let private_tmp =
+        std::env::temp_dir().join(format!("groundcontrol-{}-private-tmp", std::process::id()));
+    assert_eq!(format!("700\npre\n{}\n", private_tmp.display()), output);
+    assert!(!private_tmp.exists());
+}
+
+/// Verifies that the commands of a process cannot gain privileges with
+/// `no-new-privileges`, and have no ambient capabilities with
+/// `clear-ambient-capabilities`.
+#[test_log::test(tokio::test)]
+async fn commands_run_without_new_privileges() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        no-new-privileges = true
+        clear-ambient-capabilities = true
+        pre = [ "/bin/sh", "-c", "grep -E 'NoNewPrivs|CapAmb' /proc/self/status >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "grep -E 'NoNewPrivs|CapAmb' /proc/self/status >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        "CapAmb:\t0000000000000000\nNoNewPrivs:\t1\nCapAmb:\t0000000000000000\nNoNewPrivs:\t1\n",
+        output
+    );
+}
+
 /// Verifies that a failed `pre` execution shuts down all
 /// previously-started long-running processes.
 #[test_log::test(tokio::test)]
@@ -213,3 +458,239 @@ async fn failed_pre_shuts_down_earlier_processes() {
         output
     );
 }
+
+/// The `pre` command of a one-shot process is killed if it exceeds the
+/// process's `max-runtime`, which aborts startup.
+#[test_log::test(tokio::test)]
+async fn max_runtime_kills_oneshot_pre() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "sleep 5" ]
+        max-runtime = "100ms"
+        post = [ "/bin/sh", "-c", "echo b-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `pre` command timed out for process "b" (after 100ms)
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            a-post
+        "#},
+        output
+    );
+}
+
+/// Processes that do not finish starting within their `start-timeout`
+/// abort startup, just like a failed `pre` command.
+#[test_log::test(tokio::test)]
+async fn start_timeout_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "sleep 5" ]
+        start-timeout = "100ms"
+        run = [ "/bin/sh", "-c", "echo b-run >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo b-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            Process "b" did not start within 100ms
+            `pre` command timed out for process "b" (after 100ms)
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            a-post
+        "#},
+        output
+    );
+}
+
+/// Multiple `pre` commands run in order, and the first failure aborts
+/// startup (skipping the remaining `pre` commands) unless the command
+/// has `ignore-failure` set.
+#[test_log::test(tokio::test)]
+async fn multiple_pre_commands_run_in_order() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [
+            [ "/bin/sh", "-c", "echo a-pre-1 >> {result_path}" ],
+            { command = [ "/bin/sh", "-c", "echo a-pre-2 >> {result_path}; exit 1" ], ignore-failure = true },
+            [ "/bin/sh", "-c", "echo a-pre-3 >> {result_path}" ],
+        ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [
+            [ "/bin/sh", "-c", "echo b-pre-1 >> {result_path}; exit 2" ],
+            [ "/bin/sh", "-c", "echo b-pre-2 >> {result_path}" ],
+        ]
+        post = [ "/bin/sh", "-c", "echo b-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `pre` command failed for process "b" (exit code 2)
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre-1
+            a-pre-2
+            a-pre-3
+            b-pre-1
+            a-post
+        "#},
+        output
+    );
+}
+
+/// Daemons that are not ready within their `start-timeout` are stopped,
+/// and abort startup.
+#[test_log::test(tokio::test)]
+async fn start_timeout_limits_readiness_check() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        run = [ "/bin/sh", "{test-daemon.sh}", "b", "{result_path}", "{temp_path}" ]
+        ready = { command = [ "/bin/false" ], interval = "50ms", retries = 100 }
+        start-timeout = "500ms"
+
+        [[processes]]
+        name = "c"
+        pre = [ "/bin/sh", "-c", "echo c-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            Process "b" did not start within 500ms (process was not ready)
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            b:started
+            b:shutdown-requested
+            b:stopped
+            a-post
+        "#},
+        output
+    );
+}
+
+/// `pre` commands that exceed the `pre-timeout` are killed along with
+/// the processes that they started.
+#[test_log::test(tokio::test)]
+async fn pre_timeout_kills_children_of_pre() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "sleep 0.1; S=$(cat {temp_path}/sleep.pid); if [ -d /proc/$S ] && ! grep -q 'State:.*Z' /proc/$S/status; then echo sleep-running >> {result_path}; else echo sleep-gone >> {result_path}; fi" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "sleep 100 & echo $! > {temp_path}/sleep.pid; wait" ]
+        pre-timeout = "200ms"
+        run = [ "/bin/sh", "-c", "echo b-run >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `pre` command timed out for process "b" (after 200ms)
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            sleep-gone
+        "#},
+        output
+    );
+}
+
+/// `pre` commands that exceed the `pre-timeout` are killed, which
+/// aborts startup.
+#[test_log::test(tokio::test)]
+async fn pre_timeout_kills_pre() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "sleep 5" ]
+        pre-timeout = "100ms"
+        run = [ "/bin/sh", "-c", "echo b-run >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo b-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `pre` command timed out for process "b" (after 100ms)
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            a-post
+        "#},
+        output
+    );
+}
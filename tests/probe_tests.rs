@@ -0,0 +1,299 @@
+//! Tests that verify the `healthcheck` and `watchdog` probes used to
+//! detect (and act upon) daemons that are running, but unhealthy.
+
+use std::time::{Duration, Instant};
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Daemons that fail their health check are stopped and restarted (even
+/// though the daemon does not have a `restart` policy). The health
+/// check here only fails once, and only after the first daemon has
+/// started; the second daemon is then stopped by a graceful shutdown.
+#[test_log::test(tokio::test)]
+async fn unhealthy_daemon_is_restarted() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "if [ -f {temp_path}/checked ]; then exec /bin/sh {test-daemon.sh} daemon2 {result_path} {temp_path}; else exec /bin/sh {test-daemon.sh} daemon1 {result_path} {temp_path}; fi" ]
+        healthcheck = { command = [ "/bin/sh", "-c", "if [ -f {temp_path}/daemon1.pid ] && [ ! -f {temp_path}/checked ]; then touch {temp_path}/checked; exit 1; fi" ], interval = "10ms", failure-threshold = 1 }
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon2");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon1:started
+            daemon1:shutdown-requested
+            daemon1:stopped
+            daemon2:started
+            daemon2:shutdown-requested
+            daemon2:stopped
+        "#},
+        output
+    );
+}
+
+/// Health checks can also be configured to shut down Ground Control,
+/// which is treated as a daemon failure.
+#[test_log::test(tokio::test)]
+async fn unhealthy_daemon_triggers_shutdown() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        healthcheck = { command = [ "/bin/sh", "-c", "! test -f {temp_path}/daemon.pid" ], interval = "10ms", failure-threshold = 2, action = "shutdown" }
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// Health checks that exceed their `timeout` are killed, and count as
+/// failed checks.
+#[test_log::test(tokio::test)]
+async fn timed_out_health_check_fails() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        healthcheck = { command = [ "/bin/sh", "-c", "test -f {temp_path}/daemon.pid && sleep 5" ], initial-delay = "50ms", period = "10ms", timeout = "50ms", failure-threshold = 2, action = "shutdown" }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// Health checks with the `log-only` action keep running (and logging)
+/// after the daemon is unhealthy, but leave the daemon running.
+#[test_log::test(tokio::test)]
+async fn log_only_health_check_leaves_daemon_running() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        healthcheck = { command = [ "/bin/sh", "-c", "test -f {temp_path}/daemon.pid && echo check >> {temp_path}/checks; exit 1" ], interval = "10ms", failure-threshold = 1, action = "log-only" }
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    // Let the health check fail a few times before shutting down.
+    let checks_path = dir.path().join("checks");
+    tokio::task::spawn(async move {
+        loop {
+            let checks = tokio::fs::read_to_string(&checks_path)
+                .await
+                .unwrap_or_default();
+            if checks.lines().count() >= 3 {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons that do not touch their watchdog file within the watchdog
+/// timeout are considered hung.
+#[test_log::test(tokio::test)]
+async fn hung_daemon_triggers_shutdown() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        watchdog = { path = "{temp_path}/heartbeat", timeout = "100ms", action = "shutdown" }
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// HTTP readiness checks count malformed responses as failures, and
+/// stop the daemon once `failure-threshold` requests in a row have
+/// failed.
+#[test_log::test(tokio::test)]
+async fn malformed_http_readiness_responses_fail() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        ready = { http = "http://127.0.0.1:{port}/ready", initial-delay = "100ms", interval = "10ms", failure-threshold = 3 }
+        "##
+    .replace("{port}", &port.to_string());
+
+    tokio::task::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut request).await;
+            let _ = tokio::io::AsyncWriteExt::write_all(&mut stream, b"200 OK\r\n\r\n").await;
+        }
+    });
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// HTTP readiness requests that get no response within their `timeout`
+/// count as failures.
+#[test_log::test(tokio::test)]
+async fn slow_http_readiness_responses_time_out() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        ready = { http = "http://127.0.0.1:{port}/ready", initial-delay = "100ms", interval = "10ms", timeout = "50ms", failure-threshold = 2 }
+        "##
+    .replace("{port}", &port.to_string());
+
+    // Accept the requests, but never respond to them.
+    tokio::task::spawn(async move {
+        let mut streams = Vec::new();
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            streams.push(stream);
+        }
+    });
+
+    let (gc, _tx, dir) = start(&config).await;
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// Daemons that keep touching their watchdog file keep running.
+#[test_log::test(tokio::test)]
+async fn watchdog_keepalive() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/daemon.pid; while true; do touch {temp_path}/heartbeat; sleep 0.01; done" ]
+        watchdog = { path = "{temp_path}/heartbeat", timeout = "100ms", action = "shutdown" }
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    // Let the daemon run for several watchdog timeouts before asking
+    // Ground Control to shut down.
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("daemon-post\n", output);
+}
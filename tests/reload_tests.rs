@@ -0,0 +1,437 @@
+//! Tests that verify the `reload` mechanism, which tells a running
+//! daemon to reload its configuration without restarting the daemon,
+//! the signals that are forwarded to running daemons, and the status
+//! dump.
+
+use groundcontrol::{config::SignalConfig, Control};
+use indoc::indoc;
+use nix::sys::signal::Signal;
+use pretty_assertions::assert_eq;
+
+use crate::common::{
+    parse_config, spawn_daemon_waiter, start_with_control, stop, wait_for_result_line,
+};
+
+mod common;
+
+/// `reload` signals are sent to the daemon, which keeps running.
+#[test_log::test(tokio::test)]
+async fn reload_signal_is_sent_to_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        reload = "SIGHUP"
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(Control::Reload(None)).unwrap();
+        wait_for_result_line(&result_dir, "daemon:reload-requested").await;
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:reload-requested
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// `reload` commands are run while the daemon keeps running. Only the
+/// named process is reloaded.
+#[test_log::test(tokio::test)]
+async fn reload_command_runs_for_named_process() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        run = [ "/bin/sh", "{test-daemon.sh}", "a", "{result_path}", "{temp_path}" ]
+        reload = [ "/bin/sh", "-c", "echo a-reload >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "a", "{temp_path}" ]
+        run = [ "/bin/sh", "{test-daemon.sh}", "b", "{result_path}", "{temp_path}" ]
+        reload = [ "/bin/sh", "-c", "echo b-reload >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "b");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(Control::Reload(Some("b".into()))).unwrap();
+        wait_for_result_line(&result_dir, "b-reload").await;
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a:started
+            b:started
+            b-reload
+            b:shutdown-requested
+            b:stopped
+            a:shutdown-requested
+            a:stopped
+        "#},
+        output
+    );
+}
+
+/// Hung `reload` commands do not hold up the other control messages,
+/// and are killed once their `reload-timeout` elapses.
+#[test_log::test(tokio::test)]
+async fn reload_timeout_kills_hung_reload_command() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        reload = [ "/bin/sh", "-c", "echo reload-started >> {result_path}; sleep 5; echo reload-finished >> {result_path}" ]
+        reload-timeout = "500ms"
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(Control::Reload(None)).unwrap();
+        wait_for_result_line(&result_dir, "reload-started").await;
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            reload-started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// Forwarded signals are only sent to the daemons that forward them.
+#[test_log::test(tokio::test)]
+async fn forwarded_signals_are_sent_to_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        run = [ "/bin/sh", "{test-daemon.sh}", "a", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "a", "{temp_path}" ]
+        run = [ "/bin/sh", "{test-daemon.sh}", "b", "{result_path}", "{temp_path}" ]
+        forward-signals = [ "SIGHUP" ]
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "b");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(Control::Signal(SignalConfig::from(Signal::SIGHUP)))
+            .unwrap();
+        wait_for_result_line(&result_dir, "b:reload-requested").await;
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a:started
+            b:started
+            b:reload-requested
+            b:shutdown-requested
+            b:stopped
+            a:shutdown-requested
+            a:stopped
+        "#},
+        output
+    );
+}
+
+/// Signals are still forwarded to a daemon while the supervisor is
+/// stopping it on its own (here, for a scheduled restart, whose
+/// `pre-stop` command waits for the signal to arrive).
+#[test_log::test(tokio::test)]
+async fn signals_are_forwarded_during_scheduled_restart() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "if [ -f {temp_path}/daemon1.pid ]; then exec /bin/sh {test-daemon.sh} daemon2 {result_path} {temp_path}; else exec /bin/sh {test-daemon.sh} daemon1 {result_path} {temp_path}; fi" ]
+        restart-interval = "500ms"
+        pre-stop = [ "/bin/sh", "-c", "echo daemon-pre-stop >> {result_path}; while ! grep -q reload-requested {result_path}; do sleep 0.05; done" ]
+        pre-stop-timeout = "5s"
+        forward-signals = [ "SIGHUP" ]
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon2");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        wait_for_result_line(&result_dir, "daemon-pre-stop").await;
+        tx.send(Control::Signal(SignalConfig::from(Signal::SIGHUP)))
+            .unwrap();
+        daemon_waiter.await.unwrap();
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon1:started
+            daemon-pre-stop
+            daemon1:reload-requested
+            daemon1:shutdown-requested
+            daemon1:stopped
+            daemon2:started
+            daemon-pre-stop
+            daemon2:shutdown-requested
+            daemon2:stopped
+        "#},
+        output
+    );
+}
+
+/// Logging the status does not disturb the processes, whether they are
+/// running or have exited.
+#[test_log::test(tokio::test)]
+async fn status_dump_leaves_processes_running() {
+    let config = r##"
+        [[processes]]
+        name = "init"
+        pre = [ "/bin/sh", "-c", "echo init >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(Control::Status).unwrap();
+        tx.send(Control::Status).unwrap();
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            init
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// Signals are translated by the `signal-map` of the daemon.
+#[test_log::test(tokio::test)]
+async fn forwarded_signals_are_translated() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        forward-signals = [ "SIGWINCH" ]
+        signal-map = { SIGWINCH = "SIGHUP" }
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(Control::Signal(SignalConfig::from(Signal::SIGWINCH)))
+            .unwrap();
+        wait_for_result_line(&result_dir, "daemon:reload-requested").await;
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:reload-requested
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// Applying a new configuration stops the removed processes, restarts
+/// the changed processes, and starts the added processes (in the
+/// startup order of the new configuration), leaving the unchanged
+/// processes running.
+#[test_log::test(tokio::test)]
+async fn apply_restarts_changed_processes() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        run = [ "/bin/sh", "{test-daemon.sh}", "a", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "wait-a"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "a", "{temp_path}" ]
+
+        [[processes]]
+        name = "b"
+        run = [ "/bin/sh", "{test-daemon.sh}", "b", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "wait-b"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "b", "{temp_path}" ]
+
+        [[processes]]
+        name = "c"
+        run = [ "/bin/sh", "{test-daemon.sh}", "c", "{result_path}", "{temp_path}" ]
+        "##;
+    let new_config = r##"
+        [[processes]]
+        name = "a"
+        run = [ "/bin/sh", "{test-daemon.sh}", "a", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "wait-a"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "a", "{temp_path}" ]
+
+        [[processes]]
+        name = "d"
+        pre = [ "/bin/sh", "-c", "echo d-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        run = [ "/bin/sh", "{test-daemon.sh}", "b-new", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "wait-b"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "b", "{temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let new_config = parse_config(new_config, dir.path());
+    let c_waiter = spawn_daemon_waiter(&dir, "c");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        c_waiter.await.unwrap();
+        tx.send(Control::Apply(Box::new(new_config))).unwrap();
+        wait_for_result_line(&result_dir, "b-new:started").await;
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a:started
+            b:started
+            c:started
+            c:shutdown-requested
+            c:stopped
+            b:shutdown-requested
+            b:stopped
+            d-pre
+            b-new:started
+            b-new:shutdown-requested
+            b-new:stopped
+            a:shutdown-requested
+            a:stopped
+        "#},
+        output
+    );
+}
+
+/// Processes with `private-tmp` are left running when the configuration
+/// that is applied has not changed them.
+#[test_log::test(tokio::test)]
+async fn apply_keeps_unchanged_private_tmp_processes() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        run = [ "/bin/sh", "{test-daemon.sh}", "a", "{result_path}", "{temp_path}" ]
+        private-tmp = true
+        "##;
+    let new_config = r##"
+        [[processes]]
+        name = "a"
+        run = [ "/bin/sh", "{test-daemon.sh}", "a", "{result_path}", "{temp_path}" ]
+        private-tmp = true
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "echo b-pre >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let new_config = parse_config(new_config, dir.path());
+    let a_waiter = spawn_daemon_waiter(&dir, "a");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        a_waiter.await.unwrap();
+        tx.send(Control::Apply(Box::new(new_config))).unwrap();
+        wait_for_result_line(&result_dir, "b-pre").await;
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            a:started
+            b-pre
+            a:shutdown-requested
+            a:stopped
+        "#},
+        output
+    );
+}
@@ -0,0 +1,70 @@
+//! Tests that verify the fetching of configurations from URLs.
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+};
+
+use groundcontrol::config::Config;
+use pretty_assertions::assert_eq;
+
+const CONFIG: &str = r#"{ "processes": [ { "name": "app", "run": "/app/server" } ] }"#;
+
+/// SHA-256 checksum of [`CONFIG`].
+const CONFIG_SHA256: &str = "058955f888b4494ce22548228212439e5190f9fe9f5888ca73332e083f4aff0b";
+
+/// Serves the configuration (with the given `ETag`) to the given number
+/// of requests, and returns the URL of the configuration.
+fn serve(etag: &'static str, requests: usize) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(requests) {
+            let mut stream = stream.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nETag: {etag}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{CONFIG}",
+                CONFIG.len()
+            )
+            .unwrap();
+        }
+    });
+
+    format!("http://{address}/groundcontrol.json?version=2")
+}
+
+/// Configurations are fetched from URLs (in the format of the path of
+/// the URL).
+#[test]
+fn fetches_config() {
+    let url = serve("\"v2\"", 1);
+    let config = Config::load(&url, None).unwrap();
+    assert_eq!("app", config.processes[0].name);
+}
+
+/// Fetched configurations must have the expected checksum and `ETag`,
+/// if given.
+#[test]
+fn verifies_fetched_config() {
+    let url = serve("W/\"v2\"", 3);
+    Config::fetch(&url, None, Some(CONFIG_SHA256), Some("\"v2\"")).unwrap();
+
+    let wrong_sha256 = "0".repeat(64);
+    assert_eq!(
+        format!(
+            "Config \"{url}\" has the SHA-256 checksum {CONFIG_SHA256}, but {wrong_sha256} was expected"
+        ),
+        Config::fetch(&url, None, Some(&wrong_sha256), None)
+            .unwrap_err()
+            .to_string()
+    );
+
+    assert_eq!(
+        format!("Config \"{url}\" has the ETag W/\"v2\", but \"v1\" was expected"),
+        Config::fetch(&url, None, None, Some("\"v1\""))
+            .unwrap_err()
+            .to_string()
+    );
+}
@@ -0,0 +1,170 @@
+//! Tests that verify the expansion of processes with `replicas` into
+//! multiple instances.
+
+use groundcontrol::Control;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, start_with_control, stop, wait_for_result_line};
+
+mod common;
+
+/// Replicated processes are expanded into one process per instance,
+/// each of which is given its index in `GC_INSTANCE` (which can also be
+/// used in template expressions); references to the replicated process
+/// refer to all of its instances.
+#[test_log::test(tokio::test)]
+async fn replicas_expand_into_instances() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        depends-on = [ "worker" ]
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+
+        [[processes]]
+        name = "worker"
+        replicas = 3
+        pre = [ "/bin/sh", "-c", "echo worker-{{ GC_INSTANCE }} $GC_INSTANCE >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo worker-$GC_INSTANCE-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            worker-0 0
+            worker-1 1
+            worker-2 2
+            app
+            worker-2-post
+            worker-1-post
+            worker-0-post
+        "#},
+        output
+    );
+}
+
+/// Replicated processes can be scaled up (which starts the missing
+/// instances) and down (which stops the highest instances first) while
+/// Ground Control is running.
+#[test_log::test(tokio::test)]
+async fn replicas_can_be_scaled() {
+    let config = r##"
+        [[processes]]
+        name = "worker"
+        replicas = 1
+        run = [ "/bin/sh", "-c", "exec /bin/sh {test-daemon.sh} worker-$GC_INSTANCE {result_path} {temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let scale = |replicas| Control::Scale {
+        name: String::from("worker"),
+        replicas,
+    };
+    let worker0 = spawn_daemon_waiter(&dir, "worker-0");
+    let worker1 = spawn_daemon_waiter(&dir, "worker-1");
+    let worker2 = spawn_daemon_waiter(&dir, "worker-2");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        worker0.await.unwrap();
+        tx.send(scale(2)).unwrap();
+        worker1.await.unwrap();
+        tx.send(scale(3)).unwrap();
+        worker2.await.unwrap();
+        tx.send(scale(1)).unwrap();
+        wait_for_result_line(&result_dir, "worker-1:stopped").await;
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            worker-0:started
+            worker-1:started
+            worker-2:started
+            worker-2:shutdown-requested
+            worker-2:stopped
+            worker-1:shutdown-requested
+            worker-1:stopped
+            worker-0:shutdown-requested
+            worker-0:stopped
+        "#},
+        output
+    );
+}
+
+/// Instances that are started after the process was scaled down to zero
+/// replicas are still at the level of the replicated process in the
+/// dependency graph, and so are stopped before their dependencies.
+#[test_log::test(tokio::test)]
+async fn instances_scaled_up_from_zero_keep_their_level() {
+    let config = r##"
+        parallel_shutdown = true
+
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "{test-daemon.sh}", "db", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "worker"
+        replicas = 1
+        depends-on = [ "db" ]
+        run = [ "/bin/sh", "-c", "exec /bin/sh {test-daemon.sh} worker-$GC_INSTANCE {result_path} {temp_path}" ]
+        stop = [ "/bin/sh", "-c", "sleep 0.5; kill $GROUNDCONTROL_MAIN_PID" ]
+        "##;
+
+    let (gc, tx, dir) = start_with_control(config).await;
+
+    let scale = |replicas| Control::Scale {
+        name: String::from("worker"),
+        replicas,
+    };
+    let worker0 = spawn_daemon_waiter(&dir, "worker-0");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        worker0.await.unwrap();
+        tx.send(scale(0)).unwrap();
+        wait_for_result_line(&result_dir, "worker-0:stopped").await;
+        tx.send(scale(1)).unwrap();
+
+        // Wait for the new instance to start (it is named like the
+        // instance that was stopped).
+        let result_path = result_dir.join("results.txt");
+        while std::fs::read_to_string(&result_path)
+            .unwrap()
+            .matches("worker-0:started")
+            .count()
+            < 2
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        tx.send(Control::Shutdown).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            worker-0:started
+            worker-0:shutdown-requested
+            worker-0:stopped
+            worker-0:started
+            worker-0:shutdown-requested
+            worker-0:stopped
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
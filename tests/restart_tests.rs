@@ -0,0 +1,321 @@
+//! Tests that verify the `restart` policies that allow daemons to be
+//! restarted instead of triggering a shutdown when they exit.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// `on-failure` restarts a daemon that exits with a non-zero exit code.
+/// The daemon fails the first time, then exits cleanly the second time
+/// (which triggers a normal shutdown).
+#[test_log::test(tokio::test)]
+async fn on_failure_restarts_failed_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; if [ -f {temp_path}/ran ]; then exit 0; fi; touch {temp_path}/ran; exit 1" ]
+        restart = "on-failure"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon
+            daemon
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// `on-failure` does *not* restart a daemon that exits cleanly.
+#[test_log::test(tokio::test)]
+async fn on_failure_ignores_clean_exit() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}" ]
+        restart = "on-failure"
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("daemon\n", output);
+}
+
+/// `always` restarts a daemon even if it exits cleanly. The daemon
+/// exits the first time, and then stays running the second time, until
+/// Ground Control is asked to shut down (at which point the daemon is
+/// stopped and *not* restarted).
+#[test_log::test(tokio::test)]
+async fn always_restarts_clean_exit() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; if [ -f {temp_path}/ran ]; then exec /bin/sh {test-daemon.sh} daemon {result_path} {temp_path}; fi; touch {temp_path}/ran" ]
+        restart = "always"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon
+            daemon
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// `restart-backoff` delays restarts of the daemon (here only slightly,
+/// to keep the test fast).
+#[test_log::test(tokio::test)]
+async fn backoff_delays_restart() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; if [ -f {temp_path}/ran ]; then exit 0; fi; touch {temp_path}/ran; exit 1" ]
+        restart = "on-failure"
+        restart-backoff = { initial-delay = "10ms" }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("daemon\ndaemon\n", output);
+}
+
+/// Shutdown requests that arrive while a daemon is waiting to be
+/// restarted cancel the restart (instead of waiting for the delay).
+#[test_log::test(tokio::test)]
+async fn shutdown_during_backoff_cancels_restart() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; echo $$ > {temp_path}/daemon.pid; exit 1" ]
+        restart = "always"
+        restart-backoff = { initial-delay = "1h" }
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// `max-restarts` limits the number of restarts, after which Ground
+/// Control gives up on the daemon and shuts down.
+#[test_log::test(tokio::test)]
+async fn max_restarts_gives_up() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; exit 1" ]
+        restart = "on-failure"
+        max-restarts = 2
+        restart-window = "1m"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon
+            daemon
+            daemon
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// `restart-interval` proactively restarts a daemon that is still
+/// running, even without a `restart` policy.
+#[test_log::test(tokio::test)]
+async fn restart_interval_restarts_running_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "if [ -f {temp_path}/daemon1.pid ]; then exec /bin/sh {test-daemon.sh} daemon2 {result_path} {temp_path}; else exec /bin/sh {test-daemon.sh} daemon1 {result_path} {temp_path}; fi" ]
+        restart-interval = "200ms"
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon2");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon1:started
+            daemon1:shutdown-requested
+            daemon1:stopped
+            daemon2:started
+            daemon2:shutdown-requested
+            daemon2:stopped
+        "#},
+        output
+    );
+}
+
+/// `on-failure` does *not* restart a daemon that exits with one of its
+/// `allowed-exit-codes`.
+#[test_log::test(tokio::test)]
+async fn on_failure_ignores_allowed_exit_codes() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; exit 143" ]
+        restart = "on-failure"
+        allowed-exit-codes = [ 143 ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("daemon\n", output);
+}
+
+/// `on-crash` runs (with the exit code of the daemon) every time the
+/// daemon crashes, before the daemon is restarted.
+#[test_log::test(tokio::test)]
+async fn on_crash_runs_before_restart() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; if [ -f {temp_path}/ran ]; then exit 0; fi; touch {temp_path}/ran; exit 3" ]
+        restart = "on-failure"
+        on-crash = [ "/bin/sh", "-c", "echo crashed:${GC_EXIT_CODE}:${GC_SIGNAL:-none} >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon
+            crashed:3:none
+            daemon
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// Hung `on-crash` commands are killed once their `on-crash-timeout`
+/// elapses, and the daemon is then restarted as usual.
+#[test_log::test(tokio::test)]
+async fn on_crash_timeout_kills_hung_on_crash_command() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; if [ -f {temp_path}/ran ]; then exit 0; fi; touch {temp_path}/ran; exit 3" ]
+        restart = "on-failure"
+        on-crash = [ "/bin/sh", "-c", "echo crashed >> {result_path}; sleep 5; echo on-crash-finished >> {result_path}" ]
+        on-crash-timeout = "500ms"
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon
+            crashed
+            daemon
+        "#},
+        output
+    );
+}
+
+/// `on-crash` is given the name of the signal that killed the daemon,
+/// and then the normal shutdown logic proceeds.
+#[test_log::test(tokio::test)]
+async fn on_crash_receives_signal() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; kill -9 $$" ]
+        on-crash = [ "/bin/sh", "-c", "echo crashed:${GC_EXIT_CODE:-none}:${GC_SIGNAL} >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
+    ));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon
+            crashed:none:SIGKILL
+            daemon-post
+        "#},
+        output
+    );
+}
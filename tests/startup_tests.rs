@@ -2,7 +2,10 @@
 //! "startup" is defined as the process of getting all long-running
 //! processes into their started state).
 
-use crate::common::{spawn_daemon_waiter, start, stop};
+use groundcontrol::config::PlatformConfig;
+use indoc::indoc;
+
+use crate::common::{spawn_daemon_waiter, start, stop, wait_for_result_line};
 
 mod common;
 
@@ -84,7 +87,223 @@ async fn single_daemon_failure() {
     let (result, output) = stop(gc, dir).await;
     assert!(matches!(
         result,
-        Err(groundcontrol::Error::AbnormalShutdown)
+        Err(groundcontrol::Error::AbnormalShutdown { .. })
     ));
     assert_eq!("", output);
 }
+
+/// `start-delay` delays the start of a process until some time after
+/// the previous process has started.
+#[test_log::test(tokio::test)]
+async fn start_delay_delays_process_start() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        start-delay = "200ms"
+        pre = [ "/bin/sh", "-c", "echo b >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "echo b-run >> {result_path}" ]
+        "##;
+
+    let started = std::time::Instant::now();
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("a\nb\nb-run\n", output);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(200));
+}
+
+/// `allowed-exit-codes` treats the given (non-zero) exit codes of `pre`
+/// and `run` commands as success.
+#[test_log::test(tokio::test)]
+async fn allowed_exit_codes_are_success() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}; exit 3" ]
+        allowed-exit-codes = [ 3 ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; exit 143" ]
+        allowed-exit-codes = [ 0, 143 ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+    assert!(result.is_ok());
+    assert_eq!("a-pre\ndaemon\n", output);
+}
+
+/// Processes whose `condition` is not met are skipped (including their
+/// `post` commands), and processes that depend on them are started
+/// anyway.
+#[test_log::test(tokio::test)]
+async fn unmet_conditions_skip_processes() {
+    let config = r##"
+        [env]
+        CONDITION_ENABLED = "1"
+        CONDITION_DISABLED = "false"
+
+        [[processes]]
+        name = "enabled"
+        condition = { env = "CONDITION_ENABLED" }
+        pre = [ "/bin/sh", "-c", "echo enabled >> {result_path}" ]
+
+        [[processes]]
+        name = "disabled"
+        condition = { env = "CONDITION_DISABLED" }
+        pre = [ "/bin/sh", "-c", "echo disabled >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo disabled-post >> {result_path}" ]
+
+        [[processes]]
+        name = "unset"
+        condition = { env = "CONDITION_UNSET" }
+        pre = [ "/bin/sh", "-c", "echo unset >> {result_path}" ]
+
+        [[processes]]
+        name = "checked"
+        condition = { command = [ "/bin/sh", "-c", "echo check >> {result_path}" ] }
+        pre = [ "/bin/sh", "-c", "echo checked >> {result_path}" ]
+
+        [[processes]]
+        name = "failed-check"
+        condition = { command = [ "/bin/sh", "-c", "exit 1" ] }
+        run = [ "/bin/sh", "-c", "echo failed-check >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        depends-on = [ "disabled", "failed-check" ]
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("enabled\ncheck\nchecked\ndaemon\n", output);
+}
+
+/// Processes with `profiles` are only started if one of their profiles
+/// is active; processes without profiles are always started.
+#[test_log::test(tokio::test)]
+async fn profiles_select_processes() {
+    let config = r##"
+        profiles = [ "debug" ]
+
+        [[processes]]
+        name = "always"
+        pre = [ "/bin/sh", "-c", "echo always >> {result_path}" ]
+
+        [[processes]]
+        name = "debug-tools"
+        profiles = [ "debug" ]
+        pre = [ "/bin/sh", "-c", "echo debug-tools >> {result_path}" ]
+
+        [[processes]]
+        name = "full-only"
+        profiles = [ "full" ]
+        pre = [ "/bin/sh", "-c", "echo full-only >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo full-only-post >> {result_path}" ]
+
+        [[processes]]
+        name = "debug-or-full"
+        profiles = [ "full", "debug" ]
+        run = [ "/bin/sh", "-c", "echo debug-or-full >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("always\ndebug-tools\ndebug-or-full\n", output);
+}
+
+/// Processes with `only-on` platforms are skipped on every other
+/// platform, and processes with `skip-on` platforms are skipped on
+/// those platforms.
+#[test_log::test(tokio::test)]
+async fn platforms_select_processes() {
+    let platform = PlatformConfig::current().to_string();
+    let (os, arch) = platform.split_once('/').unwrap();
+    let other_arch = if arch == "amd64" { "s390x" } else { "amd64" };
+    let config = r##"
+        [[processes]]
+        name = "here"
+        only-on = [ "{os}" ]
+        pre = [ "/bin/sh", "-c", "echo here >> {result_path}" ]
+
+        [[processes]]
+        name = "elsewhere"
+        only-on = [ "{os}/{other_arch}" ]
+        pre = [ "/bin/sh", "-c", "echo elsewhere >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo elsewhere-post >> {result_path}" ]
+
+        [[processes]]
+        name = "not-here"
+        skip-on = [ "{os}/{other_arch}", "{platform}" ]
+        pre = [ "/bin/sh", "-c", "echo not-here >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        skip-on = [ "{os}/{other_arch}" ]
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}" ]
+        "##
+    .replace("{platform}", &platform)
+    .replace("{os}", os)
+    .replace("{other_arch}", other_arch);
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("here\ndaemon\n", output);
+}
+
+/// The `ready_file` is created, and the `ready_command` is run, once
+/// every process has started; the file is removed during shutdown.
+#[test_log::test(tokio::test)]
+async fn ready_marker_after_startup() {
+    let config = r##"
+        ready_file = "{temp_path}/all.ready"
+        ready_command = [ "/bin/sh", "-c", "test -f {temp_path}/all.ready && echo ready-command >> {result_path}" ]
+
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "test -f {temp_path}/all.ready || echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "test -f {temp_path}/all.ready || echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        ready = { file = "{temp_path}/daemon.pid" }
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        wait_for_result_line(&result_dir, "ready-command").await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        indoc! {r#"
+            a-pre
+            daemon:started
+            ready-command
+            daemon:shutdown-requested
+            daemon:stopped
+            a-post
+        "#},
+        output
+    );
+}
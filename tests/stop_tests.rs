@@ -1,9 +1,11 @@
 //! Tests the verify different aspects of the `stop` configurations that
 //! stop long-running daemons.
 
+use std::time::{Duration, Instant};
+
 use indoc::indoc;
 
-use crate::common::{spawn_daemon_waiter, start, stop};
+use crate::common::{assert_exited, spawn_daemon_waiter, start, stop, wait_for_result_line};
 
 mod common;
 
@@ -351,3 +353,451 @@ async fn not_found_stop_command_continues_shutdown() {
         output
     );
 }
+
+/// Daemons that exceed their `max-runtime` are stopped using their
+/// `stop` mechanism; what happens next is up to the `restart` policy
+/// (here, there is no policy, so the daemon's clean exit shuts down
+/// Ground Control).
+#[test_log::test(tokio::test)]
+async fn max_runtime_stops_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        max-runtime = "200ms"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// `pre-stop` commands run before the daemon is stopped.
+#[test_log::test(tokio::test)]
+async fn pre_stop_runs_before_stop() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        pre-stop = [ "/bin/sh", "-c", "echo daemon-pre-stop >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon-pre-stop
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// `pre-stop` commands that exceed their `pre-stop-timeout` are killed,
+/// and the daemon is then stopped as usual.
+#[test_log::test(tokio::test)]
+async fn pre_stop_timeout_kills_pre_stop() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        pre-stop = [ "/bin/sh", "-c", "echo daemon-pre-stop >> {result_path}; sleep 5; echo daemon-pre-stop-done >> {result_path}" ]
+        pre-stop-timeout = "100ms"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon-pre-stop
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// `stop` commands that exceed the `stop-timeout` are killed, and the
+/// shutdown continues (just like any other failed `stop` command).
+#[test_log::test(tokio::test)]
+async fn stop_timeout_kills_stop_command() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}; echo $$ > {temp_path}/daemon.pid; exec sleep 1" ]
+        stop = [ "/bin/sh", "-c", "echo daemon-stop >> {result_path}; sleep 5" ]
+        stop-timeout = "100ms"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let started = std::time::Instant::now();
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+    assert_eq!(
+        indoc! {r#"
+            daemon
+            daemon-stop
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// Daemons that are still running once the `shutdown_timeout` has
+/// elapsed are killed, and the remaining `post` commands are skipped.
+#[test_log::test(tokio::test)]
+async fn shutdown_timeout_kills_stubborn_daemon() {
+    let config = r##"
+        shutdown_timeout = "500ms"
+
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "echo a-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "stubborn"
+        run = [ "/bin/sh", "-c", "trap '' TERM; echo $$ > {temp_path}/stubborn.pid; exec sleep 10" ]
+        post = [ "/bin/sh", "-c", "echo stubborn-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "stubborn");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+
+    assert_eq!("a-pre\n", output);
+}
+
+/// Hung `stop` commands are killed once the `shutdown_timeout` has
+/// elapsed, along with the daemon.
+#[test_log::test(tokio::test)]
+async fn shutdown_timeout_kills_hung_stop_command() {
+    let config = r##"
+        shutdown_timeout = "500ms"
+
+        [[processes]]
+        name = "hung"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/hung.pid; exec sleep 100" ]
+        stop = [ "/bin/sh", "-c", "echo hung-stop >> {result_path}; exec sleep 100" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "hung");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    assert_eq!("hung-stop\n", output);
+}
+
+/// Hung `post` commands are killed (along with the processes that they
+/// started) once the `shutdown_timeout` has elapsed.
+#[test_log::test(tokio::test)]
+async fn shutdown_timeout_kills_hung_post_command() {
+    let config = r##"
+        shutdown_timeout = "500ms"
+
+        [[processes]]
+        name = "hung"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/hung.pid; exec sleep 100" ]
+        post = [ "/bin/sh", "-c", "sleep 100 & echo $! > {temp_path}/sleep.pid; echo hung-post >> {result_path}; wait" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "hung");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let sleep_waiter = spawn_daemon_waiter(&dir, "sleep");
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    assert_eq!("hung-post\n", output);
+    assert_exited(sleep_waiter.await.unwrap()).await;
+}
+
+/// Daemons that are still running when the shutdown is requested again
+/// are killed, and the remaining `post` commands are skipped.
+#[test_log::test(tokio::test)]
+async fn second_shutdown_request_kills_stubborn_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "stubborn"
+        run = [ "/bin/sh", "-c", "trap '' TERM; echo $$ > {temp_path}/stubborn.pid; exec sleep 10" ]
+        post = [ "/bin/sh", "-c", "echo stubborn-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "stubborn");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        tx.send(()).unwrap();
+    });
+
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    assert_eq!("", output);
+}
+
+/// Hung `pre-stop` commands are killed when the shutdown is requested
+/// again, and the daemon is killed without running its `stop` command.
+#[test_log::test(tokio::test)]
+async fn second_shutdown_request_kills_hung_pre_stop_command() {
+    let config = r##"
+        [[processes]]
+        name = "hung"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/hung.pid; exec sleep 100" ]
+        pre-stop = [ "/bin/sh", "-c", "echo hung-pre-stop >> {result_path}; exec sleep 100" ]
+        stop = [ "/bin/sh", "-c", "echo hung-stop >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "hung");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        tx.send(()).unwrap();
+    });
+
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    assert_eq!("hung-pre-stop\n", output);
+}
+
+/// Hung `post` commands are killed when the shutdown is requested again,
+/// and the remaining `post` commands are skipped.
+#[test_log::test(tokio::test)]
+async fn second_shutdown_request_kills_hung_post_command() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/a.pid; exec sleep 100" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "hung"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/hung.pid; exec sleep 100" ]
+        post = [
+            [ "/bin/sh", "-c", "echo hung-post >> {result_path}; exec sleep 100" ],
+            [ "/bin/sh", "-c", "echo hung-post-2 >> {result_path}" ],
+        ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "hung");
+    let result_dir = dir.path().to_owned();
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+        wait_for_result_line(&result_dir, "hung-post").await;
+        tx.send(()).unwrap();
+    });
+
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    assert_eq!("hung-post\n", output);
+}
+
+/// `stop` steps are taken in order, each once the previous step's
+/// timeout has elapsed, and the daemon is killed after the last step.
+#[test_log::test(tokio::test)]
+async fn stop_steps_escalate_until_daemon_is_killed() {
+    let config = r##"
+        [[processes]]
+        name = "stubborn"
+        run = [ "/bin/sh", "-c", "trap '' TERM; echo $$ > {temp_path}/stubborn.pid; exec sleep 10" ]
+        stop = [
+            { signal = "SIGTERM", timeout = "200ms" },
+            { command = [ "/bin/sh", "-c", "echo stubborn-step >> {result_path}" ], timeout = "200ms" },
+        ]
+        post = [ "/bin/sh", "-c", "echo stubborn-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "stubborn");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    assert_eq!("stubborn-step\nstubborn-post\n", output);
+}
+
+/// Daemons that do not stop within their `stop-timeout` are killed with
+/// `SIGKILL`, and the shutdown continues.
+#[test_log::test(tokio::test)]
+async fn stop_timeout_kills_stubborn_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "stubborn"
+        run = [ "/bin/sh", "-c", "trap '' TERM; echo $$ > {temp_path}/stubborn.pid; exec sleep 10" ]
+        stop-timeout = "200ms"
+        post = [ "/bin/sh", "-c", "echo stubborn-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "stubborn");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let started_at = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+    assert_eq!("stubborn-post\n", output);
+}
+
+/// With `kill-mode = "group"`, the `stop` signal is sent to the whole
+/// process group of the daemon (including its workers).
+#[test_log::test(tokio::test)]
+async fn group_kill_mode_stops_workers() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "trap : TERM; /bin/sh -c 'trap \"echo worker-stopped >> {result_path}; exit\" TERM; echo $$ > {temp_path}/worker.pid; while true; do sleep 0.1; done' & echo $$ > {temp_path}/daemon.pid; wait; wait" ]
+        kill-mode = "group"
+        post = [ "/bin/sh", "-c", "sleep 0.1; W=$(cat {temp_path}/worker.pid); if [ -d /proc/$W ] && ! grep -q 'State:.*Z' /proc/$W/status; then echo worker-running >> {result_path}; else echo worker-gone >> {result_path}; fi" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let worker_waiter = spawn_daemon_waiter(&dir, "worker");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        worker_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("worker-stopped\nworker-gone\n", output);
+}
+
+/// With `kill-mode = "mixed"`, the `stop` signal is only sent to the
+/// main process, and whatever is left of the process group once the
+/// daemon has stopped is killed.
+#[test_log::test(tokio::test)]
+async fn mixed_kill_mode_kills_leftover_workers() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "/bin/sh -c 'trap \"echo worker-stopped >> {result_path}; exit\" TERM; echo $$ > {temp_path}/worker.pid; while true; do sleep 0.1; done' & echo $$ > {temp_path}/daemon.pid; wait" ]
+        kill-mode = "mixed"
+        post = [ "/bin/sh", "-c", "sleep 0.1; W=$(cat {temp_path}/worker.pid); if [ -d /proc/$W ] && ! grep -q 'State:.*Z' /proc/$W/status; then echo worker-running >> {result_path}; else echo worker-gone >> {result_path}; fi" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let worker_waiter = spawn_daemon_waiter(&dir, "worker");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        worker_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("worker-gone\n", output);
+}
@@ -0,0 +1,129 @@
+//! Tests that verify the notifications that Ground Control sends to
+//! systemd when it is itself a systemd service. (these tests are in
+//! their own file, and run one at a time, since `NOTIFY_SOCKET` is set
+//! for the whole test process)
+
+use std::time::Duration;
+
+use indoc::indoc;
+use once_cell::sync::Lazy;
+use tempfile::TempDir;
+use tokio::{net::UnixDatagram, sync::Mutex};
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// Held by each test while it uses the systemd environment variables.
+static SYSTEMD_ENV: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// `READY=1` is sent once every daemon is ready, and `STOPPING=1` once
+/// the shutdown begins; the processes do not inherit `NOTIFY_SOCKET`.
+#[test_log::test(tokio::test)]
+async fn systemd_is_notified_of_readiness_and_shutdown() {
+    let _env = SYSTEMD_ENV.lock().await;
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon:NOTIFY_SOCKET=$NOTIFY_SOCKET >> {result_path}; exec /bin/sh {test-daemon.sh} daemon {result_path} {temp_path}" ]
+        ready = { file = "{temp_path}/daemon.pid" }
+        "##;
+
+    let socket_dir = TempDir::new().unwrap();
+    let socket_path = socket_dir.path().join("notify.sock");
+    let socket = UnixDatagram::bind(&socket_path).unwrap();
+    std::env::set_var("NOTIFY_SOCKET", &socket_path);
+
+    let (gc, tx, dir) = start(config).await;
+
+    let notifications = tokio::task::spawn(async move {
+        let mut notifications = Vec::new();
+        let mut buffer = vec![0; 4096];
+        while let Ok(length) = socket.recv(&mut buffer).await {
+            let notification = String::from_utf8_lossy(&buffer[..length]).into_owned();
+            if notification == "READY=1" {
+                tx.send(()).unwrap();
+            }
+
+            let stopping = notification == "STOPPING=1";
+            notifications.push(notification);
+            if stopping {
+                break;
+            }
+        }
+        notifications
+    });
+
+    let (result, output) = stop(gc, dir).await;
+    let notifications = notifications.await.unwrap();
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:NOTIFY_SOCKET=
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+    assert_eq!(vec!["READY=1", "STOPPING=1"], notifications);
+}
+
+/// `WATCHDOG=1` is sent every half of `WATCHDOG_USEC` while Ground
+/// Control is running.
+#[test_log::test(tokio::test)]
+async fn systemd_watchdog_is_pinged() {
+    let _env = SYSTEMD_ENV.lock().await;
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon:WATCHDOG_USEC=$WATCHDOG_USEC >> {result_path}; exec /bin/sh {test-daemon.sh} daemon {result_path} {temp_path}" ]
+        "##;
+
+    let socket_dir = TempDir::new().unwrap();
+    let socket_path = socket_dir.path().join("notify.sock");
+    let socket = UnixDatagram::bind(&socket_path).unwrap();
+    std::env::set_var("NOTIFY_SOCKET", &socket_path);
+    std::env::set_var("WATCHDOG_USEC", "100000");
+
+    let (gc, tx, dir) = start(config).await;
+
+    // Stop Ground Control after it has pinged the watchdog a few times.
+    let pings = tokio::task::spawn(async move {
+        let mut pings = 0;
+        let mut buffer = vec![0; 4096];
+        let received = tokio::time::timeout(Duration::from_secs(2), async {
+            while let Ok(length) = socket.recv(&mut buffer).await {
+                if &buffer[..length] == b"WATCHDOG=1" {
+                    pings += 1;
+                    if pings == 5 {
+                        break;
+                    }
+                }
+            }
+        })
+        .await;
+
+        tx.send(()).unwrap();
+        received.is_ok()
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(pings.await.unwrap());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:WATCHDOG_USEC=
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}